@@ -0,0 +1,201 @@
+//! Derive macro for `jwt-simple` custom claims.
+//!
+//! This crate is not meant to be used directly: enable the `derive` feature
+//! of `jwt-simple` and use `jwt_simple::CustomClaims` instead.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, LitStr};
+
+/// Claims already represented by `JWTClaims`, that custom claims cannot use
+const REGISTERED_CLAIMS: &[&str] = &[
+    "iss", "sub", "aud", "exp", "nbf", "iat", "jti", "nonce", "cnf", "act",
+];
+
+struct ClaimField {
+    ident: syn::Ident,
+    ty: syn::Type,
+    name: String,
+    default: bool,
+    skip_serializing_if: Option<syn::ExprPath>,
+}
+
+/// Derive `Serialize` and `Deserialize` for a custom claims structure,
+/// rejecting claim names that collide with the standard claims.
+///
+/// Attributes:
+///
+/// * `#[claims(namespace = "...")]` on the structure prefixes the name of
+///   every claim.
+/// * `#[claim(rename = "...")]` on a field sets the name of the claim.
+/// * `#[claim(default)]` on a field uses its default value if the claim is
+///   missing.
+/// * `#[claim(skip_serializing_if = "...")]` on a field omits the claim if the
+///   given function returns `true`.
+#[proc_macro_derive(CustomClaims, attributes(claims, claim))]
+pub fn derive_custom_claims(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2, Error> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "CustomClaims cannot be derived for generic types",
+        ));
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    &input.ident,
+                    "CustomClaims can only be derived for structures with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "CustomClaims can only be derived for structures",
+            ))
+        }
+    };
+
+    let mut namespace = String::new();
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("claims"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("namespace") {
+                namespace = meta.value()?.parse::<LitStr>()?.value();
+                Ok(())
+            } else {
+                Err(meta.error("unsupported claims attribute"))
+            }
+        })?;
+    }
+
+    let mut claim_fields: Vec<ClaimField> = vec![];
+    for field in fields {
+        let ident = field.ident.clone().unwrap();
+        let mut name = None;
+        let mut default = false;
+        let mut skip_serializing_if = None;
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("claim"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    name = Some(meta.value()?.parse::<LitStr>()?.value());
+                } else if meta.path.is_ident("default") {
+                    default = true;
+                } else if meta.path.is_ident("skip_serializing_if") {
+                    let path = meta.value()?.parse::<LitStr>()?;
+                    skip_serializing_if = Some(path.parse::<syn::ExprPath>()?);
+                } else {
+                    return Err(meta.error("unsupported claim attribute"));
+                }
+                Ok(())
+            })?;
+        }
+        let name = format!(
+            "{}{}",
+            namespace,
+            name.unwrap_or_else(|| ident.to_string().trim_start_matches("r#").to_string())
+        );
+        if REGISTERED_CLAIMS.contains(&name.as_str()) {
+            return Err(Error::new_spanned(
+                field,
+                format!(
+                    "`{}` is a standard claim, and is already part of JWTClaims",
+                    name
+                ),
+            ));
+        }
+        if claim_fields.iter().any(|other| other.name == name) {
+            return Err(Error::new_spanned(
+                field,
+                format!("duplicate claim `{}`", name),
+            ));
+        }
+        claim_fields.push(ClaimField {
+            ident,
+            ty: field.ty.clone(),
+            name,
+            default,
+            skip_serializing_if,
+        });
+    }
+
+    let ident = &input.ident;
+    let shadow = format_ident!("__{}Claims", ident);
+    let serde_crate = LitStr::new("::jwt_simple::reexports::serde", Span::call_site());
+
+    let serialize_entries = claim_fields.iter().map(|field| {
+        let (field_ident, name) = (&field.ident, &field.name);
+        let entry = quote! {
+            _serde::ser::SerializeMap::serialize_entry(&mut map, #name, &self.#field_ident)?;
+        };
+        match &field.skip_serializing_if {
+            Some(skip) => quote! {
+                if !#skip(&self.#field_ident) {
+                    #entry
+                }
+            },
+            None => entry,
+        }
+    });
+    let shadow_fields = claim_fields.iter().map(|field| {
+        let (field_ident, ty, name) = (&field.ident, &field.ty, &field.name);
+        let default = if field.default {
+            quote!(#[serde(default)])
+        } else {
+            quote!()
+        };
+        quote! {
+            #[serde(rename = #name)]
+            #default
+            #field_ident: #ty,
+        }
+    });
+    let field_idents: Vec<_> = claim_fields.iter().map(|field| &field.ident).collect();
+
+    Ok(quote! {
+        const _: () = {
+            use ::jwt_simple::reexports::serde as _serde;
+
+            impl _serde::Serialize for #ident {
+                fn serialize<S: _serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    let mut map = serializer.serialize_map(None)?;
+                    #(#serialize_entries)*
+                    _serde::ser::SerializeMap::end(map)
+                }
+            }
+
+            #[derive(_serde::Deserialize)]
+            #[serde(crate = #serde_crate)]
+            struct #shadow {
+                #(#shadow_fields)*
+            }
+
+            impl<'de> _serde::Deserialize<'de> for #ident {
+                fn deserialize<D: _serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    let claims = <#shadow as _serde::Deserialize>::deserialize(deserializer)?;
+                    Ok(#ident {
+                        #(#field_idents: claims.#field_idents,)*
+                    })
+                }
+            }
+        };
+    })
+}
@@ -0,0 +1,214 @@
+use std::collections::HashSet;
+
+use coarsetime::UnixTimeStamp;
+use serde::{Deserialize, Serialize};
+
+use crate::claims::*;
+use crate::common::*;
+use crate::error::*;
+use crate::nonce::{NonceProvider, RandomNonceProvider};
+use crate::serde_additions;
+
+/// The type ("typ") of JWT access tokens (RFC 9068)
+pub const ACCESS_TOKEN_TYPE: &str = "at+jwt";
+
+/// Claims of JWT access tokens (RFC 9068), in addition to the standard JWT
+/// claims
+///
+/// Application-defined claims can still be added, as
+/// `AccessTokenClaims<CustomClaims>`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessTokenClaims<CustomClaims = NoCustomClaims> {
+    /// The client the token was issued to
+    pub client_id: String,
+
+    /// The scopes granted to the client
+    #[serde(default, skip_serializing_if = "Scopes::is_empty")]
+    pub scope: Scopes,
+
+    /// Time when the end-user authentication occurred
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "self::serde_additions::unix_timestamp"
+    )]
+    pub auth_time: Option<UnixTimeStamp>,
+
+    /// Authentication context class reference
+    #[serde(rename = "acr", default, skip_serializing_if = "Option::is_none")]
+    pub authentication_context_class: Option<String>,
+
+    /// Authentication methods references
+    #[serde(rename = "amr", default, skip_serializing_if = "Option::is_none")]
+    pub authentication_methods: Option<Vec<String>>,
+
+    /// The roles of the subject (RFC 7643)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+
+    /// The groups the subject belongs to (RFC 7643)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+
+    /// The entitlements of the subject (RFC 7643)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entitlements: Vec<String>,
+
+    /// Custom (application-defined) claims
+    #[serde(flatten)]
+    pub custom: CustomClaims,
+}
+
+impl AccessTokenClaims<NoCustomClaims> {
+    /// Create access token claims for a client
+    pub fn new(client_id: impl ToString) -> Self {
+        Self::with_custom_claims(client_id, NoCustomClaims {})
+    }
+}
+
+impl<CustomClaims> AccessTokenClaims<CustomClaims> {
+    /// Create access token claims for a client, along with
+    /// application-defined claims
+    pub fn with_custom_claims(client_id: impl ToString, custom: CustomClaims) -> Self {
+        AccessTokenClaims {
+            client_id: client_id.to_string(),
+            scope: Scopes::new(),
+            auth_time: None,
+            authentication_context_class: None,
+            authentication_methods: None,
+            roles: vec![],
+            groups: vec![],
+            entitlements: vec![],
+            custom,
+        }
+    }
+
+    /// Grant one or more scopes, given as a space-delimited list
+    pub fn with_scope(mut self, scope: &str) -> Self {
+        self.scope.insert(scope);
+        self
+    }
+
+    /// Set the time when the end-user authentication occurred
+    pub fn with_auth_time(mut self, auth_time: UnixTimeStamp) -> Self {
+        self.auth_time = Some(auth_time);
+        self
+    }
+
+    /// Set the authentication context class reference
+    pub fn with_acr(mut self, acr: impl ToString) -> Self {
+        self.authentication_context_class = Some(acr.to_string());
+        self
+    }
+
+    /// Set the authentication methods references
+    pub fn with_amr(mut self, amr: impl IntoIterator<Item = impl ToString>) -> Self {
+        self.authentication_methods = Some(amr.into_iter().map(|x| x.to_string()).collect());
+        self
+    }
+
+    /// Set the roles of the subject
+    pub fn with_roles(mut self, roles: impl IntoIterator<Item = impl ToString>) -> Self {
+        self.roles = roles.into_iter().map(|x| x.to_string()).collect();
+        self
+    }
+
+    /// Set the groups the subject belongs to
+    pub fn with_groups(mut self, groups: impl IntoIterator<Item = impl ToString>) -> Self {
+        self.groups = groups.into_iter().map(|x| x.to_string()).collect();
+        self
+    }
+
+    /// Set the entitlements of the subject
+    pub fn with_entitlements(
+        mut self,
+        entitlements: impl IntoIterator<Item = impl ToString>,
+    ) -> Self {
+        self.entitlements = entitlements.into_iter().map(|x| x.to_string()).collect();
+        self
+    }
+
+    /// Return `true` if the scope has been granted
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scope.contains(scope)
+    }
+
+    /// Return `true` if the subject has the role
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|x| x == role)
+    }
+
+    /// Return `true` if the subject belongs to the group
+    pub fn in_group(&self, group: &str) -> bool {
+        self.groups.iter().any(|x| x == group)
+    }
+}
+
+/// The JWT access token profile (RFC 9068)
+pub struct AccessToken;
+
+impl AccessToken {
+    /// Sign access token claims, with the `at+jwt` type
+    ///
+    /// The issuer, subject, audience, creation and expiration dates must be
+    /// set. A random identifier (`jti` claim) is added if missing.
+    ///
+    /// The signer gets the claims and the header options, and must return a
+    /// signed token, for example using `sign_with_header_options()`.
+    pub fn sign<CustomClaims>(
+        mut claims: JWTClaims<AccessTokenClaims<CustomClaims>>,
+        signer: impl FnOnce(
+            JWTClaims<AccessTokenClaims<CustomClaims>>,
+            &HeaderOptions,
+        ) -> Result<String, Error>,
+    ) -> Result<String, Error> {
+        ensure!(claims.issuer.is_some(), JWTError::RequiredIssuerMissing);
+        ensure!(claims.subject.is_some(), JWTError::RequiredSubjectMissing);
+        ensure!(
+            claims.audiences.is_some(),
+            JWTError::RequiredAudienceMissing
+        );
+        ensure!(
+            claims.expires_at.is_some(),
+            JWTError::RequiredExpirationMissing
+        );
+        ensure!(
+            claims.issued_at.is_some(),
+            JWTError::RequiredIssuedAtMissing
+        );
+        ensure!(
+            !claims.custom.client_id.is_empty(),
+            JWTError::RequiredClaimMissing("client_id".to_string())
+        );
+        if claims.jwt_id.is_none() {
+            claims.jwt_id = Some(RandomNonceProvider.generate());
+        }
+        let header_options = HeaderOptions::default().with_signature_type(ACCESS_TOKEN_TYPE);
+        signer(claims, &header_options)
+    }
+
+    /// Verification options for access tokens issued by `issuer` for the
+    /// resource server `audience`
+    ///
+    /// The token type must be `at+jwt`, and the `iss`, `exp`, `aud`, `sub`,
+    /// `client_id`, `iat` and `jti` claims are required. Other options can be
+    /// set on the returned value.
+    pub fn verification_options(
+        issuer: impl ToString,
+        audience: impl ToString,
+    ) -> VerificationOptions {
+        VerificationOptions {
+            required_typ: Some(ACCESS_TOKEN_TYPE.to_string()),
+            require_expiration: true,
+            require_issued_at: true,
+            require_subject: true,
+            require_issuer: true,
+            require_audience: true,
+            require_jwt_id: true,
+            allowed_issuers: Some(HashSet::from([issuer.to_string()])),
+            allowed_audiences: Some(HashSet::from([audience.to_string()])),
+            ..Default::default()
+        }
+        .required_claims(&["client_id"])
+    }
+}
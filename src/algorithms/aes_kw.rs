@@ -0,0 +1,192 @@
+use aes::cipher::{generic_array::GenericArray, BlockDecrypt, BlockEncrypt, KeyInit};
+use aes::{Aes128, Aes192, Aes256};
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes128Gcm, Aes256Gcm, Nonce};
+use rand::RngCore;
+
+use crate::common::*;
+use crate::error::*;
+
+/// Default initial value of the AES key wrap algorithm (RFC 3394, section 2.2.3.1)
+const AES_KW_IV: [u8; 8] = [0xa6; 8];
+
+fn wrap_with<Cipher: BlockEncrypt + KeyInit>(kek: &[u8], key: &[u8]) -> Result<Vec<u8>, Error> {
+    ensure!(
+        key.len() >= 16 && key.len() % 8 == 0,
+        JWTError::InvalidEncryptionKey
+    );
+    let cipher = Cipher::new_from_slice(kek).map_err(|_| JWTError::InvalidEncryptionKey)?;
+    let n = key.len() / 8;
+    let mut a = AES_KW_IV;
+    let mut r = key.to_vec();
+    for j in 0..6 {
+        for i in 0..n {
+            let mut block = GenericArray::clone_from_slice(&[&a[..], &r[i * 8..][..8]].concat());
+            cipher.encrypt_block(&mut block);
+            let t = ((n * j + i + 1) as u64).to_be_bytes();
+            for (k, x) in a.iter_mut().enumerate() {
+                *x = block[k] ^ t[k];
+            }
+            r[i * 8..][..8].copy_from_slice(&block[8..]);
+        }
+    }
+    let mut wrapped = a.to_vec();
+    wrapped.extend_from_slice(&r);
+    Ok(wrapped)
+}
+
+fn unwrap_with<Cipher: BlockDecrypt + KeyInit>(
+    kek: &[u8],
+    wrapped: &[u8],
+) -> Result<Vec<u8>, Error> {
+    ensure!(
+        wrapped.len() >= 24 && wrapped.len() % 8 == 0,
+        JWTError::InvalidEncryptionKey
+    );
+    let cipher = Cipher::new_from_slice(kek).map_err(|_| JWTError::InvalidEncryptionKey)?;
+    let n = wrapped.len() / 8 - 1;
+    let mut a = [0u8; 8];
+    a.copy_from_slice(&wrapped[..8]);
+    let mut r = wrapped[8..].to_vec();
+    for j in (0..6).rev() {
+        for i in (0..n).rev() {
+            let t = ((n * j + i + 1) as u64).to_be_bytes();
+            for (x, y) in a.iter_mut().zip(t) {
+                *x ^= y;
+            }
+            let mut block = GenericArray::clone_from_slice(&[&a[..], &r[i * 8..][..8]].concat());
+            cipher.decrypt_block(&mut block);
+            a.copy_from_slice(&block[..8]);
+            r[i * 8..][..8].copy_from_slice(&block[8..]);
+        }
+    }
+    ensure!(
+        timingsafe_eq(&a, &AES_KW_IV),
+        JWTError::InvalidAuthenticationTag
+    );
+    Ok(r)
+}
+
+/// Wrap `key` with the key encryption key `kek` (RFC 3394).
+/// The size of `kek` selects AES-128, AES-192 or AES-256.
+pub(crate) fn aes_key_wrap(kek: &[u8], key: &[u8]) -> Result<Vec<u8>, Error> {
+    match kek.len() {
+        16 => wrap_with::<Aes128>(kek, key),
+        24 => wrap_with::<Aes192>(kek, key),
+        32 => wrap_with::<Aes256>(kek, key),
+        _ => bail!(JWTError::InvalidEncryptionKey),
+    }
+}
+
+/// Unwrap a key wrapped with `aes_key_wrap()`, verifying its integrity
+pub(crate) fn aes_key_unwrap(kek: &[u8], wrapped: &[u8]) -> Result<Vec<u8>, Error> {
+    match kek.len() {
+        16 => unwrap_with::<Aes128>(kek, wrapped),
+        24 => unwrap_with::<Aes192>(kek, wrapped),
+        32 => unwrap_with::<Aes256>(kek, wrapped),
+        _ => bail!(JWTError::InvalidEncryptionKey),
+    }
+}
+
+/// Length of the IV used to wrap keys with AES-GCM
+const AES_GCM_KW_IV_LENGTH: usize = 12;
+
+/// Length of the authentication tag of keys wrapped with AES-GCM
+const AES_GCM_KW_TAG_LENGTH: usize = 16;
+
+/// A key wrapped with AES-GCM, along with the parameters required to unwrap it
+pub(crate) struct GCMWrappedKey {
+    pub(crate) iv: Vec<u8>,
+    pub(crate) encrypted_key: Vec<u8>,
+    pub(crate) tag: Vec<u8>,
+}
+
+fn gcm_wrap_with<Cipher: Aead + KeyInit>(kek: &[u8], key: &[u8]) -> Result<GCMWrappedKey, Error> {
+    let cipher = Cipher::new_from_slice(kek).map_err(|_| JWTError::InvalidEncryptionKey)?;
+    let mut iv = vec![0u8; AES_GCM_KW_IV_LENGTH];
+    rand::thread_rng().fill_bytes(&mut iv);
+    let mut encrypted_key = cipher
+        .encrypt(Nonce::from_slice(&iv), Payload { msg: key, aad: &[] })
+        .map_err(|_| JWTError::InternalError("Encryption failed".into()))?;
+    let tag = encrypted_key.split_off(encrypted_key.len() - AES_GCM_KW_TAG_LENGTH);
+    Ok(GCMWrappedKey {
+        iv,
+        encrypted_key,
+        tag,
+    })
+}
+
+fn gcm_unwrap_with<Cipher: Aead + KeyInit>(
+    kek: &[u8],
+    wrapped: &GCMWrappedKey,
+) -> Result<Vec<u8>, Error> {
+    ensure!(
+        wrapped.iv.len() == AES_GCM_KW_IV_LENGTH && wrapped.tag.len() == AES_GCM_KW_TAG_LENGTH,
+        JWTError::InvalidAuthenticationTag
+    );
+    let cipher = Cipher::new_from_slice(kek).map_err(|_| JWTError::InvalidEncryptionKey)?;
+    let mut encrypted_key_and_tag = wrapped.encrypted_key.clone();
+    encrypted_key_and_tag.extend_from_slice(&wrapped.tag);
+    cipher
+        .decrypt(
+            Nonce::from_slice(&wrapped.iv),
+            Payload {
+                msg: &encrypted_key_and_tag,
+                aad: &[],
+            },
+        )
+        .map_err(|_| JWTError::InvalidAuthenticationTag.into())
+}
+
+/// Wrap `key` with AES-GCM using the key encryption key `kek` (RFC 7518, section 4.7).
+/// The size of `kek` selects AES-128 or AES-256.
+pub(crate) fn aes_gcm_key_wrap(kek: &[u8], key: &[u8]) -> Result<GCMWrappedKey, Error> {
+    match kek.len() {
+        16 => gcm_wrap_with::<Aes128Gcm>(kek, key),
+        32 => gcm_wrap_with::<Aes256Gcm>(kek, key),
+        _ => bail!(JWTError::InvalidEncryptionKey),
+    }
+}
+
+/// Unwrap a key wrapped with `aes_gcm_key_wrap()`, verifying its integrity
+pub(crate) fn aes_gcm_key_unwrap(kek: &[u8], wrapped: &GCMWrappedKey) -> Result<Vec<u8>, Error> {
+    match kek.len() {
+        16 => gcm_unwrap_with::<Aes128Gcm>(kek, wrapped),
+        32 => gcm_unwrap_with::<Aes256Gcm>(kek, wrapped),
+        _ => bail!(JWTError::InvalidEncryptionKey),
+    }
+}
+
+#[test]
+fn aes_key_wrap_rfc3394() {
+    use ct_codecs::{Decoder, Hex};
+
+    let kek = Hex::decode_to_vec("000102030405060708090A0B0C0D0E0F", None).unwrap();
+    let key = Hex::decode_to_vec("00112233445566778899AABBCCDDEEFF", None).unwrap();
+    let expected =
+        Hex::decode_to_vec("1FA68B0A8112B447AEF34BD8FB5A7B829D3E862371D2CFE5", None).unwrap();
+    let wrapped = aes_key_wrap(&kek, &key).unwrap();
+    assert_eq!(wrapped, expected);
+    assert_eq!(aes_key_unwrap(&kek, &wrapped).unwrap(), key);
+
+    let kek = Hex::decode_to_vec(
+        "000102030405060708090A0B0C0D0E0F101112131415161718191A1B1C1D1E1F",
+        None,
+    )
+    .unwrap();
+    let key = Hex::decode_to_vec(
+        "00112233445566778899AABBCCDDEEFF000102030405060708090A0B0C0D0E0F",
+        None,
+    )
+    .unwrap();
+    let expected = Hex::decode_to_vec(
+        "28C9F404C4B810F4CBCCB35CFB87F8263F5786E2D80ED326CBC7F0E71A99F43BFB988B9B7A02DD21",
+        None,
+    )
+    .unwrap();
+    let mut wrapped = aes_key_wrap(&kek, &key).unwrap();
+    assert_eq!(wrapped, expected);
+    assert_eq!(aes_key_unwrap(&kek, &wrapped).unwrap(), key);
+    wrapped[0] ^= 1;
+    assert!(aes_key_unwrap(&kek, &wrapped).is_err());
+}
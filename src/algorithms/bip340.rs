@@ -0,0 +1,527 @@
+use std::convert::TryFrom;
+
+use ct_codecs::{Base64UrlSafeNoPadding, Encoder};
+use k256::elliptic_curve::sec1::ToEncodedPoint as _;
+use k256::pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePrivateKey, EncodePublicKey};
+use k256::schnorr::{self, signature::DigestVerifier as _, signature::RandomizedDigestSigner as _};
+use rand::{CryptoRng, RngCore};
+use serde::{de::DeserializeOwned, Serialize};
+use zeroize::Zeroizing;
+
+use crate::claims::*;
+use crate::common::*;
+use crate::encrypted_pem::decrypt_pem;
+use crate::error::*;
+use crate::jwk::*;
+use crate::jwt_header::*;
+use crate::token::*;
+
+#[doc(hidden)]
+#[derive(Debug, Clone)]
+pub struct K256SchnorrPublicKey(schnorr::VerifyingKey);
+
+impl AsRef<schnorr::VerifyingKey> for K256SchnorrPublicKey {
+    fn as_ref(&self) -> &schnorr::VerifyingKey {
+        &self.0
+    }
+}
+
+impl K256SchnorrPublicKey {
+    /// Import a 32-byte x-only public key.
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        let k256_pk =
+            schnorr::VerifyingKey::from_bytes(raw).map_err(|_| JWTError::InvalidPublicKey)?;
+        Ok(K256SchnorrPublicKey(k256_pk))
+    }
+
+    /// Import a secp256k1 public key with an even y coordinate, encoded as
+    /// a SubjectPublicKeyInfo structure.
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        let k256_pk =
+            k256::PublicKey::from_public_key_der(der).map_err(|_| JWTError::InvalidPublicKey)?;
+        let k256_pk =
+            schnorr::VerifyingKey::try_from(k256_pk).map_err(|_| JWTError::InvalidPublicKey)?;
+        Ok(K256SchnorrPublicKey(k256_pk))
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        let k256_pk =
+            k256::PublicKey::from_public_key_pem(pem).map_err(|_| JWTError::InvalidPublicKey)?;
+        let k256_pk =
+            schnorr::VerifyingKey::try_from(k256_pk).map_err(|_| JWTError::InvalidPublicKey)?;
+        Ok(K256SchnorrPublicKey(k256_pk))
+    }
+
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        let k256_pk = k256::PublicKey::from_sec1_bytes(&jwk.elliptic_curve_point("secp256k1", 32)?)
+            .map_err(|_| JWTError::InvalidPublicKey)?;
+        let k256_pk =
+            schnorr::VerifyingKey::try_from(k256_pk).map_err(|_| JWTError::InvalidPublicKey)?;
+        Ok(K256SchnorrPublicKey(k256_pk))
+    }
+
+    /// Export the public key as a 32-byte x-only encoding.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.to_bytes().to_vec()
+    }
+
+    pub fn to_der(&self) -> Result<Vec<u8>, Error> {
+        let k256_pk = k256::PublicKey::from(&self.0);
+        Ok(k256_pk
+            .to_public_key_der()
+            .map_err(|_| JWTError::InvalidPublicKey)?
+            .as_ref()
+            .to_vec())
+    }
+
+    pub fn to_pem(&self) -> Result<String, Error> {
+        let k256_pk = k256::PublicKey::from(&self.0);
+        Ok(k256_pk
+            .to_public_key_pem(Default::default())
+            .map_err(|_| JWTError::InvalidPublicKey)?)
+    }
+
+    pub fn to_jwk(&self) -> Result<JWK, Error> {
+        let k256_pk = k256::PublicKey::from(&self.0);
+        JWK::new_elliptic_curve("secp256k1", k256_pk.to_encoded_point(false).as_bytes())
+    }
+}
+
+#[doc(hidden)]
+#[derive(Clone)]
+pub struct K256SchnorrKeyPair {
+    k256_sk: schnorr::SigningKey,
+    metadata: Option<KeyMetadata>,
+}
+
+impl AsRef<schnorr::SigningKey> for K256SchnorrKeyPair {
+    fn as_ref(&self) -> &schnorr::SigningKey {
+        &self.k256_sk
+    }
+}
+
+impl K256SchnorrKeyPair {
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        let k256_sk = schnorr::SigningKey::from_bytes(raw).map_err(|_| JWTError::InvalidKeyPair)?;
+        Ok(K256SchnorrKeyPair {
+            k256_sk,
+            metadata: None,
+        })
+    }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        let k256_sk = k256::SecretKey::from_pkcs8_der(der).map_err(|_| JWTError::InvalidKeyPair)?;
+        Ok(K256SchnorrKeyPair {
+            k256_sk: schnorr::SigningKey::from(k256_sk),
+            metadata: None,
+        })
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        let k256_sk = k256::SecretKey::from_pkcs8_pem(pem).map_err(|_| JWTError::InvalidKeyPair)?;
+        Ok(K256SchnorrKeyPair {
+            k256_sk: schnorr::SigningKey::from(k256_sk),
+            metadata: None,
+        })
+    }
+
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        let key_pair = Self::from_bytes(&jwk.private_key()?)?;
+        jwk.check_public_key(&key_pair.public_key().to_jwk()?)?;
+        Ok(key_pair)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.k256_sk.to_bytes().to_vec()
+    }
+
+    pub fn to_der(&self) -> Result<Vec<u8>, Error> {
+        let k256_sk = k256::SecretKey::from(self.k256_sk.as_nonzero_scalar());
+        Ok(k256_sk
+            .to_pkcs8_der()
+            .map_err(|_| JWTError::InvalidKeyPair)?
+            .as_bytes()
+            .to_vec())
+    }
+
+    pub fn to_pem(&self) -> Result<String, Error> {
+        let k256_sk = k256::SecretKey::from(self.k256_sk.as_nonzero_scalar());
+        Ok(k256_sk
+            .to_pkcs8_pem(Default::default())
+            .map_err(|_| JWTError::InvalidKeyPair)?
+            .to_string())
+    }
+
+    pub fn to_jwk(&self) -> Result<JWK, Error> {
+        self.public_key()
+            .to_jwk()?
+            .with_private_key(&Zeroizing::new(self.to_bytes()))
+    }
+
+    pub fn public_key(&self) -> K256SchnorrPublicKey {
+        let k256_pk = self.k256_sk.verifying_key();
+        K256SchnorrPublicKey(*k256_pk)
+    }
+
+    pub fn generate() -> Self {
+        Self::generate_with_rng(&mut rand::thread_rng())
+    }
+
+    pub fn generate_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        let k256_sk = schnorr::SigningKey::random(rng);
+        K256SchnorrKeyPair {
+            k256_sk,
+            metadata: None,
+        }
+    }
+}
+
+pub trait SchnorrP256kKeyPairLike {
+    fn jwt_alg_name() -> &'static str;
+    fn key_pair(&self) -> &K256SchnorrKeyPair;
+    fn key_id(&self) -> &Option<String>;
+    fn metadata(&self) -> &Option<KeyMetadata>;
+    fn attach_metadata(&mut self, metadata: KeyMetadata) -> Result<(), Error>;
+
+    /// Compute the signature of `authenticated`
+    fn signature(&self, authenticated: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut digest = hmac_sha256::Hash::new();
+        digest.update(authenticated);
+        let mut rng = rand::thread_rng();
+        let signature: schnorr::Signature = self
+            .key_pair()
+            .as_ref()
+            .sign_digest_with_rng(&mut rng, digest);
+        Ok(signature.to_bytes().to_vec())
+    }
+
+    fn sign<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata());
+        Token::build(&jwt_header, claims, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Sign claims, with additional header parameters
+    fn sign_with_header_options<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+        header_options: &HeaderOptions,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata())
+            .with_options(header_options);
+        Token::build(&jwt_header, claims, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Sign a detached payload, that is not included in the token (RFC 7797)
+    fn sign_detached(&self, payload: &str) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata());
+        Token::build_detached(jwt_header, payload, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Sign a detached binary payload, that is not included in the token
+    ///
+    /// The raw bytes of the payload are signed without being encoded
+    /// (RFC 7797), so that it can be any sequence of bytes.
+    fn sign_bytes_detached(&self, payload: &[u8]) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata());
+        Token::build_bytes_detached(jwt_header, payload, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Export the key pair as a JWK, including the private key
+    fn to_jwk(&self) -> Result<JWK, Error> {
+        Ok(self
+            .key_pair()
+            .to_jwk()?
+            .with_usage(self.key_id(), Self::jwt_alg_name(), "sig"))
+    }
+}
+
+pub trait SchnorrP256kPublicKeyLike {
+    fn jwt_alg_name() -> &'static str;
+    fn public_key(&self) -> &K256SchnorrPublicKey;
+    fn key_id(&self) -> &Option<String>;
+    fn set_key_id(&mut self, key_id: String);
+
+    /// Verify the signature of `authenticated`
+    fn verify_signature(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        let schnorr_signature =
+            schnorr::Signature::try_from(signature).map_err(|_| JWTError::InvalidSignature)?;
+        let mut digest = hmac_sha256::Hash::new();
+        digest.update(authenticated);
+        self.public_key()
+            .as_ref()
+            .verify_digest(digest, &schnorr_signature)
+            .map_err(|_| JWTError::InvalidSignature)?;
+        Ok(())
+    }
+
+    fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        check_key_policy(options.as_ref(), KeyParameters::EllipticCurve("secp256k1"))?;
+        check_key_thumbprint(options.as_ref(), || self.thumbprint().ok())?;
+        check_certificate_thumbprint(options.as_ref(), None)?;
+        Token::verify(
+            Self::jwt_alg_name(),
+            token,
+            options,
+            |authenticated, signature| self.verify_signature(authenticated, signature),
+        )
+    }
+
+    fn verify_token_untyped(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<serde_json::Value, Error> {
+        let claims = self.verify_token::<NoCustomClaims>(token, options)?;
+        Ok(serde_json::to_value(claims)?)
+    }
+
+    fn create_key_id(&mut self) -> &str {
+        self.set_key_id(
+            Base64UrlSafeNoPadding::encode_to_string(hmac_sha256::Hash::hash(
+                &self.public_key().to_bytes(),
+            ))
+            .unwrap(),
+        );
+        self.key_id().as_ref().map(|x| x.as_str()).unwrap()
+    }
+
+    /// Export the public key as a JWK, for publication in a key set
+    fn to_jwk(&self) -> Result<JWK, Error> {
+        Ok(self
+            .public_key()
+            .to_jwk()?
+            .with_usage(self.key_id(), Self::jwt_alg_name(), "sig"))
+    }
+
+    /// Compute the RFC 7638 thumbprint of the public key, using SHA-256
+    fn thumbprint(&self) -> Result<String, Error> {
+        self.to_jwk()?.thumbprint()
+    }
+
+    /// Compute the RFC 7638 thumbprint of the public key, using SHA-1, for
+    /// legacy applications
+    fn thumbprint_sha1(&self) -> Result<String, Error> {
+        self.to_jwk()?.thumbprint_sha1()
+    }
+}
+
+#[derive(Clone)]
+pub struct BIP340KeyPair {
+    key_pair: K256SchnorrKeyPair,
+    key_id: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BIP340PublicKey {
+    pk: K256SchnorrPublicKey,
+    key_id: Option<String>,
+}
+
+impl SchnorrP256kKeyPairLike for BIP340KeyPair {
+    fn jwt_alg_name() -> &'static str {
+        "BIP340"
+    }
+
+    fn key_pair(&self) -> &K256SchnorrKeyPair {
+        &self.key_pair
+    }
+
+    fn key_id(&self) -> &Option<String> {
+        &self.key_id
+    }
+
+    fn metadata(&self) -> &Option<KeyMetadata> {
+        &self.key_pair.metadata
+    }
+
+    fn attach_metadata(&mut self, metadata: KeyMetadata) -> Result<(), Error> {
+        self.key_pair.metadata = Some(metadata);
+        Ok(())
+    }
+}
+
+impl BIP340KeyPair {
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        Ok(BIP340KeyPair {
+            key_pair: K256SchnorrKeyPair::from_bytes(raw)?,
+            key_id: None,
+        })
+    }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        Ok(BIP340KeyPair {
+            key_pair: K256SchnorrKeyPair::from_der(der)?,
+            key_id: None,
+        })
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        Ok(BIP340KeyPair {
+            key_pair: K256SchnorrKeyPair::from_pem(pem)?,
+            key_id: None,
+        })
+    }
+
+    /// Import a password-protected key pair, encoded as an encrypted PKCS#8
+    /// or as a legacy encrypted PEM document
+    pub fn from_encrypted_pem(pem: &str, password: impl AsRef<[u8]>) -> Result<Self, Error> {
+        Self::from_pem(&decrypt_pem(pem, password.as_ref())?)
+    }
+
+    /// Import a key pair from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["sign"])?;
+        Ok(BIP340KeyPair {
+            key_pair: K256SchnorrKeyPair::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.key_pair.to_bytes()
+    }
+
+    pub fn to_der(&self) -> Result<Vec<u8>, Error> {
+        self.key_pair.to_der()
+    }
+
+    pub fn to_pem(&self) -> Result<String, Error> {
+        self.key_pair.to_pem()
+    }
+
+    pub fn public_key(&self) -> BIP340PublicKey {
+        BIP340PublicKey {
+            pk: self.key_pair.public_key(),
+            key_id: self.key_id.clone(),
+        }
+    }
+
+    pub fn generate() -> Self {
+        BIP340KeyPair {
+            key_pair: K256SchnorrKeyPair::generate(),
+            key_id: None,
+        }
+    }
+
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        BIP340KeyPair {
+            key_pair: K256SchnorrKeyPair::generate_with_rng(rng),
+            key_id: None,
+        }
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string());
+        self
+    }
+}
+
+impl SchnorrP256kPublicKeyLike for BIP340PublicKey {
+    fn jwt_alg_name() -> &'static str {
+        "BIP340"
+    }
+
+    fn public_key(&self) -> &K256SchnorrPublicKey {
+        &self.pk
+    }
+
+    fn key_id(&self) -> &Option<String> {
+        &self.key_id
+    }
+
+    fn set_key_id(&mut self, key_id: String) {
+        self.key_id = Some(key_id);
+    }
+}
+
+impl KeyLike for BIP340PublicKey {
+    fn key_algorithm(&self) -> &'static str {
+        Self::jwt_alg_name()
+    }
+
+    fn key_identifier(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    fn verify_authenticated(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        self.verify_signature(authenticated, signature)
+    }
+
+    fn key_parameters(&self) -> KeyParameters {
+        KeyParameters::EllipticCurve("secp256k1")
+    }
+
+    fn key_thumbprint(&self) -> Option<String> {
+        self.thumbprint().ok()
+    }
+}
+
+impl BIP340PublicKey {
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        Ok(BIP340PublicKey {
+            pk: K256SchnorrPublicKey::from_bytes(raw)?,
+            key_id: None,
+        })
+    }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        Ok(BIP340PublicKey {
+            pk: K256SchnorrPublicKey::from_der(der)?,
+            key_id: None,
+        })
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        Ok(BIP340PublicKey {
+            pk: K256SchnorrPublicKey::from_pem(pem)?,
+            key_id: None,
+        })
+    }
+
+    /// Import a public key from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["verify"])?;
+        Ok(BIP340PublicKey {
+            pk: K256SchnorrPublicKey::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.pk.to_bytes()
+    }
+
+    pub fn to_der(&self) -> Result<Vec<u8>, Error> {
+        self.pk.to_der()
+    }
+
+    pub fn to_pem(&self) -> Result<String, Error> {
+        self.pk.to_pem()
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string());
+        self
+    }
+}
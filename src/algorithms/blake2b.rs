@@ -0,0 +1,129 @@
+use blake2::digest::{consts::U32, Digest, Mac};
+use blake2::{Blake2b512, Blake2bMac};
+use rand::{CryptoRng, RngCore};
+
+use crate::common::*;
+use crate::error::*;
+use crate::jwk::*;
+use crate::token::*;
+
+use super::hmac::*;
+
+/// Maximum key length for BLAKE2b keyed hashing, in bytes.
+/// Longer keys are hashed first.
+pub const BLAKE2B_MAX_KEY_LENGTH: usize = 64;
+
+/// A key for the non-standard `BLAKE2B` algorithm (keyed BLAKE2b-256)
+#[derive(Debug, Clone)]
+pub struct Blake2bKey {
+    key: HMACKey,
+    key_id: Option<String>,
+}
+
+impl MACLike for Blake2bKey {
+    fn jwt_alg_name() -> &'static str {
+        "BLAKE2B"
+    }
+
+    fn key(&self) -> &HMACKey {
+        &self.key
+    }
+
+    fn key_id(&self) -> &Option<String> {
+        &self.key_id
+    }
+
+    fn set_key_id(&mut self, key_id: String) {
+        self.key_id = Some(key_id);
+    }
+
+    fn metadata(&self) -> &Option<KeyMetadata> {
+        &self.key.metadata
+    }
+
+    fn attach_metadata(&mut self, metadata: KeyMetadata) -> Result<(), Error> {
+        self.key.metadata = Some(metadata);
+        Ok(())
+    }
+
+    fn authentication_tag(&self, authenticated: &[u8]) -> Vec<u8> {
+        let raw_key = self.key().as_ref();
+        let mut mac = if raw_key.len() > BLAKE2B_MAX_KEY_LENGTH {
+            let hashed_key = Blake2b512::digest(raw_key);
+            <Blake2bMac<U32> as Mac>::new_from_slice(&hashed_key)
+        } else {
+            <Blake2bMac<U32> as Mac>::new_from_slice(raw_key)
+        }
+        .unwrap();
+        mac.update(authenticated);
+        mac.finalize().into_bytes().to_vec()
+    }
+}
+
+impl KeyLike for Blake2bKey {
+    fn key_algorithm(&self) -> &'static str {
+        Self::jwt_alg_name()
+    }
+
+    fn key_identifier(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    fn verify_authenticated(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        self.verify_authentication_tag(authenticated, signature)
+    }
+
+    fn key_parameters(&self) -> KeyParameters {
+        KeyParameters::MAC {
+            key_length: self.key().as_ref().len(),
+        }
+    }
+}
+
+impl Blake2bKey {
+    pub fn from_bytes(raw_key: &[u8]) -> Self {
+        Blake2bKey {
+            key: HMACKey::from_bytes(raw_key),
+            key_id: None,
+        }
+    }
+
+    /// Import a key from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["sign", "verify"])?;
+        let mut key = Self::from_bytes(&jwk.octet_sequence()?);
+        key.key_id = jwk.key_id.clone();
+        Ok(key)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.key.to_bytes()
+    }
+
+    pub fn generate() -> Self {
+        Blake2bKey {
+            key: HMACKey::generate(),
+            key_id: None,
+        }
+    }
+
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        Blake2bKey {
+            key: HMACKey::generate_with_rng(rng),
+            key_id: None,
+        }
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string());
+        self
+    }
+
+    /// Attach the identifier computed by `derived_key_id()` to the key
+    pub fn with_derived_key_id(mut self) -> Self {
+        self.key_id = Some(self.derived_key_id());
+        self
+    }
+}
@@ -0,0 +1,576 @@
+use std::convert::TryFrom;
+
+use ct_codecs::{Base64UrlSafeNoPadding, Encoder};
+use ecdsa::elliptic_curve::pkcs8::{
+    DecodePrivateKey, DecodePublicKey, EncodePrivateKey, EncodePublicKey,
+};
+use ecdsa::signature::{Signer as _, Verifier as _};
+use rand::{CryptoRng, RngCore};
+use serde::{de::DeserializeOwned, Serialize};
+use zeroize::Zeroizing;
+
+use crate::claims::*;
+use crate::common::*;
+use crate::encrypted_pem::decrypt_pem;
+use crate::error::*;
+use crate::jwk::*;
+use crate::jwt_header::*;
+use crate::token::*;
+use crate::x509;
+
+type BrainpoolP384r1SigningKey = ecdsa::SigningKey<bp384::BrainpoolP384r1>;
+type BrainpoolP384r1VerifyingKey = ecdsa::VerifyingKey<bp384::BrainpoolP384r1>;
+type BrainpoolP384r1Signature = ecdsa::Signature<bp384::BrainpoolP384r1>;
+
+#[doc(hidden)]
+#[derive(Debug, Clone)]
+pub struct BrainpoolP384r1PublicKey(BrainpoolP384r1VerifyingKey);
+
+impl AsRef<BrainpoolP384r1VerifyingKey> for BrainpoolP384r1PublicKey {
+    fn as_ref(&self) -> &BrainpoolP384r1VerifyingKey {
+        &self.0
+    }
+}
+
+impl BrainpoolP384r1PublicKey {
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        let bp384_pk = BrainpoolP384r1VerifyingKey::from_sec1_bytes(raw)
+            .map_err(|_| JWTError::InvalidPublicKey)?;
+        Ok(BrainpoolP384r1PublicKey(bp384_pk))
+    }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        let bp384_pk = BrainpoolP384r1VerifyingKey::from_public_key_der(der)
+            .map_err(|_| JWTError::InvalidPublicKey)?;
+        Ok(BrainpoolP384r1PublicKey(bp384_pk))
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        let bp384_pk = BrainpoolP384r1VerifyingKey::from_public_key_pem(pem)
+            .map_err(|_| JWTError::InvalidPublicKey)?;
+        Ok(BrainpoolP384r1PublicKey(bp384_pk))
+    }
+
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        Self::from_bytes(&jwk.elliptic_curve_point("brainpoolP384r1", 48)?)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.to_encoded_point(true).as_bytes().to_vec()
+    }
+
+    pub fn to_bytes_uncompressed(&self) -> Vec<u8> {
+        self.0.to_encoded_point(false).as_bytes().to_vec()
+    }
+
+    pub fn to_der(&self) -> Result<Vec<u8>, Error> {
+        Ok(self
+            .0
+            .to_public_key_der()
+            .map_err(|_| JWTError::InvalidPublicKey)?
+            .as_ref()
+            .to_vec())
+    }
+
+    pub fn to_pem(&self) -> Result<String, Error> {
+        Ok(self
+            .0
+            .to_public_key_pem(Default::default())
+            .map_err(|_| JWTError::InvalidPublicKey)?)
+    }
+
+    pub fn to_jwk(&self) -> Result<JWK, Error> {
+        JWK::new_elliptic_curve("brainpoolP384r1", &self.to_bytes_uncompressed())
+    }
+}
+
+#[doc(hidden)]
+#[derive(Clone)]
+pub struct BrainpoolP384r1KeyPair {
+    bp384_sk: BrainpoolP384r1SigningKey,
+    metadata: Option<KeyMetadata>,
+}
+
+impl AsRef<BrainpoolP384r1SigningKey> for BrainpoolP384r1KeyPair {
+    fn as_ref(&self) -> &BrainpoolP384r1SigningKey {
+        &self.bp384_sk
+    }
+}
+
+impl BrainpoolP384r1KeyPair {
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        let bp384_sk =
+            BrainpoolP384r1SigningKey::from_slice(raw).map_err(|_| JWTError::InvalidKeyPair)?;
+        Ok(BrainpoolP384r1KeyPair {
+            bp384_sk,
+            metadata: None,
+        })
+    }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        let bp384_sk =
+            BrainpoolP384r1SigningKey::from_pkcs8_der(der).map_err(|_| JWTError::InvalidKeyPair)?;
+        Ok(BrainpoolP384r1KeyPair {
+            bp384_sk,
+            metadata: None,
+        })
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        let bp384_sk =
+            BrainpoolP384r1SigningKey::from_pkcs8_pem(pem).map_err(|_| JWTError::InvalidKeyPair)?;
+        Ok(BrainpoolP384r1KeyPair {
+            bp384_sk,
+            metadata: None,
+        })
+    }
+
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        let key_pair = Self::from_bytes(&jwk.private_key()?)?;
+        jwk.check_public_key(&key_pair.public_key().to_jwk()?)?;
+        Ok(key_pair)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.bp384_sk.to_bytes().to_vec()
+    }
+
+    pub fn to_der(&self) -> Result<Vec<u8>, Error> {
+        Ok(self
+            .bp384_sk
+            .to_pkcs8_der()
+            .map_err(|_| JWTError::InvalidKeyPair)?
+            .as_bytes()
+            .to_vec())
+    }
+
+    pub fn to_pem(&self) -> Result<String, Error> {
+        Ok(self
+            .bp384_sk
+            .to_pkcs8_pem(Default::default())
+            .map_err(|_| JWTError::InvalidKeyPair)?
+            .to_string())
+    }
+
+    pub fn to_jwk(&self) -> Result<JWK, Error> {
+        self.public_key()
+            .to_jwk()?
+            .with_private_key(&Zeroizing::new(self.to_bytes()))
+    }
+
+    pub fn public_key(&self) -> BrainpoolP384r1PublicKey {
+        let bp384_pk = self.bp384_sk.verifying_key();
+        BrainpoolP384r1PublicKey(*bp384_pk)
+    }
+
+    pub fn generate() -> Self {
+        Self::generate_with_rng(&mut rand::thread_rng())
+    }
+
+    pub fn generate_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        let mut raw = Zeroizing::new([0u8; 48]);
+        let bp384_sk = loop {
+            rng.fill_bytes(raw.as_mut());
+            if let Ok(bp384_sk) = BrainpoolP384r1SigningKey::from_slice(raw.as_ref()) {
+                break bp384_sk;
+            }
+        };
+        BrainpoolP384r1KeyPair {
+            bp384_sk,
+            metadata: None,
+        }
+    }
+}
+
+pub trait ECDSABrainpoolP384r1KeyPairLike {
+    fn jwt_alg_name() -> &'static str;
+    fn key_pair(&self) -> &BrainpoolP384r1KeyPair;
+    fn key_id(&self) -> &Option<String>;
+    fn metadata(&self) -> &Option<KeyMetadata>;
+    fn attach_metadata(&mut self, metadata: KeyMetadata) -> Result<(), Error>;
+
+    /// Compute the signature of `authenticated`
+    fn signature(&self, authenticated: &[u8]) -> Result<Vec<u8>, Error> {
+        // Nonces are derived deterministically (RFC 6979)
+        let signature: BrainpoolP384r1Signature = self
+            .key_pair()
+            .as_ref()
+            .try_sign(authenticated)
+            .map_err(|_| JWTError::InvalidKeyPair)?;
+        Ok(signature.to_vec())
+    }
+
+    fn sign<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata());
+        Token::build(&jwt_header, claims, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Sign claims, with additional header parameters
+    fn sign_with_header_options<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+        header_options: &HeaderOptions,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata())
+            .with_options(header_options);
+        Token::build(&jwt_header, claims, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Sign a detached payload, that is not included in the token (RFC 7797)
+    fn sign_detached(&self, payload: &str) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata());
+        Token::build_detached(jwt_header, payload, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Sign a detached binary payload, that is not included in the token
+    ///
+    /// The raw bytes of the payload are signed without being encoded
+    /// (RFC 7797), so that it can be any sequence of bytes.
+    fn sign_bytes_detached(&self, payload: &[u8]) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata());
+        Token::build_bytes_detached(jwt_header, payload, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Export the key pair as a JWK, including the private key
+    fn to_jwk(&self) -> Result<JWK, Error> {
+        Ok(self
+            .key_pair()
+            .to_jwk()?
+            .with_usage(self.key_id(), Self::jwt_alg_name(), "sig"))
+    }
+}
+
+pub trait ECDSABrainpoolP384r1PublicKeyLike {
+    fn jwt_alg_name() -> &'static str;
+    fn public_key(&self) -> &BrainpoolP384r1PublicKey;
+    fn key_id(&self) -> &Option<String>;
+    fn set_key_id(&mut self, key_id: String);
+
+    /// The SHA-256 thumbprint of the certificate the key was imported from
+    fn certificate_sha256_thumbprint(&self) -> Option<&[u8; 32]> {
+        None
+    }
+
+    /// Verify the signature of `authenticated`
+    fn verify_signature(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        let ecdsa_signature = BrainpoolP384r1Signature::try_from(signature)
+            .map_err(|_| JWTError::InvalidSignature)?;
+        self.public_key()
+            .as_ref()
+            .verify(authenticated, &ecdsa_signature)
+            .map_err(|_| JWTError::InvalidSignature)?;
+        Ok(())
+    }
+
+    fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        check_key_policy(
+            options.as_ref(),
+            KeyParameters::EllipticCurve("brainpoolP384r1"),
+        )?;
+        check_key_thumbprint(options.as_ref(), || self.thumbprint().ok())?;
+        check_certificate_thumbprint(options.as_ref(), self.certificate_sha256_thumbprint())?;
+        Token::verify(
+            Self::jwt_alg_name(),
+            token,
+            options,
+            |authenticated, signature| self.verify_signature(authenticated, signature),
+        )
+    }
+
+    fn verify_token_untyped(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<serde_json::Value, Error> {
+        let claims = self.verify_token::<NoCustomClaims>(token, options)?;
+        Ok(serde_json::to_value(claims)?)
+    }
+
+    fn create_key_id(&mut self) -> &str {
+        self.set_key_id(
+            Base64UrlSafeNoPadding::encode_to_string(hmac_sha256::Hash::hash(
+                &self.public_key().to_bytes(),
+            ))
+            .unwrap(),
+        );
+        self.key_id().as_ref().map(|x| x.as_str()).unwrap()
+    }
+
+    /// Export the public key as a JWK, for publication in a key set
+    fn to_jwk(&self) -> Result<JWK, Error> {
+        Ok(self
+            .public_key()
+            .to_jwk()?
+            .with_usage(self.key_id(), Self::jwt_alg_name(), "sig"))
+    }
+
+    /// Compute the RFC 7638 thumbprint of the public key, using SHA-256
+    fn thumbprint(&self) -> Result<String, Error> {
+        self.to_jwk()?.thumbprint()
+    }
+
+    /// Compute the RFC 7638 thumbprint of the public key, using SHA-1, for
+    /// legacy applications
+    fn thumbprint_sha1(&self) -> Result<String, Error> {
+        self.to_jwk()?.thumbprint_sha1()
+    }
+}
+
+#[derive(Clone)]
+pub struct BP384R1KeyPair {
+    key_pair: BrainpoolP384r1KeyPair,
+    key_id: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BP384R1PublicKey {
+    pk: BrainpoolP384r1PublicKey,
+    key_id: Option<String>,
+    certificate_sha256_thumbprint: Option<[u8; 32]>,
+}
+
+impl ECDSABrainpoolP384r1KeyPairLike for BP384R1KeyPair {
+    fn jwt_alg_name() -> &'static str {
+        "BP384R1"
+    }
+
+    fn key_pair(&self) -> &BrainpoolP384r1KeyPair {
+        &self.key_pair
+    }
+
+    fn key_id(&self) -> &Option<String> {
+        &self.key_id
+    }
+
+    fn metadata(&self) -> &Option<KeyMetadata> {
+        &self.key_pair.metadata
+    }
+
+    fn attach_metadata(&mut self, metadata: KeyMetadata) -> Result<(), Error> {
+        self.key_pair.metadata = Some(metadata);
+        Ok(())
+    }
+}
+
+impl BP384R1KeyPair {
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        Ok(BP384R1KeyPair {
+            key_pair: BrainpoolP384r1KeyPair::from_bytes(raw)?,
+            key_id: None,
+        })
+    }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        Ok(BP384R1KeyPair {
+            key_pair: BrainpoolP384r1KeyPair::from_der(der)?,
+            key_id: None,
+        })
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        Ok(BP384R1KeyPair {
+            key_pair: BrainpoolP384r1KeyPair::from_pem(pem)?,
+            key_id: None,
+        })
+    }
+
+    /// Import a password-protected key pair, encoded as an encrypted PKCS#8
+    /// or as a legacy encrypted PEM document
+    pub fn from_encrypted_pem(pem: &str, password: impl AsRef<[u8]>) -> Result<Self, Error> {
+        Self::from_pem(&decrypt_pem(pem, password.as_ref())?)
+    }
+
+    /// Import a key pair from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["sign"])?;
+        Ok(BP384R1KeyPair {
+            key_pair: BrainpoolP384r1KeyPair::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.key_pair.to_bytes()
+    }
+
+    pub fn to_der(&self) -> Result<Vec<u8>, Error> {
+        self.key_pair.to_der()
+    }
+
+    pub fn to_pem(&self) -> Result<String, Error> {
+        self.key_pair.to_pem()
+    }
+
+    pub fn public_key(&self) -> BP384R1PublicKey {
+        BP384R1PublicKey {
+            pk: self.key_pair.public_key(),
+            key_id: self.key_id.clone(),
+            certificate_sha256_thumbprint: None,
+        }
+    }
+
+    pub fn generate() -> Self {
+        BP384R1KeyPair {
+            key_pair: BrainpoolP384r1KeyPair::generate(),
+            key_id: None,
+        }
+    }
+
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        BP384R1KeyPair {
+            key_pair: BrainpoolP384r1KeyPair::generate_with_rng(rng),
+            key_id: None,
+        }
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string());
+        self
+    }
+}
+
+impl ECDSABrainpoolP384r1PublicKeyLike for BP384R1PublicKey {
+    fn jwt_alg_name() -> &'static str {
+        "BP384R1"
+    }
+
+    fn public_key(&self) -> &BrainpoolP384r1PublicKey {
+        &self.pk
+    }
+
+    fn key_id(&self) -> &Option<String> {
+        &self.key_id
+    }
+
+    fn set_key_id(&mut self, key_id: String) {
+        self.key_id = Some(key_id);
+    }
+
+    fn certificate_sha256_thumbprint(&self) -> Option<&[u8; 32]> {
+        self.certificate_sha256_thumbprint.as_ref()
+    }
+}
+
+impl KeyLike for BP384R1PublicKey {
+    fn key_algorithm(&self) -> &'static str {
+        Self::jwt_alg_name()
+    }
+
+    fn key_identifier(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    fn verify_authenticated(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        self.verify_signature(authenticated, signature)
+    }
+
+    fn key_parameters(&self) -> KeyParameters {
+        KeyParameters::EllipticCurve("brainpoolP384r1")
+    }
+
+    fn key_thumbprint(&self) -> Option<String> {
+        self.thumbprint().ok()
+    }
+
+    fn key_certificate_thumbprint(&self) -> Option<&[u8; 32]> {
+        self.certificate_sha256_thumbprint.as_ref()
+    }
+}
+
+impl BP384R1PublicKey {
+    /// Import a public key from a compressed or uncompressed SEC1 point
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        Ok(BP384R1PublicKey {
+            pk: BrainpoolP384r1PublicKey::from_bytes(raw)?,
+            key_id: None,
+            certificate_sha256_thumbprint: None,
+        })
+    }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        Ok(BP384R1PublicKey {
+            pk: BrainpoolP384r1PublicKey::from_der(der)?,
+            key_id: None,
+            certificate_sha256_thumbprint: None,
+        })
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        Ok(BP384R1PublicKey {
+            pk: BrainpoolP384r1PublicKey::from_pem(pem)?,
+            key_id: None,
+            certificate_sha256_thumbprint: None,
+        })
+    }
+
+    /// Import the public key of a DER-encoded X.509 certificate
+    pub fn from_x509_der(der: &[u8]) -> Result<Self, Error> {
+        let mut public_key = Self::from_der(x509::certificate_public_key(der)?)?;
+        public_key.certificate_sha256_thumbprint = Some(hmac_sha256::Hash::hash(der));
+        Ok(public_key)
+    }
+
+    /// Import the public key of the first certificate of a PEM-encoded X.509
+    /// certificate chain
+    pub fn from_x509_pem(pem: &str) -> Result<Self, Error> {
+        Self::from_x509_der(&x509::certificate_from_pem(pem)?)
+    }
+
+    /// Import a public key from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["verify"])?;
+        Ok(BP384R1PublicKey {
+            pk: BrainpoolP384r1PublicKey::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+            certificate_sha256_thumbprint: None,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.pk.to_bytes()
+    }
+
+    /// Export the public key as a compressed SEC1 point
+    pub fn to_bytes_compressed(&self) -> Vec<u8> {
+        self.pk.to_bytes()
+    }
+
+    /// Export the public key as an uncompressed SEC1 point
+    pub fn to_bytes_uncompressed(&self) -> Vec<u8> {
+        self.pk.to_bytes_uncompressed()
+    }
+
+    pub fn to_der(&self) -> Result<Vec<u8>, Error> {
+        self.pk.to_der()
+    }
+
+    pub fn to_pem(&self) -> Result<String, Error> {
+        self.pk.to_pem()
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string());
+        self
+    }
+}
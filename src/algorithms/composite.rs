@@ -0,0 +1,599 @@
+use std::convert::{TryFrom, TryInto};
+
+use ct_codecs::{Base64UrlSafeNoPadding, Encoder};
+use fips204::ml_dsa_65;
+use fips204::traits::{Signer, Verifier};
+use p256::pkcs8::{self, spki::AlgorithmIdentifierRef, LineEnding, ObjectIdentifier};
+use rand::{CryptoRng, RngCore};
+use serde::{de::DeserializeOwned, Serialize};
+use zeroize::Zeroizing;
+
+use super::eddsa::{Edwards25519KeyPair, Edwards25519PublicKey};
+use super::mldsa::{
+    public_key_from_der, public_key_to_document, MLDSA65RawKeyPair, MLDSA65RawPublicKey,
+    MLDSA_SEED_LENGTH,
+};
+use crate::claims::*;
+use crate::common::*;
+use crate::encrypted_pem::decrypt_pem;
+use crate::error::*;
+use crate::jwk::*;
+use crate::jwt_header::*;
+use crate::token::*;
+
+// Domain separation values from draft-ietf-lamps-pq-composite-sigs
+const COMPOSITE_PREFIX: &[u8] = b"CompositeAlgorithmSignatures2025";
+const MLDSA65_ED25519_LABEL: &[u8] = b"COMPSIG-MLDSA65-Ed25519-SHA512";
+
+// id-MLDSA65-Ed25519-SHA512, from draft-ietf-lamps-pq-composite-sigs
+const MLDSA65_ED25519_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.5.5.7.6.48");
+
+const ED25519_PUBLIC_KEY_LENGTH: usize = 32;
+const ED25519_SEED_LENGTH: usize = 32;
+const ED25519_SIGNATURE_LENGTH: usize = 64;
+
+/// Builds the message actually signed by both components:
+/// `Prefix || Label || len(ctx) || ctx || SHA-512(M)`, with an empty context.
+fn mldsa65_ed25519_message(authenticated: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(COMPOSITE_PREFIX.len() + MLDSA65_ED25519_LABEL.len() + 65);
+    message.extend_from_slice(COMPOSITE_PREFIX);
+    message.extend_from_slice(MLDSA65_ED25519_LABEL);
+    message.push(0);
+    message.extend_from_slice(&hmac_sha512::Hash::hash(authenticated));
+    message
+}
+
+#[doc(hidden)]
+#[derive(Debug, Clone)]
+pub struct MLDSA65Ed25519RawPublicKey {
+    mldsa: MLDSA65RawPublicKey,
+    ed25519: Edwards25519PublicKey,
+}
+
+impl MLDSA65Ed25519RawPublicKey {
+    /// Import a public key encoded as `ML-DSA-65 public key || Ed25519 public key`.
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        ensure!(
+            raw.len() == ml_dsa_65::PK_LEN + ED25519_PUBLIC_KEY_LENGTH,
+            JWTError::InvalidPublicKey
+        );
+        let (mldsa_raw, ed25519_raw) = raw.split_at(ml_dsa_65::PK_LEN);
+        Ok(MLDSA65Ed25519RawPublicKey {
+            mldsa: MLDSA65RawPublicKey::from_bytes(mldsa_raw)?,
+            ed25519: Edwards25519PublicKey::from_bytes(ed25519_raw)?,
+        })
+    }
+
+    /// Import a SubjectPublicKeyInfo structure, whose key is the
+    /// concatenation of both public keys
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        Self::from_bytes(&public_key_from_der(der, MLDSA65_ED25519_OID)?)
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        let (label, der) =
+            pkcs8::Document::from_pem(pem.trim()).map_err(|_| JWTError::InvalidPublicKey)?;
+        ensure!(label == "PUBLIC KEY", JWTError::InvalidPublicKey);
+        Self::from_der(der.as_bytes())
+    }
+
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        Self::from_bytes(&jwk.algorithm_key_pair_public_key()?)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut raw = self.mldsa.to_bytes();
+        raw.extend_from_slice(&self.ed25519.to_bytes());
+        raw
+    }
+
+    pub fn to_der(&self) -> Vec<u8> {
+        public_key_to_document(&self.to_bytes(), MLDSA65_ED25519_OID)
+            .as_bytes()
+            .to_vec()
+    }
+
+    pub fn to_pem(&self) -> String {
+        public_key_to_document(&self.to_bytes(), MLDSA65_ED25519_OID)
+            .to_pem("PUBLIC KEY", LineEnding::LF)
+            .unwrap()
+    }
+
+    pub fn to_jwk(&self) -> Result<JWK, Error> {
+        JWK::new_algorithm_key_pair(&self.to_bytes())
+    }
+}
+
+#[doc(hidden)]
+#[derive(Clone)]
+pub struct MLDSA65Ed25519RawKeyPair {
+    mldsa: MLDSA65RawKeyPair,
+    ed25519: Edwards25519KeyPair,
+    metadata: Option<KeyMetadata>,
+}
+
+impl MLDSA65Ed25519RawKeyPair {
+    /// Import a key pair encoded as `ML-DSA-65 secret key || Ed25519 seed`.
+    ///
+    /// The ML-DSA-65 secret key can be either a 32-byte seed or an expanded secret key.
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        ensure!(raw.len() > ED25519_SEED_LENGTH, JWTError::InvalidKeyPair);
+        let (mldsa_raw, ed25519_raw) = raw.split_at(raw.len() - ED25519_SEED_LENGTH);
+        let ed25519_seed = ed25519_compact::Seed::from_slice(ed25519_raw)?;
+        let ed25519_kp = ed25519_compact::KeyPair::from_seed(ed25519_seed);
+        Ok(MLDSA65Ed25519RawKeyPair {
+            mldsa: MLDSA65RawKeyPair::from_bytes(mldsa_raw)?,
+            ed25519: Edwards25519KeyPair::from_bytes(ed25519_kp.as_ref())?,
+            metadata: None,
+        })
+    }
+
+    /// Import a PKCS#8 private key, encoded as `ML-DSA-65 seed || Ed25519
+    /// seed`
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        let private_key_info =
+            pkcs8::PrivateKeyInfo::try_from(der).map_err(|_| JWTError::InvalidKeyPair)?;
+        ensure!(
+            private_key_info.algorithm.oid == MLDSA65_ED25519_OID
+                && private_key_info.algorithm.parameters.is_none()
+                && private_key_info.private_key.len() == MLDSA_SEED_LENGTH + ED25519_SEED_LENGTH,
+            JWTError::InvalidKeyPair
+        );
+        Self::from_bytes(private_key_info.private_key)
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        let (label, der) =
+            pkcs8::SecretDocument::from_pem(pem.trim()).map_err(|_| JWTError::InvalidKeyPair)?;
+        ensure!(label == "PRIVATE KEY", JWTError::InvalidKeyPair);
+        Self::from_der(der.as_bytes())
+    }
+
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        let key_pair = Self::from_bytes(&jwk.private_key()?)?;
+        jwk.check_public_key(&key_pair.public_key().to_jwk()?)?;
+        Ok(key_pair)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut raw = self.mldsa.to_bytes();
+        raw.extend_from_slice(self.ed25519.as_ref().sk.seed().as_ref());
+        raw
+    }
+
+    /// Encode the key pair as PKCS#8. Key pairs imported from an expanded
+    /// ML-DSA-65 secret key cannot be encoded, as the seed is required.
+    fn to_secret_document(&self) -> Result<pkcs8::SecretDocument, Error> {
+        let private_key = Zeroizing::new(self.to_bytes());
+        ensure!(
+            private_key.len() == MLDSA_SEED_LENGTH + ED25519_SEED_LENGTH,
+            JWTError::UnsupportedKeyFormat
+        );
+        let algorithm = AlgorithmIdentifierRef {
+            oid: MLDSA65_ED25519_OID,
+            parameters: None,
+        };
+        Ok(
+            pkcs8::SecretDocument::try_from(pkcs8::PrivateKeyInfo::new(algorithm, &private_key))
+                .unwrap(),
+        )
+    }
+
+    pub fn to_der(&self) -> Result<Vec<u8>, Error> {
+        Ok(self.to_secret_document()?.as_bytes().to_vec())
+    }
+
+    pub fn to_pem(&self) -> Result<String, Error> {
+        Ok(self
+            .to_secret_document()?
+            .to_pem("PRIVATE KEY", LineEnding::LF)
+            .unwrap()
+            .to_string())
+    }
+
+    pub fn to_jwk(&self) -> Result<JWK, Error> {
+        self.public_key()
+            .to_jwk()?
+            .with_private_key(&Zeroizing::new(self.to_bytes()))
+    }
+
+    pub fn public_key(&self) -> MLDSA65Ed25519RawPublicKey {
+        MLDSA65Ed25519RawPublicKey {
+            mldsa: self.mldsa.public_key(),
+            ed25519: self.ed25519.public_key(),
+        }
+    }
+
+    pub fn generate() -> Self {
+        Self::generate_with_rng(&mut rand::thread_rng())
+    }
+
+    pub fn generate_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        MLDSA65Ed25519RawKeyPair {
+            mldsa: MLDSA65RawKeyPair::generate_with_rng(rng),
+            ed25519: Edwards25519KeyPair::generate_with_rng(rng),
+            metadata: None,
+        }
+    }
+}
+
+pub trait MLDSA65Ed25519KeyPairLike {
+    fn jwt_alg_name() -> &'static str;
+    fn key_pair(&self) -> &MLDSA65Ed25519RawKeyPair;
+    fn key_id(&self) -> &Option<String>;
+    fn metadata(&self) -> &Option<KeyMetadata>;
+    fn attach_metadata(&mut self, metadata: KeyMetadata) -> Result<(), Error>;
+
+    /// Compute the signature of `authenticated`
+    fn signature(&self, authenticated: &[u8]) -> Result<Vec<u8>, Error> {
+        let message = mldsa65_ed25519_message(authenticated);
+        let key_pair = self.key_pair();
+        let mldsa_signature = key_pair
+            .mldsa
+            .as_ref()
+            .try_sign_with_rng(&mut rand::thread_rng(), &message, MLDSA65_ED25519_LABEL)
+            .map_err(JWTError::from)?;
+        let noise = ed25519_compact::Noise::generate();
+        let ed25519_signature = key_pair.ed25519.as_ref().sk.sign(&message, Some(noise));
+        let mut signature = mldsa_signature.to_vec();
+        signature.extend_from_slice(ed25519_signature.as_ref());
+        Ok(signature)
+    }
+
+    fn sign<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata());
+        Token::build(&jwt_header, claims, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Sign claims, with additional header parameters
+    fn sign_with_header_options<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+        header_options: &HeaderOptions,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata())
+            .with_options(header_options);
+        Token::build(&jwt_header, claims, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Sign a detached payload, that is not included in the token (RFC 7797)
+    fn sign_detached(&self, payload: &str) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata());
+        Token::build_detached(jwt_header, payload, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Sign a detached binary payload, that is not included in the token
+    ///
+    /// The raw bytes of the payload are signed without being encoded
+    /// (RFC 7797), so that it can be any sequence of bytes.
+    fn sign_bytes_detached(&self, payload: &[u8]) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata());
+        Token::build_bytes_detached(jwt_header, payload, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Export the key pair as a JWK, including the private key
+    fn to_jwk(&self) -> Result<JWK, Error> {
+        Ok(self
+            .key_pair()
+            .to_jwk()?
+            .with_usage(self.key_id(), Self::jwt_alg_name(), "sig"))
+    }
+}
+
+pub trait MLDSA65Ed25519PublicKeyLike {
+    fn jwt_alg_name() -> &'static str;
+    fn public_key(&self) -> &MLDSA65Ed25519RawPublicKey;
+    fn key_id(&self) -> &Option<String>;
+    fn set_key_id(&mut self, key_id: String);
+
+    /// Verify the signature of `authenticated`
+    fn verify_signature(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        ensure!(
+            signature.len() == ml_dsa_65::SIG_LEN + ED25519_SIGNATURE_LENGTH,
+            JWTError::InvalidSignature
+        );
+        let (mldsa_signature, ed25519_signature) = signature.split_at(ml_dsa_65::SIG_LEN);
+        let mldsa_signature: &[u8; ml_dsa_65::SIG_LEN] = mldsa_signature
+            .try_into()
+            .map_err(|_| JWTError::InvalidSignature)?;
+        let ed25519_signature = ed25519_compact::Signature::from_slice(ed25519_signature)?;
+        let message = mldsa65_ed25519_message(authenticated);
+        let public_key = self.public_key();
+        // Both signatures must be valid
+        let mldsa_valid =
+            public_key
+                .mldsa
+                .as_ref()
+                .verify(&message, mldsa_signature, MLDSA65_ED25519_LABEL);
+        let ed25519_valid = public_key
+            .ed25519
+            .as_ref()
+            .verify(&message, &ed25519_signature)
+            .is_ok();
+        ensure!(mldsa_valid && ed25519_valid, JWTError::InvalidSignature);
+        Ok(())
+    }
+
+    fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        check_key_policy(
+            options.as_ref(),
+            KeyParameters::PostQuantum {
+                algorithm: Self::jwt_alg_name(),
+                curve: Some("Ed25519"),
+            },
+        )?;
+        check_key_thumbprint(options.as_ref(), || self.thumbprint().ok())?;
+        check_certificate_thumbprint(options.as_ref(), None)?;
+        Token::verify(
+            Self::jwt_alg_name(),
+            token,
+            options,
+            |authenticated, signature| self.verify_signature(authenticated, signature),
+        )
+    }
+
+    fn verify_token_untyped(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<serde_json::Value, Error> {
+        let claims = self.verify_token::<NoCustomClaims>(token, options)?;
+        Ok(serde_json::to_value(claims)?)
+    }
+
+    fn create_key_id(&mut self) -> &str {
+        self.set_key_id(
+            Base64UrlSafeNoPadding::encode_to_string(hmac_sha256::Hash::hash(
+                &self.public_key().to_bytes(),
+            ))
+            .unwrap(),
+        );
+        self.key_id().as_ref().map(|x| x.as_str()).unwrap()
+    }
+
+    /// Export the public key as a JWK, for publication in a key set
+    fn to_jwk(&self) -> Result<JWK, Error> {
+        Ok(self
+            .public_key()
+            .to_jwk()?
+            .with_usage(self.key_id(), Self::jwt_alg_name(), "sig"))
+    }
+
+    /// Compute the RFC 7638 thumbprint of the public key, using SHA-256
+    fn thumbprint(&self) -> Result<String, Error> {
+        self.to_jwk()?.thumbprint()
+    }
+
+    /// Compute the RFC 7638 thumbprint of the public key, using SHA-1, for
+    /// legacy applications
+    fn thumbprint_sha1(&self) -> Result<String, Error> {
+        self.to_jwk()?.thumbprint_sha1()
+    }
+}
+
+#[derive(Clone)]
+pub struct MLDSA65Ed25519KeyPair {
+    key_pair: MLDSA65Ed25519RawKeyPair,
+    key_id: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MLDSA65Ed25519PublicKey {
+    pk: MLDSA65Ed25519RawPublicKey,
+    key_id: Option<String>,
+}
+
+impl MLDSA65Ed25519KeyPairLike for MLDSA65Ed25519KeyPair {
+    fn jwt_alg_name() -> &'static str {
+        "ML-DSA-65-Ed25519"
+    }
+
+    fn key_pair(&self) -> &MLDSA65Ed25519RawKeyPair {
+        &self.key_pair
+    }
+
+    fn key_id(&self) -> &Option<String> {
+        &self.key_id
+    }
+
+    fn metadata(&self) -> &Option<KeyMetadata> {
+        &self.key_pair.metadata
+    }
+
+    fn attach_metadata(&mut self, metadata: KeyMetadata) -> Result<(), Error> {
+        self.key_pair.metadata = Some(metadata);
+        Ok(())
+    }
+}
+
+impl MLDSA65Ed25519KeyPair {
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        Ok(MLDSA65Ed25519KeyPair {
+            key_pair: MLDSA65Ed25519RawKeyPair::from_bytes(raw)?,
+            key_id: None,
+        })
+    }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        Ok(MLDSA65Ed25519KeyPair {
+            key_pair: MLDSA65Ed25519RawKeyPair::from_der(der)?,
+            key_id: None,
+        })
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        Ok(MLDSA65Ed25519KeyPair {
+            key_pair: MLDSA65Ed25519RawKeyPair::from_pem(pem)?,
+            key_id: None,
+        })
+    }
+
+    /// Import a password-protected key pair, encoded as an encrypted PKCS#8
+    /// or as a legacy encrypted PEM document
+    pub fn from_encrypted_pem(pem: &str, password: impl AsRef<[u8]>) -> Result<Self, Error> {
+        Self::from_pem(&decrypt_pem(pem, password.as_ref())?)
+    }
+
+    /// Import a key pair from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["sign"])?;
+        Ok(MLDSA65Ed25519KeyPair {
+            key_pair: MLDSA65Ed25519RawKeyPair::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.key_pair.to_bytes()
+    }
+
+    /// Export the key pair as PKCS#8. This requires the ML-DSA-65 seed, and
+    /// fails if the key pair was imported from an expanded secret key.
+    pub fn to_der(&self) -> Result<Vec<u8>, Error> {
+        self.key_pair.to_der()
+    }
+
+    /// Export the key pair as PEM-encoded PKCS#8, like `to_der()`
+    pub fn to_pem(&self) -> Result<String, Error> {
+        self.key_pair.to_pem()
+    }
+
+    pub fn public_key(&self) -> MLDSA65Ed25519PublicKey {
+        MLDSA65Ed25519PublicKey {
+            pk: self.key_pair.public_key(),
+            key_id: self.key_id.clone(),
+        }
+    }
+
+    pub fn generate() -> Self {
+        MLDSA65Ed25519KeyPair {
+            key_pair: MLDSA65Ed25519RawKeyPair::generate(),
+            key_id: None,
+        }
+    }
+
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        MLDSA65Ed25519KeyPair {
+            key_pair: MLDSA65Ed25519RawKeyPair::generate_with_rng(rng),
+            key_id: None,
+        }
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string());
+        self
+    }
+}
+
+impl MLDSA65Ed25519PublicKeyLike for MLDSA65Ed25519PublicKey {
+    fn jwt_alg_name() -> &'static str {
+        "ML-DSA-65-Ed25519"
+    }
+
+    fn public_key(&self) -> &MLDSA65Ed25519RawPublicKey {
+        &self.pk
+    }
+
+    fn key_id(&self) -> &Option<String> {
+        &self.key_id
+    }
+
+    fn set_key_id(&mut self, key_id: String) {
+        self.key_id = Some(key_id);
+    }
+}
+
+impl KeyLike for MLDSA65Ed25519PublicKey {
+    fn key_algorithm(&self) -> &'static str {
+        Self::jwt_alg_name()
+    }
+
+    fn key_identifier(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    fn verify_authenticated(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        self.verify_signature(authenticated, signature)
+    }
+
+    fn key_parameters(&self) -> KeyParameters {
+        KeyParameters::PostQuantum {
+            algorithm: Self::jwt_alg_name(),
+            curve: Some("Ed25519"),
+        }
+    }
+
+    fn key_thumbprint(&self) -> Option<String> {
+        self.thumbprint().ok()
+    }
+}
+
+impl MLDSA65Ed25519PublicKey {
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        Ok(MLDSA65Ed25519PublicKey {
+            pk: MLDSA65Ed25519RawPublicKey::from_bytes(raw)?,
+            key_id: None,
+        })
+    }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        Ok(MLDSA65Ed25519PublicKey {
+            pk: MLDSA65Ed25519RawPublicKey::from_der(der)?,
+            key_id: None,
+        })
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        Ok(MLDSA65Ed25519PublicKey {
+            pk: MLDSA65Ed25519RawPublicKey::from_pem(pem)?,
+            key_id: None,
+        })
+    }
+
+    /// Import a public key from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["verify"])?;
+        Ok(MLDSA65Ed25519PublicKey {
+            pk: MLDSA65Ed25519RawPublicKey::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.pk.to_bytes()
+    }
+
+    pub fn to_der(&self) -> Vec<u8> {
+        self.pk.to_der()
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.pk.to_pem()
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string());
+        self
+    }
+}
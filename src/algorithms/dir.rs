@@ -0,0 +1,147 @@
+use rand::{CryptoRng, RngCore};
+use zeroize::Zeroize;
+
+use crate::common::*;
+use crate::encrypted_token::*;
+use crate::error::*;
+use crate::jwe_header::*;
+use crate::jwk::*;
+
+/// A shared key used directly as the content encryption key of JWE tokens
+/// (`"alg":"dir"`)
+#[derive(Debug, Clone)]
+pub struct DirectKey {
+    raw_key: Vec<u8>,
+    content_encryption: ContentEncryption,
+    key_id: Option<String>,
+}
+
+impl Drop for DirectKey {
+    fn drop(&mut self) {
+        self.raw_key.zeroize();
+    }
+}
+
+impl DirectKey {
+    /// The JWE key management algorithm name ("alg")
+    pub fn jwe_alg_name() -> &'static str {
+        "dir"
+    }
+
+    /// Create a key for the given content encryption algorithm.
+    /// The key length must match the one required by the algorithm.
+    pub fn from_bytes(
+        content_encryption: ContentEncryption,
+        raw_key: &[u8],
+    ) -> Result<Self, Error> {
+        ensure!(
+            raw_key.len() == content_encryption.key_length(),
+            JWTError::InvalidEncryptionKey
+        );
+        Ok(DirectKey {
+            raw_key: raw_key.to_vec(),
+            content_encryption,
+            key_id: None,
+        })
+    }
+
+    /// Import a key from a JWK, for the given content encryption algorithm
+    pub fn from_jwk(content_encryption: ContentEncryption, jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwe_alg_name(), "enc", &["encrypt", "decrypt"])?;
+        let mut key = Self::from_bytes(content_encryption, &jwk.octet_sequence()?)?;
+        key.key_id = jwk.key_id.clone();
+        Ok(key)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.raw_key.clone()
+    }
+
+    /// Export the key as a JWK
+    pub fn to_jwk(&self) -> Result<JWK, Error> {
+        Ok(JWK::new_octet_sequence(&self.raw_key)?.with_usage(
+            &self.key_id,
+            Self::jwe_alg_name(),
+            "enc",
+        ))
+    }
+
+    pub fn generate(content_encryption: ContentEncryption) -> Self {
+        Self::generate_with_rng(&mut rand::thread_rng(), content_encryption)
+    }
+
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(
+        rng: &mut (impl CryptoRng + RngCore),
+        content_encryption: ContentEncryption,
+    ) -> Self {
+        let mut raw_key = vec![0u8; content_encryption.key_length()];
+        rng.fill_bytes(&mut raw_key);
+        DirectKey {
+            raw_key,
+            content_encryption,
+            key_id: None,
+        }
+    }
+
+    /// The content encryption algorithm this key is used with ("enc")
+    pub fn content_encryption(&self) -> ContentEncryption {
+        self.content_encryption
+    }
+
+    pub fn key_id(&self) -> &Option<String> {
+        &self.key_id
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string());
+        self
+    }
+}
+
+impl EncryptionKeyLike for DirectKey {
+    fn encrypt_payload(
+        &self,
+        payload: &[u8],
+        content_type: Option<&str>,
+        options: &EncryptionOptions,
+    ) -> Result<String, Error> {
+        let mut jwe_header = JWEHeader::new(
+            Self::jwe_alg_name().to_string(),
+            self.content_encryption.jwe_enc_name().to_string(),
+            self.key_id.clone(),
+        );
+        jwe_header.content_type = content_type.map(|x| x.to_string());
+        EncryptedToken::build_with_payload(jwe_header, payload, options, |_jwe_header| {
+            Ok((self.raw_key.clone(), vec![]))
+        })
+    }
+
+    fn encrypt_key(&self, _jwe_header: &mut JWEHeader, _cek: &[u8]) -> Result<Vec<u8>, Error> {
+        bail!(JWTError::UnsupportedKeyManagement)
+    }
+}
+
+impl DecryptionKeyLike for DirectKey {
+    fn decrypt_payload(
+        &self,
+        token: &str,
+        options: &VerificationOptions,
+    ) -> Result<DecryptedPayload, Error> {
+        EncryptedToken::decrypt_payload(
+            Self::jwe_alg_name(),
+            self.key_id.as_deref(),
+            token,
+            options,
+            |jwe_header, encrypted_key| {
+                ensure!(
+                    jwe_header.content_encryption == self.content_encryption.jwe_enc_name(),
+                    JWTError::AlgorithmMismatch
+                );
+                ensure!(encrypted_key.is_empty(), JWTError::InvalidEncryptionKey);
+                Ok(self.raw_key.clone())
+            },
+        )
+    }
+}
@@ -0,0 +1,1186 @@
+use std::convert::{TryFrom, TryInto};
+
+use ct_codecs::{Base64UrlSafeNoPadding, Decoder, Encoder};
+use ed25519_compact::x25519;
+use ed448_goldilocks_plus::pkcs8::{
+    self, der::asn1::BitStringRef, spki::AlgorithmIdentifierRef, LineEnding, ObjectIdentifier,
+};
+use ed448_goldilocks_plus::{MontgomeryPoint, Scalar};
+use p256::elliptic_curve::sec1::{FromEncodedPoint, ToEncodedPoint};
+use p256::pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePrivateKey, EncodePublicKey};
+use rand::{CryptoRng, RngCore};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::common::*;
+use crate::encrypted_pem::decrypt_pem;
+use crate::encrypted_token::*;
+use crate::error::*;
+use crate::jwe_header::*;
+use crate::jwk::*;
+
+use super::aes_kw::*;
+
+/// How the key agreed on with ECDH-ES is used
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum KeyAgreement {
+    /// The agreed key is the content encryption key ("ECDH-ES")
+    Direct,
+    /// The agreed key wraps a random content encryption key using AES-128
+    /// key wrap ("ECDH-ES+A128KW")
+    A128KW,
+}
+
+impl KeyAgreement {
+    /// The JWE key management algorithm name ("alg")
+    pub fn jwe_alg_name(&self) -> &'static str {
+        match self {
+            KeyAgreement::Direct => "ECDH-ES",
+            KeyAgreement::A128KW => "ECDH-ES+A128KW",
+        }
+    }
+}
+
+/// Concat KDF with SHA-256 (NIST SP 800-56A, RFC 7518 section 4.6.2)
+fn concat_kdf(z: &[u8], algorithm_id: &str, apu: &[u8], apv: &[u8], key_length: usize) -> Vec<u8> {
+    let mut other_info = vec![];
+    for field in [algorithm_id.as_bytes(), apu, apv] {
+        other_info.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        other_info.extend_from_slice(field);
+    }
+    other_info.extend_from_slice(&((key_length * 8) as u32).to_be_bytes());
+    let mut key = vec![];
+    let mut counter = 1u32;
+    while key.len() < key_length {
+        let mut h = hmac_sha256::Hash::new();
+        h.update(counter.to_be_bytes());
+        h.update(z);
+        h.update(&other_info);
+        key.extend_from_slice(&h.finalize());
+        counter += 1;
+    }
+    key.truncate(key_length);
+    key
+}
+
+fn derive_key(
+    key_agreement: KeyAgreement,
+    content_encryption: ContentEncryption,
+    z: &[u8],
+    apu: &[u8],
+    apv: &[u8],
+) -> Vec<u8> {
+    match key_agreement {
+        KeyAgreement::Direct => concat_kdf(
+            z,
+            content_encryption.jwe_enc_name(),
+            apu,
+            apv,
+            content_encryption.key_length(),
+        ),
+        KeyAgreement::A128KW => concat_kdf(z, key_agreement.jwe_alg_name(), apu, apv, 16),
+    }
+}
+
+/// Parameters of a recipient public key, shared by all curves
+#[derive(Debug, Clone)]
+struct RecipientParameters {
+    key_id: Option<String>,
+    key_agreement: KeyAgreement,
+    content_encryption: ContentEncryption,
+    apu: Option<Vec<u8>>,
+    apv: Option<Vec<u8>>,
+}
+
+impl RecipientParameters {
+    fn new(key_id: Option<String>, key_agreement: KeyAgreement) -> Self {
+        RecipientParameters {
+            key_id,
+            key_agreement,
+            content_encryption: ContentEncryption::A256GCM,
+            apu: None,
+            apv: None,
+        }
+    }
+}
+
+/// Agree on a key with a recipient, given a function returning the shared
+/// secret along with the ephemeral public key of the sender, and add the key
+/// management parameters to the header
+fn ecdh_es_agree<AgreementFn>(
+    recipient: &RecipientParameters,
+    jwe_header: &mut JWEHeader,
+    agreement_fn: AgreementFn,
+) -> Result<Vec<u8>, Error>
+where
+    AgreementFn: FnOnce() -> Result<(Vec<u8>, EphemeralPublicKey), Error>,
+{
+    let (mut z, epk) = agreement_fn()?;
+    let apu = recipient.apu.as_deref().unwrap_or_default();
+    let apv = recipient.apv.as_deref().unwrap_or_default();
+    jwe_header.algorithm = recipient.key_agreement.jwe_alg_name().to_string();
+    jwe_header.key_id = recipient.key_id.clone();
+    jwe_header.ephemeral_public_key = Some(epk);
+    if !apu.is_empty() {
+        jwe_header.agreement_party_u_info = Some(Base64UrlSafeNoPadding::encode_to_string(apu)?);
+    }
+    if !apv.is_empty() {
+        jwe_header.agreement_party_v_info = Some(Base64UrlSafeNoPadding::encode_to_string(apv)?);
+    }
+    let key = derive_key(
+        recipient.key_agreement,
+        recipient.content_encryption,
+        &z,
+        apu,
+        apv,
+    );
+    z.zeroize();
+    Ok(key)
+}
+
+/// Encrypt a payload to a recipient
+fn ecdh_es_encrypt<Key, AgreementFn>(
+    key: &Key,
+    recipient: &RecipientParameters,
+    payload: &[u8],
+    content_type: Option<&str>,
+    options: &EncryptionOptions,
+    agreement_fn: AgreementFn,
+) -> Result<String, Error>
+where
+    Key: EncryptionKeyLike,
+    AgreementFn: FnOnce() -> Result<(Vec<u8>, EphemeralPublicKey), Error>,
+{
+    match recipient.key_agreement {
+        KeyAgreement::Direct => {
+            let jwe_header = JWEHeader {
+                content_encryption: recipient.content_encryption.jwe_enc_name().to_string(),
+                content_type: content_type.map(|x| x.to_string()),
+                ..Default::default()
+            };
+            EncryptedToken::build_with_payload(jwe_header, payload, options, |jwe_header| {
+                let cek = ecdh_es_agree(recipient, jwe_header, agreement_fn)?;
+                Ok((cek, vec![]))
+            })
+        }
+        KeyAgreement::A128KW => EncryptedToken::build_with_key(
+            key,
+            recipient.content_encryption,
+            payload,
+            content_type,
+            options,
+        ),
+    }
+}
+
+/// Encrypt a content encryption key to a recipient
+fn ecdh_es_encrypt_key<AgreementFn>(
+    recipient: &RecipientParameters,
+    jwe_header: &mut JWEHeader,
+    cek: &[u8],
+    agreement_fn: AgreementFn,
+) -> Result<Vec<u8>, Error>
+where
+    AgreementFn: FnOnce() -> Result<(Vec<u8>, EphemeralPublicKey), Error>,
+{
+    ensure!(
+        recipient.key_agreement == KeyAgreement::A128KW,
+        JWTError::UnsupportedKeyManagement
+    );
+    let mut kek = ecdh_es_agree(recipient, jwe_header, agreement_fn)?;
+    let encrypted_key = aes_key_wrap(&kek, cek);
+    kek.zeroize();
+    encrypted_key
+}
+
+/// Decrypt a token, given a function computing the shared secret from the
+/// ephemeral public key of the sender
+fn ecdh_es_decrypt<AgreementFn>(
+    key_agreement: KeyAgreement,
+    key_id: Option<&str>,
+    token: &str,
+    options: &VerificationOptions,
+    agreement_fn: AgreementFn,
+) -> Result<DecryptedPayload, Error>
+where
+    AgreementFn: FnOnce(&EphemeralPublicKey) -> Result<Vec<u8>, Error>,
+{
+    EncryptedToken::decrypt_payload(
+        key_agreement.jwe_alg_name(),
+        key_id,
+        token,
+        options,
+        |jwe_header, encrypted_key| {
+            let content_encryption =
+                ContentEncryption::from_jwe_enc_name(&jwe_header.content_encryption)?;
+            let epk = jwe_header
+                .ephemeral_public_key
+                .as_ref()
+                .ok_or(JWTError::InvalidPublicKey)?;
+            let apu = match &jwe_header.agreement_party_u_info {
+                Some(apu) => Base64UrlSafeNoPadding::decode_to_vec(apu, None)?,
+                None => vec![],
+            };
+            let apv = match &jwe_header.agreement_party_v_info {
+                Some(apv) => Base64UrlSafeNoPadding::decode_to_vec(apv, None)?,
+                None => vec![],
+            };
+            let mut z = agreement_fn(epk)?;
+            let mut key = derive_key(key_agreement, content_encryption, &z, &apu, &apv);
+            z.zeroize();
+            match key_agreement {
+                KeyAgreement::Direct => {
+                    ensure!(encrypted_key.is_empty(), JWTError::InvalidEncryptionKey);
+                    Ok(key)
+                }
+                KeyAgreement::A128KW => {
+                    let cek = aes_key_unwrap(&key, encrypted_key);
+                    key.zeroize();
+                    cek
+                }
+            }
+        },
+    )
+}
+
+/// Check that a JWK can be used for key agreement, and return the key
+/// agreement mode it is intended for
+fn jwk_key_agreement(jwk: &JWK) -> Result<KeyAgreement, Error> {
+    let key_agreement = match jwk.algorithm.as_deref() {
+        None | Some("ECDH-ES") => KeyAgreement::Direct,
+        Some("ECDH-ES+A128KW") => KeyAgreement::A128KW,
+        Some(_) => bail!(JWTError::AlgorithmMismatch),
+    };
+    jwk.check_usage(
+        key_agreement.jwe_alg_name(),
+        "enc",
+        &["deriveKey", "deriveBits"],
+    )?;
+    Ok(key_agreement)
+}
+
+fn decode_coordinate(coordinate: &str, len: usize) -> Result<Vec<u8>, Error> {
+    let coordinate = Base64UrlSafeNoPadding::decode_to_vec(coordinate, None)?;
+    ensure!(coordinate.len() == len, JWTError::InvalidPublicKey);
+    Ok(coordinate)
+}
+
+/// A P-256 key pair for receiving tokens encrypted with ECDH-ES
+#[derive(Debug, Clone)]
+pub struct ECDHP256KeyPair {
+    p256_sk: p256::SecretKey,
+    key_id: Option<String>,
+    key_agreement: KeyAgreement,
+}
+
+/// A P-256 public key for encrypting tokens with ECDH-ES
+#[derive(Debug, Clone)]
+pub struct ECDHP256PublicKey {
+    p256_pk: p256::PublicKey,
+    parameters: RecipientParameters,
+}
+
+impl ECDHP256KeyPair {
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        let p256_sk = p256::SecretKey::from_slice(raw).map_err(|_| JWTError::InvalidKeyPair)?;
+        Ok(Self::from_secret_key(p256_sk))
+    }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        let p256_sk = p256::SecretKey::from_pkcs8_der(der).map_err(|_| JWTError::InvalidKeyPair)?;
+        Ok(Self::from_secret_key(p256_sk))
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        let p256_sk = p256::SecretKey::from_pkcs8_pem(pem).map_err(|_| JWTError::InvalidKeyPair)?;
+        Ok(Self::from_secret_key(p256_sk))
+    }
+
+    /// Import a password-protected key pair, encoded as an encrypted PKCS#8
+    /// or as a legacy encrypted PEM document
+    pub fn from_encrypted_pem(pem: &str, password: impl AsRef<[u8]>) -> Result<Self, Error> {
+        Self::from_pem(&decrypt_pem(pem, password.as_ref())?)
+    }
+
+    /// Import a key pair from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        let key_agreement = jwk_key_agreement(jwk)?;
+        let mut key_pair = Self::from_bytes(&jwk.private_key()?)?;
+        key_pair.key_agreement = key_agreement;
+        jwk.check_public_key(&key_pair.public_key().to_jwk()?)?;
+        key_pair.key_id = jwk.key_id.clone();
+        Ok(key_pair)
+    }
+
+    fn from_secret_key(p256_sk: p256::SecretKey) -> Self {
+        ECDHP256KeyPair {
+            p256_sk,
+            key_id: None,
+            key_agreement: KeyAgreement::Direct,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.p256_sk.to_bytes().to_vec()
+    }
+
+    pub fn to_der(&self) -> Result<Vec<u8>, Error> {
+        Ok(self
+            .p256_sk
+            .to_pkcs8_der()
+            .map_err(|_| JWTError::InvalidKeyPair)?
+            .as_bytes()
+            .to_vec())
+    }
+
+    pub fn to_pem(&self) -> Result<String, Error> {
+        Ok(self
+            .p256_sk
+            .to_pkcs8_pem(Default::default())
+            .map_err(|_| JWTError::InvalidKeyPair)?
+            .to_string())
+    }
+
+    /// Export the key pair as a JWK, including the private key
+    pub fn to_jwk(&self) -> Result<JWK, Error> {
+        self.public_key()
+            .to_jwk()?
+            .with_private_key(&Zeroizing::new(self.to_bytes()))
+    }
+
+    pub fn public_key(&self) -> ECDHP256PublicKey {
+        ECDHP256PublicKey {
+            p256_pk: self.p256_sk.public_key(),
+            parameters: RecipientParameters::new(self.key_id.clone(), self.key_agreement),
+        }
+    }
+
+    pub fn generate() -> Self {
+        Self::generate_with_rng(&mut rand::thread_rng())
+    }
+
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        Self::from_secret_key(p256::SecretKey::random(rng))
+    }
+
+    pub fn key_id(&self) -> &Option<String> {
+        &self.key_id
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string());
+        self
+    }
+
+    /// Set the key agreement mode of accepted tokens
+    pub fn with_key_agreement(mut self, key_agreement: KeyAgreement) -> Self {
+        self.key_agreement = key_agreement;
+        self
+    }
+}
+
+impl DecryptionKeyLike for ECDHP256KeyPair {
+    fn decrypt_payload(
+        &self,
+        token: &str,
+        options: &VerificationOptions,
+    ) -> Result<DecryptedPayload, Error> {
+        check_key_policy(Some(options), KeyParameters::EllipticCurve("P-256"))?;
+        ecdh_es_decrypt(
+            self.key_agreement,
+            self.key_id.as_deref(),
+            token,
+            options,
+            |epk| {
+                ensure!(
+                    epk.key_type == "EC" && epk.curve == "P-256",
+                    JWTError::InvalidPublicKey
+                );
+                let x = decode_coordinate(&epk.x, 32)?;
+                let y = decode_coordinate(epk.y.as_ref().ok_or(JWTError::InvalidPublicKey)?, 32)?;
+                let encoded_point = p256::EncodedPoint::from_affine_coordinates(
+                    x.as_slice().into(),
+                    y.as_slice().into(),
+                    false,
+                );
+                let epk = Option::<p256::PublicKey>::from(p256::PublicKey::from_encoded_point(
+                    &encoded_point,
+                ))
+                .ok_or(JWTError::InvalidPublicKey)?;
+                let z =
+                    p256::ecdh::diffie_hellman(self.p256_sk.to_nonzero_scalar(), epk.as_affine());
+                Ok(z.raw_secret_bytes().to_vec())
+            },
+        )
+    }
+}
+
+impl ECDHP256PublicKey {
+    /// Import a public key from a compressed or uncompressed SEC1 point
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        let p256_pk =
+            p256::PublicKey::from_sec1_bytes(raw).map_err(|_| JWTError::InvalidPublicKey)?;
+        Ok(Self::from_public_key(p256_pk))
+    }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        let p256_pk =
+            p256::PublicKey::from_public_key_der(der).map_err(|_| JWTError::InvalidPublicKey)?;
+        Ok(Self::from_public_key(p256_pk))
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        let p256_pk =
+            p256::PublicKey::from_public_key_pem(pem).map_err(|_| JWTError::InvalidPublicKey)?;
+        Ok(Self::from_public_key(p256_pk))
+    }
+
+    /// Import a public key from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        let key_agreement = jwk_key_agreement(jwk)?;
+        let mut pk = Self::from_bytes(&jwk.elliptic_curve_point("P-256", 32)?)?;
+        pk.parameters = RecipientParameters::new(jwk.key_id.clone(), key_agreement);
+        Ok(pk)
+    }
+
+    fn from_public_key(p256_pk: p256::PublicKey) -> Self {
+        ECDHP256PublicKey {
+            p256_pk,
+            parameters: RecipientParameters::new(None, KeyAgreement::Direct),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.p256_pk.to_encoded_point(true).as_bytes().to_vec()
+    }
+
+    /// Export the public key as a compressed SEC1 point
+    pub fn to_bytes_compressed(&self) -> Vec<u8> {
+        self.to_bytes()
+    }
+
+    /// Export the public key as an uncompressed SEC1 point
+    pub fn to_bytes_uncompressed(&self) -> Vec<u8> {
+        self.p256_pk.to_encoded_point(false).as_bytes().to_vec()
+    }
+
+    pub fn to_der(&self) -> Result<Vec<u8>, Error> {
+        Ok(self
+            .p256_pk
+            .to_public_key_der()
+            .map_err(|_| JWTError::InvalidPublicKey)?
+            .as_ref()
+            .to_vec())
+    }
+
+    pub fn to_pem(&self) -> Result<String, Error> {
+        Ok(self
+            .p256_pk
+            .to_public_key_pem(Default::default())
+            .map_err(|_| JWTError::InvalidPublicKey)?)
+    }
+
+    /// Export the public key as a JWK, for publication in a key set
+    pub fn to_jwk(&self) -> Result<JWK, Error> {
+        Ok(
+            JWK::new_elliptic_curve("P-256", &self.to_bytes_uncompressed())?.with_usage(
+                &self.parameters.key_id,
+                self.parameters.key_agreement.jwe_alg_name(),
+                "enc",
+            ),
+        )
+    }
+
+    /// Compute the RFC 7638 thumbprint of the public key, using SHA-256
+    pub fn thumbprint(&self) -> Result<String, Error> {
+        self.to_jwk()?.thumbprint()
+    }
+
+    /// Compute the RFC 7638 thumbprint of the public key, using SHA-1, for
+    /// legacy applications
+    pub fn thumbprint_sha1(&self) -> Result<String, Error> {
+        self.to_jwk()?.thumbprint_sha1()
+    }
+
+    pub fn key_id(&self) -> &Option<String> {
+        &self.parameters.key_id
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.parameters.key_id = Some(key_id.to_string());
+        self
+    }
+
+    /// Set the key agreement mode of new tokens
+    pub fn with_key_agreement(mut self, key_agreement: KeyAgreement) -> Self {
+        self.parameters.key_agreement = key_agreement;
+        self
+    }
+
+    /// Set the content encryption algorithm of new tokens
+    pub fn with_content_encryption(mut self, content_encryption: ContentEncryption) -> Self {
+        self.parameters.content_encryption = content_encryption;
+        self
+    }
+
+    /// Include information about the producer ("apu") and the recipient
+    /// ("apv") in the key derivation
+    pub fn with_party_info(mut self, apu: &[u8], apv: &[u8]) -> Self {
+        self.parameters.apu = Some(apu.to_vec());
+        self.parameters.apv = Some(apv.to_vec());
+        self
+    }
+
+    /// Compute a shared secret using a new ephemeral key pair
+    fn agree(&self) -> Result<(Vec<u8>, EphemeralPublicKey), Error> {
+        let ephemeral_sk = p256::ecdh::EphemeralSecret::random(&mut rand::thread_rng());
+        let z = ephemeral_sk.diffie_hellman(&self.p256_pk);
+        let ephemeral_pk = ephemeral_sk.public_key().to_encoded_point(false);
+        let epk = EphemeralPublicKey {
+            key_type: "EC".to_string(),
+            curve: "P-256".to_string(),
+            x: Base64UrlSafeNoPadding::encode_to_string(
+                ephemeral_pk.x().ok_or(JWTError::InvalidPublicKey)?,
+            )?,
+            y: Some(Base64UrlSafeNoPadding::encode_to_string(
+                ephemeral_pk.y().ok_or(JWTError::InvalidPublicKey)?,
+            )?),
+        };
+        Ok((z.raw_secret_bytes().to_vec(), epk))
+    }
+}
+
+impl EncryptionKeyLike for ECDHP256PublicKey {
+    fn encrypt_payload(
+        &self,
+        payload: &[u8],
+        content_type: Option<&str>,
+        options: &EncryptionOptions,
+    ) -> Result<String, Error> {
+        ecdh_es_encrypt(
+            self,
+            &self.parameters,
+            payload,
+            content_type,
+            options,
+            || self.agree(),
+        )
+    }
+
+    fn encrypt_key(&self, jwe_header: &mut JWEHeader, cek: &[u8]) -> Result<Vec<u8>, Error> {
+        ecdh_es_encrypt_key(&self.parameters, jwe_header, cek, || self.agree())
+    }
+}
+
+//
+
+/// An X25519 key pair for receiving tokens encrypted with ECDH-ES
+#[derive(Debug, Clone)]
+pub struct X25519KeyPair {
+    sk: x25519::SecretKey,
+    pk: x25519::PublicKey,
+    key_id: Option<String>,
+    key_agreement: KeyAgreement,
+}
+
+/// An X25519 public key for encrypting tokens with ECDH-ES
+#[derive(Debug, Clone)]
+pub struct X25519PublicKey {
+    pk: x25519::PublicKey,
+    parameters: RecipientParameters,
+}
+
+impl X25519KeyPair {
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        let sk = x25519::SecretKey::from_slice(raw).map_err(|_| JWTError::InvalidKeyPair)?;
+        let pk = sk
+            .recover_public_key()
+            .map_err(|_| JWTError::InvalidKeyPair)?;
+        Ok(X25519KeyPair {
+            sk,
+            pk,
+            key_id: None,
+            key_agreement: KeyAgreement::Direct,
+        })
+    }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        let sk = x25519::SecretKey::from_der(der).map_err(|_| JWTError::InvalidKeyPair)?;
+        Self::from_bytes(&sk[..])
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        let sk = x25519::SecretKey::from_pem(pem).map_err(|_| JWTError::InvalidKeyPair)?;
+        Self::from_bytes(&sk[..])
+    }
+
+    /// Import a password-protected key pair, encoded as an encrypted PKCS#8
+    /// or as a legacy encrypted PEM document
+    pub fn from_encrypted_pem(pem: &str, password: impl AsRef<[u8]>) -> Result<Self, Error> {
+        Self::from_pem(&decrypt_pem(pem, password.as_ref())?)
+    }
+
+    /// Import a key pair from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        let key_agreement = jwk_key_agreement(jwk)?;
+        let mut key_pair = Self::from_bytes(&jwk.private_key()?)?;
+        key_pair.key_agreement = key_agreement;
+        jwk.check_public_key(&key_pair.public_key().to_jwk()?)?;
+        key_pair.key_id = jwk.key_id.clone();
+        Ok(key_pair)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.sk.to_vec()
+    }
+
+    pub fn to_der(&self) -> Vec<u8> {
+        self.sk.to_der()
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.sk.to_pem()
+    }
+
+    /// Export the key pair as a JWK, including the private key
+    pub fn to_jwk(&self) -> Result<JWK, Error> {
+        self.public_key()
+            .to_jwk()?
+            .with_private_key(&Zeroizing::new(self.to_bytes()))
+    }
+
+    pub fn public_key(&self) -> X25519PublicKey {
+        X25519PublicKey {
+            pk: self.pk,
+            parameters: RecipientParameters::new(self.key_id.clone(), self.key_agreement),
+        }
+    }
+
+    pub fn generate() -> Self {
+        Self::generate_with_rng(&mut rand::thread_rng())
+    }
+
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        let mut raw = zeroize::Zeroizing::new([0u8; x25519::SecretKey::BYTES]);
+        rng.fill_bytes(raw.as_mut());
+        let sk = x25519::SecretKey::new(*raw);
+        let pk = sk.recover_public_key().unwrap();
+        X25519KeyPair {
+            sk,
+            pk,
+            key_id: None,
+            key_agreement: KeyAgreement::Direct,
+        }
+    }
+
+    pub fn key_id(&self) -> &Option<String> {
+        &self.key_id
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string());
+        self
+    }
+
+    /// Set the key agreement mode of accepted tokens
+    pub fn with_key_agreement(mut self, key_agreement: KeyAgreement) -> Self {
+        self.key_agreement = key_agreement;
+        self
+    }
+}
+
+impl DecryptionKeyLike for X25519KeyPair {
+    fn decrypt_payload(
+        &self,
+        token: &str,
+        options: &VerificationOptions,
+    ) -> Result<DecryptedPayload, Error> {
+        check_key_policy(Some(options), KeyParameters::EllipticCurve("X25519"))?;
+        ecdh_es_decrypt(
+            self.key_agreement,
+            self.key_id.as_deref(),
+            token,
+            options,
+            |epk| {
+                ensure!(
+                    epk.key_type == "OKP" && epk.curve == "X25519",
+                    JWTError::InvalidPublicKey
+                );
+                let epk = x25519::PublicKey::from_slice(&decode_coordinate(
+                    &epk.x,
+                    x25519::PublicKey::BYTES,
+                )?)
+                .map_err(|_| JWTError::InvalidPublicKey)?;
+                let z = epk.dh(&self.sk).map_err(|_| JWTError::InvalidPublicKey)?;
+                Ok(z.to_vec())
+            },
+        )
+    }
+}
+
+impl X25519PublicKey {
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        let pk = x25519::PublicKey::from_slice(raw).map_err(|_| JWTError::InvalidPublicKey)?;
+        Ok(X25519PublicKey {
+            pk,
+            parameters: RecipientParameters::new(None, KeyAgreement::Direct),
+        })
+    }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        let pk = x25519::PublicKey::from_der(der).map_err(|_| JWTError::InvalidPublicKey)?;
+        Self::from_bytes(&pk[..])
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        let pk = x25519::PublicKey::from_pem(pem).map_err(|_| JWTError::InvalidPublicKey)?;
+        Self::from_bytes(&pk[..])
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.pk.to_vec()
+    }
+
+    pub fn to_der(&self) -> Vec<u8> {
+        self.pk.to_der()
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.pk.to_pem()
+    }
+
+    /// Import a public key from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        let key_agreement = jwk_key_agreement(jwk)?;
+        let mut pk = Self::from_bytes(&jwk.octet_key_pair_public_key("X25519")?)?;
+        pk.parameters = RecipientParameters::new(jwk.key_id.clone(), key_agreement);
+        Ok(pk)
+    }
+
+    /// Export the public key as a JWK, for publication in a key set
+    pub fn to_jwk(&self) -> Result<JWK, Error> {
+        Ok(JWK::new_octet_key_pair("X25519", &self.pk[..])?.with_usage(
+            &self.parameters.key_id,
+            self.parameters.key_agreement.jwe_alg_name(),
+            "enc",
+        ))
+    }
+
+    /// Compute the RFC 7638 thumbprint of the public key, using SHA-256
+    pub fn thumbprint(&self) -> Result<String, Error> {
+        self.to_jwk()?.thumbprint()
+    }
+
+    /// Compute the RFC 7638 thumbprint of the public key, using SHA-1, for
+    /// legacy applications
+    pub fn thumbprint_sha1(&self) -> Result<String, Error> {
+        self.to_jwk()?.thumbprint_sha1()
+    }
+
+    pub fn key_id(&self) -> &Option<String> {
+        &self.parameters.key_id
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.parameters.key_id = Some(key_id.to_string());
+        self
+    }
+
+    /// Set the key agreement mode of new tokens
+    pub fn with_key_agreement(mut self, key_agreement: KeyAgreement) -> Self {
+        self.parameters.key_agreement = key_agreement;
+        self
+    }
+
+    /// Set the content encryption algorithm of new tokens
+    pub fn with_content_encryption(mut self, content_encryption: ContentEncryption) -> Self {
+        self.parameters.content_encryption = content_encryption;
+        self
+    }
+
+    /// Include information about the producer ("apu") and the recipient
+    /// ("apv") in the key derivation
+    pub fn with_party_info(mut self, apu: &[u8], apv: &[u8]) -> Self {
+        self.parameters.apu = Some(apu.to_vec());
+        self.parameters.apv = Some(apv.to_vec());
+        self
+    }
+
+    /// Compute a shared secret using a new ephemeral key pair
+    fn agree(&self) -> Result<(Vec<u8>, EphemeralPublicKey), Error> {
+        let ephemeral_kp = X25519KeyPair::generate();
+        let z = self
+            .pk
+            .dh(&ephemeral_kp.sk)
+            .map_err(|_| JWTError::InvalidPublicKey)?;
+        let epk = EphemeralPublicKey {
+            key_type: "OKP".to_string(),
+            curve: "X25519".to_string(),
+            x: Base64UrlSafeNoPadding::encode_to_string(*ephemeral_kp.pk)?,
+            y: None,
+        };
+        Ok((z.to_vec(), epk))
+    }
+}
+
+impl EncryptionKeyLike for X25519PublicKey {
+    fn encrypt_payload(
+        &self,
+        payload: &[u8],
+        content_type: Option<&str>,
+        options: &EncryptionOptions,
+    ) -> Result<String, Error> {
+        ecdh_es_encrypt(
+            self,
+            &self.parameters,
+            payload,
+            content_type,
+            options,
+            || self.agree(),
+        )
+    }
+
+    fn encrypt_key(&self, jwe_header: &mut JWEHeader, cek: &[u8]) -> Result<Vec<u8>, Error> {
+        ecdh_es_encrypt_key(&self.parameters, jwe_header, cek, || self.agree())
+    }
+}
+
+//
+
+const X448_KEY_LENGTH: usize = 56;
+const X448_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.101.111");
+
+/// X448 function (RFC 7748), rejecting low-order points
+fn x448(sk: &[u8; X448_KEY_LENGTH], u: &[u8; X448_KEY_LENGTH]) -> Result<Vec<u8>, Error> {
+    let mut k = Zeroizing::new(*sk);
+    k[0] &= 252;
+    k[X448_KEY_LENGTH - 1] |= 128;
+    let shared = &MontgomeryPoint(*u) * &Scalar::from_bytes(&k);
+    ensure!(
+        shared.0.iter().fold(0u8, |acc, x| acc | x) != 0,
+        JWTError::InvalidPublicKey
+    );
+    Ok(shared.0.to_vec())
+}
+
+/// An X448 key pair for receiving tokens encrypted with ECDH-ES
+#[derive(Clone)]
+pub struct X448KeyPair {
+    sk: Zeroizing<[u8; X448_KEY_LENGTH]>,
+    pk: [u8; X448_KEY_LENGTH],
+    key_id: Option<String>,
+    key_agreement: KeyAgreement,
+}
+
+impl std::fmt::Debug for X448KeyPair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("X448KeyPair")
+            .field("pk", &self.pk)
+            .field("key_id", &self.key_id)
+            .field("key_agreement", &self.key_agreement)
+            .finish()
+    }
+}
+
+/// An X448 public key for encrypting tokens with ECDH-ES
+#[derive(Debug, Clone)]
+pub struct X448PublicKey {
+    pk: [u8; X448_KEY_LENGTH],
+    parameters: RecipientParameters,
+}
+
+impl X448KeyPair {
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        let sk: [u8; X448_KEY_LENGTH] = raw.try_into().map_err(|_| JWTError::InvalidKeyPair)?;
+        let sk = Zeroizing::new(sk);
+        let pk = x448(&sk, &MontgomeryPoint::GENERATOR.0)
+            .map_err(|_| JWTError::InvalidKeyPair)?
+            .try_into()
+            .unwrap();
+        Ok(X448KeyPair {
+            sk,
+            pk,
+            key_id: None,
+            key_agreement: KeyAgreement::Direct,
+        })
+    }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        let private_key_info =
+            pkcs8::PrivateKeyInfo::try_from(der).map_err(|_| JWTError::InvalidKeyPair)?;
+        ensure!(
+            private_key_info.algorithm.oid == X448_OID,
+            JWTError::InvalidKeyPair
+        );
+        // RFC 8410: the private key is itself wrapped in an OCTET STRING
+        let raw = match private_key_info.private_key {
+            [0x04, len, raw @ ..] if *len as usize == X448_KEY_LENGTH => raw,
+            _ => bail!(JWTError::InvalidKeyPair),
+        };
+        Self::from_bytes(raw)
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        let (label, der) =
+            pkcs8::SecretDocument::from_pem(pem.trim()).map_err(|_| JWTError::InvalidKeyPair)?;
+        ensure!(label == "PRIVATE KEY", JWTError::InvalidKeyPair);
+        Self::from_der(der.as_bytes())
+    }
+
+    /// Import a password-protected key pair, encoded as an encrypted PKCS#8
+    /// or as a legacy encrypted PEM document
+    pub fn from_encrypted_pem(pem: &str, password: impl AsRef<[u8]>) -> Result<Self, Error> {
+        Self::from_pem(&decrypt_pem(pem, password.as_ref())?)
+    }
+
+    /// Import a key pair from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        let key_agreement = jwk_key_agreement(jwk)?;
+        let mut key_pair = Self::from_bytes(&jwk.private_key()?)?;
+        key_pair.key_agreement = key_agreement;
+        jwk.check_public_key(&key_pair.public_key().to_jwk()?)?;
+        key_pair.key_id = jwk.key_id.clone();
+        Ok(key_pair)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.sk.to_vec()
+    }
+
+    fn to_secret_document(&self) -> pkcs8::SecretDocument {
+        let mut private_key = Zeroizing::new(vec![0x04, X448_KEY_LENGTH as u8]);
+        private_key.extend_from_slice(self.sk.as_ref());
+        let algorithm = AlgorithmIdentifierRef {
+            oid: X448_OID,
+            parameters: None,
+        };
+        pkcs8::SecretDocument::try_from(pkcs8::PrivateKeyInfo::new(algorithm, &private_key))
+            .unwrap()
+    }
+
+    pub fn to_der(&self) -> Vec<u8> {
+        self.to_secret_document().as_bytes().to_vec()
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.to_secret_document()
+            .to_pem("PRIVATE KEY", LineEnding::LF)
+            .unwrap()
+            .to_string()
+    }
+
+    /// Export the key pair as a JWK, including the private key
+    pub fn to_jwk(&self) -> Result<JWK, Error> {
+        self.public_key()
+            .to_jwk()?
+            .with_private_key(&Zeroizing::new(self.to_bytes()))
+    }
+
+    pub fn public_key(&self) -> X448PublicKey {
+        X448PublicKey {
+            pk: self.pk,
+            parameters: RecipientParameters::new(self.key_id.clone(), self.key_agreement),
+        }
+    }
+
+    pub fn generate() -> Self {
+        Self::generate_with_rng(&mut rand::thread_rng())
+    }
+
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        let mut raw = Zeroizing::new([0u8; X448_KEY_LENGTH]);
+        rng.fill_bytes(raw.as_mut());
+        Self::from_bytes(raw.as_ref()).unwrap()
+    }
+
+    pub fn key_id(&self) -> &Option<String> {
+        &self.key_id
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string());
+        self
+    }
+
+    /// Set the key agreement mode of accepted tokens
+    pub fn with_key_agreement(mut self, key_agreement: KeyAgreement) -> Self {
+        self.key_agreement = key_agreement;
+        self
+    }
+}
+
+impl DecryptionKeyLike for X448KeyPair {
+    fn decrypt_payload(
+        &self,
+        token: &str,
+        options: &VerificationOptions,
+    ) -> Result<DecryptedPayload, Error> {
+        check_key_policy(Some(options), KeyParameters::EllipticCurve("X448"))?;
+        ecdh_es_decrypt(
+            self.key_agreement,
+            self.key_id.as_deref(),
+            token,
+            options,
+            |epk| {
+                ensure!(
+                    epk.key_type == "OKP" && epk.curve == "X448",
+                    JWTError::InvalidPublicKey
+                );
+                let epk: [u8; X448_KEY_LENGTH] = decode_coordinate(&epk.x, X448_KEY_LENGTH)?
+                    .try_into()
+                    .unwrap();
+                x448(&self.sk, &epk)
+            },
+        )
+    }
+}
+
+impl X448PublicKey {
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        let pk: [u8; X448_KEY_LENGTH] = raw.try_into().map_err(|_| JWTError::InvalidPublicKey)?;
+        Ok(X448PublicKey {
+            pk,
+            parameters: RecipientParameters::new(None, KeyAgreement::Direct),
+        })
+    }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        let spki = pkcs8::SubjectPublicKeyInfoRef::try_from(der)
+            .map_err(|_| JWTError::InvalidPublicKey)?;
+        ensure!(spki.algorithm.oid == X448_OID, JWTError::InvalidPublicKey);
+        let raw = spki
+            .subject_public_key
+            .as_bytes()
+            .ok_or(JWTError::InvalidPublicKey)?;
+        Self::from_bytes(raw)
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        let (label, der) =
+            pkcs8::Document::from_pem(pem.trim()).map_err(|_| JWTError::InvalidPublicKey)?;
+        ensure!(label == "PUBLIC KEY", JWTError::InvalidPublicKey);
+        Self::from_der(der.as_bytes())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.pk.to_vec()
+    }
+
+    fn to_document(&self) -> pkcs8::Document {
+        let spki = pkcs8::SubjectPublicKeyInfoRef {
+            algorithm: AlgorithmIdentifierRef {
+                oid: X448_OID,
+                parameters: None,
+            },
+            subject_public_key: BitStringRef::from_bytes(&self.pk).unwrap(),
+        };
+        pkcs8::Document::try_from(spki).unwrap()
+    }
+
+    pub fn to_der(&self) -> Vec<u8> {
+        self.to_document().as_bytes().to_vec()
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.to_document()
+            .to_pem("PUBLIC KEY", LineEnding::LF)
+            .unwrap()
+    }
+
+    /// Import a public key from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        let key_agreement = jwk_key_agreement(jwk)?;
+        let mut pk = Self::from_bytes(&jwk.octet_key_pair_public_key("X448")?)?;
+        pk.parameters = RecipientParameters::new(jwk.key_id.clone(), key_agreement);
+        Ok(pk)
+    }
+
+    /// Export the public key as a JWK, for publication in a key set
+    pub fn to_jwk(&self) -> Result<JWK, Error> {
+        Ok(JWK::new_octet_key_pair("X448", &self.pk)?.with_usage(
+            &self.parameters.key_id,
+            self.parameters.key_agreement.jwe_alg_name(),
+            "enc",
+        ))
+    }
+
+    /// Compute the RFC 7638 thumbprint of the public key, using SHA-256
+    pub fn thumbprint(&self) -> Result<String, Error> {
+        self.to_jwk()?.thumbprint()
+    }
+
+    /// Compute the RFC 7638 thumbprint of the public key, using SHA-1, for
+    /// legacy applications
+    pub fn thumbprint_sha1(&self) -> Result<String, Error> {
+        self.to_jwk()?.thumbprint_sha1()
+    }
+
+    pub fn key_id(&self) -> &Option<String> {
+        &self.parameters.key_id
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.parameters.key_id = Some(key_id.to_string());
+        self
+    }
+
+    /// Set the key agreement mode of new tokens
+    pub fn with_key_agreement(mut self, key_agreement: KeyAgreement) -> Self {
+        self.parameters.key_agreement = key_agreement;
+        self
+    }
+
+    /// Set the content encryption algorithm of new tokens
+    pub fn with_content_encryption(mut self, content_encryption: ContentEncryption) -> Self {
+        self.parameters.content_encryption = content_encryption;
+        self
+    }
+
+    /// Include information about the producer ("apu") and the recipient
+    /// ("apv") in the key derivation
+    pub fn with_party_info(mut self, apu: &[u8], apv: &[u8]) -> Self {
+        self.parameters.apu = Some(apu.to_vec());
+        self.parameters.apv = Some(apv.to_vec());
+        self
+    }
+
+    /// Compute a shared secret using a new ephemeral key pair
+    fn agree(&self) -> Result<(Vec<u8>, EphemeralPublicKey), Error> {
+        let ephemeral_kp = X448KeyPair::generate();
+        let z = x448(&ephemeral_kp.sk, &self.pk)?;
+        let epk = EphemeralPublicKey {
+            key_type: "OKP".to_string(),
+            curve: "X448".to_string(),
+            x: Base64UrlSafeNoPadding::encode_to_string(ephemeral_kp.pk)?,
+            y: None,
+        };
+        Ok((z, epk))
+    }
+}
+
+impl EncryptionKeyLike for X448PublicKey {
+    fn encrypt_payload(
+        &self,
+        payload: &[u8],
+        content_type: Option<&str>,
+        options: &EncryptionOptions,
+    ) -> Result<String, Error> {
+        ecdh_es_encrypt(
+            self,
+            &self.parameters,
+            payload,
+            content_type,
+            options,
+            || self.agree(),
+        )
+    }
+
+    fn encrypt_key(&self, jwe_header: &mut JWEHeader, cek: &[u8]) -> Result<Vec<u8>, Error> {
+        ecdh_es_encrypt_key(&self.parameters, jwe_header, cek, || self.agree())
+    }
+}
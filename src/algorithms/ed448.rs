@@ -0,0 +1,566 @@
+use std::convert::{TryFrom, TryInto};
+
+use ct_codecs::{Base64UrlSafeNoPadding, Encoder};
+use ed448_goldilocks_plus::pkcs8::{
+    self, DecodePublicKey as _, EncodePrivateKey as _, EncodePublicKey as _,
+};
+use ed448_goldilocks_plus::{
+    PublicKeyBytes, Signature, SigningKey, VerifyingKey, ALGORITHM_OID, PUBLIC_KEY_LENGTH,
+    SECRET_KEY_LENGTH,
+};
+use hmac_sha1_compact::Hash as SHA1;
+use hmac_sha256::Hash as SHA256;
+use rand::{CryptoRng, RngCore};
+use serde::{de::DeserializeOwned, Serialize};
+use zeroize::Zeroizing;
+
+use crate::claims::*;
+use crate::common::*;
+use crate::encrypted_pem::decrypt_pem;
+use crate::error::*;
+use crate::jwk::*;
+use crate::jwt_header::*;
+use crate::token::*;
+use crate::x509;
+
+#[doc(hidden)]
+#[derive(Debug, Clone)]
+pub struct Edwards448PublicKey(VerifyingKey);
+
+impl AsRef<VerifyingKey> for Edwards448PublicKey {
+    fn as_ref(&self) -> &VerifyingKey {
+        &self.0
+    }
+}
+
+impl Edwards448PublicKey {
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        let raw: [u8; PUBLIC_KEY_LENGTH] =
+            raw.try_into().map_err(|_| JWTError::InvalidPublicKey)?;
+        let ed448_pk =
+            VerifyingKey::try_from(PublicKeyBytes(raw)).map_err(|_| JWTError::InvalidPublicKey)?;
+        Ok(Edwards448PublicKey(ed448_pk))
+    }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        let ed448_pk =
+            VerifyingKey::from_public_key_der(der).map_err(|_| JWTError::InvalidPublicKey)?;
+        Ok(Edwards448PublicKey(ed448_pk))
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        let ed448_pk =
+            VerifyingKey::from_public_key_pem(pem).map_err(|_| JWTError::InvalidPublicKey)?;
+        Ok(Edwards448PublicKey(ed448_pk))
+    }
+
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        Self::from_bytes(&jwk.octet_key_pair_public_key("Ed448")?)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.as_ref().to_vec()
+    }
+
+    pub fn to_der(&self) -> Vec<u8> {
+        self.0.to_public_key_der().unwrap().into_vec()
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.0.to_public_key_pem(Default::default()).unwrap()
+    }
+
+    pub fn to_jwk(&self) -> Result<JWK, Error> {
+        JWK::new_octet_key_pair("Ed448", &self.to_bytes())
+    }
+}
+
+#[doc(hidden)]
+#[derive(Clone)]
+pub struct Edwards448KeyPair {
+    ed448_sk: SigningKey,
+    metadata: Option<KeyMetadata>,
+}
+
+impl AsRef<SigningKey> for Edwards448KeyPair {
+    fn as_ref(&self) -> &SigningKey {
+        &self.ed448_sk
+    }
+}
+
+impl Edwards448KeyPair {
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        ensure!(
+            raw.len() == SECRET_KEY_LENGTH || raw.len() == SECRET_KEY_LENGTH + PUBLIC_KEY_LENGTH,
+            JWTError::InvalidKeyPair
+        );
+        let ed448_sk = SigningKey::try_from(&raw[..SECRET_KEY_LENGTH])
+            .map_err(|_| JWTError::InvalidKeyPair)?;
+        if raw.len() > SECRET_KEY_LENGTH {
+            ensure!(
+                timingsafe_eq(ed448_sk.verifying_key().as_ref(), &raw[SECRET_KEY_LENGTH..]),
+                JWTError::InvalidKeyPair
+            );
+        }
+        Ok(Edwards448KeyPair {
+            ed448_sk,
+            metadata: None,
+        })
+    }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        let private_key_info =
+            pkcs8::PrivateKeyInfo::try_from(der).map_err(|_| JWTError::InvalidKeyPair)?;
+        ensure!(
+            private_key_info.algorithm.oid == ALGORITHM_OID,
+            JWTError::InvalidKeyPair
+        );
+        // RFC 8410: the private key is itself wrapped in an OCTET STRING
+        let raw = match private_key_info.private_key {
+            [0x04, len, raw @ ..] if *len as usize == SECRET_KEY_LENGTH => raw,
+            _ => bail!(JWTError::InvalidKeyPair),
+        };
+        let key_pair = Self::from_bytes(raw)?;
+        if let Some(public_key) = private_key_info.public_key {
+            ensure!(
+                timingsafe_eq(key_pair.ed448_sk.verifying_key().as_ref(), public_key),
+                JWTError::InvalidKeyPair
+            );
+        }
+        Ok(key_pair)
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        let (label, der) =
+            pkcs8::SecretDocument::from_pem(pem.trim()).map_err(|_| JWTError::InvalidKeyPair)?;
+        ensure!(label == "PRIVATE KEY", JWTError::InvalidKeyPair);
+        Self::from_der(der.as_bytes())
+    }
+
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        let key_pair = Self::from_bytes(&jwk.private_key()?)?;
+        jwk.check_public_key(&key_pair.public_key().to_jwk()?)?;
+        Ok(key_pair)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut raw = self.ed448_sk.to_bytes().to_vec();
+        raw.extend_from_slice(self.ed448_sk.verifying_key().as_ref());
+        raw
+    }
+
+    pub fn to_der(&self) -> Vec<u8> {
+        self.ed448_sk.to_pkcs8_der().unwrap().as_bytes().to_vec()
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.ed448_sk
+            .to_pkcs8_pem(Default::default())
+            .unwrap()
+            .to_string()
+    }
+
+    pub fn to_jwk(&self) -> Result<JWK, Error> {
+        self.public_key()
+            .to_jwk()?
+            .with_private_key(Zeroizing::new(self.ed448_sk.to_bytes()).as_ref())
+    }
+
+    pub fn public_key(&self) -> Edwards448PublicKey {
+        Edwards448PublicKey(self.ed448_sk.verifying_key())
+    }
+
+    pub fn generate() -> Self {
+        Self::generate_with_rng(&mut rand::thread_rng())
+    }
+
+    pub fn generate_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        let ed448_sk = SigningKey::generate(rng);
+        Edwards448KeyPair {
+            ed448_sk,
+            metadata: None,
+        }
+    }
+}
+
+pub trait EdDSA448KeyPairLike {
+    fn jwt_alg_name() -> &'static str;
+    fn key_pair(&self) -> &Edwards448KeyPair;
+    fn key_id(&self) -> &Option<String>;
+    fn metadata(&self) -> &Option<KeyMetadata>;
+    fn attach_metadata(&mut self, metadata: KeyMetadata) -> Result<(), Error>;
+
+    /// Compute the signature of `authenticated`
+    fn signature(&self, authenticated: &[u8]) -> Result<Vec<u8>, Error> {
+        let signature = self.key_pair().as_ref().sign_raw(authenticated);
+        Ok(signature.to_bytes().to_vec())
+    }
+
+    fn sign<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata());
+        Token::build(&jwt_header, claims, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Sign claims, with additional header parameters
+    fn sign_with_header_options<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+        header_options: &HeaderOptions,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata())
+            .with_options(header_options);
+        Token::build(&jwt_header, claims, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Sign a detached payload, that is not included in the token (RFC 7797)
+    fn sign_detached(&self, payload: &str) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata());
+        Token::build_detached(jwt_header, payload, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Sign a detached binary payload, that is not included in the token
+    ///
+    /// The raw bytes of the payload are signed without being encoded
+    /// (RFC 7797), so that it can be any sequence of bytes.
+    fn sign_bytes_detached(&self, payload: &[u8]) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata());
+        Token::build_bytes_detached(jwt_header, payload, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Export the key pair as a JWK, including the private key
+    fn to_jwk(&self) -> Result<JWK, Error> {
+        Ok(self
+            .key_pair()
+            .to_jwk()?
+            .with_usage(self.key_id(), Self::jwt_alg_name(), "sig"))
+    }
+}
+
+pub trait EdDSA448PublicKeyLike {
+    fn jwt_alg_name() -> &'static str;
+    fn public_key(&self) -> &Edwards448PublicKey;
+    fn key_id(&self) -> &Option<String>;
+    fn set_key_id(&mut self, key_id: String);
+
+    /// The SHA-256 thumbprint of the certificate the key was imported from
+    fn certificate_sha256_thumbprint(&self) -> Option<&[u8; 32]> {
+        None
+    }
+
+    /// Verify the signature of `authenticated`
+    fn verify_signature(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        let ed448_signature =
+            Signature::try_from(signature).map_err(|_| JWTError::InvalidSignature)?;
+        self.public_key()
+            .as_ref()
+            .verify_raw(&ed448_signature, authenticated)
+            .map_err(|_| JWTError::InvalidSignature)?;
+        Ok(())
+    }
+
+    fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        check_key_policy(options.as_ref(), KeyParameters::EllipticCurve("Ed448"))?;
+        check_key_thumbprint(options.as_ref(), || self.thumbprint().ok())?;
+        check_certificate_thumbprint(options.as_ref(), self.certificate_sha256_thumbprint())?;
+        Token::verify(
+            Self::jwt_alg_name(),
+            token,
+            options,
+            |authenticated, signature| self.verify_signature(authenticated, signature),
+        )
+    }
+
+    fn verify_token_untyped(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<serde_json::Value, Error> {
+        let claims = self.verify_token::<NoCustomClaims>(token, options)?;
+        Ok(serde_json::to_value(claims)?)
+    }
+
+    fn create_key_id(&mut self) -> &str {
+        self.set_key_id(
+            Base64UrlSafeNoPadding::encode_to_string(hmac_sha256::Hash::hash(
+                &self.public_key().to_bytes(),
+            ))
+            .unwrap(),
+        );
+        self.key_id().as_ref().map(|x| x.as_str()).unwrap()
+    }
+
+    /// Export the public key as a JWK, for publication in a key set
+    fn to_jwk(&self) -> Result<JWK, Error> {
+        Ok(self
+            .public_key()
+            .to_jwk()?
+            .with_usage(self.key_id(), Self::jwt_alg_name(), "sig"))
+    }
+
+    /// Compute the RFC 7638 thumbprint of the public key, using SHA-256
+    fn thumbprint(&self) -> Result<String, Error> {
+        self.to_jwk()?.thumbprint()
+    }
+
+    /// Compute the RFC 7638 thumbprint of the public key, using SHA-1, for
+    /// legacy applications
+    fn thumbprint_sha1(&self) -> Result<String, Error> {
+        self.to_jwk()?.thumbprint_sha1()
+    }
+}
+
+#[derive(Clone)]
+pub struct Ed448KeyPair {
+    key_pair: Edwards448KeyPair,
+    key_id: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Ed448PublicKey {
+    pk: Edwards448PublicKey,
+    key_id: Option<String>,
+    certificate_sha256_thumbprint: Option<[u8; 32]>,
+}
+
+impl EdDSA448KeyPairLike for Ed448KeyPair {
+    fn jwt_alg_name() -> &'static str {
+        "EdDSA"
+    }
+
+    fn key_pair(&self) -> &Edwards448KeyPair {
+        &self.key_pair
+    }
+
+    fn key_id(&self) -> &Option<String> {
+        &self.key_id
+    }
+
+    fn metadata(&self) -> &Option<KeyMetadata> {
+        &self.key_pair.metadata
+    }
+
+    fn attach_metadata(&mut self, metadata: KeyMetadata) -> Result<(), Error> {
+        self.key_pair.metadata = Some(metadata);
+        Ok(())
+    }
+}
+
+impl Ed448KeyPair {
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        Ok(Ed448KeyPair {
+            key_pair: Edwards448KeyPair::from_bytes(raw)?,
+            key_id: None,
+        })
+    }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        Ok(Ed448KeyPair {
+            key_pair: Edwards448KeyPair::from_der(der)?,
+            key_id: None,
+        })
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        Ok(Ed448KeyPair {
+            key_pair: Edwards448KeyPair::from_pem(pem)?,
+            key_id: None,
+        })
+    }
+
+    /// Import a password-protected key pair, encoded as an encrypted PKCS#8
+    /// or as a legacy encrypted PEM document
+    pub fn from_encrypted_pem(pem: &str, password: impl AsRef<[u8]>) -> Result<Self, Error> {
+        Self::from_pem(&decrypt_pem(pem, password.as_ref())?)
+    }
+
+    /// Import a key pair from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["sign"])?;
+        Ok(Ed448KeyPair {
+            key_pair: Edwards448KeyPair::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.key_pair.to_bytes()
+    }
+
+    pub fn to_der(&self) -> Vec<u8> {
+        self.key_pair.to_der()
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.key_pair.to_pem()
+    }
+
+    pub fn public_key(&self) -> Ed448PublicKey {
+        Ed448PublicKey {
+            pk: self.key_pair.public_key(),
+            key_id: self.key_id.clone(),
+            certificate_sha256_thumbprint: None,
+        }
+    }
+
+    pub fn generate() -> Self {
+        Ed448KeyPair {
+            key_pair: Edwards448KeyPair::generate(),
+            key_id: None,
+        }
+    }
+
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        Ed448KeyPair {
+            key_pair: Edwards448KeyPair::generate_with_rng(rng),
+            key_id: None,
+        }
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string());
+        self
+    }
+}
+
+impl EdDSA448PublicKeyLike for Ed448PublicKey {
+    fn jwt_alg_name() -> &'static str {
+        "EdDSA"
+    }
+
+    fn public_key(&self) -> &Edwards448PublicKey {
+        &self.pk
+    }
+
+    fn key_id(&self) -> &Option<String> {
+        &self.key_id
+    }
+
+    fn set_key_id(&mut self, key_id: String) {
+        self.key_id = Some(key_id);
+    }
+
+    fn certificate_sha256_thumbprint(&self) -> Option<&[u8; 32]> {
+        self.certificate_sha256_thumbprint.as_ref()
+    }
+}
+
+impl KeyLike for Ed448PublicKey {
+    fn key_algorithm(&self) -> &'static str {
+        Self::jwt_alg_name()
+    }
+
+    fn key_identifier(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    fn verify_authenticated(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        self.verify_signature(authenticated, signature)
+    }
+
+    fn key_parameters(&self) -> KeyParameters {
+        KeyParameters::EllipticCurve("Ed448")
+    }
+
+    fn key_thumbprint(&self) -> Option<String> {
+        self.thumbprint().ok()
+    }
+
+    fn key_certificate_thumbprint(&self) -> Option<&[u8; 32]> {
+        self.certificate_sha256_thumbprint.as_ref()
+    }
+}
+
+impl Ed448PublicKey {
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        Ok(Ed448PublicKey {
+            pk: Edwards448PublicKey::from_bytes(raw)?,
+            key_id: None,
+            certificate_sha256_thumbprint: None,
+        })
+    }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        Ok(Ed448PublicKey {
+            pk: Edwards448PublicKey::from_der(der)?,
+            key_id: None,
+            certificate_sha256_thumbprint: None,
+        })
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        Ok(Ed448PublicKey {
+            pk: Edwards448PublicKey::from_pem(pem)?,
+            key_id: None,
+            certificate_sha256_thumbprint: None,
+        })
+    }
+
+    /// Import the public key of a DER-encoded X.509 certificate
+    pub fn from_x509_der(der: &[u8]) -> Result<Self, Error> {
+        let mut public_key = Self::from_der(x509::certificate_public_key(der)?)?;
+        public_key.certificate_sha256_thumbprint = Some(hmac_sha256::Hash::hash(der));
+        Ok(public_key)
+    }
+
+    /// Import the public key of the first certificate of a PEM-encoded X.509
+    /// certificate chain
+    pub fn from_x509_pem(pem: &str) -> Result<Self, Error> {
+        Self::from_x509_der(&x509::certificate_from_pem(pem)?)
+    }
+
+    /// Import a public key from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["verify"])?;
+        Ok(Ed448PublicKey {
+            pk: Edwards448PublicKey::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+            certificate_sha256_thumbprint: None,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.pk.to_bytes()
+    }
+
+    pub fn to_der(&self) -> Vec<u8> {
+        self.pk.to_der()
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.pk.to_pem()
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string());
+        self
+    }
+
+    pub fn sha1_thumbprint(&self) -> String {
+        Base64UrlSafeNoPadding::encode_to_string(SHA1::hash(&self.pk.to_der())).unwrap()
+    }
+
+    pub fn sha256_thumbprint(&self) -> String {
+        Base64UrlSafeNoPadding::encode_to_string(SHA256::hash(&self.pk.to_der())).unwrap()
+    }
+}
@@ -1,15 +1,20 @@
 use ct_codecs::{Base64UrlSafeNoPadding, Encoder};
 use hmac_sha1_compact::Hash as SHA1;
 use hmac_sha256::Hash as SHA256;
+use rand::{CryptoRng, RngCore};
 use serde::{de::DeserializeOwned, Serialize};
 
 use crate::claims::*;
 use crate::common::*;
 #[cfg(feature = "cwt")]
 use crate::cwt_token::*;
+use crate::encrypted_pem::decrypt_pem;
 use crate::error::*;
+use crate::jwk::*;
 use crate::jwt_header::*;
+use crate::openssh::{self, OpenSSHPrivateKey, OpenSSHPublicKey};
 use crate::token::*;
+use crate::x509;
 
 #[doc(hidden)]
 #[derive(Debug, Clone)]
@@ -43,6 +48,17 @@ impl Edwards25519PublicKey {
         ))
     }
 
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        Self::from_bytes(&jwk.octet_key_pair_public_key("Ed25519")?)
+    }
+
+    pub fn from_openssh(line: &str) -> Result<Self, Error> {
+        match openssh::decode_public_key(line)? {
+            OpenSSHPublicKey::Ed25519(pk) => Self::from_bytes(&pk),
+            _ => bail!(JWTError::InvalidPublicKey),
+        }
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         self.0.as_ref().to_vec()
     }
@@ -54,6 +70,10 @@ impl Edwards25519PublicKey {
     pub fn to_pem(&self) -> String {
         self.0.to_pem()
     }
+
+    pub fn to_jwk(&self) -> Result<JWK, Error> {
+        JWK::new_octet_key_pair("Ed25519", &self.to_bytes())
+    }
 }
 
 #[doc(hidden)]
@@ -104,6 +124,33 @@ impl Edwards25519KeyPair {
         })
     }
 
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        let seed = ed25519_compact::Seed::from_slice(&jwk.private_key()?)?;
+        let key_pair = Edwards25519KeyPair {
+            ed25519_kp: ed25519_compact::KeyPair::from_seed(seed),
+            metadata: None,
+        };
+        jwk.check_public_key(&key_pair.public_key().to_jwk()?)?;
+        Ok(key_pair)
+    }
+
+    pub fn from_openssh(pem: &str, passphrase: Option<&[u8]>) -> Result<Self, Error> {
+        let secret_key = match openssh::decode_private_key(pem, passphrase)? {
+            OpenSSHPrivateKey::Ed25519(secret_key) => secret_key,
+            _ => bail!(JWTError::InvalidKeyPair),
+        };
+        let seed = ed25519_compact::Seed::from_slice(&secret_key[..32])?;
+        let ed25519_kp = ed25519_compact::KeyPair::from_seed(seed);
+        ensure!(
+            ed25519_kp.pk.as_ref() == &secret_key[32..],
+            JWTError::InvalidKeyPair
+        );
+        Ok(Edwards25519KeyPair {
+            ed25519_kp,
+            metadata: None,
+        })
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         self.ed25519_kp.to_vec()
     }
@@ -116,6 +163,12 @@ impl Edwards25519KeyPair {
         self.ed25519_kp.to_pem()
     }
 
+    pub fn to_jwk(&self) -> Result<JWK, Error> {
+        self.public_key()
+            .to_jwk()?
+            .with_private_key(self.ed25519_kp.sk.seed().as_ref())
+    }
+
     pub fn public_key(&self) -> Edwards25519PublicKey {
         let ed25519_pk = self.ed25519_kp.pk;
         Edwards25519PublicKey(ed25519_pk)
@@ -128,6 +181,16 @@ impl Edwards25519KeyPair {
             metadata: None,
         }
     }
+
+    pub fn generate_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        let mut seed = zeroize::Zeroizing::new([0u8; ed25519_compact::Seed::BYTES]);
+        rng.fill_bytes(seed.as_mut());
+        let ed25519_kp = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new(*seed));
+        Edwards25519KeyPair {
+            ed25519_kp,
+            metadata: None,
+        }
+    }
 }
 
 pub trait EdDSAKeyPairLike {
@@ -137,6 +200,13 @@ pub trait EdDSAKeyPairLike {
     fn metadata(&self) -> &Option<KeyMetadata>;
     fn attach_metadata(&mut self, metadata: KeyMetadata) -> Result<(), Error>;
 
+    /// Compute the signature of `authenticated`
+    fn signature(&self, authenticated: &[u8]) -> Result<Vec<u8>, Error> {
+        let noise = ed25519_compact::Noise::generate();
+        let signature = self.key_pair().as_ref().sk.sign(authenticated, Some(noise));
+        Ok(signature.to_vec())
+    }
+
     fn sign<CustomClaims: Serialize + DeserializeOwned>(
         &self,
         claims: JWTClaims<CustomClaims>,
@@ -144,11 +214,64 @@ pub trait EdDSAKeyPairLike {
         let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
             .with_metadata(self.metadata());
         Token::build(&jwt_header, claims, |authenticated| {
-            let noise = ed25519_compact::Noise::generate();
-            let signature = self.key_pair().as_ref().sk.sign(authenticated, Some(noise));
-            Ok(signature.to_vec())
+            self.signature(authenticated)
         })
     }
+
+    /// Sign claims as a CWT token (RFC 8392), using COSE_Sign1
+    #[cfg(feature = "cwt")]
+    fn sign_cwt<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+    ) -> Result<Vec<u8>, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone());
+        CWTToken::build(&jwt_header, claims, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Sign claims, with additional header parameters
+    fn sign_with_header_options<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+        header_options: &HeaderOptions,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata())
+            .with_options(header_options);
+        Token::build(&jwt_header, claims, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Sign a detached payload, that is not included in the token (RFC 7797)
+    fn sign_detached(&self, payload: &str) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata());
+        Token::build_detached(jwt_header, payload, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Sign a detached binary payload, that is not included in the token
+    ///
+    /// The raw bytes of the payload are signed without being encoded
+    /// (RFC 7797), so that it can be any sequence of bytes.
+    fn sign_bytes_detached(&self, payload: &[u8]) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata());
+        Token::build_bytes_detached(jwt_header, payload, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Export the key pair as a JWK, including the private key
+    fn to_jwk(&self) -> Result<JWK, Error> {
+        Ok(self
+            .key_pair()
+            .to_jwk()?
+            .with_usage(self.key_id(), Self::jwt_alg_name(), "sig"))
+    }
 }
 
 pub trait EdDSAPublicKeyLike {
@@ -157,44 +280,60 @@ pub trait EdDSAPublicKeyLike {
     fn key_id(&self) -> &Option<String>;
     fn set_key_id(&mut self, key_id: String);
 
+    /// The SHA-256 thumbprint of the certificate the key was imported from
+    fn certificate_sha256_thumbprint(&self) -> Option<&[u8; 32]> {
+        None
+    }
+
+    /// Verify the signature of `authenticated`
+    fn verify_signature(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        let ed25519_signature = ed25519_compact::Signature::from_slice(signature)?;
+        self.public_key()
+            .as_ref()
+            .verify(authenticated, &ed25519_signature)
+            .map_err(|_| JWTError::InvalidSignature)?;
+        Ok(())
+    }
+
     fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
         &self,
         token: &str,
         options: Option<VerificationOptions>,
     ) -> Result<JWTClaims<CustomClaims>, Error> {
+        check_key_policy(options.as_ref(), KeyParameters::EllipticCurve("Ed25519"))?;
+        check_key_thumbprint(options.as_ref(), || self.thumbprint().ok())?;
+        check_certificate_thumbprint(options.as_ref(), self.certificate_sha256_thumbprint())?;
         Token::verify(
             Self::jwt_alg_name(),
             token,
             options,
-            |authenticated, signature| {
-                let ed25519_signature = ed25519_compact::Signature::from_slice(signature)?;
-                self.public_key()
-                    .as_ref()
-                    .verify(authenticated, &ed25519_signature)
-                    .map_err(|_| JWTError::InvalidSignature)?;
-                Ok(())
-            },
+            |authenticated, signature| self.verify_signature(authenticated, signature),
         )
     }
 
+    fn verify_token_untyped(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<serde_json::Value, Error> {
+        let claims = self.verify_token::<NoCustomClaims>(token, options)?;
+        Ok(serde_json::to_value(claims)?)
+    }
+
     #[cfg(feature = "cwt")]
     fn verify_cwt_token<CustomClaims: Serialize + DeserializeOwned>(
         &self,
         token: &[u8],
         options: Option<VerificationOptions>,
-    ) -> Result<JWTClaims<NoCustomClaims>, Error> {
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        check_key_policy(options.as_ref(), KeyParameters::EllipticCurve("Ed25519"))?;
+        check_key_thumbprint(options.as_ref(), || self.thumbprint().ok())?;
+        check_certificate_thumbprint(options.as_ref(), self.certificate_sha256_thumbprint())?;
         CWTToken::verify(
             Self::jwt_alg_name(),
             token,
             options,
-            |authenticated, signature| {
-                let ed25519_signature = ed25519_compact::Signature::from_slice(signature)?;
-                self.public_key()
-                    .as_ref()
-                    .verify(authenticated, &ed25519_signature)
-                    .map_err(|_| JWTError::InvalidSignature)?;
-                Ok(())
-            },
+            |authenticated, signature| self.verify_signature(authenticated, signature),
         )
     }
 
@@ -207,6 +346,25 @@ pub trait EdDSAPublicKeyLike {
         );
         self.key_id().as_ref().map(|x| x.as_str()).unwrap()
     }
+
+    /// Export the public key as a JWK, for publication in a key set
+    fn to_jwk(&self) -> Result<JWK, Error> {
+        Ok(self
+            .public_key()
+            .to_jwk()?
+            .with_usage(self.key_id(), Self::jwt_alg_name(), "sig"))
+    }
+
+    /// Compute the RFC 7638 thumbprint of the public key, using SHA-256
+    fn thumbprint(&self) -> Result<String, Error> {
+        self.to_jwk()?.thumbprint()
+    }
+
+    /// Compute the RFC 7638 thumbprint of the public key, using SHA-1, for
+    /// legacy applications
+    fn thumbprint_sha1(&self) -> Result<String, Error> {
+        self.to_jwk()?.thumbprint_sha1()
+    }
 }
 
 #[derive(Clone)]
@@ -219,6 +377,7 @@ pub struct Ed25519KeyPair {
 pub struct Ed25519PublicKey {
     pk: Edwards25519PublicKey,
     key_id: Option<String>,
+    certificate_sha256_thumbprint: Option<[u8; 32]>,
 }
 
 impl EdDSAKeyPairLike for Ed25519KeyPair {
@@ -266,6 +425,38 @@ impl Ed25519KeyPair {
         })
     }
 
+    /// Import a password-protected key pair, encoded as an encrypted PKCS#8
+    /// or as a legacy encrypted PEM document
+    pub fn from_encrypted_pem(pem: &str, password: impl AsRef<[u8]>) -> Result<Self, Error> {
+        Self::from_pem(&decrypt_pem(pem, password.as_ref())?)
+    }
+
+    /// Import a key pair from an unencrypted OpenSSH private key
+    pub fn from_openssh(pem: &str) -> Result<Self, Error> {
+        Ok(Ed25519KeyPair {
+            key_pair: Edwards25519KeyPair::from_openssh(pem, None)?,
+            key_id: None,
+        })
+    }
+
+    /// Import a key pair from an OpenSSH private key protected with a
+    /// passphrase
+    pub fn from_encrypted_openssh(pem: &str, passphrase: impl AsRef<[u8]>) -> Result<Self, Error> {
+        Ok(Ed25519KeyPair {
+            key_pair: Edwards25519KeyPair::from_openssh(pem, Some(passphrase.as_ref()))?,
+            key_id: None,
+        })
+    }
+
+    /// Import a key pair from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["sign"])?;
+        Ok(Ed25519KeyPair {
+            key_pair: Edwards25519KeyPair::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+        })
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         self.key_pair.to_bytes()
     }
@@ -282,6 +473,7 @@ impl Ed25519KeyPair {
         Ed25519PublicKey {
             pk: self.key_pair.public_key(),
             key_id: self.key_id.clone(),
+            certificate_sha256_thumbprint: None,
         }
     }
 
@@ -292,6 +484,15 @@ impl Ed25519KeyPair {
         }
     }
 
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        Ed25519KeyPair {
+            key_pair: Edwards25519KeyPair::generate_with_rng(rng),
+            key_id: None,
+        }
+    }
+
     pub fn with_key_id(mut self, key_id: &str) -> Self {
         self.key_id = Some(key_id.to_string());
         self
@@ -314,6 +515,36 @@ impl EdDSAPublicKeyLike for Ed25519PublicKey {
     fn set_key_id(&mut self, key_id: String) {
         self.key_id = Some(key_id);
     }
+
+    fn certificate_sha256_thumbprint(&self) -> Option<&[u8; 32]> {
+        self.certificate_sha256_thumbprint.as_ref()
+    }
+}
+
+impl KeyLike for Ed25519PublicKey {
+    fn key_algorithm(&self) -> &'static str {
+        Self::jwt_alg_name()
+    }
+
+    fn key_identifier(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    fn verify_authenticated(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        self.verify_signature(authenticated, signature)
+    }
+
+    fn key_parameters(&self) -> KeyParameters {
+        KeyParameters::EllipticCurve("Ed25519")
+    }
+
+    fn key_thumbprint(&self) -> Option<String> {
+        self.thumbprint().ok()
+    }
+
+    fn key_certificate_thumbprint(&self) -> Option<&[u8; 32]> {
+        self.certificate_sha256_thumbprint.as_ref()
+    }
 }
 
 impl Ed25519PublicKey {
@@ -321,6 +552,7 @@ impl Ed25519PublicKey {
         Ok(Ed25519PublicKey {
             pk: Edwards25519PublicKey::from_bytes(raw)?,
             key_id: None,
+            certificate_sha256_thumbprint: None,
         })
     }
 
@@ -328,6 +560,7 @@ impl Ed25519PublicKey {
         Ok(Ed25519PublicKey {
             pk: Edwards25519PublicKey::from_der(der)?,
             key_id: None,
+            certificate_sha256_thumbprint: None,
         })
     }
 
@@ -335,6 +568,40 @@ impl Ed25519PublicKey {
         Ok(Ed25519PublicKey {
             pk: Edwards25519PublicKey::from_pem(pem)?,
             key_id: None,
+            certificate_sha256_thumbprint: None,
+        })
+    }
+
+    /// Import the public key of a DER-encoded X.509 certificate
+    pub fn from_x509_der(der: &[u8]) -> Result<Self, Error> {
+        let mut public_key = Self::from_der(x509::certificate_public_key(der)?)?;
+        public_key.certificate_sha256_thumbprint = Some(hmac_sha256::Hash::hash(der));
+        Ok(public_key)
+    }
+
+    /// Import the public key of the first certificate of a PEM-encoded X.509
+    /// certificate chain
+    pub fn from_x509_pem(pem: &str) -> Result<Self, Error> {
+        Self::from_x509_der(&x509::certificate_from_pem(pem)?)
+    }
+
+    /// Import a public key from a line of an OpenSSH `.pub` or
+    /// `authorized_keys` file
+    pub fn from_openssh(line: &str) -> Result<Self, Error> {
+        Ok(Ed25519PublicKey {
+            pk: Edwards25519PublicKey::from_openssh(line)?,
+            key_id: None,
+            certificate_sha256_thumbprint: None,
+        })
+    }
+
+    /// Import a public key from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["verify"])?;
+        Ok(Ed25519PublicKey {
+            pk: Edwards25519PublicKey::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+            certificate_sha256_thumbprint: None,
         })
     }
 
@@ -1,17 +1,25 @@
 use std::convert::TryFrom;
 
 use ct_codecs::{Base64UrlSafeNoPadding, Encoder};
-use k256::ecdsa::{self, signature::DigestVerifier as _, signature::RandomizedDigestSigner as _};
+use k256::ecdsa::{
+    self, signature::DigestSigner as _, signature::DigestVerifier as _,
+    signature::RandomizedDigestSigner as _,
+};
 use k256::pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePrivateKey, EncodePublicKey};
+use rand::{CryptoRng, RngCore};
 use serde::{de::DeserializeOwned, Serialize};
+use zeroize::Zeroizing;
 
 use crate::claims::*;
 use crate::common::*;
 #[cfg(feature = "cwt")]
 use crate::cwt_token::*;
+use crate::encrypted_pem::decrypt_pem;
 use crate::error::*;
+use crate::jwk::*;
 use crate::jwt_header::*;
 use crate::token::*;
+use crate::x509;
 
 #[doc(hidden)]
 #[derive(Debug, Clone)]
@@ -42,10 +50,18 @@ impl K256PublicKey {
         Ok(K256PublicKey(k256_pk))
     }
 
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        Self::from_bytes(&jwk.elliptic_curve_point("secp256k1", 32)?)
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         self.0.to_encoded_point(false).as_bytes().to_vec()
     }
 
+    pub fn to_bytes_compressed(&self) -> Vec<u8> {
+        self.0.to_encoded_point(true).as_bytes().to_vec()
+    }
+
     pub fn to_bytes_uncompressed(&self) -> Vec<u8> {
         self.0.to_encoded_point(false).as_bytes().to_vec()
     }
@@ -65,12 +81,17 @@ impl K256PublicKey {
             .to_public_key_pem(Default::default())
             .map_err(|_| JWTError::InvalidPublicKey)?)
     }
+
+    pub fn to_jwk(&self) -> Result<JWK, Error> {
+        JWK::new_elliptic_curve("secp256k1", &self.to_bytes_uncompressed())
+    }
 }
 
 #[doc(hidden)]
 pub struct K256KeyPair {
     k256_sk: ecdsa::SigningKey,
     metadata: Option<KeyMetadata>,
+    deterministic: bool,
 }
 
 impl AsRef<ecdsa::SigningKey> for K256KeyPair {
@@ -81,10 +102,11 @@ impl AsRef<ecdsa::SigningKey> for K256KeyPair {
 
 impl K256KeyPair {
     pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
-        let k256_sk = ecdsa::SigningKey::from_bytes(raw).map_err(|_| JWTError::InvalidKeyPair)?;
+        let k256_sk = ecdsa::SigningKey::from_slice(raw).map_err(|_| JWTError::InvalidKeyPair)?;
         Ok(K256KeyPair {
             k256_sk,
             metadata: None,
+            deterministic: false,
         })
     }
 
@@ -94,6 +116,7 @@ impl K256KeyPair {
         Ok(K256KeyPair {
             k256_sk,
             metadata: None,
+            deterministic: false,
         })
     }
 
@@ -103,9 +126,16 @@ impl K256KeyPair {
         Ok(K256KeyPair {
             k256_sk,
             metadata: None,
+            deterministic: false,
         })
     }
 
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        let key_pair = Self::from_bytes(&jwk.private_key()?)?;
+        jwk.check_public_key(&key_pair.public_key().to_jwk()?)?;
+        Ok(key_pair)
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         self.k256_sk.to_bytes().to_vec()
     }
@@ -127,17 +157,27 @@ impl K256KeyPair {
             .to_string())
     }
 
+    pub fn to_jwk(&self) -> Result<JWK, Error> {
+        self.public_key()
+            .to_jwk()?
+            .with_private_key(&Zeroizing::new(self.to_bytes()))
+    }
+
     pub fn public_key(&self) -> K256PublicKey {
         let k256_pk = self.k256_sk.verifying_key();
         K256PublicKey(*k256_pk)
     }
 
     pub fn generate() -> Self {
-        let mut rng = rand::thread_rng();
-        let k256_sk = ecdsa::SigningKey::random(&mut rng);
+        Self::generate_with_rng(&mut rand::thread_rng())
+    }
+
+    pub fn generate_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        let k256_sk = ecdsa::SigningKey::random(rng);
         K256KeyPair {
             k256_sk,
             metadata: None,
+            deterministic: false,
         }
     }
 }
@@ -149,6 +189,20 @@ pub trait ECDSAP256kKeyPairLike {
     fn metadata(&self) -> &Option<KeyMetadata>;
     fn attach_metadata(&mut self, metadata: KeyMetadata) -> Result<(), Error>;
 
+    /// Compute the signature of `authenticated`
+    fn signature(&self, authenticated: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut digest = hmac_sha256::Hash::new();
+        digest.update(authenticated);
+        let key_pair = self.key_pair();
+        let signature: ecdsa::Signature = if key_pair.deterministic {
+            key_pair.as_ref().sign_digest(digest)
+        } else {
+            let mut rng = rand::thread_rng();
+            key_pair.as_ref().sign_digest_with_rng(&mut rng, digest)
+        };
+        Ok(signature.to_vec())
+    }
+
     fn sign<CustomClaims: Serialize + DeserializeOwned>(
         &self,
         claims: JWTClaims<CustomClaims>,
@@ -156,16 +210,52 @@ pub trait ECDSAP256kKeyPairLike {
         let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
             .with_metadata(self.metadata());
         Token::build(&jwt_header, claims, |authenticated| {
-            let mut digest = hmac_sha256::Hash::new();
-            digest.update(authenticated.as_bytes());
-            let mut rng = rand::thread_rng();
-            let signature: ecdsa::Signature = self
-                .key_pair()
-                .as_ref()
-                .sign_digest_with_rng(&mut rng, digest);
-            Ok(signature.to_vec())
+            self.signature(authenticated)
+        })
+    }
+
+    /// Sign claims, with additional header parameters
+    fn sign_with_header_options<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+        header_options: &HeaderOptions,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata())
+            .with_options(header_options);
+        Token::build(&jwt_header, claims, |authenticated| {
+            self.signature(authenticated)
         })
     }
+
+    /// Sign a detached payload, that is not included in the token (RFC 7797)
+    fn sign_detached(&self, payload: &str) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata());
+        Token::build_detached(jwt_header, payload, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Sign a detached binary payload, that is not included in the token
+    ///
+    /// The raw bytes of the payload are signed without being encoded
+    /// (RFC 7797), so that it can be any sequence of bytes.
+    fn sign_bytes_detached(&self, payload: &[u8]) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata());
+        Token::build_bytes_detached(jwt_header, payload, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Export the key pair as a JWK, including the private key
+    fn to_jwk(&self) -> Result<JWK, Error> {
+        Ok(self
+            .key_pair()
+            .to_jwk()?
+            .with_usage(self.key_id(), Self::jwt_alg_name(), "sig"))
+    }
 }
 
 pub trait ECDSAP256kPublicKeyLike {
@@ -174,50 +264,63 @@ pub trait ECDSAP256kPublicKeyLike {
     fn key_id(&self) -> &Option<String>;
     fn set_key_id(&mut self, key_id: String);
 
+    /// The SHA-256 thumbprint of the certificate the key was imported from
+    fn certificate_sha256_thumbprint(&self) -> Option<&[u8; 32]> {
+        None
+    }
+
+    /// Verify the signature of `authenticated`
+    fn verify_signature(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        let ecdsa_signature =
+            ecdsa::Signature::try_from(signature).map_err(|_| JWTError::InvalidSignature)?;
+        let mut digest = hmac_sha256::Hash::new();
+        digest.update(authenticated);
+        self.public_key()
+            .as_ref()
+            .verify_digest(digest, &ecdsa_signature)
+            .map_err(|_| JWTError::InvalidSignature)?;
+        Ok(())
+    }
+
     fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
         &self,
         token: &str,
         options: Option<VerificationOptions>,
     ) -> Result<JWTClaims<CustomClaims>, Error> {
+        check_key_policy(options.as_ref(), KeyParameters::EllipticCurve("secp256k1"))?;
+        check_key_thumbprint(options.as_ref(), || self.thumbprint().ok())?;
+        check_certificate_thumbprint(options.as_ref(), self.certificate_sha256_thumbprint())?;
         Token::verify(
             Self::jwt_alg_name(),
             token,
             options,
-            |authenticated, signature| {
-                let ecdsa_signature = ecdsa::Signature::try_from(signature)
-                    .map_err(|_| JWTError::InvalidSignature)?;
-                let mut digest = hmac_sha256::Hash::new();
-                digest.update(authenticated.as_bytes());
-                self.public_key()
-                    .as_ref()
-                    .verify_digest(digest, &ecdsa_signature)
-                    .map_err(|_| JWTError::InvalidSignature)?;
-                Ok(())
-            },
+            |authenticated, signature| self.verify_signature(authenticated, signature),
         )
     }
 
+    fn verify_token_untyped(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<serde_json::Value, Error> {
+        let claims = self.verify_token::<NoCustomClaims>(token, options)?;
+        Ok(serde_json::to_value(claims)?)
+    }
+
     #[cfg(feature = "cwt")]
     fn verify_cwt_token<CustomClaims: Serialize + DeserializeOwned>(
         &self,
         token: &[u8],
         options: Option<VerificationOptions>,
-    ) -> Result<JWTClaims<NoCustomClaims>, Error> {
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        check_key_policy(options.as_ref(), KeyParameters::EllipticCurve("secp256k1"))?;
+        check_key_thumbprint(options.as_ref(), || self.thumbprint().ok())?;
+        check_certificate_thumbprint(options.as_ref(), self.certificate_sha256_thumbprint())?;
         CWTToken::verify(
             Self::jwt_alg_name(),
             token,
             options,
-            |authenticated, signature| {
-                let ecdsa_signature = ecdsa::Signature::try_from(signature)
-                    .map_err(|_| JWTError::InvalidSignature)?;
-                let mut digest = hmac_sha256::Hash::new();
-                digest.update(authenticated.as_bytes());
-                self.public_key()
-                    .as_ref()
-                    .verify_digest(digest, &ecdsa_signature)
-                    .map_err(|_| JWTError::InvalidSignature)?;
-                Ok(())
-            },
+            |authenticated, signature| self.verify_signature(authenticated, signature),
         )
     }
 
@@ -230,6 +333,25 @@ pub trait ECDSAP256kPublicKeyLike {
         );
         self.key_id().as_ref().map(|x| x.as_str()).unwrap()
     }
+
+    /// Export the public key as a JWK, for publication in a key set
+    fn to_jwk(&self) -> Result<JWK, Error> {
+        Ok(self
+            .public_key()
+            .to_jwk()?
+            .with_usage(self.key_id(), Self::jwt_alg_name(), "sig"))
+    }
+
+    /// Compute the RFC 7638 thumbprint of the public key, using SHA-256
+    fn thumbprint(&self) -> Result<String, Error> {
+        self.to_jwk()?.thumbprint()
+    }
+
+    /// Compute the RFC 7638 thumbprint of the public key, using SHA-1, for
+    /// legacy applications
+    fn thumbprint_sha1(&self) -> Result<String, Error> {
+        self.to_jwk()?.thumbprint_sha1()
+    }
 }
 
 pub struct ES256kKeyPair {
@@ -241,6 +363,7 @@ pub struct ES256kKeyPair {
 pub struct ES256kPublicKey {
     pk: K256PublicKey,
     key_id: Option<String>,
+    certificate_sha256_thumbprint: Option<[u8; 32]>,
 }
 
 impl ECDSAP256kKeyPairLike for ES256kKeyPair {
@@ -288,6 +411,21 @@ impl ES256kKeyPair {
         })
     }
 
+    /// Import a password-protected key pair, encoded as an encrypted PKCS#8
+    /// or as a legacy encrypted PEM document
+    pub fn from_encrypted_pem(pem: &str, password: impl AsRef<[u8]>) -> Result<Self, Error> {
+        Self::from_pem(&decrypt_pem(pem, password.as_ref())?)
+    }
+
+    /// Import a key pair from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["sign"])?;
+        Ok(ES256kKeyPair {
+            key_pair: K256KeyPair::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+        })
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         self.key_pair.to_bytes()
     }
@@ -304,6 +442,7 @@ impl ES256kKeyPair {
         ES256kPublicKey {
             pk: self.key_pair.public_key(),
             key_id: self.key_id.clone(),
+            certificate_sha256_thumbprint: None,
         }
     }
 
@@ -314,10 +453,26 @@ impl ES256kKeyPair {
         }
     }
 
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        ES256kKeyPair {
+            key_pair: K256KeyPair::generate_with_rng(rng),
+            key_id: None,
+        }
+    }
+
     pub fn with_key_id(mut self, key_id: &str) -> Self {
         self.key_id = Some(key_id.to_string());
         self
     }
+
+    /// Derive signature nonces deterministically (RFC 6979) instead of
+    /// mixing in randomness. Verification is unaffected.
+    pub fn with_deterministic_signatures(mut self) -> Self {
+        self.key_pair.deterministic = true;
+        self
+    }
 }
 
 impl ECDSAP256kPublicKeyLike for ES256kPublicKey {
@@ -336,13 +491,45 @@ impl ECDSAP256kPublicKeyLike for ES256kPublicKey {
     fn set_key_id(&mut self, key_id: String) {
         self.key_id = Some(key_id);
     }
+
+    fn certificate_sha256_thumbprint(&self) -> Option<&[u8; 32]> {
+        self.certificate_sha256_thumbprint.as_ref()
+    }
+}
+
+impl KeyLike for ES256kPublicKey {
+    fn key_algorithm(&self) -> &'static str {
+        Self::jwt_alg_name()
+    }
+
+    fn key_identifier(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    fn verify_authenticated(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        self.verify_signature(authenticated, signature)
+    }
+
+    fn key_parameters(&self) -> KeyParameters {
+        KeyParameters::EllipticCurve("secp256k1")
+    }
+
+    fn key_thumbprint(&self) -> Option<String> {
+        self.thumbprint().ok()
+    }
+
+    fn key_certificate_thumbprint(&self) -> Option<&[u8; 32]> {
+        self.certificate_sha256_thumbprint.as_ref()
+    }
 }
 
 impl ES256kPublicKey {
+    /// Import a public key from a compressed or uncompressed SEC1 point
     pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
         Ok(ES256kPublicKey {
             pk: K256PublicKey::from_bytes(raw)?,
             key_id: None,
+            certificate_sha256_thumbprint: None,
         })
     }
 
@@ -350,6 +537,7 @@ impl ES256kPublicKey {
         Ok(ES256kPublicKey {
             pk: K256PublicKey::from_der(der)?,
             key_id: None,
+            certificate_sha256_thumbprint: None,
         })
     }
 
@@ -357,6 +545,30 @@ impl ES256kPublicKey {
         Ok(ES256kPublicKey {
             pk: K256PublicKey::from_pem(pem)?,
             key_id: None,
+            certificate_sha256_thumbprint: None,
+        })
+    }
+
+    /// Import the public key of a DER-encoded X.509 certificate
+    pub fn from_x509_der(der: &[u8]) -> Result<Self, Error> {
+        let mut public_key = Self::from_der(x509::certificate_public_key(der)?)?;
+        public_key.certificate_sha256_thumbprint = Some(hmac_sha256::Hash::hash(der));
+        Ok(public_key)
+    }
+
+    /// Import the public key of the first certificate of a PEM-encoded X.509
+    /// certificate chain
+    pub fn from_x509_pem(pem: &str) -> Result<Self, Error> {
+        Self::from_x509_der(&x509::certificate_from_pem(pem)?)
+    }
+
+    /// Import a public key from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["verify"])?;
+        Ok(ES256kPublicKey {
+            pk: K256PublicKey::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+            certificate_sha256_thumbprint: None,
         })
     }
 
@@ -364,6 +576,16 @@ impl ES256kPublicKey {
         self.pk.to_bytes()
     }
 
+    /// Export the public key as a compressed SEC1 point
+    pub fn to_bytes_compressed(&self) -> Vec<u8> {
+        self.pk.to_bytes_compressed()
+    }
+
+    /// Export the public key as an uncompressed SEC1 point
+    pub fn to_bytes_uncompressed(&self) -> Vec<u8> {
+        self.pk.to_bytes_uncompressed()
+    }
+
     pub fn to_der(&self) -> Result<Vec<u8>, Error> {
         self.pk.to_der()
     }
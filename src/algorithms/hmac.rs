@@ -1,7 +1,7 @@
 use ct_codecs::{Base64UrlSafeNoPadding, Encoder};
 use hmac_sha512::sha384 as hmac_sha384;
-use rand::RngCore;
-use serde::{de::DeserializeOwned, Serialize};
+use rand::{CryptoRng, RngCore};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use zeroize::Zeroize;
 
 use crate::claims::*;
@@ -9,6 +9,7 @@ use crate::common::*;
 #[cfg(feature = "cwt")]
 use crate::cwt_token::*;
 use crate::error::*;
+use crate::jwk::*;
 use crate::jwt_header::*;
 use crate::token::*;
 
@@ -16,7 +17,7 @@ use crate::token::*;
 #[derive(Debug, Clone)]
 pub struct HMACKey {
     raw_key: Vec<u8>,
-    metadata: Option<KeyMetadata>,
+    pub(crate) metadata: Option<KeyMetadata>,
 }
 
 impl Drop for HMACKey {
@@ -38,8 +39,12 @@ impl HMACKey {
     }
 
     pub fn generate() -> Self {
+        Self::generate_with_rng(&mut rand::thread_rng())
+    }
+
+    pub fn generate_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
         let mut raw_key = vec![0u8; 32];
-        rand::thread_rng().fill_bytes(&mut raw_key);
+        rng.fill_bytes(&mut raw_key);
         HMACKey {
             raw_key,
             metadata: None,
@@ -47,12 +52,163 @@ impl HMACKey {
     }
 }
 
+/// Minimum salt length accepted when deriving a key from a password.
+pub const PASSWORD_KDF_MIN_SALT_LENGTH: usize = 16;
+
+/// Minimum number of PBKDF2 iterations accepted when deriving a key from a password.
+pub const PASSWORD_KDF_MIN_PBKDF2_ITERATIONS: u32 = 100_000;
+
+/// Parameters of the function used to derive an HMAC key from a password.
+///
+/// The parameters are serializable, so that they can be stored along with the salt
+/// and used later to derive the same key again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kdf")]
+#[non_exhaustive]
+pub enum PasswordKDFParams {
+    /// PBKDF2 with HMAC-SHA256, with at least
+    /// `PASSWORD_KDF_MIN_PBKDF2_ITERATIONS` iterations.
+    #[serde(rename = "PBKDF2-SHA256")]
+    PBKDF2SHA256 { iterations: u32 },
+    /// Argon2id (requires the `argon2` feature).
+    #[cfg(feature = "argon2")]
+    #[serde(rename = "Argon2id")]
+    Argon2id {
+        /// Memory cost, in KiB.
+        m_cost: u32,
+        /// Number of passes.
+        t_cost: u32,
+        /// Degree of parallelism.
+        p_cost: u32,
+    },
+}
+
+impl PasswordKDFParams {
+    /// PBKDF2-HMAC-SHA256 with 600,000 iterations.
+    pub fn pbkdf2_sha256() -> Self {
+        PasswordKDFParams::PBKDF2SHA256 {
+            iterations: 600_000,
+        }
+    }
+
+    /// Argon2id with 19 MiB of memory, 2 passes and no parallelism.
+    #[cfg(feature = "argon2")]
+    pub fn argon2id() -> Self {
+        PasswordKDFParams::Argon2id {
+            m_cost: 19 * 1024,
+            t_cost: 2,
+            p_cost: 1,
+        }
+    }
+}
+
+impl Default for PasswordKDFParams {
+    /// Argon2id if the `argon2` feature is enabled, PBKDF2-HMAC-SHA256 otherwise.
+    fn default() -> Self {
+        #[cfg(feature = "argon2")]
+        return Self::argon2id();
+        #[cfg(not(feature = "argon2"))]
+        return Self::pbkdf2_sha256();
+    }
+}
+
+macro_rules! pbkdf2_impl {
+    ($name:ident, $hash:ty, $block_len:expr, $out_len:expr) => {
+        /// PBKDF2 (RFC 8018), with the inner and outer HMAC states computed once
+        pub(crate) fn $name(password: &[u8], salt: &[u8], iterations: u32, out: &mut [u8]) {
+            let mut padded = [0u8; $block_len];
+            if password.len() > $block_len {
+                padded[..$out_len].copy_from_slice(&<$hash>::hash(password));
+            } else {
+                padded[..password.len()].copy_from_slice(password);
+            }
+            let mut ih = <$hash>::new();
+            ih.update(padded.map(|x| x ^ 0x36));
+            let mut oh = <$hash>::new();
+            oh.update(padded.map(|x| x ^ 0x5c));
+            padded.zeroize();
+            let prf = |data: &[&[u8]]| {
+                let mut h = ih;
+                data.iter().for_each(|x| h.update(x));
+                let mut o = oh;
+                o.update(h.finalize());
+                o.finalize()
+            };
+            for (i, block) in out.chunks_mut($out_len).enumerate() {
+                let mut u = prf(&[salt, &(i as u32 + 1).to_be_bytes()]);
+                let mut t = u;
+                for _ in 1..iterations {
+                    u = prf(&[&u]);
+                    t.iter_mut().zip(u.iter()).for_each(|(t, u)| *t ^= u);
+                }
+                block.copy_from_slice(&t[..block.len()]);
+                t.zeroize();
+                u.zeroize();
+            }
+        }
+    };
+}
+
+pbkdf2_impl!(pbkdf2_sha1, hmac_sha1_compact::Hash, 64, 20);
+pbkdf2_impl!(pbkdf2_sha256, hmac_sha256::Hash, 64, 32);
+pbkdf2_impl!(pbkdf2_sha384, hmac_sha384::Hash, 128, 48);
+pbkdf2_impl!(pbkdf2_sha512, hmac_sha512::Hash, 128, 64);
+
+impl HMACKey {
+    pub fn derive_from_password(
+        password: &[u8],
+        salt: &[u8],
+        params: &PasswordKDFParams,
+        key_len: usize,
+    ) -> Result<Self, Error> {
+        ensure!(
+            salt.len() >= PASSWORD_KDF_MIN_SALT_LENGTH,
+            JWTError::InvalidPasswordKDFParameters
+        );
+        let mut raw_key = vec![0u8; key_len];
+        match *params {
+            PasswordKDFParams::PBKDF2SHA256 { iterations } => {
+                ensure!(
+                    iterations >= PASSWORD_KDF_MIN_PBKDF2_ITERATIONS,
+                    JWTError::InvalidPasswordKDFParameters
+                );
+                pbkdf2_sha256(password, salt, iterations, &mut raw_key);
+            }
+            #[cfg(feature = "argon2")]
+            PasswordKDFParams::Argon2id {
+                m_cost,
+                t_cost,
+                p_cost,
+            } => {
+                let argon2_params = argon2::Params::new(m_cost, t_cost, p_cost, Some(key_len))
+                    .map_err(|_| JWTError::InvalidPasswordKDFParameters)?;
+                argon2::Argon2::new(
+                    argon2::Algorithm::Argon2id,
+                    argon2::Version::V0x13,
+                    argon2_params,
+                )
+                .hash_password_into(password, salt, &mut raw_key)
+                .map_err(|e| JWTError::InternalError(e.to_string()))?;
+            }
+        }
+        Ok(HMACKey {
+            raw_key,
+            metadata: None,
+        })
+    }
+}
+
 impl AsRef<[u8]> for HMACKey {
     fn as_ref(&self) -> &[u8] {
         &self.raw_key
     }
 }
 
+/// Length of key identifiers derived from HMAC keys, in bytes.
+pub const HMAC_KEY_ID_LENGTH: usize = 16;
+
+const HMAC_KEY_ID_CONTEXT: &[u8] = b"jwt-simple HMAC key identifier\0";
+
 pub trait MACLike {
     fn jwt_alg_name() -> &'static str;
     fn key(&self) -> &HMACKey;
@@ -60,7 +216,7 @@ pub trait MACLike {
     fn set_key_id(&mut self, key_id: String);
     fn metadata(&self) -> &Option<KeyMetadata>;
     fn attach_metadata(&mut self, metadata: KeyMetadata) -> Result<(), Error>;
-    fn authentication_tag(&self, authenticated: &str) -> Vec<u8>;
+    fn authentication_tag(&self, authenticated: &[u8]) -> Vec<u8>;
 
     fn authenticate<CustomClaims: Serialize + DeserializeOwned>(
         &self,
@@ -73,54 +229,142 @@ pub trait MACLike {
         })
     }
 
+    /// Authenticate claims, with additional header parameters
+    fn authenticate_with_header_options<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+        header_options: &HeaderOptions,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata())
+            .with_options(header_options);
+        Token::build(&jwt_header, claims, |authenticated| {
+            Ok(self.authentication_tag(authenticated))
+        })
+    }
+
+    /// Authenticate a detached payload, that is not included in the token
+    /// (RFC 7797)
+    fn authenticate_detached(&self, payload: &str) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata());
+        Token::build_detached(jwt_header, payload, |authenticated| {
+            Ok(self.authentication_tag(authenticated))
+        })
+    }
+
+    /// Authenticate a detached binary payload, that is not included in the
+    /// token
+    ///
+    /// The raw bytes of the payload are authenticated without being encoded
+    /// (RFC 7797), so that it can be any sequence of bytes.
+    fn authenticate_bytes_detached(&self, payload: &[u8]) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata());
+        Token::build_bytes_detached(jwt_header, payload, |authenticated| {
+            Ok(self.authentication_tag(authenticated))
+        })
+    }
+
+    /// Verify the authentication tag of `authenticated`
+    fn verify_authentication_tag(
+        &self,
+        authenticated: &[u8],
+        authentication_tag: &[u8],
+    ) -> Result<(), Error> {
+        ensure!(
+            timingsafe_eq(&self.authentication_tag(authenticated), authentication_tag),
+            JWTError::InvalidAuthenticationTag
+        );
+        Ok(())
+    }
+
     fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
         &self,
         token: &str,
         options: Option<VerificationOptions>,
     ) -> Result<JWTClaims<CustomClaims>, Error> {
+        check_key_policy(
+            options.as_ref(),
+            KeyParameters::MAC {
+                key_length: self.key().as_ref().len(),
+            },
+        )?;
+        check_key_thumbprint(options.as_ref(), || None)?;
+        check_certificate_thumbprint(options.as_ref(), None)?;
         Token::verify(
             Self::jwt_alg_name(),
             token,
             options,
             |authenticated, authentication_tag| {
-                ensure!(
-                    timingsafe_eq(&self.authentication_tag(authenticated), authentication_tag),
-                    JWTError::InvalidAuthenticationTag
-                );
-                Ok(())
+                self.verify_authentication_tag(authenticated, authentication_tag)
             },
         )
     }
 
+    fn verify_token_untyped(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<serde_json::Value, Error> {
+        let claims = self.verify_token::<NoCustomClaims>(token, options)?;
+        Ok(serde_json::to_value(claims)?)
+    }
+
     #[cfg(feature = "cwt")]
     fn verify_cwt_token(
         &self,
         token: impl AsRef<[u8]>,
         options: Option<VerificationOptions>,
     ) -> Result<JWTClaims<NoCustomClaims>, Error> {
+        check_key_policy(
+            options.as_ref(),
+            KeyParameters::MAC {
+                key_length: self.key().as_ref().len(),
+            },
+        )?;
+        check_key_thumbprint(options.as_ref(), || None)?;
+        check_certificate_thumbprint(options.as_ref(), None)?;
         CWTToken::verify(
             Self::jwt_alg_name(),
             token,
             options,
             |authenticated, authentication_tag| {
-                ensure!(
-                    timingsafe_eq(&self.authentication_tag(authenticated), authentication_tag),
-                    JWTError::InvalidAuthenticationTag
-                );
-                Ok(())
+                self.verify_authentication_tag(authenticated, authentication_tag)
             },
         )
     }
 
+    /// Compute a deterministic identifier for the key.
+    ///
+    /// The identifier is a truncated hash of the algorithm and the key, so
+    /// that keys can be safely looked up by identifier during rotation
+    /// without revealing the keys themselves.
+    fn derived_key_id(&self) -> String {
+        let mut h = hmac_sha256::Hash::new();
+        h.update(HMAC_KEY_ID_CONTEXT);
+        h.update(Self::jwt_alg_name());
+        h.update([0u8]);
+        h.update(self.key().as_ref());
+        let hash = h.finalize();
+        Base64UrlSafeNoPadding::encode_to_string(&hash[..HMAC_KEY_ID_LENGTH]).unwrap()
+    }
+
+    /// Attach the identifier computed by `derived_key_id()` to the key, and
+    /// return it.
     fn create_key_id(&mut self) -> &str {
-        self.set_key_id(
-            Base64UrlSafeNoPadding::encode_to_string(hmac_sha256::Hash::hash(
-                &self.key().to_bytes(),
-            ))
-            .unwrap(),
-        );
+        self.set_key_id(self.derived_key_id());
         self.key_id().as_ref().map(|x| x.as_str()).unwrap()
     }
+
+    /// Export the key as a JWK
+    fn to_jwk(&self) -> Result<JWK, Error> {
+        Ok(JWK::new_octet_sequence(self.key().as_ref())?.with_usage(
+            self.key_id(),
+            Self::jwt_alg_name(),
+            "sig",
+        ))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -155,8 +399,28 @@ impl MACLike for HS256Key {
         Ok(())
     }
 
-    fn authentication_tag(&self, authenticated: &str) -> Vec<u8> {
-        hmac_sha256::HMAC::mac(authenticated.as_bytes(), self.key().as_ref()).to_vec()
+    fn authentication_tag(&self, authenticated: &[u8]) -> Vec<u8> {
+        hmac_sha256::HMAC::mac(authenticated, self.key().as_ref()).to_vec()
+    }
+}
+
+impl KeyLike for HS256Key {
+    fn key_algorithm(&self) -> &'static str {
+        Self::jwt_alg_name()
+    }
+
+    fn key_identifier(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    fn verify_authenticated(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        self.verify_authentication_tag(authenticated, signature)
+    }
+
+    fn key_parameters(&self) -> KeyParameters {
+        KeyParameters::MAC {
+            key_length: self.key().as_ref().len(),
+        }
     }
 }
 
@@ -168,6 +432,14 @@ impl HS256Key {
         }
     }
 
+    /// Import a key from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["sign", "verify"])?;
+        let mut key = Self::from_bytes(&jwk.octet_sequence()?);
+        key.key_id = jwk.key_id.clone();
+        Ok(key)
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         self.key.to_bytes()
     }
@@ -179,10 +451,40 @@ impl HS256Key {
         }
     }
 
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        HS256Key {
+            key: HMACKey::generate_with_rng(rng),
+            key_id: None,
+        }
+    }
+
+    /// Derive a key from a password, using a password hashing function.
+    ///
+    /// The salt should be unique and at least `PASSWORD_KDF_MIN_SALT_LENGTH` bytes long.
+    /// The same password, salt and parameters always produce the same key.
+    pub fn derive_from_password(
+        password: impl AsRef<[u8]>,
+        salt: impl AsRef<[u8]>,
+        params: &PasswordKDFParams,
+    ) -> Result<Self, Error> {
+        Ok(HS256Key {
+            key: HMACKey::derive_from_password(password.as_ref(), salt.as_ref(), params, 32)?,
+            key_id: None,
+        })
+    }
+
     pub fn with_key_id(mut self, key_id: &str) -> Self {
         self.key_id = Some(key_id.to_string());
         self
     }
+
+    /// Attach the identifier computed by `derived_key_id()` to the key
+    pub fn with_derived_key_id(mut self) -> Self {
+        self.key_id = Some(self.derived_key_id());
+        self
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -217,8 +519,28 @@ impl MACLike for HS512Key {
         Ok(())
     }
 
-    fn authentication_tag(&self, authenticated: &str) -> Vec<u8> {
-        hmac_sha512::HMAC::mac(authenticated.as_bytes(), self.key().as_ref()).to_vec()
+    fn authentication_tag(&self, authenticated: &[u8]) -> Vec<u8> {
+        hmac_sha512::HMAC::mac(authenticated, self.key().as_ref()).to_vec()
+    }
+}
+
+impl KeyLike for HS512Key {
+    fn key_algorithm(&self) -> &'static str {
+        Self::jwt_alg_name()
+    }
+
+    fn key_identifier(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    fn verify_authenticated(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        self.verify_authentication_tag(authenticated, signature)
+    }
+
+    fn key_parameters(&self) -> KeyParameters {
+        KeyParameters::MAC {
+            key_length: self.key().as_ref().len(),
+        }
     }
 }
 
@@ -230,6 +552,14 @@ impl HS512Key {
         }
     }
 
+    /// Import a key from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["sign", "verify"])?;
+        let mut key = Self::from_bytes(&jwk.octet_sequence()?);
+        key.key_id = jwk.key_id.clone();
+        Ok(key)
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         self.key.to_bytes()
     }
@@ -241,10 +571,40 @@ impl HS512Key {
         }
     }
 
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        HS512Key {
+            key: HMACKey::generate_with_rng(rng),
+            key_id: None,
+        }
+    }
+
+    /// Derive a key from a password, using a password hashing function.
+    ///
+    /// The salt should be unique and at least `PASSWORD_KDF_MIN_SALT_LENGTH` bytes long.
+    /// The same password, salt and parameters always produce the same key.
+    pub fn derive_from_password(
+        password: impl AsRef<[u8]>,
+        salt: impl AsRef<[u8]>,
+        params: &PasswordKDFParams,
+    ) -> Result<Self, Error> {
+        Ok(HS512Key {
+            key: HMACKey::derive_from_password(password.as_ref(), salt.as_ref(), params, 64)?,
+            key_id: None,
+        })
+    }
+
     pub fn with_key_id(mut self, key_id: &str) -> Self {
         self.key_id = Some(key_id.to_string());
         self
     }
+
+    /// Attach the identifier computed by `derived_key_id()` to the key
+    pub fn with_derived_key_id(mut self) -> Self {
+        self.key_id = Some(self.derived_key_id());
+        self
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -279,8 +639,28 @@ impl MACLike for HS384Key {
         Ok(())
     }
 
-    fn authentication_tag(&self, authenticated: &str) -> Vec<u8> {
-        hmac_sha384::HMAC::mac(authenticated.as_bytes(), self.key().as_ref()).to_vec()
+    fn authentication_tag(&self, authenticated: &[u8]) -> Vec<u8> {
+        hmac_sha384::HMAC::mac(authenticated, self.key().as_ref()).to_vec()
+    }
+}
+
+impl KeyLike for HS384Key {
+    fn key_algorithm(&self) -> &'static str {
+        Self::jwt_alg_name()
+    }
+
+    fn key_identifier(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    fn verify_authenticated(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        self.verify_authentication_tag(authenticated, signature)
+    }
+
+    fn key_parameters(&self) -> KeyParameters {
+        KeyParameters::MAC {
+            key_length: self.key().as_ref().len(),
+        }
     }
 }
 
@@ -292,6 +672,14 @@ impl HS384Key {
         }
     }
 
+    /// Import a key from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["sign", "verify"])?;
+        let mut key = Self::from_bytes(&jwk.octet_sequence()?);
+        key.key_id = jwk.key_id.clone();
+        Ok(key)
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         self.key.to_bytes()
     }
@@ -303,8 +691,38 @@ impl HS384Key {
         }
     }
 
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        HS384Key {
+            key: HMACKey::generate_with_rng(rng),
+            key_id: None,
+        }
+    }
+
+    /// Derive a key from a password, using a password hashing function.
+    ///
+    /// The salt should be unique and at least `PASSWORD_KDF_MIN_SALT_LENGTH` bytes long.
+    /// The same password, salt and parameters always produce the same key.
+    pub fn derive_from_password(
+        password: impl AsRef<[u8]>,
+        salt: impl AsRef<[u8]>,
+        params: &PasswordKDFParams,
+    ) -> Result<Self, Error> {
+        Ok(HS384Key {
+            key: HMACKey::derive_from_password(password.as_ref(), salt.as_ref(), params, 48)?,
+            key_id: None,
+        })
+    }
+
     pub fn with_key_id(mut self, key_id: &str) -> Self {
         self.key_id = Some(key_id.to_string());
         self
     }
+
+    /// Attach the identifier computed by `derived_key_id()` to the key
+    pub fn with_derived_key_id(mut self) -> Self {
+        self.key_id = Some(self.derived_key_id());
+        self
+    }
 }
@@ -0,0 +1,379 @@
+use hmac::{Hmac, Mac};
+use rand::{CryptoRng, RngCore};
+use sha3::{Sha3_256, Sha3_384, Sha3_512};
+
+use crate::common::*;
+use crate::error::*;
+use crate::jwk::*;
+use crate::token::*;
+
+use super::hmac::*;
+
+/// A key for the non-standard `HS256-SHA3` algorithm (HMAC-SHA3-256)
+#[derive(Debug, Clone)]
+pub struct HS256SHA3Key {
+    key: HMACKey,
+    key_id: Option<String>,
+}
+
+impl MACLike for HS256SHA3Key {
+    fn jwt_alg_name() -> &'static str {
+        "HS256-SHA3"
+    }
+
+    fn key(&self) -> &HMACKey {
+        &self.key
+    }
+
+    fn key_id(&self) -> &Option<String> {
+        &self.key_id
+    }
+
+    fn set_key_id(&mut self, key_id: String) {
+        self.key_id = Some(key_id);
+    }
+
+    fn metadata(&self) -> &Option<KeyMetadata> {
+        &self.key.metadata
+    }
+
+    fn attach_metadata(&mut self, metadata: KeyMetadata) -> Result<(), Error> {
+        self.key.metadata = Some(metadata);
+        Ok(())
+    }
+
+    fn authentication_tag(&self, authenticated: &[u8]) -> Vec<u8> {
+        let mut mac = Hmac::<Sha3_256>::new_from_slice(self.key().as_ref()).unwrap();
+        mac.update(authenticated);
+        mac.finalize().into_bytes().to_vec()
+    }
+}
+
+impl KeyLike for HS256SHA3Key {
+    fn key_algorithm(&self) -> &'static str {
+        Self::jwt_alg_name()
+    }
+
+    fn key_identifier(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    fn verify_authenticated(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        self.verify_authentication_tag(authenticated, signature)
+    }
+
+    fn key_parameters(&self) -> KeyParameters {
+        KeyParameters::MAC {
+            key_length: self.key().as_ref().len(),
+        }
+    }
+}
+
+impl HS256SHA3Key {
+    pub fn from_bytes(raw_key: &[u8]) -> Self {
+        HS256SHA3Key {
+            key: HMACKey::from_bytes(raw_key),
+            key_id: None,
+        }
+    }
+
+    /// Import a key from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["sign", "verify"])?;
+        let mut key = Self::from_bytes(&jwk.octet_sequence()?);
+        key.key_id = jwk.key_id.clone();
+        Ok(key)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.key.to_bytes()
+    }
+
+    pub fn generate() -> Self {
+        HS256SHA3Key {
+            key: HMACKey::generate(),
+            key_id: None,
+        }
+    }
+
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        HS256SHA3Key {
+            key: HMACKey::generate_with_rng(rng),
+            key_id: None,
+        }
+    }
+
+    /// Derive a key from a password, using a password hashing function.
+    ///
+    /// The salt should be unique and at least `PASSWORD_KDF_MIN_SALT_LENGTH` bytes long.
+    /// The same password, salt and parameters always produce the same key.
+    pub fn derive_from_password(
+        password: impl AsRef<[u8]>,
+        salt: impl AsRef<[u8]>,
+        params: &PasswordKDFParams,
+    ) -> Result<Self, Error> {
+        Ok(HS256SHA3Key {
+            key: HMACKey::derive_from_password(password.as_ref(), salt.as_ref(), params, 32)?,
+            key_id: None,
+        })
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string());
+        self
+    }
+
+    /// Attach the identifier computed by `derived_key_id()` to the key
+    pub fn with_derived_key_id(mut self) -> Self {
+        self.key_id = Some(self.derived_key_id());
+        self
+    }
+}
+
+/// A key for the non-standard `HS384-SHA3` algorithm (HMAC-SHA3-384)
+#[derive(Debug, Clone)]
+pub struct HS384SHA3Key {
+    key: HMACKey,
+    key_id: Option<String>,
+}
+
+impl MACLike for HS384SHA3Key {
+    fn jwt_alg_name() -> &'static str {
+        "HS384-SHA3"
+    }
+
+    fn key(&self) -> &HMACKey {
+        &self.key
+    }
+
+    fn key_id(&self) -> &Option<String> {
+        &self.key_id
+    }
+
+    fn set_key_id(&mut self, key_id: String) {
+        self.key_id = Some(key_id);
+    }
+
+    fn metadata(&self) -> &Option<KeyMetadata> {
+        &self.key.metadata
+    }
+
+    fn attach_metadata(&mut self, metadata: KeyMetadata) -> Result<(), Error> {
+        self.key.metadata = Some(metadata);
+        Ok(())
+    }
+
+    fn authentication_tag(&self, authenticated: &[u8]) -> Vec<u8> {
+        let mut mac = Hmac::<Sha3_384>::new_from_slice(self.key().as_ref()).unwrap();
+        mac.update(authenticated);
+        mac.finalize().into_bytes().to_vec()
+    }
+}
+
+impl KeyLike for HS384SHA3Key {
+    fn key_algorithm(&self) -> &'static str {
+        Self::jwt_alg_name()
+    }
+
+    fn key_identifier(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    fn verify_authenticated(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        self.verify_authentication_tag(authenticated, signature)
+    }
+
+    fn key_parameters(&self) -> KeyParameters {
+        KeyParameters::MAC {
+            key_length: self.key().as_ref().len(),
+        }
+    }
+}
+
+impl HS384SHA3Key {
+    pub fn from_bytes(raw_key: &[u8]) -> Self {
+        HS384SHA3Key {
+            key: HMACKey::from_bytes(raw_key),
+            key_id: None,
+        }
+    }
+
+    /// Import a key from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["sign", "verify"])?;
+        let mut key = Self::from_bytes(&jwk.octet_sequence()?);
+        key.key_id = jwk.key_id.clone();
+        Ok(key)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.key.to_bytes()
+    }
+
+    pub fn generate() -> Self {
+        HS384SHA3Key {
+            key: HMACKey::generate(),
+            key_id: None,
+        }
+    }
+
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        HS384SHA3Key {
+            key: HMACKey::generate_with_rng(rng),
+            key_id: None,
+        }
+    }
+
+    /// Derive a key from a password, using a password hashing function.
+    ///
+    /// The salt should be unique and at least `PASSWORD_KDF_MIN_SALT_LENGTH` bytes long.
+    /// The same password, salt and parameters always produce the same key.
+    pub fn derive_from_password(
+        password: impl AsRef<[u8]>,
+        salt: impl AsRef<[u8]>,
+        params: &PasswordKDFParams,
+    ) -> Result<Self, Error> {
+        Ok(HS384SHA3Key {
+            key: HMACKey::derive_from_password(password.as_ref(), salt.as_ref(), params, 48)?,
+            key_id: None,
+        })
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string());
+        self
+    }
+
+    /// Attach the identifier computed by `derived_key_id()` to the key
+    pub fn with_derived_key_id(mut self) -> Self {
+        self.key_id = Some(self.derived_key_id());
+        self
+    }
+}
+
+/// A key for the non-standard `HS512-SHA3` algorithm (HMAC-SHA3-512)
+#[derive(Debug, Clone)]
+pub struct HS512SHA3Key {
+    key: HMACKey,
+    key_id: Option<String>,
+}
+
+impl MACLike for HS512SHA3Key {
+    fn jwt_alg_name() -> &'static str {
+        "HS512-SHA3"
+    }
+
+    fn key(&self) -> &HMACKey {
+        &self.key
+    }
+
+    fn key_id(&self) -> &Option<String> {
+        &self.key_id
+    }
+
+    fn set_key_id(&mut self, key_id: String) {
+        self.key_id = Some(key_id);
+    }
+
+    fn metadata(&self) -> &Option<KeyMetadata> {
+        &self.key.metadata
+    }
+
+    fn attach_metadata(&mut self, metadata: KeyMetadata) -> Result<(), Error> {
+        self.key.metadata = Some(metadata);
+        Ok(())
+    }
+
+    fn authentication_tag(&self, authenticated: &[u8]) -> Vec<u8> {
+        let mut mac = Hmac::<Sha3_512>::new_from_slice(self.key().as_ref()).unwrap();
+        mac.update(authenticated);
+        mac.finalize().into_bytes().to_vec()
+    }
+}
+
+impl KeyLike for HS512SHA3Key {
+    fn key_algorithm(&self) -> &'static str {
+        Self::jwt_alg_name()
+    }
+
+    fn key_identifier(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    fn verify_authenticated(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        self.verify_authentication_tag(authenticated, signature)
+    }
+
+    fn key_parameters(&self) -> KeyParameters {
+        KeyParameters::MAC {
+            key_length: self.key().as_ref().len(),
+        }
+    }
+}
+
+impl HS512SHA3Key {
+    pub fn from_bytes(raw_key: &[u8]) -> Self {
+        HS512SHA3Key {
+            key: HMACKey::from_bytes(raw_key),
+            key_id: None,
+        }
+    }
+
+    /// Import a key from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["sign", "verify"])?;
+        let mut key = Self::from_bytes(&jwk.octet_sequence()?);
+        key.key_id = jwk.key_id.clone();
+        Ok(key)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.key.to_bytes()
+    }
+
+    pub fn generate() -> Self {
+        HS512SHA3Key {
+            key: HMACKey::generate(),
+            key_id: None,
+        }
+    }
+
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        HS512SHA3Key {
+            key: HMACKey::generate_with_rng(rng),
+            key_id: None,
+        }
+    }
+
+    /// Derive a key from a password, using a password hashing function.
+    ///
+    /// The salt should be unique and at least `PASSWORD_KDF_MIN_SALT_LENGTH` bytes long.
+    /// The same password, salt and parameters always produce the same key.
+    pub fn derive_from_password(
+        password: impl AsRef<[u8]>,
+        salt: impl AsRef<[u8]>,
+        params: &PasswordKDFParams,
+    ) -> Result<Self, Error> {
+        Ok(HS512SHA3Key {
+            key: HMACKey::derive_from_password(password.as_ref(), salt.as_ref(), params, 64)?,
+            key_id: None,
+        })
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string());
+        self
+    }
+
+    /// Attach the identifier computed by `derived_key_id()` to the key
+    pub fn with_derived_key_id(mut self) -> Self {
+        self.key_id = Some(self.derived_key_id());
+        self
+    }
+}
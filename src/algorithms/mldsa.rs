@@ -0,0 +1,1277 @@
+use std::convert::{TryFrom, TryInto};
+
+use ct_codecs::{Base64UrlSafeNoPadding, Encoder};
+use fips204::traits::{KeyGen, SerDes, Signer, Verifier};
+use fips204::{ml_dsa_44, ml_dsa_65};
+use p256::pkcs8::{
+    self, der::asn1::BitStringRef, spki::AlgorithmIdentifierRef, LineEnding, ObjectIdentifier,
+};
+use rand::{CryptoRng, RngCore};
+use serde::{de::DeserializeOwned, Serialize};
+use zeroize::Zeroizing;
+
+use crate::claims::*;
+use crate::common::*;
+use crate::encrypted_pem::decrypt_pem;
+use crate::error::*;
+use crate::jwk::*;
+use crate::jwt_header::*;
+use crate::token::*;
+use crate::x509;
+
+/// Length of the seed from which a ML-DSA key pair is deterministically derived.
+pub const MLDSA_SEED_LENGTH: usize = 32;
+
+const MLDSA44_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.3.17");
+const MLDSA65_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.3.18");
+
+pub(super) fn public_key_from_der(der: &[u8], oid: ObjectIdentifier) -> Result<Vec<u8>, Error> {
+    let spki =
+        pkcs8::SubjectPublicKeyInfoRef::try_from(der).map_err(|_| JWTError::InvalidPublicKey)?;
+    ensure!(
+        spki.algorithm.oid == oid && spki.algorithm.parameters.is_none(),
+        JWTError::InvalidPublicKey
+    );
+    let raw = spki
+        .subject_public_key
+        .as_bytes()
+        .ok_or(JWTError::InvalidPublicKey)?;
+    Ok(raw.to_vec())
+}
+
+pub(super) fn public_key_to_document(pk: &[u8], oid: ObjectIdentifier) -> pkcs8::Document {
+    let spki = pkcs8::SubjectPublicKeyInfoRef {
+        algorithm: AlgorithmIdentifierRef {
+            oid,
+            parameters: None,
+        },
+        subject_public_key: BitStringRef::from_bytes(pk).unwrap(),
+    };
+    pkcs8::Document::try_from(spki).unwrap()
+}
+
+/// Decode a PKCS#8 private key (RFC 9881), that can be a seed, an expanded
+/// secret key, or both. Return the seed if present, or the expanded key
+/// otherwise, as well as the expanded key that the seed must expand to, if
+/// both are present.
+#[allow(clippy::type_complexity)]
+fn private_key_from_der(
+    der: &[u8],
+    oid: ObjectIdentifier,
+    sk_len: usize,
+) -> Result<(Zeroizing<Vec<u8>>, Option<Zeroizing<Vec<u8>>>), Error> {
+    let private_key_info =
+        pkcs8::PrivateKeyInfo::try_from(der).map_err(|_| JWTError::InvalidKeyPair)?;
+    ensure!(
+        private_key_info.algorithm.oid == oid && private_key_info.algorithm.parameters.is_none(),
+        JWTError::InvalidKeyPair
+    );
+    let expanded_key_header = [0x04, 0x82, (sk_len >> 8) as u8, sk_len as u8];
+    let both_len = 2 + MLDSA_SEED_LENGTH + expanded_key_header.len() + sk_len;
+    let both_header = [
+        0x30,
+        0x82,
+        (both_len >> 8) as u8,
+        both_len as u8,
+        0x04,
+        0x20,
+    ];
+    match private_key_info.private_key {
+        [0x80, 0x20, seed @ ..] if seed.len() == MLDSA_SEED_LENGTH => {
+            Ok((Zeroizing::new(seed.to_vec()), None))
+        }
+        private_key
+            if private_key.len() == expanded_key_header.len() + sk_len
+                && private_key.starts_with(&expanded_key_header) =>
+        {
+            let sk = &private_key[expanded_key_header.len()..];
+            Ok((Zeroizing::new(sk.to_vec()), None))
+        }
+        private_key
+            if private_key.len() == both_header.len() + both_len - 2
+                && private_key.starts_with(&both_header) =>
+        {
+            let (seed, expanded_key) = private_key[both_header.len()..].split_at(MLDSA_SEED_LENGTH);
+            ensure!(
+                expanded_key.starts_with(&expanded_key_header),
+                JWTError::InvalidKeyPair
+            );
+            Ok((
+                Zeroizing::new(seed.to_vec()),
+                Some(Zeroizing::new(
+                    expanded_key[expanded_key_header.len()..].to_vec(),
+                )),
+            ))
+        }
+        _ => bail!(JWTError::InvalidKeyPair),
+    }
+}
+
+/// Encode a private key as PKCS#8 (RFC 9881), using the seed if it is known,
+/// and the expanded secret key otherwise
+fn private_key_to_secret_document(
+    seed: Option<&[u8]>,
+    sk: &[u8],
+    oid: ObjectIdentifier,
+) -> pkcs8::SecretDocument {
+    let private_key = match seed {
+        Some(seed) => {
+            let mut private_key = Zeroizing::new(vec![0x80, seed.len() as u8]);
+            private_key.extend_from_slice(seed);
+            private_key
+        }
+        None => {
+            let mut private_key =
+                Zeroizing::new(vec![0x04, 0x82, (sk.len() >> 8) as u8, sk.len() as u8]);
+            private_key.extend_from_slice(sk);
+            private_key
+        }
+    };
+    let algorithm = AlgorithmIdentifierRef {
+        oid,
+        parameters: None,
+    };
+    pkcs8::SecretDocument::try_from(pkcs8::PrivateKeyInfo::new(algorithm, &private_key)).unwrap()
+}
+
+#[doc(hidden)]
+#[derive(Clone)]
+pub struct MLDSA44RawPublicKey(ml_dsa_44::PublicKey);
+
+impl std::fmt::Debug for MLDSA44RawPublicKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("MLDSA44RawPublicKey")
+            .field(&Base64UrlSafeNoPadding::encode_to_string(self.to_bytes()).unwrap())
+            .finish()
+    }
+}
+
+impl AsRef<ml_dsa_44::PublicKey> for MLDSA44RawPublicKey {
+    fn as_ref(&self) -> &ml_dsa_44::PublicKey {
+        &self.0
+    }
+}
+
+impl MLDSA44RawPublicKey {
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        Self::from_bytes(&public_key_from_der(der, MLDSA44_OID)?)
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        let (label, der) =
+            pkcs8::Document::from_pem(pem.trim()).map_err(|_| JWTError::InvalidPublicKey)?;
+        ensure!(label == "PUBLIC KEY", JWTError::InvalidPublicKey);
+        Self::from_der(der.as_bytes())
+    }
+
+    pub fn to_der(&self) -> Vec<u8> {
+        public_key_to_document(&self.to_bytes(), MLDSA44_OID)
+            .as_bytes()
+            .to_vec()
+    }
+
+    pub fn to_pem(&self) -> String {
+        public_key_to_document(&self.to_bytes(), MLDSA44_OID)
+            .to_pem("PUBLIC KEY", LineEnding::LF)
+            .unwrap()
+    }
+
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        let raw: [u8; ml_dsa_44::PK_LEN] =
+            raw.try_into().map_err(|_| JWTError::InvalidPublicKey)?;
+        let pk =
+            ml_dsa_44::PublicKey::try_from_bytes(raw).map_err(|_| JWTError::InvalidPublicKey)?;
+        Ok(MLDSA44RawPublicKey(pk))
+    }
+
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        Self::from_bytes(&jwk.algorithm_key_pair_public_key()?)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.clone().into_bytes().to_vec()
+    }
+
+    pub fn to_jwk(&self) -> Result<JWK, Error> {
+        JWK::new_algorithm_key_pair(&self.to_bytes())
+    }
+}
+
+#[doc(hidden)]
+#[derive(Clone)]
+pub struct MLDSA44RawKeyPair {
+    sk: ml_dsa_44::PrivateKey,
+    pk: ml_dsa_44::PublicKey,
+    seed: Option<Zeroizing<[u8; MLDSA_SEED_LENGTH]>>,
+    metadata: Option<KeyMetadata>,
+}
+
+impl AsRef<ml_dsa_44::PrivateKey> for MLDSA44RawKeyPair {
+    fn as_ref(&self) -> &ml_dsa_44::PrivateKey {
+        &self.sk
+    }
+}
+
+impl MLDSA44RawKeyPair {
+    /// Import a key pair from either a 32-byte seed, or an expanded secret key.
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        if let Ok(seed) = <[u8; MLDSA_SEED_LENGTH]>::try_from(raw) {
+            return Ok(Self::from_seed(seed));
+        }
+        let raw: [u8; ml_dsa_44::SK_LEN] = raw.try_into().map_err(|_| JWTError::InvalidKeyPair)?;
+        let sk =
+            ml_dsa_44::PrivateKey::try_from_bytes(raw).map_err(|_| JWTError::InvalidKeyPair)?;
+        let pk = sk.get_public_key();
+        Ok(MLDSA44RawKeyPair {
+            sk,
+            pk,
+            seed: None,
+            metadata: None,
+        })
+    }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        let (raw, expanded_key) = private_key_from_der(der, MLDSA44_OID, ml_dsa_44::SK_LEN)?;
+        let key_pair = Self::from_bytes(&raw)?;
+        if let Some(expanded_key) = expanded_key {
+            ensure!(
+                Zeroizing::new(key_pair.sk.clone().into_bytes())[..] == expanded_key[..],
+                JWTError::InvalidKeyPair
+            );
+        }
+        Ok(key_pair)
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        let (label, der) =
+            pkcs8::SecretDocument::from_pem(pem.trim()).map_err(|_| JWTError::InvalidKeyPair)?;
+        ensure!(label == "PRIVATE KEY", JWTError::InvalidKeyPair);
+        Self::from_der(der.as_bytes())
+    }
+
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        let key_pair = Self::from_bytes(&jwk.private_key()?)?;
+        jwk.check_public_key(&key_pair.public_key().to_jwk()?)?;
+        Ok(key_pair)
+    }
+
+    fn from_seed(seed: [u8; MLDSA_SEED_LENGTH]) -> Self {
+        let seed = Zeroizing::new(seed);
+        let (pk, sk) = ml_dsa_44::KG::keygen_from_seed(&seed);
+        MLDSA44RawKeyPair {
+            sk,
+            pk,
+            seed: Some(seed),
+            metadata: None,
+        }
+    }
+
+    /// Export the key pair as its seed if it is known, or as an expanded secret key otherwise.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match &self.seed {
+            Some(seed) => seed.to_vec(),
+            None => Zeroizing::new(self.sk.clone().into_bytes()).to_vec(),
+        }
+    }
+
+    pub fn to_jwk(&self) -> Result<JWK, Error> {
+        self.public_key()
+            .to_jwk()?
+            .with_private_key(&Zeroizing::new(self.to_bytes()))
+    }
+
+    fn to_secret_document(&self) -> pkcs8::SecretDocument {
+        let sk = Zeroizing::new(self.sk.clone().into_bytes());
+        private_key_to_secret_document(
+            self.seed.as_ref().map(|seed| &seed[..]),
+            &sk[..],
+            MLDSA44_OID,
+        )
+    }
+
+    pub fn to_der(&self) -> Vec<u8> {
+        self.to_secret_document().as_bytes().to_vec()
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.to_secret_document()
+            .to_pem("PRIVATE KEY", LineEnding::LF)
+            .unwrap()
+            .to_string()
+    }
+
+    pub fn public_key(&self) -> MLDSA44RawPublicKey {
+        MLDSA44RawPublicKey(self.pk.clone())
+    }
+
+    pub fn generate() -> Self {
+        Self::generate_with_rng(&mut rand::thread_rng())
+    }
+
+    pub fn generate_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        let mut seed = [0u8; MLDSA_SEED_LENGTH];
+        rng.fill_bytes(&mut seed);
+        Self::from_seed(seed)
+    }
+}
+
+pub trait MLDSA44KeyPairLike {
+    fn jwt_alg_name() -> &'static str;
+    fn key_pair(&self) -> &MLDSA44RawKeyPair;
+    fn key_id(&self) -> &Option<String>;
+    fn metadata(&self) -> &Option<KeyMetadata>;
+    fn attach_metadata(&mut self, metadata: KeyMetadata) -> Result<(), Error>;
+
+    /// Compute the signature of `authenticated`
+    fn signature(&self, authenticated: &[u8]) -> Result<Vec<u8>, Error> {
+        let signature = self
+            .key_pair()
+            .as_ref()
+            .try_sign_with_rng(&mut rand::thread_rng(), authenticated, &[])
+            .map_err(JWTError::from)?;
+        Ok(signature.to_vec())
+    }
+
+    fn sign<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata());
+        Token::build(&jwt_header, claims, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Sign claims, with additional header parameters
+    fn sign_with_header_options<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+        header_options: &HeaderOptions,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata())
+            .with_options(header_options);
+        Token::build(&jwt_header, claims, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Sign a detached payload, that is not included in the token (RFC 7797)
+    fn sign_detached(&self, payload: &str) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata());
+        Token::build_detached(jwt_header, payload, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Sign a detached binary payload, that is not included in the token
+    ///
+    /// The raw bytes of the payload are signed without being encoded
+    /// (RFC 7797), so that it can be any sequence of bytes.
+    fn sign_bytes_detached(&self, payload: &[u8]) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata());
+        Token::build_bytes_detached(jwt_header, payload, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Export the key pair as a JWK, including the private key
+    fn to_jwk(&self) -> Result<JWK, Error> {
+        Ok(self
+            .key_pair()
+            .to_jwk()?
+            .with_usage(self.key_id(), Self::jwt_alg_name(), "sig"))
+    }
+}
+
+pub trait MLDSA44PublicKeyLike {
+    fn jwt_alg_name() -> &'static str;
+    fn public_key(&self) -> &MLDSA44RawPublicKey;
+    fn key_id(&self) -> &Option<String>;
+    fn set_key_id(&mut self, key_id: String);
+
+    /// The SHA-256 thumbprint of the certificate the key was imported from
+    fn certificate_sha256_thumbprint(&self) -> Option<&[u8; 32]> {
+        None
+    }
+
+    /// Verify the signature of `authenticated`
+    fn verify_signature(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        let signature: &[u8; ml_dsa_44::SIG_LEN] = signature
+            .try_into()
+            .map_err(|_| JWTError::InvalidSignature)?;
+        ensure!(
+            self.public_key()
+                .as_ref()
+                .verify(authenticated, signature, &[]),
+            JWTError::InvalidSignature
+        );
+        Ok(())
+    }
+
+    fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        check_key_policy(
+            options.as_ref(),
+            KeyParameters::PostQuantum {
+                algorithm: Self::jwt_alg_name(),
+                curve: None,
+            },
+        )?;
+        check_key_thumbprint(options.as_ref(), || self.thumbprint().ok())?;
+        check_certificate_thumbprint(options.as_ref(), self.certificate_sha256_thumbprint())?;
+        Token::verify(
+            Self::jwt_alg_name(),
+            token,
+            options,
+            |authenticated, signature| self.verify_signature(authenticated, signature),
+        )
+    }
+
+    fn verify_token_untyped(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<serde_json::Value, Error> {
+        let claims = self.verify_token::<NoCustomClaims>(token, options)?;
+        Ok(serde_json::to_value(claims)?)
+    }
+
+    fn create_key_id(&mut self) -> &str {
+        self.set_key_id(
+            Base64UrlSafeNoPadding::encode_to_string(hmac_sha256::Hash::hash(
+                &self.public_key().to_bytes(),
+            ))
+            .unwrap(),
+        );
+        self.key_id().as_ref().map(|x| x.as_str()).unwrap()
+    }
+
+    /// Export the public key as a JWK, for publication in a key set
+    fn to_jwk(&self) -> Result<JWK, Error> {
+        Ok(self
+            .public_key()
+            .to_jwk()?
+            .with_usage(self.key_id(), Self::jwt_alg_name(), "sig"))
+    }
+
+    /// Compute the RFC 7638 thumbprint of the public key, using SHA-256
+    fn thumbprint(&self) -> Result<String, Error> {
+        self.to_jwk()?.thumbprint()
+    }
+
+    /// Compute the RFC 7638 thumbprint of the public key, using SHA-1, for
+    /// legacy applications
+    fn thumbprint_sha1(&self) -> Result<String, Error> {
+        self.to_jwk()?.thumbprint_sha1()
+    }
+}
+
+#[derive(Clone)]
+pub struct MLDSA44KeyPair {
+    key_pair: MLDSA44RawKeyPair,
+    key_id: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MLDSA44PublicKey {
+    pk: MLDSA44RawPublicKey,
+    key_id: Option<String>,
+    certificate_sha256_thumbprint: Option<[u8; 32]>,
+}
+
+impl MLDSA44KeyPairLike for MLDSA44KeyPair {
+    fn jwt_alg_name() -> &'static str {
+        "ML-DSA-44"
+    }
+
+    fn key_pair(&self) -> &MLDSA44RawKeyPair {
+        &self.key_pair
+    }
+
+    fn key_id(&self) -> &Option<String> {
+        &self.key_id
+    }
+
+    fn metadata(&self) -> &Option<KeyMetadata> {
+        &self.key_pair.metadata
+    }
+
+    fn attach_metadata(&mut self, metadata: KeyMetadata) -> Result<(), Error> {
+        self.key_pair.metadata = Some(metadata);
+        Ok(())
+    }
+}
+
+impl MLDSA44KeyPair {
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        Ok(MLDSA44KeyPair {
+            key_pair: MLDSA44RawKeyPair::from_bytes(raw)?,
+            key_id: None,
+        })
+    }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        Ok(MLDSA44KeyPair {
+            key_pair: MLDSA44RawKeyPair::from_der(der)?,
+            key_id: None,
+        })
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        Ok(MLDSA44KeyPair {
+            key_pair: MLDSA44RawKeyPair::from_pem(pem)?,
+            key_id: None,
+        })
+    }
+
+    /// Import a password-protected key pair, encoded as an encrypted PKCS#8
+    /// or as a legacy encrypted PEM document
+    pub fn from_encrypted_pem(pem: &str, password: impl AsRef<[u8]>) -> Result<Self, Error> {
+        Self::from_pem(&decrypt_pem(pem, password.as_ref())?)
+    }
+
+    /// Import a key pair from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["sign"])?;
+        Ok(MLDSA44KeyPair {
+            key_pair: MLDSA44RawKeyPair::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.key_pair.to_bytes()
+    }
+
+    pub fn to_der(&self) -> Vec<u8> {
+        self.key_pair.to_der()
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.key_pair.to_pem()
+    }
+
+    pub fn public_key(&self) -> MLDSA44PublicKey {
+        MLDSA44PublicKey {
+            pk: self.key_pair.public_key(),
+            key_id: self.key_id.clone(),
+            certificate_sha256_thumbprint: None,
+        }
+    }
+
+    pub fn generate() -> Self {
+        MLDSA44KeyPair {
+            key_pair: MLDSA44RawKeyPair::generate(),
+            key_id: None,
+        }
+    }
+
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        MLDSA44KeyPair {
+            key_pair: MLDSA44RawKeyPair::generate_with_rng(rng),
+            key_id: None,
+        }
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string());
+        self
+    }
+}
+
+impl MLDSA44PublicKeyLike for MLDSA44PublicKey {
+    fn jwt_alg_name() -> &'static str {
+        "ML-DSA-44"
+    }
+
+    fn public_key(&self) -> &MLDSA44RawPublicKey {
+        &self.pk
+    }
+
+    fn key_id(&self) -> &Option<String> {
+        &self.key_id
+    }
+
+    fn set_key_id(&mut self, key_id: String) {
+        self.key_id = Some(key_id);
+    }
+
+    fn certificate_sha256_thumbprint(&self) -> Option<&[u8; 32]> {
+        self.certificate_sha256_thumbprint.as_ref()
+    }
+}
+
+impl KeyLike for MLDSA44PublicKey {
+    fn key_algorithm(&self) -> &'static str {
+        Self::jwt_alg_name()
+    }
+
+    fn key_identifier(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    fn verify_authenticated(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        self.verify_signature(authenticated, signature)
+    }
+
+    fn key_parameters(&self) -> KeyParameters {
+        KeyParameters::PostQuantum {
+            algorithm: Self::jwt_alg_name(),
+            curve: None,
+        }
+    }
+
+    fn key_thumbprint(&self) -> Option<String> {
+        self.thumbprint().ok()
+    }
+
+    fn key_certificate_thumbprint(&self) -> Option<&[u8; 32]> {
+        self.certificate_sha256_thumbprint.as_ref()
+    }
+}
+
+impl MLDSA44PublicKey {
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        Ok(MLDSA44PublicKey {
+            pk: MLDSA44RawPublicKey::from_bytes(raw)?,
+            key_id: None,
+            certificate_sha256_thumbprint: None,
+        })
+    }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        Ok(MLDSA44PublicKey {
+            pk: MLDSA44RawPublicKey::from_der(der)?,
+            key_id: None,
+            certificate_sha256_thumbprint: None,
+        })
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        Ok(MLDSA44PublicKey {
+            pk: MLDSA44RawPublicKey::from_pem(pem)?,
+            key_id: None,
+            certificate_sha256_thumbprint: None,
+        })
+    }
+
+    /// Import the public key of a DER-encoded X.509 certificate
+    pub fn from_x509_der(der: &[u8]) -> Result<Self, Error> {
+        let mut public_key = Self::from_der(x509::certificate_public_key(der)?)?;
+        public_key.certificate_sha256_thumbprint = Some(hmac_sha256::Hash::hash(der));
+        Ok(public_key)
+    }
+
+    /// Import the public key of the first certificate of a PEM-encoded X.509
+    /// certificate chain
+    pub fn from_x509_pem(pem: &str) -> Result<Self, Error> {
+        Self::from_x509_der(&x509::certificate_from_pem(pem)?)
+    }
+
+    /// Import a public key from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["verify"])?;
+        Ok(MLDSA44PublicKey {
+            pk: MLDSA44RawPublicKey::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+            certificate_sha256_thumbprint: None,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.pk.to_bytes()
+    }
+
+    pub fn to_der(&self) -> Vec<u8> {
+        self.pk.to_der()
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.pk.to_pem()
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string());
+        self
+    }
+}
+
+#[doc(hidden)]
+#[derive(Clone)]
+pub struct MLDSA65RawPublicKey(ml_dsa_65::PublicKey);
+
+impl std::fmt::Debug for MLDSA65RawPublicKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("MLDSA65RawPublicKey")
+            .field(&Base64UrlSafeNoPadding::encode_to_string(self.to_bytes()).unwrap())
+            .finish()
+    }
+}
+
+impl AsRef<ml_dsa_65::PublicKey> for MLDSA65RawPublicKey {
+    fn as_ref(&self) -> &ml_dsa_65::PublicKey {
+        &self.0
+    }
+}
+
+impl MLDSA65RawPublicKey {
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        Self::from_bytes(&public_key_from_der(der, MLDSA65_OID)?)
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        let (label, der) =
+            pkcs8::Document::from_pem(pem.trim()).map_err(|_| JWTError::InvalidPublicKey)?;
+        ensure!(label == "PUBLIC KEY", JWTError::InvalidPublicKey);
+        Self::from_der(der.as_bytes())
+    }
+
+    pub fn to_der(&self) -> Vec<u8> {
+        public_key_to_document(&self.to_bytes(), MLDSA65_OID)
+            .as_bytes()
+            .to_vec()
+    }
+
+    pub fn to_pem(&self) -> String {
+        public_key_to_document(&self.to_bytes(), MLDSA65_OID)
+            .to_pem("PUBLIC KEY", LineEnding::LF)
+            .unwrap()
+    }
+
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        let raw: [u8; ml_dsa_65::PK_LEN] =
+            raw.try_into().map_err(|_| JWTError::InvalidPublicKey)?;
+        let pk =
+            ml_dsa_65::PublicKey::try_from_bytes(raw).map_err(|_| JWTError::InvalidPublicKey)?;
+        Ok(MLDSA65RawPublicKey(pk))
+    }
+
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        Self::from_bytes(&jwk.algorithm_key_pair_public_key()?)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.clone().into_bytes().to_vec()
+    }
+
+    pub fn to_jwk(&self) -> Result<JWK, Error> {
+        JWK::new_algorithm_key_pair(&self.to_bytes())
+    }
+}
+
+#[doc(hidden)]
+#[derive(Clone)]
+pub struct MLDSA65RawKeyPair {
+    sk: ml_dsa_65::PrivateKey,
+    pk: ml_dsa_65::PublicKey,
+    seed: Option<Zeroizing<[u8; MLDSA_SEED_LENGTH]>>,
+    metadata: Option<KeyMetadata>,
+}
+
+impl AsRef<ml_dsa_65::PrivateKey> for MLDSA65RawKeyPair {
+    fn as_ref(&self) -> &ml_dsa_65::PrivateKey {
+        &self.sk
+    }
+}
+
+impl MLDSA65RawKeyPair {
+    /// Import a key pair from either a 32-byte seed, or an expanded secret key.
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        if let Ok(seed) = <[u8; MLDSA_SEED_LENGTH]>::try_from(raw) {
+            return Ok(Self::from_seed(seed));
+        }
+        let raw: [u8; ml_dsa_65::SK_LEN] = raw.try_into().map_err(|_| JWTError::InvalidKeyPair)?;
+        let sk =
+            ml_dsa_65::PrivateKey::try_from_bytes(raw).map_err(|_| JWTError::InvalidKeyPair)?;
+        let pk = sk.get_public_key();
+        Ok(MLDSA65RawKeyPair {
+            sk,
+            pk,
+            seed: None,
+            metadata: None,
+        })
+    }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        let (raw, expanded_key) = private_key_from_der(der, MLDSA65_OID, ml_dsa_65::SK_LEN)?;
+        let key_pair = Self::from_bytes(&raw)?;
+        if let Some(expanded_key) = expanded_key {
+            ensure!(
+                Zeroizing::new(key_pair.sk.clone().into_bytes())[..] == expanded_key[..],
+                JWTError::InvalidKeyPair
+            );
+        }
+        Ok(key_pair)
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        let (label, der) =
+            pkcs8::SecretDocument::from_pem(pem.trim()).map_err(|_| JWTError::InvalidKeyPair)?;
+        ensure!(label == "PRIVATE KEY", JWTError::InvalidKeyPair);
+        Self::from_der(der.as_bytes())
+    }
+
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        let key_pair = Self::from_bytes(&jwk.private_key()?)?;
+        jwk.check_public_key(&key_pair.public_key().to_jwk()?)?;
+        Ok(key_pair)
+    }
+
+    fn from_seed(seed: [u8; MLDSA_SEED_LENGTH]) -> Self {
+        let seed = Zeroizing::new(seed);
+        let (pk, sk) = ml_dsa_65::KG::keygen_from_seed(&seed);
+        MLDSA65RawKeyPair {
+            sk,
+            pk,
+            seed: Some(seed),
+            metadata: None,
+        }
+    }
+
+    /// Export the key pair as its seed if it is known, or as an expanded secret key otherwise.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match &self.seed {
+            Some(seed) => seed.to_vec(),
+            None => Zeroizing::new(self.sk.clone().into_bytes()).to_vec(),
+        }
+    }
+
+    pub fn to_jwk(&self) -> Result<JWK, Error> {
+        self.public_key()
+            .to_jwk()?
+            .with_private_key(&Zeroizing::new(self.to_bytes()))
+    }
+
+    fn to_secret_document(&self) -> pkcs8::SecretDocument {
+        let sk = Zeroizing::new(self.sk.clone().into_bytes());
+        private_key_to_secret_document(
+            self.seed.as_ref().map(|seed| &seed[..]),
+            &sk[..],
+            MLDSA65_OID,
+        )
+    }
+
+    pub fn to_der(&self) -> Vec<u8> {
+        self.to_secret_document().as_bytes().to_vec()
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.to_secret_document()
+            .to_pem("PRIVATE KEY", LineEnding::LF)
+            .unwrap()
+            .to_string()
+    }
+
+    pub fn public_key(&self) -> MLDSA65RawPublicKey {
+        MLDSA65RawPublicKey(self.pk.clone())
+    }
+
+    pub fn generate() -> Self {
+        Self::generate_with_rng(&mut rand::thread_rng())
+    }
+
+    pub fn generate_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        let mut seed = [0u8; MLDSA_SEED_LENGTH];
+        rng.fill_bytes(&mut seed);
+        Self::from_seed(seed)
+    }
+}
+
+pub trait MLDSA65KeyPairLike {
+    fn jwt_alg_name() -> &'static str;
+    fn key_pair(&self) -> &MLDSA65RawKeyPair;
+    fn key_id(&self) -> &Option<String>;
+    fn metadata(&self) -> &Option<KeyMetadata>;
+    fn attach_metadata(&mut self, metadata: KeyMetadata) -> Result<(), Error>;
+
+    /// Compute the signature of `authenticated`
+    fn signature(&self, authenticated: &[u8]) -> Result<Vec<u8>, Error> {
+        let signature = self
+            .key_pair()
+            .as_ref()
+            .try_sign_with_rng(&mut rand::thread_rng(), authenticated, &[])
+            .map_err(JWTError::from)?;
+        Ok(signature.to_vec())
+    }
+
+    fn sign<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata());
+        Token::build(&jwt_header, claims, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Sign claims, with additional header parameters
+    fn sign_with_header_options<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+        header_options: &HeaderOptions,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata())
+            .with_options(header_options);
+        Token::build(&jwt_header, claims, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Sign a detached payload, that is not included in the token (RFC 7797)
+    fn sign_detached(&self, payload: &str) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata());
+        Token::build_detached(jwt_header, payload, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Sign a detached binary payload, that is not included in the token
+    ///
+    /// The raw bytes of the payload are signed without being encoded
+    /// (RFC 7797), so that it can be any sequence of bytes.
+    fn sign_bytes_detached(&self, payload: &[u8]) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata());
+        Token::build_bytes_detached(jwt_header, payload, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Export the key pair as a JWK, including the private key
+    fn to_jwk(&self) -> Result<JWK, Error> {
+        Ok(self
+            .key_pair()
+            .to_jwk()?
+            .with_usage(self.key_id(), Self::jwt_alg_name(), "sig"))
+    }
+}
+
+pub trait MLDSA65PublicKeyLike {
+    fn jwt_alg_name() -> &'static str;
+    fn public_key(&self) -> &MLDSA65RawPublicKey;
+    fn key_id(&self) -> &Option<String>;
+    fn set_key_id(&mut self, key_id: String);
+
+    /// The SHA-256 thumbprint of the certificate the key was imported from
+    fn certificate_sha256_thumbprint(&self) -> Option<&[u8; 32]> {
+        None
+    }
+
+    /// Verify the signature of `authenticated`
+    fn verify_signature(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        let signature: &[u8; ml_dsa_65::SIG_LEN] = signature
+            .try_into()
+            .map_err(|_| JWTError::InvalidSignature)?;
+        ensure!(
+            self.public_key()
+                .as_ref()
+                .verify(authenticated, signature, &[]),
+            JWTError::InvalidSignature
+        );
+        Ok(())
+    }
+
+    fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        check_key_policy(
+            options.as_ref(),
+            KeyParameters::PostQuantum {
+                algorithm: Self::jwt_alg_name(),
+                curve: None,
+            },
+        )?;
+        check_key_thumbprint(options.as_ref(), || self.thumbprint().ok())?;
+        check_certificate_thumbprint(options.as_ref(), self.certificate_sha256_thumbprint())?;
+        Token::verify(
+            Self::jwt_alg_name(),
+            token,
+            options,
+            |authenticated, signature| self.verify_signature(authenticated, signature),
+        )
+    }
+
+    fn verify_token_untyped(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<serde_json::Value, Error> {
+        let claims = self.verify_token::<NoCustomClaims>(token, options)?;
+        Ok(serde_json::to_value(claims)?)
+    }
+
+    fn create_key_id(&mut self) -> &str {
+        self.set_key_id(
+            Base64UrlSafeNoPadding::encode_to_string(hmac_sha256::Hash::hash(
+                &self.public_key().to_bytes(),
+            ))
+            .unwrap(),
+        );
+        self.key_id().as_ref().map(|x| x.as_str()).unwrap()
+    }
+
+    /// Export the public key as a JWK, for publication in a key set
+    fn to_jwk(&self) -> Result<JWK, Error> {
+        Ok(self
+            .public_key()
+            .to_jwk()?
+            .with_usage(self.key_id(), Self::jwt_alg_name(), "sig"))
+    }
+
+    /// Compute the RFC 7638 thumbprint of the public key, using SHA-256
+    fn thumbprint(&self) -> Result<String, Error> {
+        self.to_jwk()?.thumbprint()
+    }
+
+    /// Compute the RFC 7638 thumbprint of the public key, using SHA-1, for
+    /// legacy applications
+    fn thumbprint_sha1(&self) -> Result<String, Error> {
+        self.to_jwk()?.thumbprint_sha1()
+    }
+}
+
+#[derive(Clone)]
+pub struct MLDSA65KeyPair {
+    key_pair: MLDSA65RawKeyPair,
+    key_id: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MLDSA65PublicKey {
+    pk: MLDSA65RawPublicKey,
+    key_id: Option<String>,
+    certificate_sha256_thumbprint: Option<[u8; 32]>,
+}
+
+impl MLDSA65KeyPairLike for MLDSA65KeyPair {
+    fn jwt_alg_name() -> &'static str {
+        "ML-DSA-65"
+    }
+
+    fn key_pair(&self) -> &MLDSA65RawKeyPair {
+        &self.key_pair
+    }
+
+    fn key_id(&self) -> &Option<String> {
+        &self.key_id
+    }
+
+    fn metadata(&self) -> &Option<KeyMetadata> {
+        &self.key_pair.metadata
+    }
+
+    fn attach_metadata(&mut self, metadata: KeyMetadata) -> Result<(), Error> {
+        self.key_pair.metadata = Some(metadata);
+        Ok(())
+    }
+}
+
+impl MLDSA65KeyPair {
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        Ok(MLDSA65KeyPair {
+            key_pair: MLDSA65RawKeyPair::from_bytes(raw)?,
+            key_id: None,
+        })
+    }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        Ok(MLDSA65KeyPair {
+            key_pair: MLDSA65RawKeyPair::from_der(der)?,
+            key_id: None,
+        })
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        Ok(MLDSA65KeyPair {
+            key_pair: MLDSA65RawKeyPair::from_pem(pem)?,
+            key_id: None,
+        })
+    }
+
+    /// Import a password-protected key pair, encoded as an encrypted PKCS#8
+    /// or as a legacy encrypted PEM document
+    pub fn from_encrypted_pem(pem: &str, password: impl AsRef<[u8]>) -> Result<Self, Error> {
+        Self::from_pem(&decrypt_pem(pem, password.as_ref())?)
+    }
+
+    /// Import a key pair from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["sign"])?;
+        Ok(MLDSA65KeyPair {
+            key_pair: MLDSA65RawKeyPair::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.key_pair.to_bytes()
+    }
+
+    pub fn to_der(&self) -> Vec<u8> {
+        self.key_pair.to_der()
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.key_pair.to_pem()
+    }
+
+    pub fn public_key(&self) -> MLDSA65PublicKey {
+        MLDSA65PublicKey {
+            pk: self.key_pair.public_key(),
+            key_id: self.key_id.clone(),
+            certificate_sha256_thumbprint: None,
+        }
+    }
+
+    pub fn generate() -> Self {
+        MLDSA65KeyPair {
+            key_pair: MLDSA65RawKeyPair::generate(),
+            key_id: None,
+        }
+    }
+
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        MLDSA65KeyPair {
+            key_pair: MLDSA65RawKeyPair::generate_with_rng(rng),
+            key_id: None,
+        }
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string());
+        self
+    }
+}
+
+impl MLDSA65PublicKeyLike for MLDSA65PublicKey {
+    fn jwt_alg_name() -> &'static str {
+        "ML-DSA-65"
+    }
+
+    fn public_key(&self) -> &MLDSA65RawPublicKey {
+        &self.pk
+    }
+
+    fn key_id(&self) -> &Option<String> {
+        &self.key_id
+    }
+
+    fn set_key_id(&mut self, key_id: String) {
+        self.key_id = Some(key_id);
+    }
+
+    fn certificate_sha256_thumbprint(&self) -> Option<&[u8; 32]> {
+        self.certificate_sha256_thumbprint.as_ref()
+    }
+}
+
+impl KeyLike for MLDSA65PublicKey {
+    fn key_algorithm(&self) -> &'static str {
+        Self::jwt_alg_name()
+    }
+
+    fn key_identifier(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    fn verify_authenticated(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        self.verify_signature(authenticated, signature)
+    }
+
+    fn key_parameters(&self) -> KeyParameters {
+        KeyParameters::PostQuantum {
+            algorithm: Self::jwt_alg_name(),
+            curve: None,
+        }
+    }
+
+    fn key_thumbprint(&self) -> Option<String> {
+        self.thumbprint().ok()
+    }
+
+    fn key_certificate_thumbprint(&self) -> Option<&[u8; 32]> {
+        self.certificate_sha256_thumbprint.as_ref()
+    }
+}
+
+impl MLDSA65PublicKey {
+    pub fn from_bytes(raw: &[u8]) -> Result<Self, Error> {
+        Ok(MLDSA65PublicKey {
+            pk: MLDSA65RawPublicKey::from_bytes(raw)?,
+            key_id: None,
+            certificate_sha256_thumbprint: None,
+        })
+    }
+
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        Ok(MLDSA65PublicKey {
+            pk: MLDSA65RawPublicKey::from_der(der)?,
+            key_id: None,
+            certificate_sha256_thumbprint: None,
+        })
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        Ok(MLDSA65PublicKey {
+            pk: MLDSA65RawPublicKey::from_pem(pem)?,
+            key_id: None,
+            certificate_sha256_thumbprint: None,
+        })
+    }
+
+    /// Import the public key of a DER-encoded X.509 certificate
+    pub fn from_x509_der(der: &[u8]) -> Result<Self, Error> {
+        let mut public_key = Self::from_der(x509::certificate_public_key(der)?)?;
+        public_key.certificate_sha256_thumbprint = Some(hmac_sha256::Hash::hash(der));
+        Ok(public_key)
+    }
+
+    /// Import the public key of the first certificate of a PEM-encoded X.509
+    /// certificate chain
+    pub fn from_x509_pem(pem: &str) -> Result<Self, Error> {
+        Self::from_x509_der(&x509::certificate_from_pem(pem)?)
+    }
+
+    /// Import a public key from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["verify"])?;
+        Ok(MLDSA65PublicKey {
+            pk: MLDSA65RawPublicKey::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+            certificate_sha256_thumbprint: None,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.pk.to_bytes()
+    }
+
+    pub fn to_der(&self) -> Vec<u8> {
+        self.pk.to_der()
+    }
+
+    pub fn to_pem(&self) -> String {
+        self.pk.to_pem()
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string());
+        self
+    }
+}
@@ -1,13 +1,274 @@
+mod aes_kw;
+mod bip340;
+#[cfg(feature = "blake2b")]
+mod blake2b;
+mod bp256;
+mod bp384;
+#[cfg(feature = "pq")]
+mod composite;
+mod dir;
+mod ecdh_es;
+mod ed448;
 mod eddsa;
 mod es256;
 mod es256k;
 mod es384;
+mod es512;
 mod hmac;
+#[cfg(feature = "sha3")]
+mod hmac_sha3;
+#[cfg(feature = "pq")]
+mod mldsa;
+mod pbes2;
 mod rsa;
+mod rsa_oaep;
+mod wrapping_key;
 
+pub use self::bip340::*;
+#[cfg(feature = "blake2b")]
+pub use self::blake2b::*;
+pub use self::bp256::*;
+pub use self::bp384::*;
+#[cfg(feature = "pq")]
+pub use self::composite::*;
+pub use self::dir::*;
+pub use self::ecdh_es::*;
+pub use self::ed448::*;
 pub use self::eddsa::*;
 pub use self::es256::*;
 pub use self::es256k::*;
 pub use self::es384::*;
+pub use self::es512::*;
 pub use self::hmac::*;
+#[cfg(feature = "sha3")]
+pub use self::hmac_sha3::*;
+#[cfg(feature = "pq")]
+pub use self::mldsa::*;
+pub use self::pbes2::*;
 pub use self::rsa::*;
+pub use self::rsa_oaep::*;
+pub use self::wrapping_key::*;
+
+/// Evaluate `$body` with `$key_pair` and `$public_key` set to the key types
+/// of the signature algorithm named `$alg`, or `$otherwise` if there is no
+/// such algorithm
+///
+/// `$curve` tells algorithms sharing a name apart (`EdDSA` uses Ed25519,
+/// unless the curve is `Ed448`). This is the list of algorithms used to
+/// import keys at runtime, by JWK sets, key rings and the command-line tool.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! with_key_pair_algorithm {
+    ($alg:expr, $curve:expr, |$key_pair:ident, $public_key:ident| $body:expr, $otherwise:expr) => {
+        match ($alg, $curve) {
+            ("ES256", _) => $crate::with_key_pair_algorithm!(@types $key_pair = ES256KeyPair, $public_key = ES256PublicKey, $body),
+            ("ES384", _) => $crate::with_key_pair_algorithm!(@types $key_pair = ES384KeyPair, $public_key = ES384PublicKey, $body),
+            ("ES512", _) => $crate::with_key_pair_algorithm!(@types $key_pair = ES512KeyPair, $public_key = ES512PublicKey, $body),
+            ("ES256K", _) => $crate::with_key_pair_algorithm!(@types $key_pair = ES256kKeyPair, $public_key = ES256kPublicKey, $body),
+            ("BP256R1", _) => $crate::with_key_pair_algorithm!(@types $key_pair = BP256R1KeyPair, $public_key = BP256R1PublicKey, $body),
+            ("BP384R1", _) => $crate::with_key_pair_algorithm!(@types $key_pair = BP384R1KeyPair, $public_key = BP384R1PublicKey, $body),
+            ("BIP340", _) => $crate::with_key_pair_algorithm!(@types $key_pair = BIP340KeyPair, $public_key = BIP340PublicKey, $body),
+            ("EdDSA", Some("Ed448")) => $crate::with_key_pair_algorithm!(@types $key_pair = Ed448KeyPair, $public_key = Ed448PublicKey, $body),
+            ("EdDSA", _) => $crate::with_key_pair_algorithm!(@types $key_pair = Ed25519KeyPair, $public_key = Ed25519PublicKey, $body),
+            ("RS256", _) => $crate::with_key_pair_algorithm!(@types $key_pair = RS256KeyPair, $public_key = RS256PublicKey, $body),
+            ("RS384", _) => $crate::with_key_pair_algorithm!(@types $key_pair = RS384KeyPair, $public_key = RS384PublicKey, $body),
+            ("RS512", _) => $crate::with_key_pair_algorithm!(@types $key_pair = RS512KeyPair, $public_key = RS512PublicKey, $body),
+            ("PS256", _) => $crate::with_key_pair_algorithm!(@types $key_pair = PS256KeyPair, $public_key = PS256PublicKey, $body),
+            ("PS384", _) => $crate::with_key_pair_algorithm!(@types $key_pair = PS384KeyPair, $public_key = PS384PublicKey, $body),
+            ("PS512", _) => $crate::with_key_pair_algorithm!(@types $key_pair = PS512KeyPair, $public_key = PS512PublicKey, $body),
+            #[cfg(feature = "pq")]
+            ("ML-DSA-44", _) => $crate::with_key_pair_algorithm!(@types $key_pair = MLDSA44KeyPair, $public_key = MLDSA44PublicKey, $body),
+            #[cfg(feature = "pq")]
+            ("ML-DSA-65", _) => $crate::with_key_pair_algorithm!(@types $key_pair = MLDSA65KeyPair, $public_key = MLDSA65PublicKey, $body),
+            #[cfg(feature = "pq")]
+            ("ML-DSA-65-Ed25519", _) => $crate::with_key_pair_algorithm!(@types $key_pair = MLDSA65Ed25519KeyPair, $public_key = MLDSA65Ed25519PublicKey, $body),
+            _ => $otherwise,
+        }
+    };
+    (@types $key_pair:ident = $key_pair_type:ident, $public_key:ident = $public_key_type:ident, $body:expr) => {{
+        #[allow(dead_code)]
+        type $key_pair = $crate::algorithms::$key_pair_type;
+        #[allow(dead_code)]
+        type $public_key = $crate::algorithms::$public_key_type;
+        $body
+    }};
+}
+
+/// Evaluate `$body` with `$key` set to the type of the shared key of the MAC
+/// algorithm named `$alg`, or `$otherwise` if there is no such algorithm
+#[doc(hidden)]
+#[macro_export]
+macro_rules! with_shared_key_algorithm {
+    ($alg:expr, |$key:ident| $body:expr, $otherwise:expr) => {
+        match $alg {
+            "HS256" => $crate::with_shared_key_algorithm!(@type $key = HS256Key, $body),
+            "HS384" => $crate::with_shared_key_algorithm!(@type $key = HS384Key, $body),
+            "HS512" => $crate::with_shared_key_algorithm!(@type $key = HS512Key, $body),
+            _ => $otherwise,
+        }
+    };
+    (@type $key:ident = $key_type:ident, $body:expr) => {{
+        type $key = $crate::algorithms::$key_type;
+        $body
+    }};
+}
+
+#[cfg(feature = "zeroize")]
+mod zeroize_on_drop {
+    use zeroize::ZeroizeOnDrop;
+
+    use super::*;
+
+    macro_rules! zeroize_on_drop {
+        ($($secret_key:ty),* $(,)?) => {
+            $(impl ZeroizeOnDrop for $secret_key {})*
+        };
+    }
+
+    zeroize_on_drop!(
+        HMACKey,
+        HS256Key,
+        HS384Key,
+        HS512Key,
+        DirectKey,
+        WrappingKey,
+        PasswordKey,
+        P256KeyPair,
+        ES256KeyPair,
+        P384KeyPair,
+        ES384KeyPair,
+        P521KeyPair,
+        ES512KeyPair,
+        K256KeyPair,
+        ES256kKeyPair,
+        K256SchnorrKeyPair,
+        BIP340KeyPair,
+        BrainpoolP256r1KeyPair,
+        BP256R1KeyPair,
+        BrainpoolP384r1KeyPair,
+        BP384R1KeyPair,
+        Edwards25519KeyPair,
+        Ed25519KeyPair,
+        Edwards448KeyPair,
+        Ed448KeyPair,
+        RSAKeyPair,
+        RS256KeyPair,
+        RS384KeyPair,
+        RS512KeyPair,
+        PS256KeyPair,
+        PS384KeyPair,
+        PS512KeyPair,
+        RSAOAEPKeyPair,
+        RSAOAEP256KeyPair,
+        ECDHP256KeyPair,
+        X25519KeyPair,
+        X448KeyPair,
+    );
+
+    #[cfg(feature = "sha3")]
+    zeroize_on_drop!(HS256SHA3Key, HS384SHA3Key, HS512SHA3Key);
+
+    #[cfg(feature = "blake2b")]
+    zeroize_on_drop!(Blake2bKey);
+
+    #[cfg(feature = "pq")]
+    zeroize_on_drop!(
+        MLDSA44RawKeyPair,
+        MLDSA44KeyPair,
+        MLDSA65RawKeyPair,
+        MLDSA65KeyPair,
+        MLDSA65Ed25519RawKeyPair,
+        MLDSA65Ed25519KeyPair,
+    );
+}
+
+#[cfg(feature = "serde")]
+mod serde_keys {
+    use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::*;
+    use crate::jwk::JWK;
+
+    macro_rules! jwk_serde {
+        ($($key:ty),* $(,)?) => {
+            $(
+                impl Serialize for $key {
+                    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                        self.to_jwk()
+                            .map_err(ser::Error::custom)?
+                            .serialize(serializer)
+                    }
+                }
+
+                impl<'de> Deserialize<'de> for $key {
+                    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                        let jwk = JWK::deserialize(deserializer)?;
+                        <$key>::from_jwk(&jwk).map_err(de::Error::custom)
+                    }
+                }
+            )*
+        };
+    }
+
+    jwk_serde!(
+        HS256Key,
+        HS384Key,
+        HS512Key,
+        ES256KeyPair,
+        ES256PublicKey,
+        ES384KeyPair,
+        ES384PublicKey,
+        ES512KeyPair,
+        ES512PublicKey,
+        ES256kKeyPair,
+        ES256kPublicKey,
+        BP256R1KeyPair,
+        BP256R1PublicKey,
+        BP384R1KeyPair,
+        BP384R1PublicKey,
+        BIP340KeyPair,
+        BIP340PublicKey,
+        Ed25519KeyPair,
+        Ed25519PublicKey,
+        Ed448KeyPair,
+        Ed448PublicKey,
+        RS256KeyPair,
+        RS256PublicKey,
+        RS384KeyPair,
+        RS384PublicKey,
+        RS512KeyPair,
+        RS512PublicKey,
+        PS256KeyPair,
+        PS256PublicKey,
+        PS384KeyPair,
+        PS384PublicKey,
+        PS512KeyPair,
+        PS512PublicKey,
+        RSAOAEPKeyPair,
+        RSAOAEPPublicKey,
+        RSAOAEP256KeyPair,
+        RSAOAEP256PublicKey,
+        ECDHP256KeyPair,
+        ECDHP256PublicKey,
+        X25519KeyPair,
+        X25519PublicKey,
+        X448KeyPair,
+        X448PublicKey,
+    );
+
+    #[cfg(feature = "sha3")]
+    jwk_serde!(HS256SHA3Key, HS384SHA3Key, HS512SHA3Key);
+
+    #[cfg(feature = "blake2b")]
+    jwk_serde!(Blake2bKey);
+
+    #[cfg(feature = "pq")]
+    jwk_serde!(
+        MLDSA44KeyPair,
+        MLDSA44PublicKey,
+        MLDSA65KeyPair,
+        MLDSA65PublicKey,
+        MLDSA65Ed25519KeyPair,
+        MLDSA65Ed25519PublicKey,
+    );
+}
@@ -0,0 +1,225 @@
+use ct_codecs::{Base64UrlSafeNoPadding, Decoder, Encoder};
+use rand::RngCore;
+use zeroize::Zeroize;
+
+use crate::common::*;
+use crate::encrypted_token::*;
+use crate::error::*;
+use crate::jwe_header::*;
+
+use super::aes_kw::*;
+use super::hmac::{pbkdf2_sha256, pbkdf2_sha384, pbkdf2_sha512};
+
+/// Default number of PBKDF2 iterations for new tokens.
+pub const PBES2_DEFAULT_ITERATIONS: u32 = 600_000;
+
+/// Default minimum number of PBKDF2 iterations accepted when decrypting a token.
+pub const PBES2_DEFAULT_MIN_ITERATIONS: u32 = 100_000;
+
+/// Default maximum number of PBKDF2 iterations accepted when decrypting a token.
+/// The count is chosen by the sender, so it has to be bounded to prevent denial of service.
+pub const PBES2_DEFAULT_MAX_ITERATIONS: u32 = 1_000_000;
+
+/// Length of the random salt input of new tokens, in bytes
+const PBES2_SALT_INPUT_LENGTH: usize = 16;
+
+/// Minimum length of the salt input accepted when decrypting a token (RFC 7518, section 4.8.1.1)
+const PBES2_MIN_SALT_INPUT_LENGTH: usize = 8;
+
+/// Password-based key encryption algorithms: a key derived from a password
+/// with PBKDF2 wraps a random content encryption key
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PBES2 {
+    /// PBKDF2-HMAC-SHA256 and AES-128 key wrap
+    HS256A128KW,
+    /// PBKDF2-HMAC-SHA384 and AES-192 key wrap
+    HS384A192KW,
+    /// PBKDF2-HMAC-SHA512 and AES-256 key wrap
+    HS512A256KW,
+}
+
+impl PBES2 {
+    /// The JWE key management algorithm name ("alg")
+    pub fn jwe_alg_name(&self) -> &'static str {
+        match self {
+            PBES2::HS256A128KW => "PBES2-HS256+A128KW",
+            PBES2::HS384A192KW => "PBES2-HS384+A192KW",
+            PBES2::HS512A256KW => "PBES2-HS512+A256KW",
+        }
+    }
+
+    /// Derive the key encryption key from a password
+    fn derive_key(&self, password: &[u8], salt_input: &[u8], iterations: u32) -> Vec<u8> {
+        let mut salt = self.jwe_alg_name().as_bytes().to_vec();
+        salt.push(0);
+        salt.extend_from_slice(salt_input);
+        match self {
+            PBES2::HS256A128KW => {
+                let mut kek = vec![0u8; 16];
+                pbkdf2_sha256(password, &salt, iterations, &mut kek);
+                kek
+            }
+            PBES2::HS384A192KW => {
+                let mut kek = vec![0u8; 24];
+                pbkdf2_sha384(password, &salt, iterations, &mut kek);
+                kek
+            }
+            PBES2::HS512A256KW => {
+                let mut kek = vec![0u8; 32];
+                pbkdf2_sha512(password, &salt, iterations, &mut kek);
+                kek
+            }
+        }
+    }
+}
+
+/// A password used to encrypt JWE tokens
+#[derive(Debug, Clone)]
+pub struct PasswordKey {
+    password: Vec<u8>,
+    pbes2: PBES2,
+    iterations: u32,
+    min_iterations: u32,
+    max_iterations: u32,
+    content_encryption: ContentEncryption,
+    key_id: Option<String>,
+}
+
+impl Drop for PasswordKey {
+    fn drop(&mut self) {
+        self.password.zeroize();
+    }
+}
+
+impl PasswordKey {
+    pub fn from_password(pbes2: PBES2, password: impl AsRef<[u8]>) -> Self {
+        PasswordKey {
+            password: password.as_ref().to_vec(),
+            pbes2,
+            iterations: PBES2_DEFAULT_ITERATIONS,
+            min_iterations: PBES2_DEFAULT_MIN_ITERATIONS,
+            max_iterations: PBES2_DEFAULT_MAX_ITERATIONS,
+            content_encryption: ContentEncryption::A256GCM,
+            key_id: None,
+        }
+    }
+
+    /// The password-based encryption algorithm this key is used with ("alg")
+    pub fn pbes2(&self) -> PBES2 {
+        self.pbes2
+    }
+
+    /// Set the number of PBKDF2 iterations for new tokens ("p2c")
+    pub fn with_iterations(mut self, iterations: u32) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    /// Set the minimum number of PBKDF2 iterations accepted when decrypting a token
+    pub fn with_min_iterations(mut self, min_iterations: u32) -> Self {
+        self.min_iterations = min_iterations;
+        self
+    }
+
+    /// Set the maximum number of PBKDF2 iterations accepted when decrypting a token
+    pub fn with_max_iterations(mut self, max_iterations: u32) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    fn check_iterations(&self, iterations: u32) -> Result<(), Error> {
+        ensure!(
+            iterations > 0
+                && iterations >= self.min_iterations
+                && iterations <= self.max_iterations,
+            JWTError::InvalidPasswordKDFParameters
+        );
+        Ok(())
+    }
+
+    pub fn key_id(&self) -> &Option<String> {
+        &self.key_id
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string());
+        self
+    }
+
+    /// Set the content encryption algorithm of new tokens
+    pub fn with_content_encryption(mut self, content_encryption: ContentEncryption) -> Self {
+        self.content_encryption = content_encryption;
+        self
+    }
+}
+
+impl EncryptionKeyLike for PasswordKey {
+    fn encrypt_payload(
+        &self,
+        payload: &[u8],
+        content_type: Option<&str>,
+        options: &EncryptionOptions,
+    ) -> Result<String, Error> {
+        EncryptedToken::build_with_key(
+            self,
+            self.content_encryption,
+            payload,
+            content_type,
+            options,
+        )
+    }
+
+    fn encrypt_key(&self, jwe_header: &mut JWEHeader, cek: &[u8]) -> Result<Vec<u8>, Error> {
+        self.check_iterations(self.iterations)?;
+        let mut salt_input = [0u8; PBES2_SALT_INPUT_LENGTH];
+        rand::thread_rng().fill_bytes(&mut salt_input);
+        jwe_header.algorithm = self.pbes2.jwe_alg_name().to_string();
+        jwe_header.key_id = self.key_id.clone();
+        jwe_header.pbes2_salt_input = Some(Base64UrlSafeNoPadding::encode_to_string(salt_input)?);
+        jwe_header.pbes2_count = Some(self.iterations);
+        let mut kek = self
+            .pbes2
+            .derive_key(&self.password, &salt_input, self.iterations);
+        let encrypted_key = aes_key_wrap(&kek, cek);
+        kek.zeroize();
+        encrypted_key
+    }
+}
+
+impl DecryptionKeyLike for PasswordKey {
+    fn decrypt_payload(
+        &self,
+        token: &str,
+        options: &VerificationOptions,
+    ) -> Result<DecryptedPayload, Error> {
+        EncryptedToken::decrypt_payload(
+            self.pbes2.jwe_alg_name(),
+            self.key_id.as_deref(),
+            token,
+            options,
+            |jwe_header, encrypted_key| {
+                let iterations = jwe_header
+                    .pbes2_count
+                    .ok_or(JWTError::InvalidPasswordKDFParameters)?;
+                self.check_iterations(iterations)?;
+                let salt_input = Base64UrlSafeNoPadding::decode_to_vec(
+                    jwe_header
+                        .pbes2_salt_input
+                        .as_ref()
+                        .ok_or(JWTError::InvalidPasswordKDFParameters)?,
+                    None,
+                )?;
+                ensure!(
+                    salt_input.len() >= PBES2_MIN_SALT_INPUT_LENGTH,
+                    JWTError::InvalidPasswordKDFParameters
+                );
+                let mut kek = self
+                    .pbes2
+                    .derive_key(&self.password, &salt_input, iterations);
+                let cek = aes_key_unwrap(&kek, encrypted_key);
+                kek.zeroize();
+                cek
+            },
+        )
+    }
+}
@@ -1,3 +1,4 @@
+use std::convert::TryFrom;
 use std::mem;
 
 use ct_codecs::{Base64UrlSafeNoPadding, Encoder};
@@ -5,20 +6,35 @@ use hmac_sha1_compact::Hash as SHA1;
 use hmac_sha256::Hash as SHA256;
 use hmac_sha512::sha384::Hash as SHA384;
 use hmac_sha512::Hash as SHA512;
-use rsa::pkcs1::{DecodeRsaPrivateKey as _, DecodeRsaPublicKey};
-use rsa::pkcs8::{DecodePrivateKey as _, DecodePublicKey as _, EncodePrivateKey as _};
+use rand::{CryptoRng, RngCore};
+use rsa::pkcs1::DecodeRsaPrivateKey as _;
+use rsa::pkcs8::{DecodePrivateKey as _, EncodePrivateKey as _};
 use rsa::{BigUint, PublicKey as _, PublicKeyParts as _};
 use serde::{de::DeserializeOwned, Serialize};
 #[allow(unused_imports)]
 use spki::{DecodePublicKey as _, EncodePublicKey as _};
+use zeroize::Zeroizing;
 
 use crate::claims::*;
 use crate::common::*;
 #[cfg(feature = "cwt")]
 use crate::cwt_token::*;
+use crate::encrypted_pem::decrypt_pem;
 use crate::error::*;
+use crate::jwk::*;
 use crate::jwt_header::*;
+use crate::openssh::{self, OpenSSHPrivateKey, OpenSSHPublicKey};
 use crate::token::*;
+use crate::x509;
+
+/// The largest public exponent accepted by the `rsa` crate
+const RSA_MAX_PUBLIC_EXPONENT: u64 = (1 << 33) - 1;
+
+fn biguint_to_u64(x: &BigUint) -> u64 {
+    x.to_bytes_be()
+        .iter()
+        .fold(0u64, |acc, &b| acc.wrapping_shl(8) | b as u64)
+}
 
 #[doc(hidden)]
 #[derive(Debug, Clone)]
@@ -30,6 +46,40 @@ impl AsRef<rsa::RsaPublicKey> for RSAPublicKey {
     }
 }
 
+/// The largest supported RSA modulus size, in bits
+pub const RSA_MAX_MODULUS_BITS: usize = 8192;
+
+/// Options for generating a new RSA key pair
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RSAKeyGenerationOptions {
+    /// Modulus size, in bits. Must be a multiple of 1024, between 2048 and
+    /// 8192.
+    pub modulus_bits: usize,
+    /// Public exponent. Must be odd, and between 3 and 2^33-1.
+    pub public_exponent: u64,
+}
+
+impl Default for RSAKeyGenerationOptions {
+    fn default() -> Self {
+        RSAKeyGenerationOptions {
+            modulus_bits: 2048,
+            public_exponent: 65537,
+        }
+    }
+}
+
+impl RSAKeyGenerationOptions {
+    pub fn with_modulus_bits(mut self, modulus_bits: usize) -> Self {
+        self.modulus_bits = modulus_bits;
+        self
+    }
+
+    pub fn with_public_exponent(mut self, public_exponent: u64) -> Self {
+        self.public_exponent = public_exponent;
+        self
+    }
+}
+
 pub struct RSAPublicKeyComponents {
     pub n: Vec<u8>,
     pub e: Vec<u8>,
@@ -37,25 +87,54 @@ pub struct RSAPublicKeyComponents {
 
 impl RSAPublicKey {
     pub fn from_der(der: &[u8]) -> Result<Self, Error> {
-        let rsa_pk = rsa::RsaPublicKey::from_public_key_der(der)
-            .or_else(|_| rsa::RsaPublicKey::from_pkcs1_der(der))?;
-        Ok(RSAPublicKey(rsa_pk))
+        // Accept both SubjectPublicKeyInfo and bare PKCS#1 encodings.
+        // Keys are decoded manually, as the `rsa` crate rejects moduli larger
+        // than 4096 bits.
+        let pkcs1_der = match rsa::pkcs8::SubjectPublicKeyInfo::try_from(der) {
+            Ok(spki) => {
+                ensure!(
+                    spki.algorithm.oid == rsa::pkcs1::ALGORITHM_OID,
+                    JWTError::InvalidPublicKey
+                );
+                spki.subject_public_key
+            }
+            Err(_) => der,
+        };
+        let pkcs1_pk = rsa::pkcs1::RsaPublicKey::try_from(pkcs1_der)?;
+        Self::from_components(
+            pkcs1_pk.modulus.as_bytes(),
+            pkcs1_pk.public_exponent.as_bytes(),
+        )
     }
 
     pub fn from_pem(pem: &str) -> Result<Self, Error> {
-        let pem = pem.trim();
-        let rsa_pk = rsa::RsaPublicKey::from_public_key_pem(pem)
-            .or_else(|_| rsa::RsaPublicKey::from_pkcs1_pem(pem))?;
-        Ok(RSAPublicKey(rsa_pk))
+        let (label, der) = rsa::pkcs1::der::Document::from_pem(pem.trim())?;
+        ensure!(
+            label == "PUBLIC KEY" || label == "RSA PUBLIC KEY",
+            JWTError::InvalidPublicKey
+        );
+        Self::from_der(der.as_bytes())
     }
 
     pub fn from_components(n: &[u8], e: &[u8]) -> Result<Self, Error> {
         let n = BigUint::from_bytes_be(n);
         let e = BigUint::from_bytes_be(e);
-        let rsa_pk = rsa::RsaPublicKey::new(n, e)?;
+        let rsa_pk = rsa::RsaPublicKey::new_with_max_size(n, e, RSA_MAX_MODULUS_BITS)?;
         Ok(RSAPublicKey(rsa_pk))
     }
 
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        let (n, e) = jwk.rsa_public_key()?;
+        Self::from_components(&n, &e)
+    }
+
+    pub fn from_openssh(line: &str) -> Result<Self, Error> {
+        match openssh::decode_public_key(line)? {
+            OpenSSHPublicKey::Rsa { n, e } => Self::from_components(&n, &e),
+            _ => bail!(JWTError::InvalidPublicKey),
+        }
+    }
+
     pub fn to_der(&self) -> Result<Vec<u8>, Error> {
         self.0
             .to_public_key_der()
@@ -74,6 +153,57 @@ impl RSAPublicKey {
         let e = self.0.e().to_bytes_be();
         RSAPublicKeyComponents { n, e }
     }
+
+    pub fn to_jwk(&self) -> Result<JWK, Error> {
+        let components = self.to_components();
+        JWK::new_rsa(&components.n, &components.e)
+    }
+
+    pub fn modulus_bits(&self) -> usize {
+        self.0.n().bits()
+    }
+
+    pub fn public_exponent(&self) -> u64 {
+        biguint_to_u64(self.0.e())
+    }
+
+    /// Recover the salt length of a PSS signature (RFC 8017, section 9.1.2).
+    /// The signature itself must be verified separately.
+    fn pss_salt_length(
+        &self,
+        signature: &[u8],
+        hash: fn(&[u8]) -> Vec<u8>,
+    ) -> Result<usize, Error> {
+        let em_bits = self.0.n().bits() - 1;
+        let em_len = em_bits.div_ceil(8);
+        let h_len = hash(&[]).len();
+        let em = BigUint::from_bytes_be(signature)
+            .modpow(self.0.e(), self.0.n())
+            .to_bytes_be();
+        ensure!(
+            em.len() <= em_len && em_len >= h_len + 2,
+            JWTError::InvalidSignature
+        );
+        let mut em_padded = vec![0u8; em_len - em.len()];
+        em_padded.extend_from_slice(&em);
+        ensure!(em_padded[em_len - 1] == 0xbc, JWTError::InvalidSignature);
+        let (masked_db, h) = em_padded[..em_len - 1].split_at(em_len - h_len - 1);
+        let mut db = masked_db.to_vec();
+        for (counter, chunk) in db.chunks_mut(h_len).enumerate() {
+            let mut seed = h.to_vec();
+            seed.extend_from_slice(&(counter as u32).to_be_bytes());
+            for (x, mask) in chunk.iter_mut().zip(hash(&seed)) {
+                *x ^= mask;
+            }
+        }
+        db[0] &= 0xff >> (8 * em_len - em_bits);
+        let separator = db
+            .iter()
+            .position(|&x| x != 0)
+            .ok_or(JWTError::InvalidSignature)?;
+        ensure!(db[separator] == 0x01, JWTError::InvalidSignature);
+        Ok(db.len() - separator - 1)
+    }
 }
 
 #[doc(hidden)]
@@ -90,10 +220,34 @@ impl AsRef<rsa::RsaPrivateKey> for RSAKeyPair {
 }
 
 impl RSAKeyPair {
+    fn validate(rsa_sk: &rsa::RsaPrivateKey) -> Result<(), Error> {
+        if rsa_sk.n().bits() <= rsa::RsaPublicKey::MAX_SIZE {
+            return Ok(rsa_sk.validate()?);
+        }
+        // The `rsa` crate refuses to validate moduli larger than 4096 bits
+        rsa::RsaPublicKey::new_with_max_size(
+            rsa_sk.n().clone(),
+            rsa_sk.e().clone(),
+            RSA_MAX_MODULUS_BITS,
+        )?;
+        let one = BigUint::from(1u8);
+        let mut m = one.clone();
+        for prime in rsa_sk.primes() {
+            ensure!(*prime > one, JWTError::InvalidKeyPair);
+            m *= prime;
+        }
+        ensure!(&m == rsa_sk.n(), JWTError::InvalidKeyPair);
+        let de = rsa_sk.d() * rsa_sk.e();
+        for prime in rsa_sk.primes() {
+            ensure!(&de % (prime - &one) == one, JWTError::InvalidKeyPair);
+        }
+        Ok(())
+    }
+
     pub fn from_der(der: &[u8]) -> Result<Self, Error> {
         let mut rsa_sk = rsa::RsaPrivateKey::from_pkcs8_der(der)
             .or_else(|_| rsa::RsaPrivateKey::from_pkcs1_der(der))?;
-        rsa_sk.validate()?;
+        Self::validate(&rsa_sk)?;
         rsa_sk.precompute()?;
         Ok(RSAKeyPair {
             rsa_sk,
@@ -105,7 +259,43 @@ impl RSAKeyPair {
         let pem = pem.trim();
         let mut rsa_sk = rsa::RsaPrivateKey::from_pkcs8_pem(pem)
             .or_else(|_| rsa::RsaPrivateKey::from_pkcs1_pem(pem))?;
-        rsa_sk.validate()?;
+        Self::validate(&rsa_sk)?;
+        rsa_sk.precompute()?;
+        Ok(RSAKeyPair {
+            rsa_sk,
+            metadata: None,
+        })
+    }
+
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        let (n, e) = jwk.rsa_public_key()?;
+        let [d, p, q] = jwk.rsa_private_key()?;
+        let mut rsa_sk = rsa::RsaPrivateKey::from_components(
+            BigUint::from_bytes_be(&n),
+            BigUint::from_bytes_be(&e),
+            BigUint::from_bytes_be(&d),
+            vec![BigUint::from_bytes_be(&p), BigUint::from_bytes_be(&q)],
+        )?;
+        Self::validate(&rsa_sk)?;
+        rsa_sk.precompute()?;
+        Ok(RSAKeyPair {
+            rsa_sk,
+            metadata: None,
+        })
+    }
+
+    pub fn from_openssh(pem: &str, passphrase: Option<&[u8]>) -> Result<Self, Error> {
+        let (n, e, d, p, q) = match openssh::decode_private_key(pem, passphrase)? {
+            OpenSSHPrivateKey::Rsa { n, e, d, p, q } => (n, e, d, p, q),
+            _ => bail!(JWTError::InvalidKeyPair),
+        };
+        let mut rsa_sk = rsa::RsaPrivateKey::from_components(
+            BigUint::from_bytes_be(&n),
+            BigUint::from_bytes_be(&e),
+            BigUint::from_bytes_be(&d),
+            vec![BigUint::from_bytes_be(&p), BigUint::from_bytes_be(&q)],
+        )?;
+        Self::validate(&rsa_sk)?;
         rsa_sk.precompute()?;
         Ok(RSAKeyPair {
             rsa_sk,
@@ -127,23 +317,70 @@ impl RSAKeyPair {
             .map(|x| x.to_string())
     }
 
+    /// Export the key pair as a JWK. Only keys with two prime factors can be
+    /// exported.
+    pub fn to_jwk(&self) -> Result<JWK, Error> {
+        let primes = self.rsa_sk.primes();
+        ensure!(primes.len() == 2, JWTError::InvalidKeyPair);
+        let (d, p, q) = (self.rsa_sk.d(), &primes[0], &primes[1]);
+        let one = BigUint::from(1u8);
+        let dp = Zeroizing::new(d % (p - &one));
+        let dq = Zeroizing::new(d % (q - &one));
+        // p is prime, so q^(p-2) is the inverse of q mod p
+        let qi = Zeroizing::new(q.modpow(&(p - BigUint::from(2u8)), p));
+        let members = [d, p, q, &*dp, &*dq, &*qi].map(|x| Zeroizing::new(x.to_bytes_be()));
+        self.public_key().to_jwk()?.with_rsa_private_key(&members)
+    }
+
     pub fn public_key(&self) -> RSAPublicKey {
         let rsa_pk = self.rsa_sk.to_public_key();
         RSAPublicKey(rsa_pk)
     }
 
     pub fn generate(modulus_bits: usize) -> Result<Self, Error> {
-        match modulus_bits {
-            2048 | 3072 | 4096 => {}
-            _ => bail!(JWTError::UnsupportedRSAModulus),
-        };
-        let mut rng = rand::thread_rng();
-        let rsa_sk = rsa::RsaPrivateKey::new(&mut rng, modulus_bits)?;
+        Self::generate_with_rng(&mut rand::thread_rng(), modulus_bits)
+    }
+
+    pub fn generate_with_rng(
+        rng: &mut (impl CryptoRng + RngCore),
+        modulus_bits: usize,
+    ) -> Result<Self, Error> {
+        let options = RSAKeyGenerationOptions::default().with_modulus_bits(modulus_bits);
+        Self::generate_with_options_and_rng(rng, &options)
+    }
+
+    pub fn generate_with_options_and_rng(
+        rng: &mut (impl CryptoRng + RngCore),
+        options: &RSAKeyGenerationOptions,
+    ) -> Result<Self, Error> {
+        ensure!(
+            options.modulus_bits % 1024 == 0
+                && (2048..=RSA_MAX_MODULUS_BITS).contains(&options.modulus_bits),
+            JWTError::UnsupportedRSAModulus
+        );
+        ensure!(
+            options.public_exponent % 2 == 1
+                && (3..=RSA_MAX_PUBLIC_EXPONENT).contains(&options.public_exponent),
+            JWTError::UnsupportedRSAPublicExponent
+        );
+        let rsa_sk = rsa::RsaPrivateKey::new_with_exp(
+            rng,
+            options.modulus_bits,
+            &BigUint::from(options.public_exponent),
+        )?;
         Ok(RSAKeyPair {
             rsa_sk,
             metadata: None,
         })
     }
+
+    pub fn modulus_bits(&self) -> usize {
+        self.rsa_sk.n().bits()
+    }
+
+    pub fn public_exponent(&self) -> u64 {
+        biguint_to_u64(self.rsa_sk.e())
+    }
 }
 
 pub trait RSAKeyPairLike {
@@ -155,6 +392,33 @@ pub trait RSAKeyPairLike {
     fn hash(message: &[u8]) -> Vec<u8>;
     fn padding_scheme(&self) -> rsa::PaddingScheme;
 
+    /// The size of the modulus, in bits
+    fn modulus_bits(&self) -> usize {
+        self.key_pair().modulus_bits()
+    }
+
+    /// The public exponent
+    fn public_exponent(&self) -> u64 {
+        self.key_pair().public_exponent()
+    }
+
+    /// Return an error if the modulus is smaller than `min_modulus_bits`
+    fn ensure_min_modulus_bits(&self, min_modulus_bits: usize) -> Result<(), Error> {
+        ensure!(self.modulus_bits() >= min_modulus_bits, JWTError::WeakKey);
+        Ok(())
+    }
+
+    /// Compute the signature of `authenticated`
+    fn signature(&self, authenticated: &[u8]) -> Result<Vec<u8>, Error> {
+        let digest = Self::hash(authenticated);
+        let mut rng = rand::thread_rng();
+        let token =
+            self.key_pair()
+                .as_ref()
+                .sign_blinded(&mut rng, self.padding_scheme(), &digest)?;
+        Ok(token)
+    }
+
     fn sign<CustomClaims: Serialize + DeserializeOwned>(
         &self,
         claims: JWTClaims<CustomClaims>,
@@ -162,15 +426,52 @@ pub trait RSAKeyPairLike {
         let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
             .with_metadata(self.metadata());
         Token::build(&jwt_header, claims, |authenticated| {
-            let digest = Self::hash(authenticated.as_bytes());
-            let mut rng = rand::thread_rng();
-            let token =
-                self.key_pair()
-                    .as_ref()
-                    .sign_blinded(&mut rng, self.padding_scheme(), &digest)?;
-            Ok(token)
+            self.signature(authenticated)
+        })
+    }
+
+    /// Sign claims, with additional header parameters
+    fn sign_with_header_options<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+        header_options: &HeaderOptions,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata())
+            .with_options(header_options);
+        Token::build(&jwt_header, claims, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Sign a detached payload, that is not included in the token (RFC 7797)
+    fn sign_detached(&self, payload: &str) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata());
+        Token::build_detached(jwt_header, payload, |authenticated| {
+            self.signature(authenticated)
+        })
+    }
+
+    /// Sign a detached binary payload, that is not included in the token
+    ///
+    /// The raw bytes of the payload are signed without being encoded
+    /// (RFC 7797), so that it can be any sequence of bytes.
+    fn sign_bytes_detached(&self, payload: &[u8]) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(Self::jwt_alg_name().to_string(), self.key_id().clone())
+            .with_metadata(self.metadata());
+        Token::build_bytes_detached(jwt_header, payload, |authenticated| {
+            self.signature(authenticated)
         })
     }
+
+    /// Export the key pair as a JWK, including the private key
+    fn to_jwk(&self) -> Result<JWK, Error> {
+        Ok(self
+            .key_pair()
+            .to_jwk()?
+            .with_usage(self.key_id(), Self::jwt_alg_name(), "sig"))
+    }
 }
 
 pub trait RSAPublicKeyLike {
@@ -178,49 +479,122 @@ pub trait RSAPublicKeyLike {
     fn public_key(&self) -> &RSAPublicKey;
     fn key_id(&self) -> &Option<String>;
     fn set_key_id(&mut self, key_id: String);
+
+    /// The SHA-256 thumbprint of the certificate the key was imported from
+    fn certificate_sha256_thumbprint(&self) -> Option<&[u8; 32]> {
+        None
+    }
     fn hash(message: &[u8]) -> Vec<u8>;
     fn padding_scheme(&self) -> rsa::PaddingScheme;
 
+    /// The size of the modulus, in bits
+    fn modulus_bits(&self) -> usize {
+        self.public_key().modulus_bits()
+    }
+
+    /// The public exponent
+    fn public_exponent(&self) -> u64 {
+        self.public_key().public_exponent()
+    }
+
+    /// Return an error if the modulus is smaller than `min_modulus_bits`
+    fn ensure_min_modulus_bits(&self, min_modulus_bits: usize) -> Result<(), Error> {
+        ensure!(self.modulus_bits() >= min_modulus_bits, JWTError::WeakKey);
+        Ok(())
+    }
+
+    /// The PSS salt length required for a signature to be accepted.
+    /// If `None`, any salt length is accepted.
+    fn salt_length(&self) -> Option<usize> {
+        None
+    }
+
+    /// Verify the signature of `authenticated`
+    fn verify_signature(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        let digest = Self::hash(authenticated);
+        self.public_key()
+            .as_ref()
+            .verify(self.padding_scheme(), &digest, signature)
+            .map_err(|_| JWTError::InvalidSignature)?;
+        if let Some(salt_length) = self.salt_length() {
+            ensure!(
+                self.public_key().pss_salt_length(signature, Self::hash)? == salt_length,
+                JWTError::InvalidSignature
+            );
+        }
+        Ok(())
+    }
+
     fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
         &self,
         token: &str,
         options: Option<VerificationOptions>,
     ) -> Result<JWTClaims<CustomClaims>, Error> {
+        check_key_policy(
+            options.as_ref(),
+            KeyParameters::RSA {
+                modulus_bits: self.modulus_bits(),
+            },
+        )?;
+        check_key_thumbprint(options.as_ref(), || self.thumbprint().ok())?;
+        check_certificate_thumbprint(options.as_ref(), self.certificate_sha256_thumbprint())?;
         Token::verify(
             Self::jwt_alg_name(),
             token,
             options,
-            |authenticated, signature| {
-                let digest = Self::hash(authenticated.as_bytes());
-                self.public_key()
-                    .as_ref()
-                    .verify(self.padding_scheme(), &digest, signature)
-                    .map_err(|_| JWTError::InvalidSignature)?;
-                Ok(())
-            },
+            |authenticated, signature| self.verify_signature(authenticated, signature),
         )
     }
 
+    fn verify_token_untyped(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<serde_json::Value, Error> {
+        let claims = self.verify_token::<NoCustomClaims>(token, options)?;
+        Ok(serde_json::to_value(claims)?)
+    }
+
     #[cfg(feature = "cwt")]
     fn verify_cwt_token<CustomClaims: Serialize + DeserializeOwned>(
         &self,
         token: &[u8],
         options: Option<VerificationOptions>,
-    ) -> Result<JWTClaims<NoCustomClaims>, Error> {
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        check_key_policy(
+            options.as_ref(),
+            KeyParameters::RSA {
+                modulus_bits: self.modulus_bits(),
+            },
+        )?;
+        check_key_thumbprint(options.as_ref(), || self.thumbprint().ok())?;
+        check_certificate_thumbprint(options.as_ref(), self.certificate_sha256_thumbprint())?;
         CWTToken::verify(
             Self::jwt_alg_name(),
             token,
             options,
-            |authenticated, signature| {
-                let digest = Self::hash(authenticated.as_bytes());
-                self.public_key()
-                    .as_ref()
-                    .verify(self.padding_scheme(), &digest, signature)
-                    .map_err(|_| JWTError::InvalidSignature)?;
-                Ok(())
-            },
+            |authenticated, signature| self.verify_signature(authenticated, signature),
         )
     }
+
+    /// Export the public key as a JWK, for publication in a key set
+    fn to_jwk(&self) -> Result<JWK, Error> {
+        Ok(self
+            .public_key()
+            .to_jwk()?
+            .with_usage(self.key_id(), Self::jwt_alg_name(), "sig"))
+    }
+
+    /// Compute the RFC 7638 thumbprint of the public key, using SHA-256
+    fn thumbprint(&self) -> Result<String, Error> {
+        self.to_jwk()?.thumbprint()
+    }
+
+    /// Compute the RFC 7638 thumbprint of the public key, using SHA-1, for
+    /// legacy applications
+    fn thumbprint_sha1(&self) -> Result<String, Error> {
+        self.to_jwk()?.thumbprint_sha1()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -233,6 +607,7 @@ pub struct RS256KeyPair {
 pub struct RS256PublicKey {
     pk: RSAPublicKey,
     key_id: Option<String>,
+    certificate_sha256_thumbprint: Option<[u8; 32]>,
 }
 
 impl RSAKeyPairLike for RS256KeyPair {
@@ -281,6 +656,38 @@ impl RS256KeyPair {
         })
     }
 
+    /// Import a password-protected key pair, encoded as an encrypted PKCS#8
+    /// or as a legacy encrypted PEM document
+    pub fn from_encrypted_pem(pem: &str, password: impl AsRef<[u8]>) -> Result<Self, Error> {
+        Self::from_pem(&decrypt_pem(pem, password.as_ref())?)
+    }
+
+    /// Import a key pair from an unencrypted OpenSSH private key
+    pub fn from_openssh(pem: &str) -> Result<Self, Error> {
+        Ok(RS256KeyPair {
+            key_pair: RSAKeyPair::from_openssh(pem, None)?,
+            key_id: None,
+        })
+    }
+
+    /// Import a key pair from an OpenSSH private key protected with a
+    /// passphrase
+    pub fn from_encrypted_openssh(pem: &str, passphrase: impl AsRef<[u8]>) -> Result<Self, Error> {
+        Ok(RS256KeyPair {
+            key_pair: RSAKeyPair::from_openssh(pem, Some(passphrase.as_ref()))?,
+            key_id: None,
+        })
+    }
+
+    /// Import a key pair from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["sign"])?;
+        Ok(RS256KeyPair {
+            key_pair: RSAKeyPair::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+        })
+    }
+
     pub fn to_der(&self) -> Result<Vec<u8>, Error> {
         self.key_pair.to_der()
     }
@@ -293,6 +700,7 @@ impl RS256KeyPair {
         RS256PublicKey {
             pk: self.key_pair.public_key(),
             key_id: self.key_id.clone(),
+            certificate_sha256_thumbprint: None,
         }
     }
 
@@ -303,6 +711,26 @@ impl RS256KeyPair {
         })
     }
 
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(
+        rng: &mut (impl CryptoRng + RngCore),
+        modulus_bits: usize,
+    ) -> Result<Self, Error> {
+        Ok(RS256KeyPair {
+            key_pair: RSAKeyPair::generate_with_rng(rng, modulus_bits)?,
+            key_id: None,
+        })
+    }
+
+    /// Generate a new key pair using the given options
+    pub fn generate_with_options(options: &RSAKeyGenerationOptions) -> Result<Self, Error> {
+        Ok(RS256KeyPair {
+            key_pair: RSAKeyPair::generate_with_options_and_rng(&mut rand::thread_rng(), options)?,
+            key_id: None,
+        })
+    }
+
     pub fn with_key_id(mut self, key_id: &str) -> Self {
         self.key_id = Some(key_id.to_string());
         self
@@ -333,6 +761,38 @@ impl RSAPublicKeyLike for RS256PublicKey {
     fn set_key_id(&mut self, key_id: String) {
         self.key_id = Some(key_id);
     }
+
+    fn certificate_sha256_thumbprint(&self) -> Option<&[u8; 32]> {
+        self.certificate_sha256_thumbprint.as_ref()
+    }
+}
+
+impl KeyLike for RS256PublicKey {
+    fn key_algorithm(&self) -> &'static str {
+        Self::jwt_alg_name()
+    }
+
+    fn key_identifier(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    fn verify_authenticated(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        self.verify_signature(authenticated, signature)
+    }
+
+    fn key_parameters(&self) -> KeyParameters {
+        KeyParameters::RSA {
+            modulus_bits: self.modulus_bits(),
+        }
+    }
+
+    fn key_thumbprint(&self) -> Option<String> {
+        self.thumbprint().ok()
+    }
+
+    fn key_certificate_thumbprint(&self) -> Option<&[u8; 32]> {
+        self.certificate_sha256_thumbprint.as_ref()
+    }
 }
 
 impl RS256PublicKey {
@@ -340,6 +800,7 @@ impl RS256PublicKey {
         Ok(RS256PublicKey {
             pk: RSAPublicKey::from_der(der)?,
             key_id: None,
+            certificate_sha256_thumbprint: None,
         })
     }
 
@@ -347,6 +808,40 @@ impl RS256PublicKey {
         Ok(RS256PublicKey {
             pk: RSAPublicKey::from_pem(pem)?,
             key_id: None,
+            certificate_sha256_thumbprint: None,
+        })
+    }
+
+    /// Import the public key of a DER-encoded X.509 certificate
+    pub fn from_x509_der(der: &[u8]) -> Result<Self, Error> {
+        let mut public_key = Self::from_der(x509::certificate_public_key(der)?)?;
+        public_key.certificate_sha256_thumbprint = Some(hmac_sha256::Hash::hash(der));
+        Ok(public_key)
+    }
+
+    /// Import the public key of the first certificate of a PEM-encoded X.509
+    /// certificate chain
+    pub fn from_x509_pem(pem: &str) -> Result<Self, Error> {
+        Self::from_x509_der(&x509::certificate_from_pem(pem)?)
+    }
+
+    /// Import a public key from a line of an OpenSSH `.pub` or
+    /// `authorized_keys` file
+    pub fn from_openssh(line: &str) -> Result<Self, Error> {
+        Ok(RS256PublicKey {
+            pk: RSAPublicKey::from_openssh(line)?,
+            key_id: None,
+            certificate_sha256_thumbprint: None,
+        })
+    }
+
+    /// Import a public key from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["verify"])?;
+        Ok(RS256PublicKey {
+            pk: RSAPublicKey::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+            certificate_sha256_thumbprint: None,
         })
     }
 
@@ -354,6 +849,7 @@ impl RS256PublicKey {
         Ok(RS256PublicKey {
             pk: RSAPublicKey::from_components(n, e)?,
             key_id: None,
+            certificate_sha256_thumbprint: None,
         })
     }
 
@@ -395,6 +891,7 @@ pub struct RS512KeyPair {
 pub struct RS512PublicKey {
     pk: RSAPublicKey,
     key_id: Option<String>,
+    certificate_sha256_thumbprint: Option<[u8; 32]>,
 }
 
 impl RSAKeyPairLike for RS512KeyPair {
@@ -443,6 +940,38 @@ impl RS512KeyPair {
         })
     }
 
+    /// Import a password-protected key pair, encoded as an encrypted PKCS#8
+    /// or as a legacy encrypted PEM document
+    pub fn from_encrypted_pem(pem: &str, password: impl AsRef<[u8]>) -> Result<Self, Error> {
+        Self::from_pem(&decrypt_pem(pem, password.as_ref())?)
+    }
+
+    /// Import a key pair from an unencrypted OpenSSH private key
+    pub fn from_openssh(pem: &str) -> Result<Self, Error> {
+        Ok(RS512KeyPair {
+            key_pair: RSAKeyPair::from_openssh(pem, None)?,
+            key_id: None,
+        })
+    }
+
+    /// Import a key pair from an OpenSSH private key protected with a
+    /// passphrase
+    pub fn from_encrypted_openssh(pem: &str, passphrase: impl AsRef<[u8]>) -> Result<Self, Error> {
+        Ok(RS512KeyPair {
+            key_pair: RSAKeyPair::from_openssh(pem, Some(passphrase.as_ref()))?,
+            key_id: None,
+        })
+    }
+
+    /// Import a key pair from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["sign"])?;
+        Ok(RS512KeyPair {
+            key_pair: RSAKeyPair::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+        })
+    }
+
     pub fn to_der(&self) -> Result<Vec<u8>, Error> {
         self.key_pair.to_der()
     }
@@ -455,6 +984,7 @@ impl RS512KeyPair {
         RS512PublicKey {
             pk: self.key_pair.public_key(),
             key_id: self.key_id.clone(),
+            certificate_sha256_thumbprint: None,
         }
     }
 
@@ -465,6 +995,26 @@ impl RS512KeyPair {
         })
     }
 
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(
+        rng: &mut (impl CryptoRng + RngCore),
+        modulus_bits: usize,
+    ) -> Result<Self, Error> {
+        Ok(RS512KeyPair {
+            key_pair: RSAKeyPair::generate_with_rng(rng, modulus_bits)?,
+            key_id: None,
+        })
+    }
+
+    /// Generate a new key pair using the given options
+    pub fn generate_with_options(options: &RSAKeyGenerationOptions) -> Result<Self, Error> {
+        Ok(RS512KeyPair {
+            key_pair: RSAKeyPair::generate_with_options_and_rng(&mut rand::thread_rng(), options)?,
+            key_id: None,
+        })
+    }
+
     pub fn with_key_id(mut self, key_id: &str) -> Self {
         self.key_id = Some(key_id.to_string());
         self
@@ -495,6 +1045,38 @@ impl RSAPublicKeyLike for RS512PublicKey {
     fn set_key_id(&mut self, key_id: String) {
         self.key_id = Some(key_id);
     }
+
+    fn certificate_sha256_thumbprint(&self) -> Option<&[u8; 32]> {
+        self.certificate_sha256_thumbprint.as_ref()
+    }
+}
+
+impl KeyLike for RS512PublicKey {
+    fn key_algorithm(&self) -> &'static str {
+        Self::jwt_alg_name()
+    }
+
+    fn key_identifier(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    fn verify_authenticated(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        self.verify_signature(authenticated, signature)
+    }
+
+    fn key_parameters(&self) -> KeyParameters {
+        KeyParameters::RSA {
+            modulus_bits: self.modulus_bits(),
+        }
+    }
+
+    fn key_thumbprint(&self) -> Option<String> {
+        self.thumbprint().ok()
+    }
+
+    fn key_certificate_thumbprint(&self) -> Option<&[u8; 32]> {
+        self.certificate_sha256_thumbprint.as_ref()
+    }
 }
 
 impl RS512PublicKey {
@@ -502,6 +1084,7 @@ impl RS512PublicKey {
         Ok(RS512PublicKey {
             pk: RSAPublicKey::from_der(der)?,
             key_id: None,
+            certificate_sha256_thumbprint: None,
         })
     }
 
@@ -509,6 +1092,40 @@ impl RS512PublicKey {
         Ok(RS512PublicKey {
             pk: RSAPublicKey::from_pem(pem)?,
             key_id: None,
+            certificate_sha256_thumbprint: None,
+        })
+    }
+
+    /// Import the public key of a DER-encoded X.509 certificate
+    pub fn from_x509_der(der: &[u8]) -> Result<Self, Error> {
+        let mut public_key = Self::from_der(x509::certificate_public_key(der)?)?;
+        public_key.certificate_sha256_thumbprint = Some(hmac_sha256::Hash::hash(der));
+        Ok(public_key)
+    }
+
+    /// Import the public key of the first certificate of a PEM-encoded X.509
+    /// certificate chain
+    pub fn from_x509_pem(pem: &str) -> Result<Self, Error> {
+        Self::from_x509_der(&x509::certificate_from_pem(pem)?)
+    }
+
+    /// Import a public key from a line of an OpenSSH `.pub` or
+    /// `authorized_keys` file
+    pub fn from_openssh(line: &str) -> Result<Self, Error> {
+        Ok(RS512PublicKey {
+            pk: RSAPublicKey::from_openssh(line)?,
+            key_id: None,
+            certificate_sha256_thumbprint: None,
+        })
+    }
+
+    /// Import a public key from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["verify"])?;
+        Ok(RS512PublicKey {
+            pk: RSAPublicKey::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+            certificate_sha256_thumbprint: None,
         })
     }
 
@@ -516,6 +1133,7 @@ impl RS512PublicKey {
         Ok(RS512PublicKey {
             pk: RSAPublicKey::from_components(n, e)?,
             key_id: None,
+            certificate_sha256_thumbprint: None,
         })
     }
 
@@ -557,6 +1175,7 @@ pub struct RS384KeyPair {
 pub struct RS384PublicKey {
     pk: RSAPublicKey,
     key_id: Option<String>,
+    certificate_sha256_thumbprint: Option<[u8; 32]>,
 }
 
 impl RSAKeyPairLike for RS384KeyPair {
@@ -605,6 +1224,38 @@ impl RS384KeyPair {
         })
     }
 
+    /// Import a password-protected key pair, encoded as an encrypted PKCS#8
+    /// or as a legacy encrypted PEM document
+    pub fn from_encrypted_pem(pem: &str, password: impl AsRef<[u8]>) -> Result<Self, Error> {
+        Self::from_pem(&decrypt_pem(pem, password.as_ref())?)
+    }
+
+    /// Import a key pair from an unencrypted OpenSSH private key
+    pub fn from_openssh(pem: &str) -> Result<Self, Error> {
+        Ok(RS384KeyPair {
+            key_pair: RSAKeyPair::from_openssh(pem, None)?,
+            key_id: None,
+        })
+    }
+
+    /// Import a key pair from an OpenSSH private key protected with a
+    /// passphrase
+    pub fn from_encrypted_openssh(pem: &str, passphrase: impl AsRef<[u8]>) -> Result<Self, Error> {
+        Ok(RS384KeyPair {
+            key_pair: RSAKeyPair::from_openssh(pem, Some(passphrase.as_ref()))?,
+            key_id: None,
+        })
+    }
+
+    /// Import a key pair from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["sign"])?;
+        Ok(RS384KeyPair {
+            key_pair: RSAKeyPair::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+        })
+    }
+
     pub fn to_der(&self) -> Result<Vec<u8>, Error> {
         self.key_pair.to_der()
     }
@@ -617,6 +1268,7 @@ impl RS384KeyPair {
         RS384PublicKey {
             pk: self.key_pair.public_key(),
             key_id: self.key_id.clone(),
+            certificate_sha256_thumbprint: None,
         }
     }
 
@@ -627,6 +1279,26 @@ impl RS384KeyPair {
         })
     }
 
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(
+        rng: &mut (impl CryptoRng + RngCore),
+        modulus_bits: usize,
+    ) -> Result<Self, Error> {
+        Ok(RS384KeyPair {
+            key_pair: RSAKeyPair::generate_with_rng(rng, modulus_bits)?,
+            key_id: None,
+        })
+    }
+
+    /// Generate a new key pair using the given options
+    pub fn generate_with_options(options: &RSAKeyGenerationOptions) -> Result<Self, Error> {
+        Ok(RS384KeyPair {
+            key_pair: RSAKeyPair::generate_with_options_and_rng(&mut rand::thread_rng(), options)?,
+            key_id: None,
+        })
+    }
+
     pub fn with_key_id(mut self, key_id: &str) -> Self {
         self.key_id = Some(key_id.to_string());
         self
@@ -657,6 +1329,38 @@ impl RSAPublicKeyLike for RS384PublicKey {
     fn set_key_id(&mut self, key_id: String) {
         self.key_id = Some(key_id);
     }
+
+    fn certificate_sha256_thumbprint(&self) -> Option<&[u8; 32]> {
+        self.certificate_sha256_thumbprint.as_ref()
+    }
+}
+
+impl KeyLike for RS384PublicKey {
+    fn key_algorithm(&self) -> &'static str {
+        Self::jwt_alg_name()
+    }
+
+    fn key_identifier(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    fn verify_authenticated(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        self.verify_signature(authenticated, signature)
+    }
+
+    fn key_parameters(&self) -> KeyParameters {
+        KeyParameters::RSA {
+            modulus_bits: self.modulus_bits(),
+        }
+    }
+
+    fn key_thumbprint(&self) -> Option<String> {
+        self.thumbprint().ok()
+    }
+
+    fn key_certificate_thumbprint(&self) -> Option<&[u8; 32]> {
+        self.certificate_sha256_thumbprint.as_ref()
+    }
 }
 
 impl RS384PublicKey {
@@ -664,6 +1368,7 @@ impl RS384PublicKey {
         Ok(RS384PublicKey {
             pk: RSAPublicKey::from_der(der)?,
             key_id: None,
+            certificate_sha256_thumbprint: None,
         })
     }
 
@@ -671,6 +1376,40 @@ impl RS384PublicKey {
         Ok(RS384PublicKey {
             pk: RSAPublicKey::from_pem(pem)?,
             key_id: None,
+            certificate_sha256_thumbprint: None,
+        })
+    }
+
+    /// Import the public key of a DER-encoded X.509 certificate
+    pub fn from_x509_der(der: &[u8]) -> Result<Self, Error> {
+        let mut public_key = Self::from_der(x509::certificate_public_key(der)?)?;
+        public_key.certificate_sha256_thumbprint = Some(hmac_sha256::Hash::hash(der));
+        Ok(public_key)
+    }
+
+    /// Import the public key of the first certificate of a PEM-encoded X.509
+    /// certificate chain
+    pub fn from_x509_pem(pem: &str) -> Result<Self, Error> {
+        Self::from_x509_der(&x509::certificate_from_pem(pem)?)
+    }
+
+    /// Import a public key from a line of an OpenSSH `.pub` or
+    /// `authorized_keys` file
+    pub fn from_openssh(line: &str) -> Result<Self, Error> {
+        Ok(RS384PublicKey {
+            pk: RSAPublicKey::from_openssh(line)?,
+            key_id: None,
+            certificate_sha256_thumbprint: None,
+        })
+    }
+
+    /// Import a public key from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["verify"])?;
+        Ok(RS384PublicKey {
+            pk: RSAPublicKey::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+            certificate_sha256_thumbprint: None,
         })
     }
 
@@ -678,6 +1417,7 @@ impl RS384PublicKey {
         Ok(RS384PublicKey {
             pk: RSAPublicKey::from_components(n, e)?,
             key_id: None,
+            certificate_sha256_thumbprint: None,
         })
     }
 
@@ -713,12 +1453,15 @@ impl RS384PublicKey {
 pub struct PS256KeyPair {
     key_pair: RSAKeyPair,
     key_id: Option<String>,
+    salt_length: Option<usize>,
 }
 
 #[derive(Debug, Clone)]
 pub struct PS256PublicKey {
     pk: RSAPublicKey,
     key_id: Option<String>,
+    salt_length: Option<usize>,
+    certificate_sha256_thumbprint: Option<[u8; 32]>,
 }
 
 impl RSAKeyPairLike for PS256KeyPair {
@@ -748,7 +1491,10 @@ impl RSAKeyPairLike for PS256KeyPair {
     }
 
     fn padding_scheme(&self) -> rsa::PaddingScheme {
-        rsa::PaddingScheme::new_pss::<SHA256>()
+        match self.salt_length {
+            Some(salt_length) => rsa::PaddingScheme::new_pss_with_salt::<SHA256>(salt_length),
+            None => rsa::PaddingScheme::new_pss::<SHA256>(),
+        }
     }
 }
 
@@ -757,6 +1503,7 @@ impl PS256KeyPair {
         Ok(PS256KeyPair {
             key_pair: RSAKeyPair::from_der(der)?,
             key_id: None,
+            salt_length: None,
         })
     }
 
@@ -764,6 +1511,42 @@ impl PS256KeyPair {
         Ok(PS256KeyPair {
             key_pair: RSAKeyPair::from_pem(pem)?,
             key_id: None,
+            salt_length: None,
+        })
+    }
+
+    /// Import a password-protected key pair, encoded as an encrypted PKCS#8
+    /// or as a legacy encrypted PEM document
+    pub fn from_encrypted_pem(pem: &str, password: impl AsRef<[u8]>) -> Result<Self, Error> {
+        Self::from_pem(&decrypt_pem(pem, password.as_ref())?)
+    }
+
+    /// Import a key pair from an unencrypted OpenSSH private key
+    pub fn from_openssh(pem: &str) -> Result<Self, Error> {
+        Ok(PS256KeyPair {
+            key_pair: RSAKeyPair::from_openssh(pem, None)?,
+            key_id: None,
+            salt_length: None,
+        })
+    }
+
+    /// Import a key pair from an OpenSSH private key protected with a
+    /// passphrase
+    pub fn from_encrypted_openssh(pem: &str, passphrase: impl AsRef<[u8]>) -> Result<Self, Error> {
+        Ok(PS256KeyPair {
+            key_pair: RSAKeyPair::from_openssh(pem, Some(passphrase.as_ref()))?,
+            key_id: None,
+            salt_length: None,
+        })
+    }
+
+    /// Import a key pair from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["sign"])?;
+        Ok(PS256KeyPair {
+            key_pair: RSAKeyPair::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+            salt_length: None,
         })
     }
 
@@ -779,6 +1562,8 @@ impl PS256KeyPair {
         PS256PublicKey {
             pk: self.key_pair.public_key(),
             key_id: self.key_id.clone(),
+            certificate_sha256_thumbprint: None,
+            salt_length: self.salt_length,
         }
     }
 
@@ -786,6 +1571,29 @@ impl PS256KeyPair {
         Ok(PS256KeyPair {
             key_pair: RSAKeyPair::generate(modulus_bits)?,
             key_id: None,
+            salt_length: None,
+        })
+    }
+
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(
+        rng: &mut (impl CryptoRng + RngCore),
+        modulus_bits: usize,
+    ) -> Result<Self, Error> {
+        Ok(PS256KeyPair {
+            key_pair: RSAKeyPair::generate_with_rng(rng, modulus_bits)?,
+            key_id: None,
+            salt_length: None,
+        })
+    }
+
+    /// Generate a new key pair using the given options
+    pub fn generate_with_options(options: &RSAKeyGenerationOptions) -> Result<Self, Error> {
+        Ok(PS256KeyPair {
+            key_pair: RSAKeyPair::generate_with_options_and_rng(&mut rand::thread_rng(), options)?,
+            key_id: None,
+            salt_length: None,
         })
     }
 
@@ -793,6 +1601,13 @@ impl PS256KeyPair {
         self.key_id = Some(key_id.to_string());
         self
     }
+
+    /// Use a fixed PSS salt length for new signatures, instead of the
+    /// largest possible salt.
+    pub fn with_salt_length(mut self, salt_length: usize) -> Self {
+        self.salt_length = Some(salt_length);
+        self
+    }
 }
 
 impl RSAPublicKeyLike for PS256PublicKey {
@@ -808,6 +1623,10 @@ impl RSAPublicKeyLike for PS256PublicKey {
         rsa::PaddingScheme::new_pss::<SHA256>()
     }
 
+    fn salt_length(&self) -> Option<usize> {
+        self.salt_length
+    }
+
     fn public_key(&self) -> &RSAPublicKey {
         &self.pk
     }
@@ -819,6 +1638,38 @@ impl RSAPublicKeyLike for PS256PublicKey {
     fn set_key_id(&mut self, key_id: String) {
         self.key_id = Some(key_id);
     }
+
+    fn certificate_sha256_thumbprint(&self) -> Option<&[u8; 32]> {
+        self.certificate_sha256_thumbprint.as_ref()
+    }
+}
+
+impl KeyLike for PS256PublicKey {
+    fn key_algorithm(&self) -> &'static str {
+        Self::jwt_alg_name()
+    }
+
+    fn key_identifier(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    fn verify_authenticated(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        self.verify_signature(authenticated, signature)
+    }
+
+    fn key_parameters(&self) -> KeyParameters {
+        KeyParameters::RSA {
+            modulus_bits: self.modulus_bits(),
+        }
+    }
+
+    fn key_thumbprint(&self) -> Option<String> {
+        self.thumbprint().ok()
+    }
+
+    fn key_certificate_thumbprint(&self) -> Option<&[u8; 32]> {
+        self.certificate_sha256_thumbprint.as_ref()
+    }
 }
 
 impl PS256PublicKey {
@@ -826,6 +1677,8 @@ impl PS256PublicKey {
         Ok(PS256PublicKey {
             pk: RSAPublicKey::from_der(der)?,
             key_id: None,
+            certificate_sha256_thumbprint: None,
+            salt_length: None,
         })
     }
 
@@ -833,6 +1686,43 @@ impl PS256PublicKey {
         Ok(PS256PublicKey {
             pk: RSAPublicKey::from_pem(pem)?,
             key_id: None,
+            certificate_sha256_thumbprint: None,
+            salt_length: None,
+        })
+    }
+
+    /// Import the public key of a DER-encoded X.509 certificate
+    pub fn from_x509_der(der: &[u8]) -> Result<Self, Error> {
+        let mut public_key = Self::from_der(x509::certificate_public_key(der)?)?;
+        public_key.certificate_sha256_thumbprint = Some(hmac_sha256::Hash::hash(der));
+        Ok(public_key)
+    }
+
+    /// Import the public key of the first certificate of a PEM-encoded X.509
+    /// certificate chain
+    pub fn from_x509_pem(pem: &str) -> Result<Self, Error> {
+        Self::from_x509_der(&x509::certificate_from_pem(pem)?)
+    }
+
+    /// Import a public key from a line of an OpenSSH `.pub` or
+    /// `authorized_keys` file
+    pub fn from_openssh(line: &str) -> Result<Self, Error> {
+        Ok(PS256PublicKey {
+            pk: RSAPublicKey::from_openssh(line)?,
+            key_id: None,
+            certificate_sha256_thumbprint: None,
+            salt_length: None,
+        })
+    }
+
+    /// Import a public key from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["verify"])?;
+        Ok(PS256PublicKey {
+            pk: RSAPublicKey::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+            certificate_sha256_thumbprint: None,
+            salt_length: None,
         })
     }
 
@@ -840,6 +1730,8 @@ impl PS256PublicKey {
         Ok(PS256PublicKey {
             pk: RSAPublicKey::from_components(n, e)?,
             key_id: None,
+            certificate_sha256_thumbprint: None,
+            salt_length: None,
         })
     }
 
@@ -859,6 +1751,13 @@ impl PS256PublicKey {
         self.key_id = Some(key_id.to_string());
         self
     }
+
+    /// Only accept signatures using the given PSS salt length.
+    /// By default, the salt length is automatically detected.
+    pub fn with_salt_length(mut self, salt_length: usize) -> Self {
+        self.salt_length = Some(salt_length);
+        self
+    }
 }
 
 //
@@ -867,12 +1766,15 @@ impl PS256PublicKey {
 pub struct PS512KeyPair {
     key_pair: RSAKeyPair,
     key_id: Option<String>,
+    salt_length: Option<usize>,
 }
 
 #[derive(Debug, Clone)]
 pub struct PS512PublicKey {
     pk: RSAPublicKey,
     key_id: Option<String>,
+    salt_length: Option<usize>,
+    certificate_sha256_thumbprint: Option<[u8; 32]>,
 }
 
 impl RSAKeyPairLike for PS512KeyPair {
@@ -902,7 +1804,10 @@ impl RSAKeyPairLike for PS512KeyPair {
     }
 
     fn padding_scheme(&self) -> rsa::PaddingScheme {
-        rsa::PaddingScheme::new_pss::<SHA512>()
+        match self.salt_length {
+            Some(salt_length) => rsa::PaddingScheme::new_pss_with_salt::<SHA512>(salt_length),
+            None => rsa::PaddingScheme::new_pss::<SHA512>(),
+        }
     }
 }
 
@@ -911,6 +1816,7 @@ impl PS512KeyPair {
         Ok(PS512KeyPair {
             key_pair: RSAKeyPair::from_der(der)?,
             key_id: None,
+            salt_length: None,
         })
     }
 
@@ -918,6 +1824,42 @@ impl PS512KeyPair {
         Ok(PS512KeyPair {
             key_pair: RSAKeyPair::from_pem(pem)?,
             key_id: None,
+            salt_length: None,
+        })
+    }
+
+    /// Import a password-protected key pair, encoded as an encrypted PKCS#8
+    /// or as a legacy encrypted PEM document
+    pub fn from_encrypted_pem(pem: &str, password: impl AsRef<[u8]>) -> Result<Self, Error> {
+        Self::from_pem(&decrypt_pem(pem, password.as_ref())?)
+    }
+
+    /// Import a key pair from an unencrypted OpenSSH private key
+    pub fn from_openssh(pem: &str) -> Result<Self, Error> {
+        Ok(PS512KeyPair {
+            key_pair: RSAKeyPair::from_openssh(pem, None)?,
+            key_id: None,
+            salt_length: None,
+        })
+    }
+
+    /// Import a key pair from an OpenSSH private key protected with a
+    /// passphrase
+    pub fn from_encrypted_openssh(pem: &str, passphrase: impl AsRef<[u8]>) -> Result<Self, Error> {
+        Ok(PS512KeyPair {
+            key_pair: RSAKeyPair::from_openssh(pem, Some(passphrase.as_ref()))?,
+            key_id: None,
+            salt_length: None,
+        })
+    }
+
+    /// Import a key pair from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["sign"])?;
+        Ok(PS512KeyPair {
+            key_pair: RSAKeyPair::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+            salt_length: None,
         })
     }
 
@@ -933,6 +1875,8 @@ impl PS512KeyPair {
         PS512PublicKey {
             pk: self.key_pair.public_key(),
             key_id: self.key_id.clone(),
+            certificate_sha256_thumbprint: None,
+            salt_length: self.salt_length,
         }
     }
 
@@ -940,6 +1884,29 @@ impl PS512KeyPair {
         Ok(PS512KeyPair {
             key_pair: RSAKeyPair::generate(modulus_bits)?,
             key_id: None,
+            salt_length: None,
+        })
+    }
+
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(
+        rng: &mut (impl CryptoRng + RngCore),
+        modulus_bits: usize,
+    ) -> Result<Self, Error> {
+        Ok(PS512KeyPair {
+            key_pair: RSAKeyPair::generate_with_rng(rng, modulus_bits)?,
+            key_id: None,
+            salt_length: None,
+        })
+    }
+
+    /// Generate a new key pair using the given options
+    pub fn generate_with_options(options: &RSAKeyGenerationOptions) -> Result<Self, Error> {
+        Ok(PS512KeyPair {
+            key_pair: RSAKeyPair::generate_with_options_and_rng(&mut rand::thread_rng(), options)?,
+            key_id: None,
+            salt_length: None,
         })
     }
 
@@ -947,6 +1914,13 @@ impl PS512KeyPair {
         self.key_id = Some(key_id.to_string());
         self
     }
+
+    /// Use a fixed PSS salt length for new signatures, instead of the
+    /// largest possible salt.
+    pub fn with_salt_length(mut self, salt_length: usize) -> Self {
+        self.salt_length = Some(salt_length);
+        self
+    }
 }
 
 impl RSAPublicKeyLike for PS512PublicKey {
@@ -962,6 +1936,10 @@ impl RSAPublicKeyLike for PS512PublicKey {
         rsa::PaddingScheme::new_pss::<SHA512>()
     }
 
+    fn salt_length(&self) -> Option<usize> {
+        self.salt_length
+    }
+
     fn public_key(&self) -> &RSAPublicKey {
         &self.pk
     }
@@ -973,6 +1951,38 @@ impl RSAPublicKeyLike for PS512PublicKey {
     fn set_key_id(&mut self, key_id: String) {
         self.key_id = Some(key_id);
     }
+
+    fn certificate_sha256_thumbprint(&self) -> Option<&[u8; 32]> {
+        self.certificate_sha256_thumbprint.as_ref()
+    }
+}
+
+impl KeyLike for PS512PublicKey {
+    fn key_algorithm(&self) -> &'static str {
+        Self::jwt_alg_name()
+    }
+
+    fn key_identifier(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    fn verify_authenticated(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        self.verify_signature(authenticated, signature)
+    }
+
+    fn key_parameters(&self) -> KeyParameters {
+        KeyParameters::RSA {
+            modulus_bits: self.modulus_bits(),
+        }
+    }
+
+    fn key_thumbprint(&self) -> Option<String> {
+        self.thumbprint().ok()
+    }
+
+    fn key_certificate_thumbprint(&self) -> Option<&[u8; 32]> {
+        self.certificate_sha256_thumbprint.as_ref()
+    }
 }
 
 impl PS512PublicKey {
@@ -980,6 +1990,8 @@ impl PS512PublicKey {
         Ok(PS512PublicKey {
             pk: RSAPublicKey::from_der(der)?,
             key_id: None,
+            certificate_sha256_thumbprint: None,
+            salt_length: None,
         })
     }
 
@@ -987,6 +1999,43 @@ impl PS512PublicKey {
         Ok(PS512PublicKey {
             pk: RSAPublicKey::from_pem(pem)?,
             key_id: None,
+            certificate_sha256_thumbprint: None,
+            salt_length: None,
+        })
+    }
+
+    /// Import the public key of a DER-encoded X.509 certificate
+    pub fn from_x509_der(der: &[u8]) -> Result<Self, Error> {
+        let mut public_key = Self::from_der(x509::certificate_public_key(der)?)?;
+        public_key.certificate_sha256_thumbprint = Some(hmac_sha256::Hash::hash(der));
+        Ok(public_key)
+    }
+
+    /// Import the public key of the first certificate of a PEM-encoded X.509
+    /// certificate chain
+    pub fn from_x509_pem(pem: &str) -> Result<Self, Error> {
+        Self::from_x509_der(&x509::certificate_from_pem(pem)?)
+    }
+
+    /// Import a public key from a line of an OpenSSH `.pub` or
+    /// `authorized_keys` file
+    pub fn from_openssh(line: &str) -> Result<Self, Error> {
+        Ok(PS512PublicKey {
+            pk: RSAPublicKey::from_openssh(line)?,
+            key_id: None,
+            certificate_sha256_thumbprint: None,
+            salt_length: None,
+        })
+    }
+
+    /// Import a public key from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["verify"])?;
+        Ok(PS512PublicKey {
+            pk: RSAPublicKey::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+            certificate_sha256_thumbprint: None,
+            salt_length: None,
         })
     }
 
@@ -994,6 +2043,8 @@ impl PS512PublicKey {
         Ok(PS512PublicKey {
             pk: RSAPublicKey::from_components(n, e)?,
             key_id: None,
+            certificate_sha256_thumbprint: None,
+            salt_length: None,
         })
     }
 
@@ -1014,6 +2065,13 @@ impl PS512PublicKey {
         self
     }
 
+    /// Only accept signatures using the given PSS salt length.
+    /// By default, the salt length is automatically detected.
+    pub fn with_salt_length(mut self, salt_length: usize) -> Self {
+        self.salt_length = Some(salt_length);
+        self
+    }
+
     pub fn sha1_thumbprint(&self) -> String {
         Base64UrlSafeNoPadding::encode_to_string(SHA1::hash(&self.pk.to_der().unwrap())).unwrap()
     }
@@ -1029,12 +2087,15 @@ impl PS512PublicKey {
 pub struct PS384KeyPair {
     key_pair: RSAKeyPair,
     key_id: Option<String>,
+    salt_length: Option<usize>,
 }
 
 #[derive(Debug, Clone)]
 pub struct PS384PublicKey {
     pk: RSAPublicKey,
     key_id: Option<String>,
+    salt_length: Option<usize>,
+    certificate_sha256_thumbprint: Option<[u8; 32]>,
 }
 
 impl RSAKeyPairLike for PS384KeyPair {
@@ -1064,7 +2125,10 @@ impl RSAKeyPairLike for PS384KeyPair {
     }
 
     fn padding_scheme(&self) -> rsa::PaddingScheme {
-        rsa::PaddingScheme::new_pss::<SHA384>()
+        match self.salt_length {
+            Some(salt_length) => rsa::PaddingScheme::new_pss_with_salt::<SHA384>(salt_length),
+            None => rsa::PaddingScheme::new_pss::<SHA384>(),
+        }
     }
 }
 
@@ -1073,6 +2137,7 @@ impl PS384KeyPair {
         Ok(PS384KeyPair {
             key_pair: RSAKeyPair::from_der(der)?,
             key_id: None,
+            salt_length: None,
         })
     }
 
@@ -1080,6 +2145,42 @@ impl PS384KeyPair {
         Ok(PS384KeyPair {
             key_pair: RSAKeyPair::from_pem(pem)?,
             key_id: None,
+            salt_length: None,
+        })
+    }
+
+    /// Import a password-protected key pair, encoded as an encrypted PKCS#8
+    /// or as a legacy encrypted PEM document
+    pub fn from_encrypted_pem(pem: &str, password: impl AsRef<[u8]>) -> Result<Self, Error> {
+        Self::from_pem(&decrypt_pem(pem, password.as_ref())?)
+    }
+
+    /// Import a key pair from an unencrypted OpenSSH private key
+    pub fn from_openssh(pem: &str) -> Result<Self, Error> {
+        Ok(PS384KeyPair {
+            key_pair: RSAKeyPair::from_openssh(pem, None)?,
+            key_id: None,
+            salt_length: None,
+        })
+    }
+
+    /// Import a key pair from an OpenSSH private key protected with a
+    /// passphrase
+    pub fn from_encrypted_openssh(pem: &str, passphrase: impl AsRef<[u8]>) -> Result<Self, Error> {
+        Ok(PS384KeyPair {
+            key_pair: RSAKeyPair::from_openssh(pem, Some(passphrase.as_ref()))?,
+            key_id: None,
+            salt_length: None,
+        })
+    }
+
+    /// Import a key pair from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["sign"])?;
+        Ok(PS384KeyPair {
+            key_pair: RSAKeyPair::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+            salt_length: None,
         })
     }
 
@@ -1095,6 +2196,8 @@ impl PS384KeyPair {
         PS384PublicKey {
             pk: self.key_pair.public_key(),
             key_id: self.key_id.clone(),
+            certificate_sha256_thumbprint: None,
+            salt_length: self.salt_length,
         }
     }
 
@@ -1102,6 +2205,29 @@ impl PS384KeyPair {
         Ok(PS384KeyPair {
             key_pair: RSAKeyPair::generate(modulus_bits)?,
             key_id: None,
+            salt_length: None,
+        })
+    }
+
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(
+        rng: &mut (impl CryptoRng + RngCore),
+        modulus_bits: usize,
+    ) -> Result<Self, Error> {
+        Ok(PS384KeyPair {
+            key_pair: RSAKeyPair::generate_with_rng(rng, modulus_bits)?,
+            key_id: None,
+            salt_length: None,
+        })
+    }
+
+    /// Generate a new key pair using the given options
+    pub fn generate_with_options(options: &RSAKeyGenerationOptions) -> Result<Self, Error> {
+        Ok(PS384KeyPair {
+            key_pair: RSAKeyPair::generate_with_options_and_rng(&mut rand::thread_rng(), options)?,
+            key_id: None,
+            salt_length: None,
         })
     }
 
@@ -1109,6 +2235,13 @@ impl PS384KeyPair {
         self.key_id = Some(key_id.to_string());
         self
     }
+
+    /// Use a fixed PSS salt length for new signatures, instead of the
+    /// largest possible salt.
+    pub fn with_salt_length(mut self, salt_length: usize) -> Self {
+        self.salt_length = Some(salt_length);
+        self
+    }
 }
 
 impl RSAPublicKeyLike for PS384PublicKey {
@@ -1124,6 +2257,10 @@ impl RSAPublicKeyLike for PS384PublicKey {
         rsa::PaddingScheme::new_pss::<SHA384>()
     }
 
+    fn salt_length(&self) -> Option<usize> {
+        self.salt_length
+    }
+
     fn public_key(&self) -> &RSAPublicKey {
         &self.pk
     }
@@ -1135,6 +2272,38 @@ impl RSAPublicKeyLike for PS384PublicKey {
     fn set_key_id(&mut self, key_id: String) {
         self.key_id = Some(key_id);
     }
+
+    fn certificate_sha256_thumbprint(&self) -> Option<&[u8; 32]> {
+        self.certificate_sha256_thumbprint.as_ref()
+    }
+}
+
+impl KeyLike for PS384PublicKey {
+    fn key_algorithm(&self) -> &'static str {
+        Self::jwt_alg_name()
+    }
+
+    fn key_identifier(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    fn verify_authenticated(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        self.verify_signature(authenticated, signature)
+    }
+
+    fn key_parameters(&self) -> KeyParameters {
+        KeyParameters::RSA {
+            modulus_bits: self.modulus_bits(),
+        }
+    }
+
+    fn key_thumbprint(&self) -> Option<String> {
+        self.thumbprint().ok()
+    }
+
+    fn key_certificate_thumbprint(&self) -> Option<&[u8; 32]> {
+        self.certificate_sha256_thumbprint.as_ref()
+    }
 }
 
 impl PS384PublicKey {
@@ -1142,6 +2311,8 @@ impl PS384PublicKey {
         Ok(PS384PublicKey {
             pk: RSAPublicKey::from_der(der)?,
             key_id: None,
+            certificate_sha256_thumbprint: None,
+            salt_length: None,
         })
     }
 
@@ -1149,6 +2320,43 @@ impl PS384PublicKey {
         Ok(PS384PublicKey {
             pk: RSAPublicKey::from_pem(pem)?,
             key_id: None,
+            certificate_sha256_thumbprint: None,
+            salt_length: None,
+        })
+    }
+
+    /// Import the public key of a DER-encoded X.509 certificate
+    pub fn from_x509_der(der: &[u8]) -> Result<Self, Error> {
+        let mut public_key = Self::from_der(x509::certificate_public_key(der)?)?;
+        public_key.certificate_sha256_thumbprint = Some(hmac_sha256::Hash::hash(der));
+        Ok(public_key)
+    }
+
+    /// Import the public key of the first certificate of a PEM-encoded X.509
+    /// certificate chain
+    pub fn from_x509_pem(pem: &str) -> Result<Self, Error> {
+        Self::from_x509_der(&x509::certificate_from_pem(pem)?)
+    }
+
+    /// Import a public key from a line of an OpenSSH `.pub` or
+    /// `authorized_keys` file
+    pub fn from_openssh(line: &str) -> Result<Self, Error> {
+        Ok(PS384PublicKey {
+            pk: RSAPublicKey::from_openssh(line)?,
+            key_id: None,
+            certificate_sha256_thumbprint: None,
+            salt_length: None,
+        })
+    }
+
+    /// Import a public key from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwt_alg_name(), "sig", &["verify"])?;
+        Ok(PS384PublicKey {
+            pk: RSAPublicKey::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+            certificate_sha256_thumbprint: None,
+            salt_length: None,
         })
     }
 
@@ -1156,6 +2364,8 @@ impl PS384PublicKey {
         Ok(PS384PublicKey {
             pk: RSAPublicKey::from_components(n, e)?,
             key_id: None,
+            certificate_sha256_thumbprint: None,
+            salt_length: None,
         })
     }
 
@@ -1176,6 +2386,13 @@ impl PS384PublicKey {
         self
     }
 
+    /// Only accept signatures using the given PSS salt length.
+    /// By default, the salt length is automatically detected.
+    pub fn with_salt_length(mut self, salt_length: usize) -> Self {
+        self.salt_length = Some(salt_length);
+        self
+    }
+
     pub fn sha1_thumbprint(&self) -> String {
         Base64UrlSafeNoPadding::encode_to_string(SHA1::hash(&self.pk.to_der().unwrap())).unwrap()
     }
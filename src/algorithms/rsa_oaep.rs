@@ -0,0 +1,499 @@
+use hmac_sha1_compact::WrappedHash as SHA1;
+use hmac_sha256::Hash as SHA256;
+use rand::{CryptoRng, RngCore};
+use rsa::PublicKey as _;
+
+use crate::common::*;
+use crate::encrypted_pem::decrypt_pem;
+use crate::encrypted_token::*;
+use crate::error::*;
+use crate::jwe_header::*;
+use crate::jwk::*;
+
+use super::rsa::*;
+
+pub trait RSAOAEPKeyPairLike {
+    fn jwe_alg_name() -> &'static str;
+    fn key_pair(&self) -> &RSAKeyPair;
+    fn key_id(&self) -> &Option<String>;
+    fn padding_scheme(&self) -> rsa::PaddingScheme;
+
+    /// Export the key pair as a JWK, including the private key
+    fn to_jwk(&self) -> Result<JWK, Error> {
+        Ok(self
+            .key_pair()
+            .to_jwk()?
+            .with_usage(self.key_id(), Self::jwe_alg_name(), "enc"))
+    }
+}
+
+impl<KeyPair: RSAOAEPKeyPairLike> DecryptionKeyLike for KeyPair {
+    fn decrypt_payload(
+        &self,
+        token: &str,
+        options: &VerificationOptions,
+    ) -> Result<DecryptedPayload, Error> {
+        check_key_policy(
+            Some(options),
+            KeyParameters::RSA {
+                modulus_bits: self.key_pair().modulus_bits(),
+            },
+        )?;
+        EncryptedToken::decrypt_payload(
+            KeyPair::jwe_alg_name(),
+            self.key_id().as_deref(),
+            token,
+            options,
+            |jwe_header, encrypted_key| {
+                let content_encryption =
+                    ContentEncryption::from_jwe_enc_name(&jwe_header.content_encryption)?;
+                // Use a random key if the content encryption key cannot be
+                // decrypted, so that padding errors cannot be distinguished
+                // from authentication errors (RFC 7516, section 11.5).
+                let mut random_cek = vec![0u8; content_encryption.key_length()];
+                rand::thread_rng().fill_bytes(&mut random_cek);
+                match self
+                    .key_pair()
+                    .as_ref()
+                    .decrypt(self.padding_scheme(), encrypted_key)
+                {
+                    Ok(cek) if cek.len() == random_cek.len() => Ok(cek),
+                    _ => Ok(random_cek),
+                }
+            },
+        )
+    }
+}
+
+pub trait RSAOAEPPublicKeyLike {
+    fn jwe_alg_name() -> &'static str;
+    fn public_key(&self) -> &RSAPublicKey;
+    fn key_id(&self) -> &Option<String>;
+    fn content_encryption(&self) -> ContentEncryption;
+    fn padding_scheme(&self) -> rsa::PaddingScheme;
+
+    /// Export the public key as a JWK, for publication in a key set
+    fn to_jwk(&self) -> Result<JWK, Error> {
+        Ok(self
+            .public_key()
+            .to_jwk()?
+            .with_usage(self.key_id(), Self::jwe_alg_name(), "enc"))
+    }
+
+    /// Compute the RFC 7638 thumbprint of the public key, using SHA-256
+    fn thumbprint(&self) -> Result<String, Error> {
+        self.to_jwk()?.thumbprint()
+    }
+
+    /// Compute the RFC 7638 thumbprint of the public key, using SHA-1, for
+    /// legacy applications
+    fn thumbprint_sha1(&self) -> Result<String, Error> {
+        self.to_jwk()?.thumbprint_sha1()
+    }
+}
+
+impl<PublicKey: RSAOAEPPublicKeyLike> EncryptionKeyLike for PublicKey {
+    fn encrypt_payload(
+        &self,
+        payload: &[u8],
+        content_type: Option<&str>,
+        options: &EncryptionOptions,
+    ) -> Result<String, Error> {
+        EncryptedToken::build_with_key(
+            self,
+            self.content_encryption(),
+            payload,
+            content_type,
+            options,
+        )
+    }
+
+    fn encrypt_key(&self, jwe_header: &mut JWEHeader, cek: &[u8]) -> Result<Vec<u8>, Error> {
+        jwe_header.algorithm = PublicKey::jwe_alg_name().to_string();
+        jwe_header.key_id = self.key_id().clone();
+        let encrypted_key = self.public_key().as_ref().encrypt(
+            &mut rand::thread_rng(),
+            self.padding_scheme(),
+            cek,
+        )?;
+        Ok(encrypted_key)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RSAOAEPKeyPair {
+    key_pair: RSAKeyPair,
+    key_id: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RSAOAEPPublicKey {
+    pk: RSAPublicKey,
+    key_id: Option<String>,
+    content_encryption: ContentEncryption,
+}
+
+impl RSAOAEPKeyPairLike for RSAOAEPKeyPair {
+    fn jwe_alg_name() -> &'static str {
+        "RSA-OAEP"
+    }
+
+    fn key_pair(&self) -> &RSAKeyPair {
+        &self.key_pair
+    }
+
+    fn key_id(&self) -> &Option<String> {
+        &self.key_id
+    }
+
+    fn padding_scheme(&self) -> rsa::PaddingScheme {
+        rsa::PaddingScheme::new_oaep::<SHA1>()
+    }
+}
+
+impl RSAOAEPKeyPair {
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        Ok(RSAOAEPKeyPair {
+            key_pair: RSAKeyPair::from_der(der)?,
+            key_id: None,
+        })
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        Ok(RSAOAEPKeyPair {
+            key_pair: RSAKeyPair::from_pem(pem)?,
+            key_id: None,
+        })
+    }
+
+    /// Import a password-protected key pair, encoded as an encrypted PKCS#8
+    /// or as a legacy encrypted PEM document
+    pub fn from_encrypted_pem(pem: &str, password: impl AsRef<[u8]>) -> Result<Self, Error> {
+        Self::from_pem(&decrypt_pem(pem, password.as_ref())?)
+    }
+
+    /// Import a key pair from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwe_alg_name(), "enc", &["decrypt", "unwrapKey"])?;
+        Ok(RSAOAEPKeyPair {
+            key_pair: RSAKeyPair::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+        })
+    }
+
+    pub fn to_der(&self) -> Result<Vec<u8>, Error> {
+        self.key_pair.to_der()
+    }
+
+    pub fn to_pem(&self) -> Result<String, Error> {
+        self.key_pair.to_pem()
+    }
+
+    pub fn public_key(&self) -> RSAOAEPPublicKey {
+        RSAOAEPPublicKey {
+            pk: self.key_pair.public_key(),
+            key_id: self.key_id.clone(),
+            content_encryption: ContentEncryption::A256GCM,
+        }
+    }
+
+    pub fn generate(modulus_bits: usize) -> Result<Self, Error> {
+        Self::generate_with_rng(&mut rand::thread_rng(), modulus_bits)
+    }
+
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(
+        rng: &mut (impl CryptoRng + RngCore),
+        modulus_bits: usize,
+    ) -> Result<Self, Error> {
+        Ok(RSAOAEPKeyPair {
+            key_pair: RSAKeyPair::generate_with_rng(rng, modulus_bits)?,
+            key_id: None,
+        })
+    }
+
+    /// Generate a new key pair using the given options
+    pub fn generate_with_options(options: &RSAKeyGenerationOptions) -> Result<Self, Error> {
+        Ok(RSAOAEPKeyPair {
+            key_pair: RSAKeyPair::generate_with_options_and_rng(&mut rand::thread_rng(), options)?,
+            key_id: None,
+        })
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string());
+        self
+    }
+}
+
+impl RSAOAEPPublicKeyLike for RSAOAEPPublicKey {
+    fn jwe_alg_name() -> &'static str {
+        "RSA-OAEP"
+    }
+
+    fn public_key(&self) -> &RSAPublicKey {
+        &self.pk
+    }
+
+    fn key_id(&self) -> &Option<String> {
+        &self.key_id
+    }
+
+    fn content_encryption(&self) -> ContentEncryption {
+        self.content_encryption
+    }
+
+    fn padding_scheme(&self) -> rsa::PaddingScheme {
+        rsa::PaddingScheme::new_oaep::<SHA1>()
+    }
+}
+
+impl RSAOAEPPublicKey {
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        Ok(RSAOAEPPublicKey {
+            pk: RSAPublicKey::from_der(der)?,
+            key_id: None,
+            content_encryption: ContentEncryption::A256GCM,
+        })
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        Ok(RSAOAEPPublicKey {
+            pk: RSAPublicKey::from_pem(pem)?,
+            key_id: None,
+            content_encryption: ContentEncryption::A256GCM,
+        })
+    }
+
+    /// Import a public key from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwe_alg_name(), "enc", &["encrypt", "wrapKey"])?;
+        Ok(RSAOAEPPublicKey {
+            pk: RSAPublicKey::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+            content_encryption: ContentEncryption::A256GCM,
+        })
+    }
+
+    pub fn from_components(n: &[u8], e: &[u8]) -> Result<Self, Error> {
+        Ok(RSAOAEPPublicKey {
+            pk: RSAPublicKey::from_components(n, e)?,
+            key_id: None,
+            content_encryption: ContentEncryption::A256GCM,
+        })
+    }
+
+    pub fn to_der(&self) -> Result<Vec<u8>, Error> {
+        self.pk.to_der()
+    }
+
+    pub fn to_pem(&self) -> Result<String, Error> {
+        self.pk.to_pem()
+    }
+
+    pub fn to_components(&self) -> RSAPublicKeyComponents {
+        self.pk.to_components()
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string());
+        self
+    }
+
+    /// Set the content encryption algorithm of new tokens
+    pub fn with_content_encryption(mut self, content_encryption: ContentEncryption) -> Self {
+        self.content_encryption = content_encryption;
+        self
+    }
+}
+
+//
+
+#[derive(Debug, Clone)]
+pub struct RSAOAEP256KeyPair {
+    key_pair: RSAKeyPair,
+    key_id: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RSAOAEP256PublicKey {
+    pk: RSAPublicKey,
+    key_id: Option<String>,
+    content_encryption: ContentEncryption,
+}
+
+impl RSAOAEPKeyPairLike for RSAOAEP256KeyPair {
+    fn jwe_alg_name() -> &'static str {
+        "RSA-OAEP-256"
+    }
+
+    fn key_pair(&self) -> &RSAKeyPair {
+        &self.key_pair
+    }
+
+    fn key_id(&self) -> &Option<String> {
+        &self.key_id
+    }
+
+    fn padding_scheme(&self) -> rsa::PaddingScheme {
+        rsa::PaddingScheme::new_oaep::<SHA256>()
+    }
+}
+
+impl RSAOAEP256KeyPair {
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        Ok(RSAOAEP256KeyPair {
+            key_pair: RSAKeyPair::from_der(der)?,
+            key_id: None,
+        })
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        Ok(RSAOAEP256KeyPair {
+            key_pair: RSAKeyPair::from_pem(pem)?,
+            key_id: None,
+        })
+    }
+
+    /// Import a password-protected key pair, encoded as an encrypted PKCS#8
+    /// or as a legacy encrypted PEM document
+    pub fn from_encrypted_pem(pem: &str, password: impl AsRef<[u8]>) -> Result<Self, Error> {
+        Self::from_pem(&decrypt_pem(pem, password.as_ref())?)
+    }
+
+    /// Import a key pair from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwe_alg_name(), "enc", &["decrypt", "unwrapKey"])?;
+        Ok(RSAOAEP256KeyPair {
+            key_pair: RSAKeyPair::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+        })
+    }
+
+    pub fn to_der(&self) -> Result<Vec<u8>, Error> {
+        self.key_pair.to_der()
+    }
+
+    pub fn to_pem(&self) -> Result<String, Error> {
+        self.key_pair.to_pem()
+    }
+
+    pub fn public_key(&self) -> RSAOAEP256PublicKey {
+        RSAOAEP256PublicKey {
+            pk: self.key_pair.public_key(),
+            key_id: self.key_id.clone(),
+            content_encryption: ContentEncryption::A256GCM,
+        }
+    }
+
+    pub fn generate(modulus_bits: usize) -> Result<Self, Error> {
+        Self::generate_with_rng(&mut rand::thread_rng(), modulus_bits)
+    }
+
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(
+        rng: &mut (impl CryptoRng + RngCore),
+        modulus_bits: usize,
+    ) -> Result<Self, Error> {
+        Ok(RSAOAEP256KeyPair {
+            key_pair: RSAKeyPair::generate_with_rng(rng, modulus_bits)?,
+            key_id: None,
+        })
+    }
+
+    /// Generate a new key pair using the given options
+    pub fn generate_with_options(options: &RSAKeyGenerationOptions) -> Result<Self, Error> {
+        Ok(RSAOAEP256KeyPair {
+            key_pair: RSAKeyPair::generate_with_options_and_rng(&mut rand::thread_rng(), options)?,
+            key_id: None,
+        })
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string());
+        self
+    }
+}
+
+impl RSAOAEPPublicKeyLike for RSAOAEP256PublicKey {
+    fn jwe_alg_name() -> &'static str {
+        "RSA-OAEP-256"
+    }
+
+    fn public_key(&self) -> &RSAPublicKey {
+        &self.pk
+    }
+
+    fn key_id(&self) -> &Option<String> {
+        &self.key_id
+    }
+
+    fn content_encryption(&self) -> ContentEncryption {
+        self.content_encryption
+    }
+
+    fn padding_scheme(&self) -> rsa::PaddingScheme {
+        rsa::PaddingScheme::new_oaep::<SHA256>()
+    }
+}
+
+impl RSAOAEP256PublicKey {
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        Ok(RSAOAEP256PublicKey {
+            pk: RSAPublicKey::from_der(der)?,
+            key_id: None,
+            content_encryption: ContentEncryption::A256GCM,
+        })
+    }
+
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        Ok(RSAOAEP256PublicKey {
+            pk: RSAPublicKey::from_pem(pem)?,
+            key_id: None,
+            content_encryption: ContentEncryption::A256GCM,
+        })
+    }
+
+    /// Import a public key from a JWK
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(Self::jwe_alg_name(), "enc", &["encrypt", "wrapKey"])?;
+        Ok(RSAOAEP256PublicKey {
+            pk: RSAPublicKey::from_jwk(jwk)?,
+            key_id: jwk.key_id.clone(),
+            content_encryption: ContentEncryption::A256GCM,
+        })
+    }
+
+    pub fn from_components(n: &[u8], e: &[u8]) -> Result<Self, Error> {
+        Ok(RSAOAEP256PublicKey {
+            pk: RSAPublicKey::from_components(n, e)?,
+            key_id: None,
+            content_encryption: ContentEncryption::A256GCM,
+        })
+    }
+
+    pub fn to_der(&self) -> Result<Vec<u8>, Error> {
+        self.pk.to_der()
+    }
+
+    pub fn to_pem(&self) -> Result<String, Error> {
+        self.pk.to_pem()
+    }
+
+    pub fn to_components(&self) -> RSAPublicKeyComponents {
+        self.pk.to_components()
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string());
+        self
+    }
+
+    /// Set the content encryption algorithm of new tokens
+    pub fn with_content_encryption(mut self, content_encryption: ContentEncryption) -> Self {
+        self.content_encryption = content_encryption;
+        self
+    }
+}
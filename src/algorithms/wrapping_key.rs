@@ -0,0 +1,211 @@
+use ct_codecs::{Base64UrlSafeNoPadding, Decoder, Encoder};
+use rand::{CryptoRng, RngCore};
+use zeroize::Zeroize;
+
+use crate::common::*;
+use crate::encrypted_token::*;
+use crate::error::*;
+use crate::jwe_header::*;
+use crate::jwk::*;
+
+use super::aes_kw::*;
+
+/// Key wrapping algorithms, used to encrypt a random content encryption key
+/// with a shared key
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum KeyWrapping {
+    /// AES-128 key wrap (RFC 3394)
+    A128KW,
+    /// AES-256 key wrap (RFC 3394)
+    A256KW,
+    /// AES-128-GCM key wrap
+    A128GCMKW,
+    /// AES-256-GCM key wrap
+    A256GCMKW,
+}
+
+impl KeyWrapping {
+    /// The JWE key management algorithm name ("alg")
+    pub fn jwe_alg_name(&self) -> &'static str {
+        match self {
+            KeyWrapping::A128KW => "A128KW",
+            KeyWrapping::A256KW => "A256KW",
+            KeyWrapping::A128GCMKW => "A128GCMKW",
+            KeyWrapping::A256GCMKW => "A256GCMKW",
+        }
+    }
+
+    /// The length of the key encryption key, in bytes
+    pub fn key_length(&self) -> usize {
+        match self {
+            KeyWrapping::A128KW | KeyWrapping::A128GCMKW => 16,
+            KeyWrapping::A256KW | KeyWrapping::A256GCMKW => 32,
+        }
+    }
+}
+
+/// A shared key encrypting the per-token content encryption keys of JWE tokens
+#[derive(Debug, Clone)]
+pub struct WrappingKey {
+    raw_key: Vec<u8>,
+    key_wrapping: KeyWrapping,
+    content_encryption: ContentEncryption,
+    key_id: Option<String>,
+}
+
+impl Drop for WrappingKey {
+    fn drop(&mut self) {
+        self.raw_key.zeroize();
+    }
+}
+
+impl WrappingKey {
+    /// Create a key for the given key wrapping algorithm.
+    /// The key length must match the one required by the algorithm.
+    pub fn from_bytes(key_wrapping: KeyWrapping, raw_key: &[u8]) -> Result<Self, Error> {
+        ensure!(
+            raw_key.len() == key_wrapping.key_length(),
+            JWTError::InvalidEncryptionKey
+        );
+        Ok(WrappingKey {
+            raw_key: raw_key.to_vec(),
+            key_wrapping,
+            content_encryption: ContentEncryption::A256GCM,
+            key_id: None,
+        })
+    }
+
+    /// Import a key from a JWK, for the given key wrapping algorithm
+    pub fn from_jwk(key_wrapping: KeyWrapping, jwk: &JWK) -> Result<Self, Error> {
+        jwk.check_usage(
+            key_wrapping.jwe_alg_name(),
+            "enc",
+            &["wrapKey", "unwrapKey"],
+        )?;
+        let mut key = Self::from_bytes(key_wrapping, &jwk.octet_sequence()?)?;
+        key.key_id = jwk.key_id.clone();
+        Ok(key)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.raw_key.clone()
+    }
+
+    /// Export the key as a JWK
+    pub fn to_jwk(&self) -> Result<JWK, Error> {
+        Ok(JWK::new_octet_sequence(&self.raw_key)?.with_usage(
+            &self.key_id,
+            self.key_wrapping.jwe_alg_name(),
+            "enc",
+        ))
+    }
+
+    pub fn generate(key_wrapping: KeyWrapping) -> Self {
+        Self::generate_with_rng(&mut rand::thread_rng(), key_wrapping)
+    }
+
+    /// Generate a new key using the given cryptographically secure random
+    /// number generator
+    pub fn generate_with_rng(
+        rng: &mut (impl CryptoRng + RngCore),
+        key_wrapping: KeyWrapping,
+    ) -> Self {
+        let mut raw_key = vec![0u8; key_wrapping.key_length()];
+        rng.fill_bytes(&mut raw_key);
+        WrappingKey {
+            raw_key,
+            key_wrapping,
+            content_encryption: ContentEncryption::A256GCM,
+            key_id: None,
+        }
+    }
+
+    /// The key wrapping algorithm this key is used with ("alg")
+    pub fn key_wrapping(&self) -> KeyWrapping {
+        self.key_wrapping
+    }
+
+    pub fn key_id(&self) -> &Option<String> {
+        &self.key_id
+    }
+
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string());
+        self
+    }
+
+    /// Set the content encryption algorithm of new tokens
+    pub fn with_content_encryption(mut self, content_encryption: ContentEncryption) -> Self {
+        self.content_encryption = content_encryption;
+        self
+    }
+}
+
+impl EncryptionKeyLike for WrappingKey {
+    fn encrypt_payload(
+        &self,
+        payload: &[u8],
+        content_type: Option<&str>,
+        options: &EncryptionOptions,
+    ) -> Result<String, Error> {
+        EncryptedToken::build_with_key(
+            self,
+            self.content_encryption,
+            payload,
+            content_type,
+            options,
+        )
+    }
+
+    fn encrypt_key(&self, jwe_header: &mut JWEHeader, cek: &[u8]) -> Result<Vec<u8>, Error> {
+        jwe_header.algorithm = self.key_wrapping.jwe_alg_name().to_string();
+        jwe_header.key_id = self.key_id.clone();
+        match self.key_wrapping {
+            KeyWrapping::A128KW | KeyWrapping::A256KW => aes_key_wrap(&self.raw_key, cek),
+            KeyWrapping::A128GCMKW | KeyWrapping::A256GCMKW => {
+                let wrapped = aes_gcm_key_wrap(&self.raw_key, cek)?;
+                jwe_header.key_wrapping_iv =
+                    Some(Base64UrlSafeNoPadding::encode_to_string(&wrapped.iv)?);
+                jwe_header.key_wrapping_tag =
+                    Some(Base64UrlSafeNoPadding::encode_to_string(&wrapped.tag)?);
+                Ok(wrapped.encrypted_key)
+            }
+        }
+    }
+}
+
+impl DecryptionKeyLike for WrappingKey {
+    fn decrypt_payload(
+        &self,
+        token: &str,
+        options: &VerificationOptions,
+    ) -> Result<DecryptedPayload, Error> {
+        EncryptedToken::decrypt_payload(
+            self.key_wrapping.jwe_alg_name(),
+            self.key_id.as_deref(),
+            token,
+            options,
+            |jwe_header, encrypted_key| match self.key_wrapping {
+                KeyWrapping::A128KW | KeyWrapping::A256KW => {
+                    aes_key_unwrap(&self.raw_key, encrypted_key)
+                }
+                KeyWrapping::A128GCMKW | KeyWrapping::A256GCMKW => {
+                    let iv = jwe_header
+                        .key_wrapping_iv
+                        .as_ref()
+                        .ok_or(JWTError::InvalidEncryptionKey)?;
+                    let tag = jwe_header
+                        .key_wrapping_tag
+                        .as_ref()
+                        .ok_or(JWTError::InvalidEncryptionKey)?;
+                    let wrapped = GCMWrappedKey {
+                        iv: Base64UrlSafeNoPadding::decode_to_vec(iv, None)?,
+                        encrypted_key: encrypted_key.to_vec(),
+                        tag: Base64UrlSafeNoPadding::decode_to_vec(tag, None)?,
+                    };
+                    aes_gcm_key_unwrap(&self.raw_key, &wrapped)
+                }
+            },
+        )
+    }
+}
@@ -0,0 +1,432 @@
+//! Command-line tool to generate keys, and to sign, verify and inspect tokens
+//! using the same code as applications built with `jwt-simple`.
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::process;
+
+use jwt_simple::prelude::*;
+use jwt_simple::{with_key_pair_algorithm, with_shared_key_algorithm, Error};
+use serde_json::{Map, Value};
+
+const USAGE: &str = "Usage:
+  jwt-simple keygen --alg <ALG> [--curve <CRV>] [--format pem|jwk] [--key-id <KID>]
+  jwt-simple public-key --alg <ALG> [--curve <CRV>] --key <FILE> [--format pem|jwk]
+  jwt-simple sign --alg <ALG> [--curve <CRV>] --key <FILE> [--claims <FILE>] [--valid-for <SECS>] [--key-id <KID>]
+  jwt-simple verify --key <FILE> [--alg <ALG>] [--curve <CRV>] [<TOKEN>]
+  jwt-simple decode [<TOKEN>]
+
+Keys are read as PEM, as a JWK, or as a JWK set (verification only).
+Shared keys (HS*) are generated as a JWK, and can also be read as raw bytes.
+Claims and tokens are read from the standard input if not given, or if set to \"-\".
+EdDSA keys use Ed25519, unless the curve is Ed448, or the key is an Ed448 JWK.
+
+Algorithms: HS256 HS384 HS512 ES256 ES384 ES512 ES256K BP256R1 BP384R1 BIP340 EdDSA
+  RS256 RS384 RS512 PS256 PS384 PS512, and ML-DSA-44 ML-DSA-65 ML-DSA-65-Ed25519
+  with the pq feature";
+
+const DEFAULT_VALIDITY_SECS: u64 = 3600;
+const DEFAULT_RSA_MODULUS_BITS: usize = 2048;
+
+/// Command-line arguments: a command, options with a value, and operands
+struct Args {
+    command: String,
+    options: Vec<(String, String)>,
+    operands: Vec<String>,
+}
+
+impl Args {
+    fn parse(args: impl Iterator<Item = String>) -> Result<Self, Error> {
+        let mut args = args.skip(1);
+        let command = args
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("missing command"))?;
+        let mut options = vec![];
+        let mut operands = vec![];
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(name) => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| anyhow::anyhow!("missing value for --{}", name))?;
+                    options.push((name.to_string(), value));
+                }
+                None => operands.push(arg),
+            }
+        }
+        Ok(Args {
+            command,
+            options,
+            operands,
+        })
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(option_name, _)| option_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn required_option(&self, name: &str) -> Result<&str, Error> {
+        self.option(name)
+            .ok_or_else(|| anyhow::anyhow!("missing --{}", name))
+    }
+
+    fn input(&self) -> Result<String, Error> {
+        match self.operands.first().map(String::as_str) {
+            None | Some("-") => read_stdin(),
+            Some(operand) => Ok(operand.to_string()),
+        }
+    }
+}
+
+fn read_stdin() -> Result<String, Error> {
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input)?;
+    Ok(input)
+}
+
+/// The encoding of a key file
+fn key_format(data: &[u8]) -> KeyFormat {
+    let text = std::str::from_utf8(data).unwrap_or_default().trim_start();
+    if text.starts_with("-----BEGIN") {
+        KeyFormat::PEM
+    } else if text.starts_with('{') {
+        KeyFormat::JWK
+    } else {
+        KeyFormat::Raw
+    }
+}
+
+fn output_format(args: &Args) -> Result<KeyFormat, Error> {
+    match args.option("format").unwrap_or("pem") {
+        "pem" => Ok(KeyFormat::PEM),
+        "jwk" => Ok(KeyFormat::JWK),
+        format => anyhow::bail!("unsupported key format: {}", format),
+    }
+}
+
+fn unsupported_algorithm(alg: &str) -> Error {
+    anyhow::anyhow!("unsupported algorithm: {}", alg)
+}
+
+/// The curve of the key: the `--curve` option, or the curve of a JWK
+fn curve(args: &Args, data: Option<&[u8]>) -> Option<String> {
+    if let Some(curve) = args.option("curve") {
+        return Some(curve.to_string());
+    }
+    let json = std::str::from_utf8(data?).ok()?;
+    JWK::from_json(json).ok()?.curve
+}
+
+/// Generate a new key, and return its private encoding
+fn keygen(args: &Args) -> Result<String, Error> {
+    let alg = args.required_option("alg")?;
+    let key_id = args.option("key-id");
+    let curve = curve(args, None);
+    let format = output_format(args)?;
+    let exported = with_shared_key_algorithm!(
+        alg,
+        |Key| {
+            let mut key = Key::generate();
+            if let Some(key_id) = key_id {
+                key = key.with_key_id(key_id);
+            }
+            key.export(KeyFormat::JWK)?
+        },
+        with_key_pair_algorithm!(
+            alg,
+            curve.as_deref(),
+            |KeyPair, PublicKey| {
+                let mut key_pair = KeyPair::generate_key_pair()?;
+                if let Some(key_id) = key_id {
+                    key_pair = key_pair.with_key_id(key_id);
+                }
+                key_pair.export(format)?
+            },
+            return Err(unsupported_algorithm(alg))
+        )
+    );
+    Ok(String::from_utf8(exported)?)
+}
+
+/// Key pairs that can be generated by the command-line tool
+trait GenerateKeyPair: Sized {
+    fn generate_key_pair() -> Result<Self, Error>;
+}
+
+macro_rules! generate_key_pair {
+    ($($key_pair:ty),*; $($rsa_key_pair:ty),*) => {
+        $(impl GenerateKeyPair for $key_pair {
+            fn generate_key_pair() -> Result<Self, Error> {
+                Ok(<$key_pair>::generate())
+            }
+        })*
+        $(impl GenerateKeyPair for $rsa_key_pair {
+            fn generate_key_pair() -> Result<Self, Error> {
+                <$rsa_key_pair>::generate(DEFAULT_RSA_MODULUS_BITS)
+            }
+        })*
+    };
+}
+
+generate_key_pair!(
+    ES256KeyPair, ES384KeyPair, ES512KeyPair, ES256kKeyPair, BP256R1KeyPair, BP384R1KeyPair,
+    BIP340KeyPair, Ed25519KeyPair, Ed448KeyPair;
+    RS256KeyPair, RS384KeyPair, RS512KeyPair, PS256KeyPair, PS384KeyPair, PS512KeyPair
+);
+
+#[cfg(feature = "pq")]
+generate_key_pair!(MLDSA44KeyPair, MLDSA65KeyPair, MLDSA65Ed25519KeyPair;);
+
+/// Return the public key of a key pair
+fn public_key(args: &Args) -> Result<String, Error> {
+    let alg = args.required_option("alg")?;
+    let data = fs::read(args.required_option("key")?)?;
+    let format = output_format(args)?;
+    let exported = with_key_pair_algorithm!(
+        alg,
+        curve(args, Some(&data)).as_deref(),
+        |KeyPair, PublicKey| {
+            let key_pair = KeyPair::import(&data, key_format(&data))?;
+            key_pair.public_key().export(format)?
+        },
+        return Err(unsupported_algorithm(alg))
+    );
+    Ok(String::from_utf8(exported)?)
+}
+
+/// Sign claims read as JSON. Standard and custom claims are used as-is, and
+/// the expiration date is set if missing.
+fn sign(args: &Args) -> Result<String, Error> {
+    let alg = args.required_option("alg")?;
+    let data = fs::read(args.required_option("key")?)?;
+    let key_id = args.option("key-id");
+    let claims_json = match args.option("claims") {
+        None | Some("-") => read_stdin()?,
+        Some(path) => fs::read_to_string(path)?,
+    };
+    let mut claims: JWTClaims<Map<String, Value>> = serde_json::from_str(&claims_json)?;
+    if claims.expires_at.is_none() {
+        let valid_for = match args.option("valid-for") {
+            Some(valid_for) => valid_for.parse()?,
+            None => DEFAULT_VALIDITY_SECS,
+        };
+        let now = Clock::now_since_epoch();
+        claims.issued_at.get_or_insert(now);
+        claims.expires_at = Some(now + Duration::from_secs(valid_for));
+    }
+    with_shared_key_algorithm!(
+        alg,
+        |Key| {
+            let mut key = Key::import(&data, key_format(&data))?;
+            if let Some(key_id) = key_id {
+                key = key.with_key_id(key_id);
+            }
+            key.authenticate(claims)
+        },
+        with_key_pair_algorithm!(
+            alg,
+            curve(args, Some(&data)).as_deref(),
+            |KeyPair, PublicKey| {
+                let mut key_pair = KeyPair::import(&data, key_format(&data))?;
+                if let Some(key_id) = key_id {
+                    key_pair = key_pair.with_key_id(key_id);
+                }
+                key_pair.sign(claims)
+            },
+            Err(unsupported_algorithm(alg))
+        )
+    )
+}
+
+/// Verify a token, and return its claims. The algorithm is required for
+/// keys that are not JWK sets.
+fn verify(args: &Args) -> Result<Value, Error> {
+    let token = Token::normalize_lenient(&args.input()?)?;
+    let data = fs::read(args.required_option("key")?)?;
+    let format = key_format(&data);
+    if format == KeyFormat::JWK {
+        let json = std::str::from_utf8(&data)?;
+        let key_set = match serde_json::from_str::<Value>(json)?.get("keys") {
+            Some(_) => JWKSet::from_json(json)?,
+            None => JWKSet {
+                keys: vec![JWK::from_json(json)?],
+            },
+        };
+        if args.option("alg").is_none() {
+            return key_set.verify_token_untyped(&token, None);
+        }
+    }
+    let alg = args.required_option("alg")?;
+    with_shared_key_algorithm!(
+        alg,
+        |Key| Key::import(&data, format)?.verify_token_untyped(&token, None),
+        with_key_pair_algorithm!(
+            alg,
+            curve(args, Some(&data)).as_deref(),
+            |KeyPair, PublicKey| PublicKey::import(&data, format)?
+                .verify_token_untyped(&token, None),
+            Err(unsupported_algorithm(alg))
+        )
+    )
+}
+
+/// Describe a token without verifying it
+fn decode(args: &Args) -> Result<String, Error> {
+    let token = Token::normalize_lenient(&args.input()?)?;
+    Ok(Token::explain(&token)?.to_string())
+}
+
+fn run() -> Result<String, Error> {
+    let args = Args::parse(std::env::args())?;
+    match args.command.as_str() {
+        "keygen" => keygen(&args),
+        "public-key" => public_key(&args),
+        "sign" => sign(&args),
+        "verify" => Ok(serde_json::to_string_pretty(&verify(&args)?)?),
+        "decode" => decode(&args),
+        "help" | "--help" | "-h" => Ok(USAGE.to_string()),
+        command => anyhow::bail!("unknown command: {}", command),
+    }
+}
+
+fn main() {
+    match run() {
+        Ok(output) => {
+            let _ = writeln!(io::stdout(), "{}", output.trim_end());
+        }
+        Err(err) => {
+            eprintln!("Error: {}\n\n{}", err, USAGE);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Args {
+        let args = ["jwt-simple"].iter().chain(args).map(|arg| arg.to_string());
+        Args::parse(args).unwrap()
+    }
+
+    fn temp_file(name: &str, contents: &str) -> String {
+        let path = std::env::temp_dir().join(format!("jwt-simple-{}-{}", process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    fn round_trip(alg: &str, curve: Option<&str>, format: &str) {
+        let name = format!("{}-{}-{}", alg, curve.unwrap_or_default(), format);
+        let mut alg_options = vec!["--alg", alg];
+        if let Some(curve) = curve {
+            alg_options.extend(["--curve", curve]);
+        }
+        let with_alg = |command: &[&str]| args(&[command, &alg_options].concat());
+
+        let key = keygen(&with_alg(&["keygen", "--format", format])).unwrap();
+        let key_file = temp_file(&format!("{}.key", name), &key);
+        let public_key = public_key(&with_alg(&[
+            "public-key",
+            "--key",
+            &key_file,
+            "--format",
+            format,
+        ]))
+        .unwrap();
+        let public_key_file = temp_file(&format!("{}.pub", name), &public_key);
+
+        let claims_file = temp_file(
+            &format!("{}.json", name),
+            r#"{"sub": "alice", "role": "admin"}"#,
+        );
+        let token = sign(&with_alg(&[
+            "sign",
+            "--key",
+            &key_file,
+            "--claims",
+            &claims_file,
+            "--key-id",
+            "key-1",
+        ]))
+        .unwrap();
+
+        let claims = verify(&with_alg(&["verify", "--key", &public_key_file, &token])).unwrap();
+        assert_eq!(claims["sub"], "alice");
+        assert_eq!(claims["role"], "admin");
+        assert!(claims["exp"].is_u64());
+        if format == "jwk" {
+            let claims = verify(&args(&["verify", "--key", &public_key_file, &token])).unwrap();
+            assert_eq!(claims["role"], "admin");
+        }
+        assert!(verify(&with_alg(&[
+            "verify",
+            "--key",
+            &public_key_file,
+            &token[1..]
+        ]))
+        .is_err());
+
+        let decoded = decode(&args(&["decode", &token])).unwrap();
+        assert!(decoded.contains(alg));
+        assert!(decoded.contains("key-1"));
+    }
+
+    #[test]
+    fn shared_keys() {
+        for alg in ["HS256", "HS384", "HS512"] {
+            let key = keygen(&args(&["keygen", "--alg", alg])).unwrap();
+            let key_file = temp_file(&format!("{}.key", alg), &key);
+            let claims_file = temp_file(&format!("{}.json", alg), r#"{"role": "admin"}"#);
+            let token = sign(&args(&[
+                "sign",
+                "--alg",
+                alg,
+                "--key",
+                &key_file,
+                "--claims",
+                &claims_file,
+            ]))
+            .unwrap();
+            let claims =
+                verify(&args(&["verify", "--alg", alg, "--key", &key_file, &token])).unwrap();
+            assert_eq!(claims["role"], "admin");
+            assert!(decode(&args(&["decode", &token])).unwrap().contains(alg));
+        }
+    }
+
+    #[test]
+    fn key_pairs() {
+        for alg in [
+            "ES256", "ES384", "ES512", "ES256K", "BP256R1", "BP384R1", "BIP340", "EdDSA",
+        ] {
+            round_trip(alg, None, "pem");
+            round_trip(alg, None, "jwk");
+        }
+        round_trip("EdDSA", Some("Ed448"), "pem");
+        round_trip("EdDSA", Some("Ed448"), "jwk");
+        round_trip("RS256", None, "pem");
+        round_trip("PS384", None, "jwk");
+    }
+
+    #[cfg(feature = "pq")]
+    #[test]
+    fn post_quantum_key_pairs() {
+        for alg in ["ML-DSA-44", "ML-DSA-65"] {
+            round_trip(alg, None, "pem");
+            round_trip(alg, None, "jwk");
+        }
+        round_trip("ML-DSA-65-Ed25519", None, "pem");
+        round_trip("ML-DSA-65-Ed25519", None, "jwk");
+    }
+
+    #[test]
+    fn unsupported_algorithm() {
+        assert!(keygen(&args(&["keygen", "--alg", "none"])).is_err());
+        assert!(keygen(&args(&["keygen", "--alg", "RSA-OAEP"])).is_err());
+    }
+}
@@ -1,13 +1,18 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::convert::TryInto;
+use std::fmt;
+use std::iter::FromIterator;
 
-use coarsetime::{Clock, Duration, UnixTimeStamp};
-use ct_codecs::{Base64UrlSafeNoPadding, Encoder};
-use rand::RngCore;
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use coarsetime::{Duration, UnixTimeStamp};
+use ct_codecs::{Base64UrlSafeNoPadding, Decoder};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
 
-use crate::common::VerificationOptions;
+use crate::common::{
+    matching_issuer, SharedTimeSource, SystemTimeSource, TimeSource, VerificationOptions,
+};
 use crate::error::*;
+use crate::jwk::JWK;
+use crate::nonce::{NonceProvider, RandomNonceProvider};
 use crate::serde_additions;
 
 pub const DEFAULT_TIME_TOLERANCE_SECS: u64 = 900;
@@ -46,6 +51,20 @@ impl Audiences {
         }
     }
 
+    /// Return `true` if the audiences match any of the `patterns`, where `*`
+    /// matches any non-empty sequence of characters other than `/`
+    pub fn matches(&self, patterns: &HashSet<String>) -> bool {
+        let matches = |audience: &String| {
+            patterns
+                .iter()
+                .any(|pattern| wildcard_matches(audience, pattern))
+        };
+        match self {
+            Audiences::AsString(audience) => matches(audience),
+            Audiences::AsSet(audiences) => audiences.iter().any(matches),
+        }
+    }
+
     /// Get the audiences as a set
     pub fn into_set(self) -> HashSet<String> {
         match self {
@@ -100,6 +119,266 @@ impl<T: ToString> From<T> for Audiences {
     }
 }
 
+/// A set of OAuth 2.0 scopes (RFC 6749, section 3.3)
+///
+/// Scopes are case-sensitive. In the `scope` claim, they are represented as a
+/// space-delimited string, which is how this type is serialized.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Scopes(BTreeSet<String>);
+
+impl Scopes {
+    /// Create an empty set of scopes
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse a space-delimited list of scopes
+    pub fn parse(scopes: &str) -> Self {
+        let mut parsed = Self::new();
+        parsed.insert(scopes);
+        parsed
+    }
+
+    /// Add one or more scopes, given as a space-delimited list
+    pub fn insert(&mut self, scopes: &str) {
+        self.0.extend(
+            scopes
+                .split(' ')
+                .filter(|scope| !scope.is_empty())
+                .map(|scope| scope.to_string()),
+        );
+    }
+
+    /// Return `true` if the set includes the scope
+    pub fn contains(&self, scope: &str) -> bool {
+        self.0.contains(scope)
+    }
+
+    /// Return `true` if the set includes all the `required_scopes` entries
+    pub fn contains_all(&self, required_scopes: &HashSet<String>) -> bool {
+        required_scopes.iter().all(|scope| self.contains(scope))
+    }
+
+    /// The scopes, in lexicographic order
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(|scope| scope.as_str())
+    }
+
+    /// The number of scopes
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Return `true` if the set is empty
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Display for Scopes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.iter().collect::<Vec<_>>().join(" "))
+    }
+}
+
+impl<S: AsRef<str>> FromIterator<S> for Scopes {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Self {
+        let mut scopes = Self::new();
+        for scope in iter {
+            scopes.insert(scope.as_ref());
+        }
+        scopes
+    }
+}
+
+impl Serialize for Scopes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Scopes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let scopes = String::deserialize(deserializer)?;
+        Ok(Scopes::parse(&scopes))
+    }
+}
+
+/// An actor claim (`act`, RFC 8693), identifying the party acting on behalf
+/// of the subject of a token
+///
+/// In a delegation chain, the actor nests the previous actor, down to the
+/// earliest one. Members other than `sub`, `iss` and `act` are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Actor {
+    /// Subject of the actor
+    #[serde(rename = "sub", default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+
+    /// Issuer of the actor's subject identifier
+    #[serde(rename = "iss", default, skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+
+    /// The previous actor of the delegation chain
+    #[serde(rename = "act", default, skip_serializing_if = "Option::is_none")]
+    pub actor: Option<Box<Actor>>,
+}
+
+impl Actor {
+    /// Create an actor with the given subject
+    pub fn new(subject: impl ToString) -> Self {
+        Actor {
+            subject: Some(subject.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Set the issuer of the actor's subject identifier
+    pub fn with_issuer(mut self, issuer: impl ToString) -> Self {
+        self.issuer = Some(issuer.to_string());
+        self
+    }
+
+    /// Set the previous actor of the delegation chain
+    pub fn with_actor(mut self, actor: Actor) -> Self {
+        self.actor = Some(Box::new(actor));
+        self
+    }
+
+    /// Return a new actor, delegating from this one
+    pub fn delegate_to(self, actor: Actor) -> Actor {
+        actor.with_actor(self)
+    }
+
+    /// The delegation chain, from the current actor to the earliest one
+    pub fn chain(&self) -> impl Iterator<Item = &Actor> {
+        std::iter::successors(Some(self), |actor| actor.actor.as_deref())
+    }
+
+    /// The number of actors in the delegation chain
+    pub fn depth(&self) -> usize {
+        self.chain().count()
+    }
+}
+
+/// A confirmation claim (`cnf`, RFC 7800), binding a token to a key that its
+/// presenter has to prove possession of
+///
+/// Members that are not supported are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Confirmation {
+    /// RFC 7638 thumbprint of the key, base64url-encoded ("jkt", RFC 9449)
+    #[serde(rename = "jkt", default, skip_serializing_if = "Option::is_none")]
+    pub jwk_thumbprint: Option<String>,
+
+    /// The public key ("jwk")
+    #[serde(rename = "jwk", default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<JWK>,
+
+    /// SHA-256 thumbprint of the X.509 certificate of the key, base64url-encoded
+    /// ("x5t#S256", RFC 8705)
+    #[serde(rename = "x5t#S256", default, skip_serializing_if = "Option::is_none")]
+    pub certificate_sha256_thumbprint: Option<String>,
+}
+
+impl Confirmation {
+    /// Confirmation by the RFC 7638 thumbprint of a key, as used by DPoP
+    pub fn from_jwk_thumbprint(thumbprint: impl ToString) -> Self {
+        Confirmation {
+            jwk_thumbprint: Some(thumbprint.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Confirmation by a public key, that must not include any private members
+    pub fn from_public_key(jwk: JWK) -> Self {
+        Confirmation {
+            public_key: Some(jwk),
+            ..Default::default()
+        }
+    }
+
+    /// Confirmation by the SHA-256 thumbprint of an X.509 certificate, as used
+    /// by mutual TLS
+    pub fn from_certificate_sha256_thumbprint(thumbprint: impl ToString) -> Self {
+        Confirmation {
+            certificate_sha256_thumbprint: Some(thumbprint.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Check that the token is bound to the key with the given RFC 7638
+    /// thumbprint. If the confirmation includes both a thumbprint and a key,
+    /// both have to match.
+    pub fn check_key_thumbprint(&self, thumbprint: &str) -> Result<(), Error> {
+        let public_key_thumbprint = match &self.public_key {
+            Some(public_key) => Some(public_key.thumbprint()?),
+            None => None,
+        };
+        let thumbprints: Vec<&str> = self
+            .jwk_thumbprint
+            .iter()
+            .chain(public_key_thumbprint.iter())
+            .map(|thumbprint| thumbprint.as_str())
+            .collect();
+        ensure!(
+            !thumbprints.is_empty(),
+            JWTError::RequiredConfirmationMissing
+        );
+        for confirmed in thumbprints {
+            ensure!(
+                thumbprints_match(confirmed, thumbprint),
+                JWTError::ConfirmationMismatch
+            );
+        }
+        Ok(())
+    }
+
+    /// Check that the token is bound to the X.509 certificate with the given
+    /// SHA-256 thumbprint
+    pub fn check_certificate_thumbprint(&self, thumbprint: &str) -> Result<(), Error> {
+        let confirmed = self
+            .certificate_sha256_thumbprint
+            .as_deref()
+            .ok_or(JWTError::RequiredConfirmationMissing)?;
+        ensure!(
+            thumbprints_match(confirmed, thumbprint),
+            JWTError::ConfirmationMismatch
+        );
+        Ok(())
+    }
+}
+
+fn thumbprints_match(a: &str, b: &str) -> bool {
+    match (
+        Base64UrlSafeNoPadding::decode_to_vec(a, None),
+        Base64UrlSafeNoPadding::decode_to_vec(b, None),
+    ) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+pub(crate) fn wildcard_matches(value: &str, pattern: &str) -> bool {
+    let (prefix, rest) = match pattern.split_once('*') {
+        Some(parts) => parts,
+        None => return value == pattern,
+    };
+    let value = match value.strip_prefix(prefix) {
+        Some(value) => value,
+        None => return false,
+    };
+    for (i, c) in value.char_indices() {
+        if c == '/' {
+            break;
+        }
+        if wildcard_matches(&value[i + c.len_utf8()..], rest) {
+            return true;
+        }
+    }
+    false
+}
+
 /// A set of JWT claims.
 ///
 /// The `CustomClaims` parameter can be set to `NoCustomClaims` if only standard
@@ -166,21 +445,144 @@ pub struct JWTClaims<CustomClaims> {
     #[serde(rename = "nonce", default, skip_serializing_if = "Option::is_none")]
     pub nonce: Option<String>,
 
+    /// Actor - The party acting on behalf of the subject, along with the
+    /// previous actors of a delegation chain
+    #[serde(rename = "act", default, skip_serializing_if = "Option::is_none")]
+    pub actor: Option<Actor>,
+
+    /// Confirmation - The key the presenter of the token has to prove
+    /// possession of
+    #[serde(rename = "cnf", default, skip_serializing_if = "Option::is_none")]
+    pub confirmation: Option<Confirmation>,
+
     /// Custom (application-defined) claims
     #[serde(flatten)]
     pub custom: CustomClaims,
+
+    /// Claims that are neither standard claims nor part of the custom claims
+    ///
+    /// They are kept when a token is deserialized, and serialized along with
+    /// the other claims, so that they can be passed through when a token is
+    /// reissued. They should not collide with the other claims.
+    #[serde(flatten)]
+    pub extra_claims: BTreeMap<String, serde_json::Value>,
 }
 
-impl<CustomClaims> JWTClaims<CustomClaims> {
+type ClaimsCheck<CustomClaims> =
+    fn(&JWTClaims<CustomClaims>, &VerificationOptions, UnixTimeStamp) -> Result<(), Error>;
+
+impl<CustomClaims: Serialize> JWTClaims<CustomClaims> {
+    /// Independent checks, run in order by `validate()`
+    fn checks() -> [ClaimsCheck<CustomClaims>; 13] {
+        [
+            Self::check_required_standard_claims,
+            Self::check_reject_before,
+            Self::check_issued_at,
+            Self::check_invalid_before,
+            Self::check_expiration,
+            Self::check_lifetime,
+            Self::check_issuer,
+            Self::check_subject,
+            Self::check_nonce,
+            Self::check_confirmation,
+            Self::check_actor,
+            Self::check_audiences,
+            Self::check_claims_validators,
+        ]
+    }
+
     pub(crate) fn validate(&self, options: &VerificationOptions) -> Result<(), Error> {
-        let now = Clock::now_since_epoch();
-        let time_tolerance = options.time_tolerance.unwrap_or_default();
+        self.validate_at(options, options.now())
+    }
 
+    /// Run all the checks, using `now` as the current time
+    pub(crate) fn validate_at(
+        &self,
+        options: &VerificationOptions,
+        now: UnixTimeStamp,
+    ) -> Result<(), Error> {
+        for check in Self::checks().iter() {
+            check(self, options, now)?;
+        }
+        Ok(())
+    }
+
+    /// Run all the checks, and return every failure instead of the first one
+    pub(crate) fn validation_failures(&self, options: &VerificationOptions) -> Vec<Error> {
+        let now = options.now();
+        Self::checks()
+            .iter()
+            .filter_map(|check| check(self, options, now).err())
+            .collect()
+    }
+
+    fn check_required_standard_claims(
+        &self,
+        options: &VerificationOptions,
+        _now: UnixTimeStamp,
+    ) -> Result<(), Error> {
+        ensure!(
+            !options.require_expiration || self.expires_at.is_some(),
+            JWTError::RequiredExpirationMissing
+        );
+        ensure!(
+            !options.require_issued_at || self.issued_at.is_some(),
+            JWTError::RequiredIssuedAtMissing
+        );
+        ensure!(
+            !options.require_not_before || self.invalid_before.is_some(),
+            JWTError::RequiredNotBeforeMissing
+        );
+        ensure!(
+            !options.require_subject || self.subject.is_some(),
+            JWTError::RequiredSubjectMissing
+        );
+        ensure!(
+            !options.require_issuer || self.issuer.is_some(),
+            JWTError::RequiredIssuerMissing
+        );
+        ensure!(
+            !options.require_audience || self.audiences.is_some(),
+            JWTError::RequiredAudienceMissing
+        );
+        ensure!(
+            !options.require_jwt_id || self.jwt_id.is_some(),
+            JWTError::RequiredJWTIdMissing
+        );
+        Ok(())
+    }
+
+    fn check_reject_before(
+        &self,
+        options: &VerificationOptions,
+        now: UnixTimeStamp,
+    ) -> Result<(), Error> {
+        if options.time_checks_disabled {
+            return Ok(());
+        }
         if let Some(reject_before) = options.reject_before {
             ensure!(now <= reject_before, JWTError::OldTokenReused);
         }
+        Ok(())
+    }
+
+    fn check_issued_at(
+        &self,
+        options: &VerificationOptions,
+        now: UnixTimeStamp,
+    ) -> Result<(), Error> {
+        if options.time_checks_disabled {
+            return Ok(());
+        }
+        let issued_at_tolerance = options
+            .issued_at_tolerance
+            .or(options.time_tolerance)
+            .unwrap_or_default();
         if let Some(time_issued) = self.issued_at {
-            ensure!(time_issued <= now + time_tolerance, JWTError::ClockDrift);
+            ensure!(
+                time_issued <= now + issued_at_tolerance,
+                JWTError::ClockDrift
+            );
             if let Some(max_validity) = options.max_validity {
                 ensure!(
                     now <= time_issued || now - time_issued <= max_validity,
@@ -188,30 +590,132 @@ impl<CustomClaims> JWTClaims<CustomClaims> {
                 );
             }
         }
+        Ok(())
+    }
+
+    fn check_invalid_before(
+        &self,
+        options: &VerificationOptions,
+        now: UnixTimeStamp,
+    ) -> Result<(), Error> {
+        if options.time_checks_disabled {
+            return Ok(());
+        }
+        let not_before_tolerance = options
+            .not_before_tolerance
+            .or(options.time_tolerance)
+            .unwrap_or_default();
         if !options.accept_future {
             if let Some(invalid_before) = self.invalid_before {
                 ensure!(
-                    now + time_tolerance >= invalid_before,
+                    now + not_before_tolerance >= invalid_before,
                     JWTError::TokenNotValidYet
                 );
             }
         }
+        Ok(())
+    }
+
+    fn check_expiration(
+        &self,
+        options: &VerificationOptions,
+        now: UnixTimeStamp,
+    ) -> Result<(), Error> {
+        if options.time_checks_disabled {
+            return Ok(());
+        }
+        let expiration_tolerance = options
+            .expiration_tolerance
+            .or(options.time_tolerance)
+            .unwrap_or_default();
+        let expiration_grace_period = options.expiration_grace_period.unwrap_or_default();
         if let Some(expires_at) = self.expires_at {
+            // Expiration dates too far in the future to be offset never expire
             ensure!(
-                now - time_tolerance <= expires_at,
+                expiration_tolerance
+                    .checked_add(expiration_grace_period)
+                    .and_then(|tolerance| expires_at.checked_add(tolerance))
+                    .is_none_or(|limit| now <= limit),
                 JWTError::TokenHasExpired
             );
         }
+        Ok(())
+    }
+
+    /// Return `true` if the token had expired at `now`, but was still within
+    /// the expiration grace period
+    pub(crate) fn is_stale_at(&self, options: &VerificationOptions, now: UnixTimeStamp) -> bool {
+        if options.time_checks_disabled || options.expiration_grace_period.is_none() {
+            return false;
+        }
+        let expiration_tolerance = options
+            .expiration_tolerance
+            .or(options.time_tolerance)
+            .unwrap_or_default();
+        self.expires_at
+            .and_then(|expires_at| expires_at.checked_add(expiration_tolerance))
+            .is_some_and(|limit| now > limit)
+    }
+
+    fn check_lifetime(
+        &self,
+        options: &VerificationOptions,
+        now: UnixTimeStamp,
+    ) -> Result<(), Error> {
+        if let Some(max_lifetime) = options.max_lifetime {
+            let expires_at = self.expires_at.ok_or(JWTError::RequiredExpirationMissing)?;
+            let valid_from = self.issued_at.unwrap_or(now);
+            ensure!(
+                expires_at <= valid_from || expires_at - valid_from <= max_lifetime,
+                JWTError::TokenLifetimeTooLong
+            );
+        }
+        Ok(())
+    }
+
+    fn check_issuer(
+        &self,
+        options: &VerificationOptions,
+        _now: UnixTimeStamp,
+    ) -> Result<(), Error> {
         if let Some(allowed_issuers) = &options.allowed_issuers {
             if let Some(issuer) = &self.issuer {
                 ensure!(
-                    allowed_issuers.contains(issuer),
+                    matching_issuer(
+                        allowed_issuers,
+                        options.issuer_normalization.as_ref(),
+                        issuer
+                    )
+                    .is_some(),
                     JWTError::RequiredIssuerMismatch
                 );
             } else {
                 bail!(JWTError::RequiredIssuerMissing);
             }
         }
+        Ok(())
+    }
+
+    fn check_subject(
+        &self,
+        options: &VerificationOptions,
+        _now: UnixTimeStamp,
+    ) -> Result<(), Error> {
+        if options.allowed_subjects.is_some() || options.subject_pattern.is_some() {
+            if let Some(subject) = &self.subject {
+                let allowed = options
+                    .allowed_subjects
+                    .as_ref()
+                    .is_some_and(|allowed_subjects| allowed_subjects.contains(subject))
+                    || options
+                        .subject_pattern
+                        .as_ref()
+                        .is_some_and(|pattern| pattern.matches(subject));
+                ensure!(allowed, JWTError::RequiredSubjectMismatch);
+            } else {
+                bail!(JWTError::RequiredSubjectMissing);
+            }
+        }
         if let Some(required_subject) = &options.required_subject {
             if let Some(subject) = &self.subject {
                 ensure!(
@@ -222,6 +726,10 @@ impl<CustomClaims> JWTClaims<CustomClaims> {
                 bail!(JWTError::RequiredSubjectMissing);
             }
         }
+        Ok(())
+    }
+
+    fn check_nonce(&self, options: &VerificationOptions, _now: UnixTimeStamp) -> Result<(), Error> {
         if let Some(required_nonce) = &options.required_nonce {
             if let Some(nonce) = &self.nonce {
                 ensure!(nonce == required_nonce, JWTError::RequiredNonceMismatch);
@@ -229,12 +737,57 @@ impl<CustomClaims> JWTClaims<CustomClaims> {
                 bail!(JWTError::RequiredNonceMissing);
             }
         }
-        if let Some(allowed_audiences) = &options.allowed_audiences {
+        Ok(())
+    }
+
+    fn check_confirmation(
+        &self,
+        options: &VerificationOptions,
+        _now: UnixTimeStamp,
+    ) -> Result<(), Error> {
+        if options.required_confirmation_key_thumbprint.is_some()
+            || options
+                .required_confirmation_certificate_thumbprint
+                .is_some()
+        {
+            let confirmation = self
+                .confirmation
+                .as_ref()
+                .ok_or(JWTError::RequiredConfirmationMissing)?;
+            if let Some(thumbprint) = &options.required_confirmation_key_thumbprint {
+                confirmation.check_key_thumbprint(thumbprint)?;
+            }
+            if let Some(thumbprint) = &options.required_confirmation_certificate_thumbprint {
+                confirmation.check_certificate_thumbprint(thumbprint)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_actor(&self, options: &VerificationOptions, _now: UnixTimeStamp) -> Result<(), Error> {
+        if let Some(max_actor_chain_depth) = options.max_actor_chain_depth {
+            let depth = self.actor.as_ref().map_or(0, |actor| actor.depth());
+            ensure!(depth <= max_actor_chain_depth, JWTError::ActorChainTooDeep);
+        }
+        Ok(())
+    }
+
+    fn check_audiences(
+        &self,
+        options: &VerificationOptions,
+        _now: UnixTimeStamp,
+    ) -> Result<(), Error> {
+        if options.allowed_audiences.is_some() || options.allowed_audience_patterns.is_some() {
             if let Some(audiences) = &self.audiences {
-                ensure!(
-                    audiences.contains(allowed_audiences),
-                    JWTError::RequiredAudienceMismatch
-                );
+                let allowed = options
+                    .allowed_audiences
+                    .as_ref()
+                    .is_some_and(|allowed_audiences| audiences.contains(allowed_audiences))
+                    || options
+                        .allowed_audience_patterns
+                        .as_ref()
+                        .is_some_and(|patterns| audiences.matches(patterns));
+                ensure!(allowed, JWTError::RequiredAudienceMismatch);
             } else {
                 bail!(JWTError::RequiredAudienceMissing);
             }
@@ -242,6 +795,53 @@ impl<CustomClaims> JWTClaims<CustomClaims> {
         Ok(())
     }
 
+    fn check_claims_validators(
+        &self,
+        options: &VerificationOptions,
+        _now: UnixTimeStamp,
+    ) -> Result<(), Error> {
+        if !options.claims_validators.is_empty() {
+            let claims = serde_json::to_value(self)?;
+            for validator in &options.claims_validators {
+                validator.validate(&claims)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<CustomClaims> JWTClaims<CustomClaims> {
+    /// The entry of `allowed_issuers` that the issuer of the token matched,
+    /// possibly after normalization
+    pub fn matched_issuer<'t>(&self, options: &'t VerificationOptions) -> Option<&'t str> {
+        matching_issuer(
+            options.allowed_issuers.as_ref()?,
+            options.issuer_normalization.as_ref(),
+            self.issuer.as_deref()?,
+        )
+    }
+
+    /// Convert the custom claims, keeping the other claims as-is
+    pub(crate) fn map_custom_claims<OtherCustomClaims, E>(
+        self,
+        f: impl FnOnce(CustomClaims) -> Result<OtherCustomClaims, E>,
+    ) -> Result<JWTClaims<OtherCustomClaims>, E> {
+        Ok(JWTClaims {
+            issued_at: self.issued_at,
+            expires_at: self.expires_at,
+            invalid_before: self.invalid_before,
+            issuer: self.issuer,
+            subject: self.subject,
+            audiences: self.audiences,
+            jwt_id: self.jwt_id,
+            nonce: self.nonce,
+            confirmation: self.confirmation,
+            actor: self.actor,
+            custom: f(self.custom)?,
+            extra_claims: self.extra_claims,
+        })
+    }
+
     /// Set the token as not being valid until `unix_timestamp`
     pub fn invalid_before(mut self, unix_timestamp: UnixTimeStamp) -> Self {
         self.invalid_before = Some(unix_timestamp);
@@ -262,9 +862,12 @@ impl<CustomClaims> JWTClaims<CustomClaims> {
 
     /// Register one or more audiences (optional recipient identifiers), as a
     /// set
-    pub fn with_audiences(mut self, audiences: HashSet<impl ToString>) -> Self {
+    ///
+    /// The audiences can be given as any collection, such as a `HashSet`, a
+    /// `Vec` or an array.
+    pub fn with_audiences(mut self, audiences: impl IntoIterator<Item = impl ToString>) -> Self {
         self.audiences = Some(Audiences::AsSet(
-            audiences.iter().map(|x| x.to_string()).collect(),
+            audiences.into_iter().map(|x| x.to_string()).collect(),
         ));
         self
     }
@@ -287,14 +890,44 @@ impl<CustomClaims> JWTClaims<CustomClaims> {
         self
     }
 
+    /// Bind the token to a key, that the presenter will have to prove
+    /// possession of
+    pub fn with_confirmation(mut self, confirmation: Confirmation) -> Self {
+        self.confirmation = Some(confirmation);
+        self
+    }
+
+    /// Set the party acting on behalf of the subject
+    pub fn with_actor(mut self, actor: Actor) -> Self {
+        self.actor = Some(actor);
+        self
+    }
+
+    /// Add a claim that is neither a standard claim nor part of the custom
+    /// claims
+    pub fn with_extra_claim(mut self, name: impl ToString, value: serde_json::Value) -> Self {
+        self.extra_claims.insert(name.to_string(), value);
+        self
+    }
+
     /// Create a nonce, attach it and return it
     pub fn create_nonce(&mut self) -> String {
-        let mut raw_nonce = [0u8; 24];
-        let mut rng = rand::thread_rng();
-        rng.fill_bytes(&mut raw_nonce);
-        let nonce = Base64UrlSafeNoPadding::encode_to_string(raw_nonce).unwrap();
-        self.nonce = Some(nonce);
-        self.nonce.as_deref().unwrap().to_string()
+        self.create_nonce_with(&RandomNonceProvider)
+    }
+
+    /// Create a nonce using the given provider, attach it and return it
+    pub fn create_nonce_with(&mut self, nonce_provider: &dyn NonceProvider) -> String {
+        let nonce = nonce_provider.generate();
+        self.nonce = Some(nonce.clone());
+        nonce
+    }
+
+    /// Create a JWT identifier using the given provider, attach it and return
+    /// it
+    pub fn create_jwt_id(&mut self, nonce_provider: &dyn NonceProvider) -> String {
+        let jwt_id = nonce_provider.generate();
+        self.jwt_id = Some(jwt_id.clone());
+        jwt_id
     }
 }
 
@@ -304,18 +937,16 @@ impl Claims {
     /// Create a new set of claims, without custom data, expiring in
     /// `valid_for`.
     pub fn create(valid_for: Duration) -> JWTClaims<NoCustomClaims> {
-        let now = Some(Clock::now_since_epoch());
-        JWTClaims {
-            issued_at: now,
-            expires_at: Some(now.unwrap() + valid_for),
-            invalid_before: now,
-            audiences: None,
-            issuer: None,
-            jwt_id: None,
-            subject: None,
-            nonce: None,
-            custom: NoCustomClaims {},
-        }
+        Self::create_with_time_source(valid_for, &SystemTimeSource)
+    }
+
+    /// Create a new set of claims, without custom data, expiring in
+    /// `valid_for`, using the given source for the current time.
+    pub fn create_with_time_source(
+        valid_for: Duration,
+        time_source: &dyn TimeSource,
+    ) -> JWTClaims<NoCustomClaims> {
+        Self::with_custom_claims_and_time_source(NoCustomClaims {}, valid_for, time_source)
     }
 
     /// Create a new set of claims, with custom data, expiring in `valid_for`.
@@ -323,25 +954,356 @@ impl Claims {
         custom_claims: CustomClaims,
         valid_for: Duration,
     ) -> JWTClaims<CustomClaims> {
-        let now = Some(Clock::now_since_epoch());
+        Self::with_custom_claims_and_time_source(custom_claims, valid_for, &SystemTimeSource)
+    }
+
+    /// Create a new set of claims, with custom data, expiring in `valid_for`,
+    /// using the given source for the current time.
+    pub fn with_custom_claims_and_time_source<CustomClaims>(
+        custom_claims: CustomClaims,
+        valid_for: Duration,
+        time_source: &dyn TimeSource,
+    ) -> JWTClaims<CustomClaims> {
+        let now = time_source.now();
         JWTClaims {
-            issued_at: now,
-            expires_at: Some(now.unwrap() + valid_for),
-            invalid_before: now,
+            issued_at: Some(now),
+            expires_at: Some(now + valid_for),
+            invalid_before: Some(now),
             audiences: None,
             issuer: None,
             jwt_id: None,
             subject: None,
             nonce: None,
+            confirmation: None,
+            actor: None,
             custom: custom_claims,
+            extra_claims: BTreeMap::new(),
         }
     }
 }
 
+/// Deserialize the claims of a token, after having normalized its time
+/// claims if `lenient_numeric_dates` is set
+pub(crate) fn decode_claims<CustomClaims: DeserializeOwned>(
+    claims_json: &[u8],
+    options: &VerificationOptions,
+) -> Result<JWTClaims<CustomClaims>, Error> {
+    if !options.lenient_numeric_dates {
+        return Ok(serde_json::from_slice(claims_json)?);
+    }
+    let mut claims: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_slice(claims_json)?;
+    for name in ["iat", "exp", "nbf"].iter() {
+        if let Some(value) = claims.get_mut(*name) {
+            if let Some(secs) = lenient_numeric_date(value) {
+                *value = secs.into();
+            }
+        }
+    }
+    Ok(serde_json::from_value(serde_json::Value::Object(claims))?)
+}
+
+/// Interpret a numeric date given as an integer, a float or a string, clamped
+/// to the range of `UnixTimeStamp`, with a one-second resolution
+fn lenient_numeric_date(value: &serde_json::Value) -> Option<u64> {
+    let secs = match value {
+        serde_json::Value::Number(number) => number.as_f64()?,
+        serde_json::Value::String(string) => string.trim().parse::<f64>().ok()?,
+        _ => return None,
+    };
+    if secs.is_nan() {
+        return None;
+    }
+    Some(secs.clamp(0.0, u32::MAX as f64) as u64)
+}
+
+/// A builder for claims, that checks their consistency before they are
+/// signed
+///
+/// `build()` fails with `JWTError::InconsistentTimestamps` if the token would
+/// expire before it becomes valid or before it was created, with
+/// `JWTError::EmptyClaim` if a string claim or the audience set is empty, and
+/// with `JWTError::RequiredClaimMissing` if a claim required with
+/// `required_claims()` was not set.
+#[derive(Debug, Clone)]
+pub struct ClaimsBuilder<CustomClaims = NoCustomClaims> {
+    claims: JWTClaims<CustomClaims>,
+    required_claims: HashSet<String>,
+}
+
+impl ClaimsBuilder<NoCustomClaims> {
+    /// Start building claims, without custom data, expiring in `valid_for`
+    pub fn new(valid_for: Duration) -> Self {
+        Self::with_custom_claims(NoCustomClaims {}, valid_for)
+    }
+}
+
+impl<CustomClaims> ClaimsBuilder<CustomClaims> {
+    /// Start building claims, with custom data, expiring in `valid_for`
+    pub fn with_custom_claims(custom_claims: CustomClaims, valid_for: Duration) -> Self {
+        Self::with_custom_claims_and_time_source(custom_claims, valid_for, &SystemTimeSource)
+    }
+
+    /// Start building claims, with custom data, expiring in `valid_for`,
+    /// using the given source for the current time
+    pub fn with_custom_claims_and_time_source(
+        custom_claims: CustomClaims,
+        valid_for: Duration,
+        time_source: &dyn TimeSource,
+    ) -> Self {
+        ClaimsBuilder {
+            claims: Claims::with_custom_claims_and_time_source(
+                custom_claims,
+                valid_for,
+                time_source,
+            ),
+            required_claims: HashSet::new(),
+        }
+    }
+
+    /// Set the issuer
+    pub fn issuer(mut self, issuer: impl ToString) -> Self {
+        self.claims = self.claims.with_issuer(issuer);
+        self
+    }
+
+    /// Set the subject
+    pub fn subject(mut self, subject: impl ToString) -> Self {
+        self.claims = self.claims.with_subject(subject);
+        self
+    }
+
+    /// Set a unique audience, as a string
+    pub fn audience(mut self, audience: impl ToString) -> Self {
+        self.claims = self.claims.with_audience(audience);
+        self
+    }
+
+    /// Set one or more audiences, as a set
+    pub fn audiences(mut self, audiences: impl IntoIterator<Item = impl ToString>) -> Self {
+        self.claims = self.claims.with_audiences(audiences);
+        self
+    }
+
+    /// Set the JWT identifier
+    pub fn jwt_id(mut self, jwt_id: impl ToString) -> Self {
+        self.claims = self.claims.with_jwt_id(jwt_id);
+        self
+    }
+
+    /// Set the nonce
+    pub fn nonce(mut self, nonce: impl ToString) -> Self {
+        self.claims = self.claims.with_nonce(nonce);
+        self
+    }
+
+    /// Set the token as not being valid until `unix_timestamp`
+    pub fn invalid_before(mut self, unix_timestamp: UnixTimeStamp) -> Self {
+        self.claims = self.claims.invalid_before(unix_timestamp);
+        self
+    }
+
+    /// Set the expiration date
+    pub fn expires_at(mut self, unix_timestamp: UnixTimeStamp) -> Self {
+        self.claims.expires_at = Some(unix_timestamp);
+        self
+    }
+
+    /// Require claims (`iss`, `sub`, `aud`, `jti`, `nonce`, `exp`, `iat` or
+    /// `nbf`) to be set before the claims can be built
+    pub fn required_claims(mut self, claims: &[impl ToString]) -> Self {
+        self.required_claims
+            .extend(claims.iter().map(|claim| claim.to_string()));
+        self
+    }
+
+    /// Check the consistency of the claims, and return them
+    pub fn build(self) -> Result<JWTClaims<CustomClaims>, Error> {
+        let claims = self.claims;
+        if let Some(expires_at) = claims.expires_at {
+            ensure!(
+                claims
+                    .invalid_before
+                    .is_none_or(|invalid_before| invalid_before <= expires_at),
+                JWTError::InconsistentTimestamps
+            );
+            ensure!(
+                claims
+                    .issued_at
+                    .is_none_or(|issued_at| issued_at <= expires_at),
+                JWTError::InconsistentTimestamps
+            );
+        }
+        for (name, value) in [
+            ("iss", &claims.issuer),
+            ("sub", &claims.subject),
+            ("jti", &claims.jwt_id),
+            ("nonce", &claims.nonce),
+        ]
+        .iter()
+        {
+            ensure!(
+                value.as_ref().is_none_or(|value| !value.is_empty()),
+                JWTError::EmptyClaim(name.to_string())
+            );
+        }
+        if let Some(audiences) = &claims.audiences {
+            let empty = match audiences {
+                Audiences::AsSet(audiences) => {
+                    audiences.is_empty() || audiences.iter().any(|audience| audience.is_empty())
+                }
+                Audiences::AsString(audience) => audience.is_empty(),
+            };
+            ensure!(!empty, JWTError::EmptyClaim("aud".to_string()));
+        }
+        for claim in &self.required_claims {
+            let present = match claim.as_str() {
+                "iss" => claims.issuer.is_some(),
+                "sub" => claims.subject.is_some(),
+                "aud" => claims.audiences.is_some(),
+                "jti" => claims.jwt_id.is_some(),
+                "nonce" => claims.nonce.is_some(),
+                "exp" => claims.expires_at.is_some(),
+                "iat" => claims.issued_at.is_some(),
+                "nbf" => claims.invalid_before.is_some(),
+                _ => false,
+            };
+            ensure!(present, JWTError::RequiredClaimMissing(claim.clone()));
+        }
+        Ok(claims)
+    }
+}
+
+/// Options for `JWTClaims::reissue()`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReissueOptions {
+    /// Set the creation date (`iat` claim) to the current time, instead of
+    /// preserving the one of the original token
+    pub reset_issued_at: bool,
+
+    /// Shorten the expiration date (`exp` claim), so that the new token
+    /// expires at most `max_validity` from now
+    pub max_validity: Option<Duration>,
+
+    /// Source of the current time, instead of the system clock
+    pub time_source: Option<SharedTimeSource>,
+}
+
+impl<CustomClaims> JWTClaims<CustomClaims> {
+    /// Create a new token from verified claims
+    ///
+    /// `mutate` can change the claims (for example to drop claims or to
+    /// change the audience) before `options` are applied, and `sign` signs
+    /// the resulting claims, typically with a different key than the one the
+    /// original token was verified with.
+    ///
+    /// Fails with `JWTError::InconsistentTimestamps` if the new token would
+    /// expire before it was created.
+    pub fn reissue(
+        mut self,
+        options: &ReissueOptions,
+        mutate: impl FnOnce(&mut Self),
+        sign: impl FnOnce(Self) -> Result<String, Error>,
+    ) -> Result<String, Error> {
+        mutate(&mut self);
+        let now = match &options.time_source {
+            Some(time_source) => time_source.now(),
+            None => SystemTimeSource.now(),
+        };
+        if options.reset_issued_at {
+            self.issued_at = Some(now);
+        }
+        if let Some(max_validity) = options.max_validity {
+            let max_expires_at = now + max_validity;
+            self.expires_at = Some(
+                self.expires_at
+                    .map_or(max_expires_at, |expires_at| expires_at.min(max_expires_at)),
+            );
+        }
+        if let (Some(issued_at), Some(expires_at)) = (self.issued_at, self.expires_at) {
+            ensure!(issued_at <= expires_at, JWTError::InconsistentTimestamps);
+        }
+        sign(self)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn should_check_claims_when_building() {
+        let claims = ClaimsBuilder::new(Duration::from_hours(1))
+            .issuer("issuer")
+            .audiences(vec!["api"])
+            .required_claims(&["iss", "aud"])
+            .build()
+            .unwrap();
+        assert_eq!(claims.issuer.as_deref(), Some("issuer"));
+
+        let now = coarsetime::Clock::now_since_epoch();
+        let err = ClaimsBuilder::new(Duration::from_hours(1))
+            .invalid_before(now + Duration::from_hours(2))
+            .build()
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<JWTError>(),
+            Some(JWTError::InconsistentTimestamps)
+        ));
+        assert!(ClaimsBuilder::new(Duration::from_hours(1))
+            .expires_at(now - Duration::from_hours(1))
+            .build()
+            .is_err());
+
+        let err = ClaimsBuilder::new(Duration::from_hours(1))
+            .issuer("")
+            .build()
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<JWTError>(),
+            Some(JWTError::EmptyClaim(claim)) if claim == "iss"
+        ));
+        assert!(ClaimsBuilder::new(Duration::from_hours(1))
+            .audiences(Vec::<String>::new())
+            .build()
+            .is_err());
+
+        let err = ClaimsBuilder::new(Duration::from_hours(1))
+            .issuer("issuer")
+            .required_claims(&["iss", "aud"])
+            .build()
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<JWTError>(),
+            Some(JWTError::RequiredClaimMissing(claim)) if claim == "aud"
+        ));
+    }
+
+    #[test]
+    fn should_keep_extra_claims() {
+        #[derive(Serialize, Deserialize)]
+        struct CustomClaims {
+            tenant: String,
+        }
+
+        let json = r#"{"iss":"issuer","tenant":"a","region":"eu","flags":[1,2]}"#;
+        let claims: JWTClaims<CustomClaims> = serde_json::from_str(json).unwrap();
+        assert_eq!(claims.custom.tenant, "a");
+        assert_eq!(claims.extra_claims.len(), 2);
+        assert_eq!(claims.extra_claims["region"], "eu");
+        assert!(!claims.extra_claims.contains_key("iss"));
+
+        let claims = claims.with_extra_claim("trace", serde_json::json!("x"));
+        let value = serde_json::to_value(&claims).unwrap();
+        assert_eq!(value["tenant"], "a");
+        assert_eq!(value["region"], "eu");
+        assert_eq!(value["flags"], serde_json::json!([1, 2]));
+        assert_eq!(value["trace"], "x");
+
+        let claims: JWTClaims<NoCustomClaims> = serde_json::from_str(json).unwrap();
+        assert_eq!(claims.extra_claims.len(), 3);
+    }
+
     #[test]
     fn should_set_standard_claims() {
         let exp = Duration::from_mins(10);
@@ -362,6 +1324,94 @@ mod tests {
         assert_eq!(claims.subject, Some("subject".to_owned()));
     }
 
+    #[test]
+    fn should_match_audience_patterns() {
+        let mut patterns = HashSet::new();
+        patterns.insert("https://*.example.com/api".to_string());
+        patterns.insert("urn:service:*".to_string());
+
+        let matches = |audience: &str| Audiences::from(audience).matches(&patterns);
+        assert!(matches("https://eu.example.com/api"));
+        assert!(matches("https://a.b.example.com/api"));
+        assert!(matches("urn:service:billing"));
+        assert!(!matches("https://example.com/api"));
+        assert!(!matches("https://.example.com/api"));
+        assert!(!matches("https://evil.com/.example.com/api"));
+        assert!(!matches("https://eu.example.com/api/admin"));
+        assert!(!matches("urn:service:"));
+
+        let options = VerificationOptions {
+            allowed_audiences: Some(vec!["api".to_string()].into_iter().collect()),
+            allowed_audience_patterns: Some(patterns),
+            ..Default::default()
+        };
+        let claims = Claims::create(Duration::from_mins(10));
+        assert!(claims
+            .clone()
+            .with_audience("api")
+            .validate(&options)
+            .is_ok());
+        assert!(claims
+            .clone()
+            .with_audience("https://eu.example.com/api")
+            .validate(&options)
+            .is_ok());
+        assert!(claims
+            .clone()
+            .with_audience("https://example.com/api")
+            .validate(&options)
+            .is_err());
+        assert!(claims.validate(&options).is_err());
+    }
+
+    #[test]
+    fn should_parse_string_or_array_audiences() {
+        let claims: JWTClaims<NoCustomClaims> = serde_json::from_str(r#"{"aud":"a"}"#).unwrap();
+        assert_eq!(claims.audiences, Some(Audiences::AsString("a".to_string())));
+
+        let claims: JWTClaims<NoCustomClaims> =
+            serde_json::from_str(r#"{"aud":["a","b"]}"#).unwrap();
+        let audiences = claims.audiences.unwrap();
+        assert!(audiences.is_set());
+        assert_eq!(audiences.into_set().len(), 2);
+
+        let claims: JWTClaims<NoCustomClaims> = serde_json::from_str(r#"{"aud":null}"#).unwrap();
+        assert_eq!(claims.audiences, None);
+
+        let claims = Claims::create(Duration::from_mins(10)).with_audiences(["a", "b"]);
+        let json = serde_json::to_string(&claims).unwrap();
+        let claims: JWTClaims<NoCustomClaims> = serde_json::from_str(&json).unwrap();
+        let mut options = VerificationOptions {
+            allowed_audiences: Some(vec!["b".to_string()].into_iter().collect()),
+            ..Default::default()
+        };
+        assert!(claims.validate(&options).is_ok());
+        options.allowed_audiences = Some(vec!["c".to_string()].into_iter().collect());
+        assert!(claims.validate(&options).is_err());
+    }
+
+    #[test]
+    fn should_parse_scopes() {
+        let scopes = Scopes::parse("read  write read Admin");
+        assert_eq!(scopes.len(), 3);
+        assert!(scopes.contains("read"));
+        assert!(scopes.contains("Admin"));
+        assert!(!scopes.contains("admin"));
+        assert_eq!(scopes.to_string(), "Admin read write");
+
+        let required: HashSet<String> = vec!["read".to_string()].into_iter().collect();
+        assert!(scopes.contains_all(&required));
+        assert!(!Scopes::new().contains_all(&required));
+
+        let json = serde_json::to_string(&scopes).unwrap();
+        assert_eq!(json, r#""Admin read write""#);
+        assert_eq!(serde_json::from_str::<Scopes>(&json).unwrap(), scopes);
+        assert_eq!(
+            vec!["write", "read Admin"].into_iter().collect::<Scopes>(),
+            scopes
+        );
+    }
+
     #[test]
     fn parse_floating_point_unix_time() {
         let claims: JWTClaims<()> = serde_json::from_str(r#"{"exp":1617757825.8}"#).unwrap();
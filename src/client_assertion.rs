@@ -0,0 +1,163 @@
+use std::collections::HashSet;
+
+use coarsetime::Duration;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::claims::*;
+use crate::common::*;
+use crate::error::*;
+use crate::nonce::{NonceProvider, RandomNonceProvider};
+use crate::replay_guard::{SharedTokenReplayGuard, TokenReplayGuard};
+use crate::token::*;
+
+/// The value of the `client_assertion_type` parameter for JWT client
+/// assertions (RFC 7523)
+pub const CLIENT_ASSERTION_TYPE: &str = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+
+/// The default validity period of new client assertions
+pub const DEFAULT_CLIENT_ASSERTION_VALIDITY: Duration = Duration::from_secs(60);
+
+/// The default maximum lifetime of the client assertions to accept
+pub const DEFAULT_CLIENT_ASSERTION_MAX_LIFETIME: Duration = Duration::from_secs(3600);
+
+/// JWT assertions authenticating OAuth clients (`private_key_jwt` and
+/// `client_secret_jwt`, RFC 7523)
+pub struct ClientAssertion;
+
+impl ClientAssertion {
+    /// Create an assertion authenticating `client_id` to the authorization
+    /// server `audience`, usually its token endpoint
+    ///
+    /// The signer gets the claims, and must return a token signed with a key
+    /// registered for the client, for example using `sign()`, or
+    /// `authenticate()` with the client secret.
+    pub fn create(
+        client_id: &str,
+        audience: &str,
+        signer: impl FnOnce(JWTClaims<NoCustomClaims>) -> Result<String, Error>,
+    ) -> Result<String, Error> {
+        Self::create_with_validity(
+            client_id,
+            audience,
+            DEFAULT_CLIENT_ASSERTION_VALIDITY,
+            signer,
+        )
+    }
+
+    /// Create an assertion like `create()`, valid for `valid_for`
+    pub fn create_with_validity(
+        client_id: &str,
+        audience: &str,
+        valid_for: Duration,
+        signer: impl FnOnce(JWTClaims<NoCustomClaims>) -> Result<String, Error>,
+    ) -> Result<String, Error> {
+        let claims = Claims::create(valid_for)
+            .with_issuer(client_id)
+            .with_subject(client_id)
+            .with_audience(audience)
+            .with_jwt_id(RandomNonceProvider.generate());
+        signer(claims)
+    }
+
+    /// The client an assertion claims to authenticate, WITHOUT VERIFYING IT
+    ///
+    /// This must only be used to look up the keys of the client, before
+    /// verifying the assertion with them.
+    pub fn client_id(assertion: &str) -> Result<String, Error> {
+        let claims = Token::decode_claims_unverified::<NoCustomClaims>(assertion)?;
+        claims
+            .issuer
+            .ok_or_else(|| JWTError::RequiredIssuerMissing.into())
+    }
+}
+
+/// Verification of client assertions by an authorization server
+///
+/// The issuer and the subject must both be the client, the audience must be
+/// one of the accepted audiences, and the expiration date and identifier are
+/// required. Assertions valid for more than the maximum lifetime are
+/// rejected.
+#[derive(Debug, Clone)]
+pub struct ClientAssertionVerifier {
+    client_id: String,
+    audiences: HashSet<String>,
+    max_lifetime: Duration,
+    replay_guard: Option<SharedTokenReplayGuard>,
+    options: VerificationOptions,
+}
+
+impl ClientAssertionVerifier {
+    /// Verify assertions authenticating `client_id`, for the audience
+    /// `audience`, usually the URL of the token endpoint
+    pub fn new(client_id: impl ToString, audience: impl ToString) -> Self {
+        ClientAssertionVerifier {
+            client_id: client_id.to_string(),
+            audiences: HashSet::from([audience.to_string()]),
+            max_lifetime: DEFAULT_CLIENT_ASSERTION_MAX_LIFETIME,
+            replay_guard: None,
+            options: VerificationOptions::default(),
+        }
+    }
+
+    /// Accept another audience, such as the issuer identifier of the
+    /// authorization server
+    pub fn with_audience(mut self, audience: impl ToString) -> Self {
+        self.audiences.insert(audience.to_string());
+        self
+    }
+
+    /// Set the maximum lifetime of the assertions to accept
+    pub fn with_max_lifetime(mut self, max_lifetime: Duration) -> Self {
+        self.max_lifetime = max_lifetime;
+        self
+    }
+
+    /// Reject assertions whose identifier has already been seen by the
+    /// replay guard
+    pub fn with_replay_guard(mut self, replay_guard: impl TokenReplayGuard + 'static) -> Self {
+        self.replay_guard = Some(SharedTokenReplayGuard::new(replay_guard));
+        self
+    }
+
+    /// Start from the given verification options, for example to set a time
+    /// tolerance or a time source, instead of the default ones
+    ///
+    /// The options enforced by the verifier take precedence.
+    pub fn with_verification_options(mut self, options: VerificationOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// The verification options enforcing the checks of the verifier
+    pub fn verification_options(&self) -> VerificationOptions {
+        VerificationOptions {
+            require_expiration: true,
+            require_issuer: true,
+            require_subject: true,
+            require_audience: true,
+            require_jwt_id: true,
+            required_subject: Some(self.client_id.clone()),
+            allowed_subjects: None,
+            subject_pattern: None,
+            allowed_issuers: Some(HashSet::from([self.client_id.clone()])),
+            issuer_normalization: None,
+            allowed_audiences: Some(self.audiences.clone()),
+            allowed_audience_patterns: None,
+            max_lifetime: Some(self.max_lifetime),
+            replay_guard: self
+                .replay_guard
+                .clone()
+                .or_else(|| self.options.replay_guard.clone()),
+            ..self.options.clone()
+        }
+    }
+
+    /// Verify an assertion using any of the keys registered for the client
+    pub fn verify<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        keys: &[&dyn KeyLike],
+        assertion: &str,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        Token::verify_with_keys(keys, assertion, Some(self.verification_options()))
+    }
+}
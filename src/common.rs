@@ -1,11 +1,83 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, OnceLock};
 
-use coarsetime::{Duration, UnixTimeStamp};
-use ct_codecs::{Base64UrlSafeNoPadding, Decoder, Encoder, Hex};
+use coarsetime::{Clock, Duration, UnixTimeStamp};
+use ct_codecs::{Base64, Base64UrlSafeNoPadding, Decoder, Encoder, Hex};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::{claims::DEFAULT_TIME_TOLERANCE_SECS, error::*};
+use crate::{
+    claims::{wildcard_matches, JWTClaims, Scopes, DEFAULT_TIME_TOLERANCE_SECS},
+    error::*,
+    jwk::JWK,
+    oidc::{check_oidc_claims, has_oidc_checks, OIDCClaims},
+    replay_guard::{SharedTokenReplayGuard, TokenReplayGuard},
+    revocation::{RevocationCheck, SharedRevocationCheck},
+    token::KeyLike,
+};
 
 pub const DEFAULT_MAX_TOKEN_LENGTH: usize = 1_000_000;
+pub const DEFAULT_MAX_DECOMPRESSED_LENGTH: usize = 1_000_000;
+
+static DEFAULT_LIMITS: OnceLock<DefaultLimits> = OnceLock::new();
+
+/// Process-wide size limits, used by `VerificationOptions::default()`
+///
+/// They apply to every verification that doesn't set its own limits, so that
+/// an application can raise or lower them in a single place.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DefaultLimits {
+    /// Maximum length of the base64-encoded JWT header to accept.
+    /// `MAX_HEADER_LENGTH` is used if not set.
+    pub max_header_length: Option<usize>,
+
+    /// Maximum length of the decoded claims to accept
+    pub max_claims_length: Option<usize>,
+}
+
+impl DefaultLimits {
+    /// Set the maximum length of the base64-encoded JWT header to accept
+    pub fn with_max_header_length(mut self, max_header_length: usize) -> Self {
+        self.max_header_length = Some(max_header_length);
+        self
+    }
+
+    /// Set the maximum length of the decoded claims to accept
+    pub fn with_max_claims_length(mut self, max_claims_length: usize) -> Self {
+        self.max_claims_length = Some(max_claims_length);
+        self
+    }
+
+    /// Install the limits for the whole process
+    ///
+    /// This can only be done once, and should be done at startup, before any
+    /// token is verified. An error is returned if limits have already been
+    /// installed.
+    ///
+    /// ```
+    /// use jwt_simple::prelude::*;
+    ///
+    /// DefaultLimits::default()
+    ///     .with_max_header_length(16384)
+    ///     .with_max_claims_length(65536)
+    ///     .install()
+    ///     .unwrap();
+    /// assert_eq!(VerificationOptions::default().max_header_length, Some(16384));
+    /// assert_eq!(VerificationOptions::default().max_claims_length, Some(65536));
+    /// assert!(DefaultLimits::default().install().is_err());
+    /// ```
+    pub fn install(self) -> Result<(), Error> {
+        DEFAULT_LIMITS
+            .set(self)
+            .map_err(|_| JWTError::DefaultLimitsAlreadySet)?;
+        Ok(())
+    }
+
+    /// Return the process-wide limits
+    pub fn current() -> Self {
+        DEFAULT_LIMITS.get().copied().unwrap_or_default()
+    }
+}
 
 /// Additional features to enable during verification.
 /// Signatures and token expiration are already automatically verified.
@@ -21,54 +93,878 @@ pub struct VerificationOptions {
     /// Accept tokens created with a date in the future
     pub accept_future: bool,
 
+    /// Skip all the checks based on the current time, set with
+    /// `insecure_disable_time_checks()`
+    pub(crate) time_checks_disabled: bool,
+
+    /// Accept time claims (`iat`, `exp`, `nbf`) given as strings, as well as
+    /// numbers out of the range of `UnixTimeStamp`, that are clamped.
+    /// Fractional seconds are truncated.
+    pub lenient_numeric_dates: bool,
+
+    /// Reject tokens without an expiration date (`exp` claim)
+    pub require_expiration: bool,
+
+    /// Reject tokens without a creation date (`iat` claim)
+    pub require_issued_at: bool,
+
+    /// Reject tokens without a start date (`nbf` claim)
+    pub require_not_before: bool,
+
+    /// Reject tokens without a subject (`sub` claim)
+    pub require_subject: bool,
+
+    /// Reject tokens without an issuer (`iss` claim)
+    pub require_issuer: bool,
+
+    /// Reject tokens without an audience (`aud` claim)
+    pub require_audience: bool,
+
+    /// Reject tokens without an identifier (`jti` claim)
+    pub require_jwt_id: bool,
+
     /// Require a specific subject to be present
     pub required_subject: Option<String>,
 
+    /// Require the subject to be present in the set
+    pub allowed_subjects: Option<HashSet<String>>,
+
+    /// Require the subject to match a pattern, if it is not in
+    /// `allowed_subjects`
+    pub subject_pattern: Option<SubjectPattern>,
+
     /// Require a specific key identifier to be present
     pub required_key_id: Option<String>,
 
     /// Require a specific public key to be present
     pub required_public_key: Option<String>,
 
+    /// Require the RFC 7638 thumbprint of the verification key to match a
+    /// pinned value, encoded as base64url or as hex. Shared keys have no
+    /// public key thumbprint, and are rejected if this is set.
+    pub required_public_key_thumbprint: Option<String>,
+
+    /// Require the SHA-256 thumbprint of the signer's certificate to match a
+    /// pinned value, encoded as base64url or as hex.
+    ///
+    /// The certificate is the one the verification key was imported from
+    /// with `from_x509_der()` or `from_x509_pem()`, or the first certificate
+    /// of the chain validated by `TrustedCertificates::verify_token()`.
+    /// Verification fails with other keys. Header parameters are never used.
+    pub required_certificate_sha256_thumbprint: Option<String>,
+
     /// Require a specific nonce to be present
     pub required_nonce: Option<String>,
 
+    /// Require the token type ("typ" header parameter) to be present and
+    /// match, such as "at+jwt" for access tokens or "dpop+jwt" for DPoP
+    /// proofs
+    ///
+    /// Types are compared case-insensitively, and the "application/" prefix
+    /// is optional.
+    pub required_typ: Option<String>,
+
     /// Require the issuer to be present in the set
     pub allowed_issuers: Option<HashSet<String>>,
 
+    /// Normalize issuers before comparing them with `allowed_issuers`
+    pub issuer_normalization: Option<IssuerNormalization>,
+
     /// Require the audience to be present in the set
     pub allowed_audiences: Option<HashSet<String>>,
 
+    /// Require the audience to match one of the patterns of the set, if it
+    /// is not in `allowed_audiences`
+    ///
+    /// In a pattern, `*` matches any non-empty sequence of characters other
+    /// than `/`, so that `https://*.example.com/api` matches
+    /// `https://eu.example.com/api`, but not `https://example.com/api` or
+    /// `https://evil.com/.example.com/api`.
+    pub allowed_audience_patterns: Option<HashSet<String>>,
+
+    /// Require the token algorithm ("alg") to be present in the set
+    pub allowed_algorithms: Option<HashSet<String>>,
+
     /// How much clock drift to tolerate when verifying token timestamps
     pub time_tolerance: Option<Duration>,
 
+    /// How long to accept tokens after their expiration date (`exp` claim),
+    /// instead of `time_tolerance`
+    pub expiration_tolerance: Option<Duration>,
+
+    /// How long to accept tokens that have already expired, beyond the
+    /// expiration tolerance
+    ///
+    /// Such tokens are verified, but are reported as stale by
+    /// `VerifiedToken::is_stale()`. This is meant for refresh endpoints, that
+    /// exchange a just-expired access token for a new one.
+    pub expiration_grace_period: Option<Duration>,
+
+    /// How long to accept tokens before their start date (`nbf` claim),
+    /// instead of `time_tolerance`
+    pub not_before_tolerance: Option<Duration>,
+
+    /// Reject tokens whose creation date (`iat` claim) is in the future by
+    /// more than `issued_at_tolerance`, instead of `time_tolerance`
+    pub issued_at_tolerance: Option<Duration>,
+
     /// Reject tokens created more than `max_validity` ago
     pub max_validity: Option<Duration>,
 
+    /// Reject tokens valid for more than `max_lifetime`, from their creation
+    /// date (`iat` claim) to their expiration date (`exp` claim), no matter
+    /// what the expiration date is. Tokens without a creation date are
+    /// checked from the current time, and tokens without an expiration date
+    /// are rejected.
+    pub max_lifetime: Option<Duration>,
+
     /// Maximum token length to accept
     pub max_token_length: Option<usize>,
 
-    /// Maximum unsafe, untrusted, unverified JWT header length to accept
+    /// Maximum unsafe, untrusted, unverified JWT header length to accept,
+    /// base64-encoded. The process-wide default set with
+    /// `DefaultLimits::install()`, or else `MAX_HEADER_LENGTH`, is used if not
+    /// set.
+    ///
+    /// Tokens with large certificate chains (`x5c`) may need a higher limit.
     pub max_header_length: Option<usize>,
+
+    /// Maximum length of the claims (payload) to accept, once decoded.
+    /// Defaults to the process-wide limit set with `DefaultLimits::install()`.
+    pub max_claims_length: Option<usize>,
+
+    /// Maximum length of a decompressed JWE payload to accept
+    pub max_decompressed_length: Option<usize>,
+
+    /// Reject tokens if the verification key doesn't comply with the policy
+    pub key_policy: Option<KeyPolicy>,
+
+    /// Require claims to be present in the token, with a value that is not
+    /// `null`
+    ///
+    /// Claims are looked up in the token itself, so that custom claims that
+    /// would be given a default value when deserialized are still reported as
+    /// missing.
+    pub required_claims: Option<HashSet<String>>,
+
+    /// Require the token to be bound (`cnf` claim) to the key with the given
+    /// RFC 7638 thumbprint, that the presenter has proven possession of, for
+    /// example with a DPoP proof
+    pub required_confirmation_key_thumbprint: Option<String>,
+
+    /// Require the token to be bound (`cnf` claim) to the X.509 certificate
+    /// with the given SHA-256 thumbprint, that the presenter has proven
+    /// possession of, for example with mutual TLS
+    pub required_confirmation_certificate_thumbprint: Option<String>,
+
+    /// Reject tokens whose delegation chain (`act` claim) includes more than
+    /// `max_actor_chain_depth` actors. `0` rejects any delegated token.
+    pub max_actor_chain_depth: Option<usize>,
+
+    /// Reject ID tokens whose end-user authentication (`auth_time` claim)
+    /// occurred more than `max_auth_age` ago
+    pub max_auth_age: Option<Duration>,
+
+    /// Require the authentication context class reference (`acr` claim) of
+    /// ID tokens to be in the set
+    pub allowed_acr_values: Option<HashSet<String>>,
+
+    /// Require the authorized party (`azp` claim) of ID tokens to match, if
+    /// present. Tokens with multiple audiences must include it.
+    pub required_authorized_party: Option<String>,
+
+    /// Require the space-delimited `scope` claim to include all the scopes of
+    /// the set. Scopes are case-sensitive.
+    pub required_scopes: Option<HashSet<String>>,
+
+    /// Source of the current time, to check the time-related claims against.
+    /// The system clock is used if not set.
+    pub time_source: Option<SharedTimeSource>,
+
+    /// Reject tokens that have been revoked
+    pub revocation_check: Option<SharedRevocationCheck>,
+
+    /// Reject tokens whose identifier (`jti` claim) has already been seen,
+    /// for one-time-use tokens
+    pub replay_guard: Option<SharedTokenReplayGuard>,
+
+    /// Application-defined checks, run after the standard claims have been
+    /// validated. Tokens are rejected if any of them returns an error.
+    pub claims_validators: Vec<ClaimsValidator>,
+
+    /// Extensions that can be listed as critical ("crit" header parameter),
+    /// with the validator of their header parameter
+    ///
+    /// Tokens with critical header parameters that are not in this map are
+    /// rejected, as required by RFC 7515.
+    pub critical_header_extensions: BTreeMap<String, CriticalHeaderValidator>,
 }
 
 impl Default for VerificationOptions {
     fn default() -> Self {
+        let default_limits = DefaultLimits::current();
         Self {
             reject_before: None,
             accept_future: false,
+            time_checks_disabled: false,
+            lenient_numeric_dates: false,
+            require_expiration: false,
+            require_issued_at: false,
+            require_not_before: false,
+            require_subject: false,
+            require_issuer: false,
+            require_audience: false,
+            require_jwt_id: false,
             required_subject: None,
+            allowed_subjects: None,
+            subject_pattern: None,
             required_key_id: None,
             required_public_key: None,
+            required_public_key_thumbprint: None,
+            required_certificate_sha256_thumbprint: None,
             required_nonce: None,
+            required_typ: None,
             allowed_issuers: None,
+            issuer_normalization: None,
             allowed_audiences: None,
+            allowed_audience_patterns: None,
+            allowed_algorithms: None,
             time_tolerance: Some(Duration::from_secs(DEFAULT_TIME_TOLERANCE_SECS)),
+            expiration_tolerance: None,
+            expiration_grace_period: None,
+            not_before_tolerance: None,
+            issued_at_tolerance: None,
             max_validity: None,
+            max_lifetime: None,
             max_token_length: Some(DEFAULT_MAX_TOKEN_LENGTH),
-            max_header_length: None,
+            max_header_length: default_limits.max_header_length,
+            max_claims_length: default_limits.max_claims_length,
+            max_decompressed_length: Some(DEFAULT_MAX_DECOMPRESSED_LENGTH),
+            key_policy: None,
+            required_claims: None,
+            required_scopes: None,
+            required_confirmation_key_thumbprint: None,
+            required_confirmation_certificate_thumbprint: None,
+            max_actor_chain_depth: None,
+            max_auth_age: None,
+            allowed_acr_values: None,
+            required_authorized_party: None,
+            time_source: None,
+            revocation_check: None,
+            replay_guard: None,
+            claims_validators: vec![],
+            critical_header_extensions: BTreeMap::new(),
+        }
+    }
+}
+
+impl VerificationOptions {
+    /// Require claims to be present in the token, with a value that is not
+    /// `null`
+    pub fn required_claims(mut self, claims: &[impl ToString]) -> Self {
+        self.required_claims
+            .get_or_insert_with(HashSet::new)
+            .extend(claims.iter().map(|claim| claim.to_string()));
+        self
+    }
+
+    /// Require the space-delimited `scope` claim to include all the given
+    /// scopes
+    pub fn required_scopes(mut self, scopes: &[impl ToString]) -> Self {
+        self.required_scopes
+            .get_or_insert_with(HashSet::new)
+            .extend(scopes.iter().map(|scope| scope.to_string()));
+        self
+    }
+
+    /// Set the maximum length of the base64-encoded JWT header to accept
+    pub fn with_max_header_length(mut self, max_header_length: usize) -> Self {
+        self.max_header_length = Some(max_header_length);
+        self
+    }
+
+    /// Set the maximum length of the decoded claims to accept
+    pub fn with_max_claims_length(mut self, max_claims_length: usize) -> Self {
+        self.max_claims_length = Some(max_claims_length);
+        self
+    }
+
+    /// Add an application-defined check, run after the standard claims have
+    /// been validated
+    pub fn with_claims_validator<CustomClaims: DeserializeOwned + 'static>(
+        mut self,
+        validator: impl Fn(&JWTClaims<CustomClaims>) -> Result<(), Error> + Send + Sync + 'static,
+    ) -> Self {
+        self.claims_validators.push(ClaimsValidator::new(validator));
+        self
+    }
+
+    /// Accept tokens listing the header parameter `name` as critical, if the
+    /// validator accepts its value
+    pub fn with_critical_header_extension(
+        mut self,
+        name: impl ToString,
+        validator: impl Fn(&serde_json::Value) -> Result<(), Error> + Send + Sync + 'static,
+    ) -> Self {
+        self.critical_header_extensions
+            .insert(name.to_string(), CriticalHeaderValidator::new(validator));
+        self
+    }
+
+    /// Check the token type ("typ") against `required_typ`
+    pub(crate) fn check_typ(&self, typ: Option<&str>) -> Result<(), Error> {
+        if let Some(required_typ) = &self.required_typ {
+            let typ = typ.ok_or(JWTError::TokenTypeMismatch)?;
+            ensure!(
+                normalize_media_type(typ) == normalize_media_type(required_typ),
+                JWTError::TokenTypeMismatch
+            );
+        }
+        Ok(())
+    }
+
+    /// Check the critical header parameters ("crit") of a token
+    ///
+    /// `builtin` lists the parameters that are already processed by this
+    /// crate. Every other critical parameter must be present, not be a
+    /// registered parameter, and be accepted by its validator.
+    pub(crate) fn check_critical_header(
+        &self,
+        critical: Option<&[String]>,
+        parameters: &BTreeMap<String, serde_json::Value>,
+        builtin: &[&str],
+    ) -> Result<(), Error> {
+        let critical = match critical {
+            None => return Ok(()),
+            Some(critical) => critical,
+        };
+        ensure!(!critical.is_empty(), JWTError::InvalidCriticalHeader);
+        for (i, name) in critical.iter().enumerate() {
+            ensure!(
+                !critical[..i].contains(name),
+                JWTError::InvalidCriticalHeader
+            );
+            if builtin.contains(&name.as_str()) {
+                continue;
+            }
+            ensure!(
+                !RESERVED_HEADER_PARAMETERS.contains(&name.as_str()),
+                JWTError::InvalidCriticalHeader
+            );
+            let validator = self
+                .critical_header_extensions
+                .get(name)
+                .ok_or(JWTError::UnsupportedCriticalHeader)?;
+            let value = parameters
+                .get(name)
+                .ok_or(JWTError::InvalidCriticalHeader)?;
+            validator.validate(value)?;
+        }
+        Ok(())
+    }
+
+    /// Check the time-related claims against the given time source instead
+    /// of the system clock
+    pub fn with_time_source(mut self, time_source: impl TimeSource + 'static) -> Self {
+        self.time_source = Some(SharedTimeSource::new(time_source));
+        self
+    }
+
+    /// Reject tokens that have been revoked, according to the revocation
+    /// check
+    pub fn with_revocation_check(
+        mut self,
+        revocation_check: impl RevocationCheck + 'static,
+    ) -> Self {
+        self.revocation_check = Some(SharedRevocationCheck::new(revocation_check));
+        self
+    }
+
+    /// Reject tokens whose identifier has already been seen by the replay
+    /// guard
+    pub fn with_replay_guard(mut self, replay_guard: impl TokenReplayGuard + 'static) -> Self {
+        self.replay_guard = Some(SharedTokenReplayGuard::new(replay_guard));
+        self
+    }
+
+    /// Skip all the checks based on the current time: expiration and start
+    /// dates, creation dates in the future, `max_validity`, `reject_before`
+    /// and `max_auth_age`
+    ///
+    /// This is INSECURE, and only meant for tools that analyze historical
+    /// tokens, such as tokens found in logs during an incident response.
+    /// Signatures and all the other claims are still verified. The validity
+    /// periods of X.509 certificates are still checked against the time
+    /// source.
+    pub fn insecure_disable_time_checks(mut self) -> Self {
+        self.time_checks_disabled = true;
+        self
+    }
+
+    pub(crate) fn now(&self) -> UnixTimeStamp {
+        match &self.time_source {
+            Some(time_source) => time_source.now(),
+            None => SystemTimeSource.now(),
+        }
+    }
+}
+
+/// A source of the current time
+///
+/// The system clock is used by default. A different time source can be set
+/// for deterministic tests, or on platforms where the system clock is not
+/// available.
+pub trait TimeSource: Send + Sync {
+    /// The current time
+    fn now(&self) -> UnixTimeStamp;
+}
+
+/// The system clock
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemTimeSource;
+
+impl TimeSource for SystemTimeSource {
+    fn now(&self) -> UnixTimeStamp {
+        Clock::now_since_epoch()
+    }
+}
+
+/// A time source that always returns the same time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FixedTimeSource(pub UnixTimeStamp);
+
+impl TimeSource for FixedTimeSource {
+    fn now(&self) -> UnixTimeStamp {
+        self.0
+    }
+}
+
+/// A time source that can be attached to `VerificationOptions`
+///
+/// Time sources are compared by identity.
+#[derive(Clone)]
+pub struct SharedTimeSource(Arc<dyn TimeSource>);
+
+impl SharedTimeSource {
+    /// Wrap a time source
+    pub fn new(time_source: impl TimeSource + 'static) -> Self {
+        SharedTimeSource(Arc::new(time_source))
+    }
+}
+
+impl TimeSource for SharedTimeSource {
+    fn now(&self) -> UnixTimeStamp {
+        self.0.now()
+    }
+}
+
+impl fmt::Debug for SharedTimeSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SharedTimeSource")
+    }
+}
+
+impl PartialEq for SharedTimeSource {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for SharedTimeSource {}
+
+type ClaimsValidatorFn = dyn Fn(&serde_json::Value) -> Result<(), Error> + Send + Sync;
+
+/// An application-defined check of the claims of a token
+///
+/// The claims are decoded as `JWTClaims<CustomClaims>`, where `CustomClaims`
+/// doesn't have to be the type the token is verified with. Validators are
+/// compared by identity.
+#[derive(Clone)]
+pub struct ClaimsValidator(Arc<ClaimsValidatorFn>);
+
+impl ClaimsValidator {
+    /// Create a validator from a function that returns an error if the claims
+    /// are not acceptable
+    pub fn new<CustomClaims: DeserializeOwned + 'static>(
+        validator: impl Fn(&JWTClaims<CustomClaims>) -> Result<(), Error> + Send + Sync + 'static,
+    ) -> Self {
+        ClaimsValidator(Arc::new(move |claims| {
+            let claims = JWTClaims::<CustomClaims>::deserialize(claims)?;
+            validator(&claims)
+        }))
+    }
+
+    pub(crate) fn validate(&self, claims: &serde_json::Value) -> Result<(), Error> {
+        (self.0)(claims)
+    }
+}
+
+impl fmt::Debug for ClaimsValidator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ClaimsValidator")
+    }
+}
+
+impl PartialEq for ClaimsValidator {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for ClaimsValidator {}
+
+type CriticalHeaderValidatorFn = dyn Fn(&serde_json::Value) -> Result<(), Error> + Send + Sync;
+
+/// A validator for a critical header parameter that the application
+/// understands, set with `VerificationOptions::with_critical_header_extension()`
+///
+/// Validators are compared by identity.
+#[derive(Clone)]
+pub struct CriticalHeaderValidator(Arc<CriticalHeaderValidatorFn>);
+
+impl CriticalHeaderValidator {
+    /// Create a validator from a function that returns an error if the value
+    /// of the header parameter is not acceptable
+    pub fn new(
+        validator: impl Fn(&serde_json::Value) -> Result<(), Error> + Send + Sync + 'static,
+    ) -> Self {
+        CriticalHeaderValidator(Arc::new(validator))
+    }
+
+    pub(crate) fn validate(&self, value: &serde_json::Value) -> Result<(), Error> {
+        (self.0)(value)
+    }
+}
+
+impl fmt::Debug for CriticalHeaderValidator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CriticalHeaderValidator")
+    }
+}
+
+impl PartialEq for CriticalHeaderValidator {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CriticalHeaderValidator {}
+
+/// Properties of a key that can be checked against a `KeyPolicy`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyParameters {
+    /// RSA key, with the size of the modulus in bits
+    RSA { modulus_bits: usize },
+    /// Elliptic curve key, with the name of the curve
+    EllipticCurve(&'static str),
+    /// Symmetric key, with its length in bytes
+    MAC { key_length: usize },
+    /// Post-quantum or composite key, with the name of its algorithm, and the
+    /// curve of its traditional component, if any
+    PostQuantum {
+        algorithm: &'static str,
+        curve: Option<&'static str>,
+    },
+    /// Key not covered by the policy. Rejected by policies that restrict
+    /// curves or post-quantum algorithms.
+    Other,
+}
+
+/// Rules to normalize issuers with, so that variants of the same issuer
+/// match each other
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IssuerNormalization {
+    /// Ignore trailing slashes
+    pub ignore_trailing_slash: bool,
+
+    /// Ignore the case of the scheme and of the host of URLs
+    pub ignore_scheme_and_host_case: bool,
+}
+
+impl IssuerNormalization {
+    /// Normalize an issuer
+    pub fn normalize(&self, issuer: &str) -> String {
+        let mut issuer = if self.ignore_trailing_slash {
+            issuer.trim_end_matches('/')
+        } else {
+            issuer
+        }
+        .to_string();
+        if self.ignore_scheme_and_host_case {
+            if let Some(scheme_end) = issuer.find("://") {
+                let host_start = scheme_end + 3;
+                let host_end = issuer[host_start..]
+                    .find(['/', '?', '#'])
+                    .map_or(issuer.len(), |host_len| host_start + host_len);
+                issuer[..host_end].make_ascii_lowercase();
+            }
+        }
+        issuer
+    }
+}
+
+/// A pattern that subjects (`sub` claim) must match
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum SubjectPattern {
+    /// The subject must start with the given prefix, for example
+    /// `spiffe://example.org/ns/payments/`
+    Prefix(String),
+
+    /// The subject must match the pattern, where `*` matches any non-empty
+    /// sequence of characters other than `/`, for example
+    /// `spiffe://example.org/ns/*/sa/billing`
+    Wildcard(String),
+
+    /// The subject must match the regular expression
+    ///
+    /// The expression is used as is, and can match a part of the subject.
+    /// `SubjectPattern::regex()` creates one that has to match the whole subject.
+    #[cfg(feature = "regex")]
+    Regex(regex::Regex),
+}
+
+impl SubjectPattern {
+    /// Create a pattern from a regular expression, that has to match the
+    /// whole subject, for example `spiffe://example\.org/ns/[a-z]+/sa/billing`
+    #[cfg(feature = "regex")]
+    pub fn regex(pattern: &str) -> Result<Self, Error> {
+        let regex = regex::Regex::new(&format!("^(?:{})$", pattern))?;
+        Ok(SubjectPattern::Regex(regex))
+    }
+
+    /// Return `true` if the subject matches the pattern
+    pub fn matches(&self, subject: &str) -> bool {
+        match self {
+            SubjectPattern::Prefix(prefix) => subject.starts_with(prefix.as_str()),
+            SubjectPattern::Wildcard(pattern) => wildcard_matches(subject, pattern),
+            #[cfg(feature = "regex")]
+            SubjectPattern::Regex(regex) => regex.is_match(subject),
+        }
+    }
+}
+
+impl PartialEq for SubjectPattern {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (SubjectPattern::Prefix(a), SubjectPattern::Prefix(b)) => a == b,
+            (SubjectPattern::Wildcard(a), SubjectPattern::Wildcard(b)) => a == b,
+            #[cfg(feature = "regex")]
+            (SubjectPattern::Regex(a), SubjectPattern::Regex(b)) => a.as_str() == b.as_str(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for SubjectPattern {}
+
+pub(crate) fn matching_issuer<'t>(
+    allowed_issuers: &'t HashSet<String>,
+    issuer_normalization: Option<&IssuerNormalization>,
+    issuer: &str,
+) -> Option<&'t str> {
+    if let Some(allowed_issuer) = allowed_issuers.get(issuer) {
+        return Some(allowed_issuer);
+    }
+    let issuer_normalization = issuer_normalization?;
+    let issuer = issuer_normalization.normalize(issuer);
+    allowed_issuers
+        .iter()
+        .find(|allowed_issuer| issuer_normalization.normalize(allowed_issuer) == issuer)
+        .map(|allowed_issuer| allowed_issuer.as_str())
+}
+
+/// Minimum strength requirements for keys.
+///
+/// A policy can be attached to `VerificationOptions`, so that tokens are
+/// rejected if the verification key doesn't comply with it, and can be
+/// enforced on keys right after they have been imported.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyPolicy {
+    /// Minimum size of RSA moduli, in bits
+    pub min_rsa_modulus_bits: usize,
+
+    /// Require elliptic curve keys to use a curve from the set
+    /// (`"P-256"`, `"P-384"`, `"P-521"`, `"secp256k1"`, `"Ed25519"`, `"Ed448"`,
+    /// `"X25519"`, `"X448"`, `"brainpoolP256r1"`, `"brainpoolP384r1"`)
+    pub allowed_curves: Option<HashSet<String>>,
+
+    /// Minimum length of symmetric keys, in bytes
+    pub min_hmac_key_length: usize,
+
+    /// Require post-quantum and composite keys to use an algorithm from the
+    /// set (`"ML-DSA-44"`, `"ML-DSA-65"`, `"ML-DSA-65-Ed25519"`)
+    pub allowed_post_quantum_algorithms: Option<HashSet<String>>,
+}
+
+impl Default for KeyPolicy {
+    fn default() -> Self {
+        Self {
+            min_rsa_modulus_bits: 2048,
+            allowed_curves: None,
+            min_hmac_key_length: 32,
+            allowed_post_quantum_algorithms: None,
+        }
+    }
+}
+
+impl KeyPolicy {
+    /// Return `JWTError::WeakKey` if a key with the given parameters doesn't
+    /// comply with the policy
+    pub fn check(&self, key_parameters: &KeyParameters) -> Result<(), Error> {
+        match key_parameters {
+            KeyParameters::RSA { modulus_bits } => {
+                ensure!(
+                    *modulus_bits >= self.min_rsa_modulus_bits,
+                    JWTError::WeakKey
+                )
+            }
+            KeyParameters::EllipticCurve(curve) => {
+                if let Some(allowed_curves) = &self.allowed_curves {
+                    ensure!(allowed_curves.contains(*curve), JWTError::WeakKey)
+                }
+            }
+            KeyParameters::MAC { key_length } => {
+                ensure!(*key_length >= self.min_hmac_key_length, JWTError::WeakKey)
+            }
+            KeyParameters::PostQuantum { algorithm, curve } => {
+                if let Some(allowed_algorithms) = &self.allowed_post_quantum_algorithms {
+                    ensure!(allowed_algorithms.contains(*algorithm), JWTError::WeakKey)
+                }
+                if let (Some(allowed_curves), Some(curve)) = (&self.allowed_curves, curve) {
+                    ensure!(allowed_curves.contains(*curve), JWTError::WeakKey)
+                }
+            }
+            KeyParameters::Other => {
+                ensure!(
+                    self.allowed_curves.is_none() && self.allowed_post_quantum_algorithms.is_none(),
+                    JWTError::WeakKey
+                )
+            }
+        }
+        Ok(())
+    }
+
+    /// Return the key if it complies with the policy, or `JWTError::WeakKey`
+    /// otherwise
+    pub fn enforce<K: KeyLike>(&self, key: K) -> Result<K, Error> {
+        self.check(&key.key_parameters())?;
+        Ok(key)
+    }
+}
+
+pub(crate) fn check_key_policy(
+    options: Option<&VerificationOptions>,
+    key_parameters: KeyParameters,
+) -> Result<(), Error> {
+    match options.and_then(|options| options.key_policy.as_ref()) {
+        Some(key_policy) => key_policy.check(&key_parameters),
+        None => Ok(()),
+    }
+}
+
+pub(crate) fn check_key_thumbprint(
+    options: Option<&VerificationOptions>,
+    thumbprint: impl FnOnce() -> Option<String>,
+) -> Result<(), Error> {
+    let required_thumbprint =
+        match options.and_then(|options| options.required_public_key_thumbprint.as_deref()) {
+            Some(required_thumbprint) => required_thumbprint,
+            None => return Ok(()),
+        };
+    let thumbprint = thumbprint().ok_or(JWTError::PublicKeyThumbprintMismatch)?;
+    let thumbprint = Base64UrlSafeNoPadding::decode_to_vec(thumbprint, None)?;
+    let required_thumbprint = if required_thumbprint.len() == 64 {
+        Hex::decode_to_vec(required_thumbprint, None)
+    } else {
+        Base64UrlSafeNoPadding::decode_to_vec(required_thumbprint, None)
+    }
+    .map_err(|_| JWTError::PublicKeyThumbprintMismatch)?;
+    ensure!(
+        thumbprint == required_thumbprint,
+        JWTError::PublicKeyThumbprintMismatch
+    );
+    Ok(())
+}
+
+pub(crate) fn check_certificate_thumbprint(
+    options: Option<&VerificationOptions>,
+    thumbprint: Option<&[u8; 32]>,
+) -> Result<(), Error> {
+    let required_thumbprint = match options
+        .and_then(|options| options.required_certificate_sha256_thumbprint.as_deref())
+    {
+        Some(required_thumbprint) => required_thumbprint,
+        None => return Ok(()),
+    };
+    let mut bin = [0u8; 32];
+    let decoded = if required_thumbprint.len() == 64 {
+        Hex::decode(&mut bin, required_thumbprint, None)
+    } else {
+        Base64UrlSafeNoPadding::decode(&mut bin, required_thumbprint, None)
+    };
+    ensure!(
+        decoded.map_err(|_| JWTError::InvalidCertThumprint)?.len() == bin.len(),
+        JWTError::InvalidCertThumprint
+    );
+    let thumbprint = thumbprint.ok_or(JWTError::MissingCertificateThumbprint)?;
+    ensure!(
+        timingsafe_eq(thumbprint, &bin),
+        JWTError::CertificateThumbprintMismatch
+    );
+    Ok(())
+}
+
+pub(crate) fn check_required_claims(
+    options: &VerificationOptions,
+    has_claim: impl Fn(&str) -> bool,
+) -> Result<(), Error> {
+    if let Some(required_claims) = &options.required_claims {
+        for claim in required_claims {
+            ensure!(
+                has_claim(claim),
+                JWTError::RequiredClaimMissing(claim.clone())
+            );
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn check_required_scopes(
+    options: &VerificationOptions,
+    scope: Option<&str>,
+) -> Result<(), Error> {
+    if let Some(required_scopes) = &options.required_scopes {
+        let scopes = Scopes::parse(scope.unwrap_or_default());
+        for required_scope in required_scopes {
+            ensure!(
+                scopes.contains(required_scope),
+                JWTError::RequiredScopeMissing(required_scope.clone())
+            );
         }
     }
+    Ok(())
+}
+
+pub(crate) fn check_json_claims(
+    options: &VerificationOptions,
+    claims_json: &[u8],
+) -> Result<(), Error> {
+    if has_oidc_checks(options) {
+        let claims: JWTClaims<OIDCClaims> = serde_json::from_slice(claims_json)?;
+        check_oidc_claims(options, &claims.custom, claims.audiences.as_ref())?;
+    }
+    if options.required_claims.is_none() && options.required_scopes.is_none() {
+        return Ok(());
+    }
+    let claims: serde_json::Map<String, serde_json::Value> = serde_json::from_slice(claims_json)?;
+    check_required_claims(options, |claim| {
+        claims.get(claim).is_some_and(|value| !value.is_null())
+    })?;
+    check_required_scopes(
+        options,
+        claims.get("scope").and_then(|scope| scope.as_str()),
+    )
 }
 
 /// Unsigned metadata about a key to be attached to tokens.
@@ -79,6 +975,7 @@ pub struct KeyMetadata {
     pub(crate) key_set_url: Option<String>,
     pub(crate) public_key: Option<String>,
     pub(crate) certificate_url: Option<String>,
+    pub(crate) certificate_chain: Option<Vec<String>>,
     pub(crate) certificate_sha1_thumbprint: Option<String>,
     pub(crate) certificate_sha256_thumbprint: Option<String>,
 }
@@ -102,6 +999,52 @@ impl KeyMetadata {
         self
     }
 
+    /// Add the SHA-1 and SHA-256 thumbprints of the DER-encoded certificate
+    /// of the signing key to the metadata ("x5t" and "x5t#S256")
+    pub fn with_certificate(mut self, certificate: &[u8]) -> Result<Self, Error> {
+        crate::x509::certificate_public_key(certificate)?;
+        let sha1_thumbprint = hmac_sha1_compact::Hash::hash(certificate);
+        let sha256_thumbprint = hmac_sha256::Hash::hash(certificate);
+        self.certificate_sha1_thumbprint =
+            Some(Base64UrlSafeNoPadding::encode_to_string(sha1_thumbprint)?);
+        self.certificate_sha256_thumbprint =
+            Some(Base64UrlSafeNoPadding::encode_to_string(sha256_thumbprint)?);
+        Ok(self)
+    }
+
+    /// Add the SHA-1 and SHA-256 thumbprints of the PEM-encoded certificate
+    /// of the signing key to the metadata ("x5t" and "x5t#S256")
+    pub fn with_certificate_pem(self, certificate: &str) -> Result<Self, Error> {
+        let certificate_chain = crate::x509::certificates_from_pem(certificate)?;
+        self.with_certificate(&certificate_chain[0])
+    }
+
+    /// Add a chain of DER-encoded certificates to the metadata ("x5c"),
+    /// starting with the certificate of the signing key.
+    ///
+    /// The thumbprints of the first certificate are also added.
+    pub fn with_certificate_chain(
+        self,
+        certificate_chain: &[impl AsRef<[u8]>],
+    ) -> Result<Self, Error> {
+        let certificate = certificate_chain
+            .first()
+            .ok_or(JWTError::InvalidCertificate)?;
+        let mut metadata = self.with_certificate(certificate.as_ref())?;
+        let certificate_chain = certificate_chain
+            .iter()
+            .map(|certificate| Base64::encode_to_string(certificate.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+        metadata.certificate_chain = Some(certificate_chain);
+        Ok(metadata)
+    }
+
+    /// Add a chain of PEM-encoded certificates to the metadata ("x5c"),
+    /// starting with the certificate of the signing key
+    pub fn with_certificate_chain_pem(self, certificate_chain: &str) -> Result<Self, Error> {
+        self.with_certificate_chain(&crate::x509::certificates_from_pem(certificate_chain)?)
+    }
+
     /// Add a certificate SHA-1 thumbprint to the metadata ("x5t")
     pub fn with_certificate_sha1_thumbprint(
         mut self,
@@ -151,6 +1094,96 @@ impl KeyMetadata {
     }
 }
 
+/// Header parameters to add to a token, in addition to the ones set from the
+/// key and its metadata
+///
+/// Like the rest of the header, these parameters are covered by the
+/// signature, but can be read before the token is verified.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeaderOptions {
+    pub(crate) content_type: Option<String>,
+    pub(crate) signature_type: Option<String>,
+    pub(crate) public_key: Option<String>,
+    pub(crate) parameters: BTreeMap<String, serde_json::Value>,
+    pub(crate) critical: Vec<String>,
+}
+
+impl HeaderOptions {
+    /// Set the content type ("cty")
+    pub fn with_content_type(mut self, content_type: impl ToString) -> Self {
+        self.content_type = Some(content_type.to_string());
+        self
+    }
+
+    /// Set the signature type ("typ"), instead of "JWT"
+    ///
+    /// Tokens can only be verified by this crate if the type is "JWT" or
+    /// ends with "+jwt", such as "at+jwt".
+    pub fn with_signature_type(mut self, signature_type: impl ToString) -> Self {
+        self.signature_type = Some(signature_type.to_string());
+        self
+    }
+
+    /// Embed a public key ("jwk"), that must not include any private members
+    pub fn with_public_key(mut self, public_key: &JWK) -> Result<Self, Error> {
+        ensure!(
+            !public_key.has_private_members(),
+            JWTError::InvalidPublicKey
+        );
+        self.public_key = Some(public_key.to_json()?);
+        Ok(self)
+    }
+
+    /// Add a custom header parameter, such as "url" or "nonce"
+    ///
+    /// Registered parameters that are set from the key, its metadata or the
+    /// other header options cannot be set this way.
+    pub fn with_parameter(
+        mut self,
+        name: impl ToString,
+        value: impl Serialize,
+    ) -> Result<Self, Error> {
+        let name = name.to_string();
+        ensure!(
+            !RESERVED_HEADER_PARAMETERS.contains(&name.as_str()),
+            JWTError::ReservedHeaderParameter
+        );
+        self.parameters.insert(name, serde_json::to_value(value)?);
+        Ok(self)
+    }
+
+    /// Add a custom header parameter, and list it as critical ("crit")
+    ///
+    /// Verifiers reject the token unless they understand this parameter.
+    pub fn with_critical_parameter(
+        mut self,
+        name: impl ToString,
+        value: impl Serialize,
+    ) -> Result<Self, Error> {
+        let name = name.to_string();
+        self = self.with_parameter(&name, value)?;
+        if !self.critical.contains(&name) {
+            self.critical.push(name);
+        }
+        Ok(self)
+    }
+}
+
+/// Lowercase a media type, and remove the "application/" prefix if the
+/// remaining type doesn't include any other "/" (RFC 7515, Section 4.1.9)
+fn normalize_media_type(media_type: &str) -> String {
+    let media_type = media_type.to_ascii_lowercase();
+    match media_type.strip_prefix("application/") {
+        Some(subtype) if !subtype.contains('/') => subtype.to_string(),
+        _ => media_type,
+    }
+}
+
+const RESERVED_HEADER_PARAMETERS: [&str; 14] = [
+    "alg", "enc", "zip", "cty", "kid", "typ", "crit", "b64", "jku", "jwk", "x5u", "x5c", "x5t",
+    "x5t#S256",
+];
+
 #[inline(never)]
 pub(crate) fn timingsafe_eq(a: &[u8], b: &[u8]) -> bool {
     if a.len() != b.len() {
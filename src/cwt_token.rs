@@ -1,16 +1,19 @@
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 use std::io::Cursor;
 
 use binstring::*;
 use ciborium::de::from_reader as from_cbor;
 use ciborium::ser::into_writer as to_cbor;
 use ciborium::value::Value as CBORValue;
-use coarsetime::Duration;
+use serde::{de::DeserializeOwned, Serialize};
 
 use crate::claims::*;
 use crate::common::*;
 use crate::error::*;
 use crate::jwt_header::*;
+use crate::oidc::*;
+use crate::replay_guard::*;
+use crate::revocation::*;
 
 pub const MAX_CWT_HEADER_LENGTH: usize = 4096;
 
@@ -18,14 +21,67 @@ pub const MAX_CWT_HEADER_LENGTH: usize = 4096;
 pub struct CWTToken;
 
 impl CWTToken {
-    pub(crate) fn verify<AuthenticationOrSignatureFn>(
+    /// Create a COSE_Sign1 token
+    ///
+    /// Registered claims are encoded with their integer labels, other claims
+    /// with their names.
+    pub(crate) fn build<SignatureFn, CustomClaims: Serialize + DeserializeOwned>(
+        jwt_header: &JWTHeader,
+        claims: JWTClaims<CustomClaims>,
+        signature_fn: SignatureFn,
+    ) -> Result<Vec<u8>, Error>
+    where
+        SignatureFn: FnOnce(&[u8]) -> Result<Vec<u8>, Error>,
+    {
+        let mut protected = vec![(
+            CBORValue::from(I_ALG),
+            CBORValue::from(cose_algorithm(&jwt_header.algorithm)?),
+        )];
+        if let Some(key_id) = &jwt_header.key_id {
+            protected.push((
+                CBORValue::from(I_KID),
+                CBORValue::Bytes(key_id.as_bytes().to_vec()),
+            ));
+        }
+        let mut protected_bytes = vec![];
+        to_cbor(&CBORValue::Map(protected), &mut protected_bytes)?;
+        let mut claims_bytes = vec![];
+        to_cbor(&CBORValue::Map(claims.to_cwt()?), &mut claims_bytes)?;
+
+        let protected_cbor = CBORValue::Bytes(protected_bytes);
+        let claims_cbor = CBORValue::Bytes(claims_bytes);
+        let authenticated_cbor = CBORValue::Array(vec![
+            CBORValue::Text("Signature1".into()),
+            protected_cbor.clone(),
+            CBORValue::Bytes(vec![]),
+            claims_cbor.clone(),
+        ]);
+        let mut authenticated_cbor_bytes = vec![];
+        to_cbor(&authenticated_cbor, &mut authenticated_cbor_bytes)?;
+        let signature = signature_fn(&authenticated_cbor_bytes)?;
+
+        let token_cbor = CBORValue::Tag(
+            18,
+            Box::new(CBORValue::Array(vec![
+                protected_cbor,
+                CBORValue::Map(vec![]),
+                claims_cbor,
+                CBORValue::Bytes(signature),
+            ])),
+        );
+        let mut token = vec![];
+        to_cbor(&token_cbor, &mut token)?;
+        Ok(token)
+    }
+
+    pub(crate) fn verify<AuthenticationOrSignatureFn, CustomClaims: Serialize + DeserializeOwned>(
         jwt_alg_name: &'static str,
         token: impl AsRef<[u8]>,
         options: Option<VerificationOptions>,
         authentication_or_signature_fn: AuthenticationOrSignatureFn,
-    ) -> Result<JWTClaims<NoCustomClaims>, Error>
+    ) -> Result<JWTClaims<CustomClaims>, Error>
     where
-        AuthenticationOrSignatureFn: FnOnce(&str, &[u8]) -> Result<(), Error>,
+        AuthenticationOrSignatureFn: FnOnce(&[u8], &[u8]) -> Result<(), Error>,
     {
         let options = options.unwrap_or_default();
         let token = token.as_ref();
@@ -56,16 +112,15 @@ impl CWTToken {
         ensure!(header_len > 0 && header_len <= MAX_CWT_HEADER_LENGTH);
 
         let mut jwt_header = JWTHeader::default();
-        let mut claims: JWTClaims<NoCustomClaims> = Claims::create(Default::default());
 
         let mut protected_reader =
             Cursor::new(parts_cbor[0].as_bytes().ok_or(JWTError::CWTDecodingError)?);
         let protected_cbor: CBORValue = from_cbor(&mut protected_reader)?;
         let protected = protected_cbor.as_map().ok_or(JWTError::CWTDecodingError)?;
-        jwt_header.mix_cwt(&protected)?;
+        jwt_header.mix_cwt(protected)?;
 
         let unprotected = parts_cbor[1].as_map().ok_or(JWTError::CWTDecodingError)?;
-        jwt_header.mix_cwt(&unprotected)?;
+        jwt_header.mix_cwt(unprotected)?;
 
         ensure!(
             jwt_header.algorithm == jwt_alg_name,
@@ -100,88 +155,104 @@ impl CWTToken {
 
         to_cbor(&authenticated_cbor, &mut authenticated_cbor_bytes)?;
 
-        authentication_or_signature_fn(
-            BinString::from(authenticated_cbor_bytes).as_str(),
-            authentication_tag_or_signature,
-        )?;
+        authentication_or_signature_fn(&authenticated_cbor_bytes, authentication_tag_or_signature)?;
 
         let mut claims_reader =
             Cursor::new(parts_cbor[2].as_bytes().ok_or(JWTError::CWTDecodingError)?);
         let claims_cbor: CBORValue = from_cbor(&mut claims_reader)?;
         let claims_ = claims_cbor.as_map().ok_or(JWTError::CWTDecodingError)?;
-        claims.mix_cwt(&claims_)?;
+        let claims: JWTClaims<CustomClaims> = JWTClaims::from_cwt(claims_)?;
 
         claims.validate(&options)?;
+        check_required_claims(&options, |claim| {
+            let label = match claim {
+                "iss" => I_ISS,
+                "sub" => I_SUB,
+                "aud" => I_AUD,
+                "exp" => I_EXP,
+                "nbf" => I_NBF,
+                "iat" => I_IAT,
+                "jti" => I_CTI,
+                "nonce" => I_NONCE,
+                _ => return false,
+            };
+            claims_
+                .iter()
+                .any(|(key, value)| key.as_integer() == Some(label.into()) && !value.is_null())
+        })?;
+        let scope = claims_.iter().find_map(|(key, value)| {
+            if key.as_integer() == Some(I_SCOPE.into()) {
+                value.as_text()
+            } else {
+                None
+            }
+        });
+        check_required_scopes(&options, scope)?;
+        // OpenID Connect claims have no registered CWT labels
+        check_oidc_claims(&options, &OIDCClaims::new(), claims.audiences.as_ref())?;
+        check_revocation(&options, &claims)?;
+        check_replay(&options, &claims)?;
         Ok(claims)
     }
 }
 
-impl<CustomClaims> JWTClaims<CustomClaims> {
-    fn mix_cwt(&mut self, cwt: &[(CBORValue, CBORValue)]) -> Result<(), Error> {
-        for (key, value) in cwt {
-            let key_id: i32 = key
-                .as_integer()
-                .ok_or(JWTError::CWTDecodingError)?
-                .try_into()
-                .map_err(|_| JWTError::CWTDecodingError)?;
-            match key_id {
-                I_IAT => {
-                    let ts: u64 = if let Some(ts) = value.as_integer() {
-                        ts.try_into().map_err(|_| JWTError::CWTDecodingError)?
-                    } else if let Some(ts) = value.as_float() {
-                        let f: f64 = ts.try_into().map_err(|_| JWTError::CWTDecodingError)?;
-                        f.round() as _
-                    } else {
-                        bail!(JWTError::CWTDecodingError)
-                    };
-                    self.issued_at = Some(Duration::from_secs(ts));
-                }
-                I_EXP => {
-                    let ts: u64 = if let Some(ts) = value.as_integer() {
-                        ts.try_into().map_err(|_| JWTError::CWTDecodingError)?
-                    } else if let Some(ts) = value.as_float() {
-                        let f: f64 = ts.try_into().map_err(|_| JWTError::CWTDecodingError)?;
-                        f.round() as _
-                    } else {
-                        bail!(JWTError::CWTDecodingError)
-                    };
-                    self.expires_at = Some(Duration::from_secs(ts));
-                }
-                I_NBF => {
-                    let ts: u64 = if let Some(ts) = value.as_integer() {
-                        ts.try_into().map_err(|_| JWTError::CWTDecodingError)?
-                    } else if let Some(ts) = value.as_float() {
-                        let f: f64 = ts.try_into().map_err(|_| JWTError::CWTDecodingError)?;
-                        f.round() as _
-                    } else {
-                        bail!(JWTError::CWTDecodingError)
-                    };
-                    self.invalid_before = Some(Duration::from_secs(ts));
-                }
-                I_ISS => {
-                    self.issuer = Some(value.as_text().ok_or(JWTError::CWTDecodingError)?.into());
+impl<CustomClaims: Serialize + DeserializeOwned> JWTClaims<CustomClaims> {
+    fn to_cwt(&self) -> Result<Vec<(CBORValue, CBORValue)>, Error> {
+        let claims = match serde_json::to_value(self)? {
+            serde_json::Value::Object(claims) => claims,
+            _ => bail!(JWTError::CWTEncodingError),
+        };
+        let mut cwt = Vec::with_capacity(claims.len());
+        for (name, value) in claims {
+            let key = match claim_label(&name) {
+                Some(label) => CBORValue::from(label),
+                None => CBORValue::Text(name),
+            };
+            let value = match (key.as_integer().map(i32::try_from), value) {
+                (Some(Ok(I_CTI | I_NONCE)), serde_json::Value::String(v)) => {
+                    CBORValue::Bytes(v.into_bytes())
                 }
-                I_SUB => {
-                    self.subject = Some(value.as_text().ok_or(JWTError::CWTDecodingError)?.into());
+                (_, value) => {
+                    CBORValue::serialized(&value).map_err(|_| JWTError::CWTEncodingError)?
                 }
-                I_AUD => {
-                    let audiences = value.as_text().ok_or(JWTError::CWTDecodingError)?.into();
-                    self.audiences = Some(Audiences::AsString(audiences));
+            };
+            cwt.push((key, value));
+        }
+        Ok(cwt)
+    }
+
+    fn from_cwt(cwt: &[(CBORValue, CBORValue)]) -> Result<Self, Error> {
+        let mut claims = serde_json::Map::new();
+        for (key, value) in cwt {
+            let (label, name) = match key {
+                CBORValue::Text(name) => (None, name.as_str()),
+                CBORValue::Integer(label) => {
+                    let label: i32 = (*label)
+                        .try_into()
+                        .map_err(|_| JWTError::CWTDecodingError)?;
+                    match claim_name(label) {
+                        Some(name) => (Some(label), name),
+                        None => continue,
+                    }
                 }
-                I_CTI => {
+                _ => bail!(JWTError::CWTDecodingError),
+            };
+            let value = match (label, value) {
+                (Some(I_CTI | I_NONCE), _) => {
                     let v = value.as_bytes().ok_or(JWTError::CWTDecodingError)?;
-                    let v = BinString::from(v).into();
-                    self.jwt_id = Some(v);
+                    serde_json::Value::String(BinString::from(v).into())
                 }
-                I_NONCE => {
-                    let v = value.as_bytes().ok_or(JWTError::CWTDecodingError)?;
-                    let v = BinString::from(v).into();
-                    self.nonce = Some(v);
+                (Some(I_IAT | I_EXP | I_NBF), CBORValue::Float(ts)) => {
+                    serde_json::Value::from(ts.round() as u64)
                 }
-                _ => {}
-            }
+                _ => value
+                    .deserialized::<serde_json::Value>()
+                    .map_err(|_| JWTError::CWTDecodingError)?,
+            };
+            claims.insert(name.to_string(), value);
         }
-        Ok(())
+        serde_json::from_value(serde_json::Value::Object(claims))
+            .map_err(|_| JWTError::CWTDecodingError.into())
     }
 }
 
@@ -224,8 +295,13 @@ impl JWTHeader {
                     self.content_type = Some(content_type.into());
                 }
                 I_KID => {
-                    let key_id = value.as_text().ok_or(JWTError::CWTDecodingError)?;
-                    self.key_id = Some(key_id.into());
+                    let key_id = match value {
+                        CBORValue::Text(key_id) => key_id.clone(),
+                        CBORValue::Bytes(key_id) => String::from_utf8(key_id.clone())
+                            .map_err(|_| JWTError::CWTDecodingError)?,
+                        _ => bail!(JWTError::CWTDecodingError),
+                    };
+                    self.key_id = Some(key_id);
                 }
                 I_CRIT => {
                     let crit_cbor = value.as_array().ok_or(JWTError::CWTDecodingError)?;
@@ -260,6 +336,42 @@ impl JWTHeader {
     }
 }
 
+fn cose_algorithm(jwt_alg_name: &str) -> Result<i32, Error> {
+    Ok(match jwt_alg_name {
+        "EdDSA" => I_EDDSA,
+        "RS512" => I_RS512,
+        "RS384" => I_RS384,
+        "RS256" => I_RS256,
+        "ES256K" => I_ES256K,
+        "PS512" => I_PS512,
+        "PS384" => I_PS384,
+        "PS256" => I_PS256,
+        "ES256" => I_ES256,
+        "ES384" => I_ES384,
+        "ES512" => I_ES512,
+        "HS256" => I_HS256,
+        "HS384" => I_HS384,
+        "HS512" => I_HS512,
+        _ => bail!(JWTError::AlgorithmMismatch),
+    })
+}
+
+/// The CWT label of a registered JWT claim
+fn claim_label(name: &str) -> Option<i32> {
+    CLAIM_LABELS
+        .iter()
+        .find(|(_, claim_name)| *claim_name == name)
+        .map(|(label, _)| *label)
+}
+
+/// The JWT name of a registered CWT claim
+fn claim_name(label: i32) -> Option<&'static str> {
+    CLAIM_LABELS
+        .iter()
+        .find(|(claim_label, _)| *claim_label == label)
+        .map(|(_, name)| *name)
+}
+
 const I_ALG: i32 = 1;
 const I_CRIT: i32 = 2;
 const I_CTY: i32 = 3;
@@ -290,8 +402,21 @@ const I_EXP: i32 = 4;
 const I_NBF: i32 = 5;
 const I_IAT: i32 = 6;
 const I_CTI: i32 = 7;
+const I_SCOPE: i32 = 9;
 const I_NONCE: i32 = 10;
 
+const CLAIM_LABELS: [(i32, &str); 9] = [
+    (I_ISS, "iss"),
+    (I_SUB, "sub"),
+    (I_AUD, "aud"),
+    (I_EXP, "exp"),
+    (I_NBF, "nbf"),
+    (I_IAT, "iat"),
+    (I_CTI, "jti"),
+    (I_SCOPE, "scope"),
+    (I_NONCE, "nonce"),
+];
+
 #[test]
 fn should_verify_token() {
     use ct_codecs::{Decoder, Hex};
@@ -306,3 +431,53 @@ fn should_verify_token() {
     let token = Hex::decode_to_vec(token_hex, None).unwrap();
     let _ = key.verify_cwt_token(token, Default::default()).unwrap();
 }
+
+#[test]
+fn should_sign_and_verify_token() {
+    use crate::prelude::*;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Telemetry {
+        device_type: String,
+        firmware: u32,
+    }
+
+    let custom = Telemetry {
+        device_type: "thermostat".to_string(),
+        firmware: 42,
+    };
+    let claims = Claims::with_custom_claims(custom, Duration::from_hours(1))
+        .with_issuer("fleet")
+        .with_audience("gateway")
+        .with_jwt_id("device-1234");
+
+    let key_pair = ES256KeyPair::generate().with_key_id("device key");
+    let token = key_pair.sign_cwt(claims).unwrap();
+    let options = VerificationOptions {
+        allowed_issuers: Some(HashSet::from_strings(&["fleet"])),
+        allowed_audiences: Some(HashSet::from_strings(&["gateway"])),
+        required_key_id: Some("device key".to_string()),
+        ..Default::default()
+    };
+    let claims = key_pair
+        .public_key()
+        .verify_cwt_token::<Telemetry>(&token, Some(options))
+        .unwrap();
+    assert_eq!(claims.custom.device_type, "thermostat");
+    assert_eq!(claims.custom.firmware, 42);
+    assert_eq!(claims.jwt_id.as_deref(), Some("device-1234"));
+    assert!(ES256KeyPair::generate()
+        .public_key()
+        .verify_cwt_token::<Telemetry>(&token, None)
+        .is_err());
+
+    let key_pair = Ed25519KeyPair::generate();
+    let token = key_pair
+        .sign_cwt(Claims::create(Duration::from_hours(1)).with_subject("sensor"))
+        .unwrap();
+    let claims = key_pair
+        .public_key()
+        .verify_cwt_token::<NoCustomClaims>(&token, None)
+        .unwrap();
+    assert_eq!(claims.subject.as_deref(), Some("sensor"));
+}
@@ -0,0 +1,63 @@
+//! Raw DEFLATE (RFC 1951) compression, used by the JWE `"zip":"DEF"` parameter.
+//!
+//! Decompression stops as soon as the output exceeds the given limit.
+
+use miniz_oxide::inflate::TINFLStatus;
+
+use crate::error::*;
+
+/// Compression level used for new tokens
+const COMPRESSION_LEVEL: u8 = 6;
+
+/// Compress `data` into a raw DEFLATE stream
+pub(crate) fn compress(data: &[u8]) -> Vec<u8> {
+    miniz_oxide::deflate::compress_to_vec(data, COMPRESSION_LEVEL)
+}
+
+/// Decompress a raw DEFLATE stream, failing if the output would be larger
+/// than `max_length` bytes
+pub(crate) fn decompress(data: &[u8], max_length: usize) -> Result<Vec<u8>, Error> {
+    miniz_oxide::inflate::decompress_to_vec_with_limit(data, max_length).map_err(|e| {
+        match e.status {
+            TINFLStatus::HasMoreOutput => JWTError::DecompressedPayloadTooLarge.into(),
+            _ => JWTError::InvalidCompressedData.into(),
+        }
+    })
+}
+
+#[test]
+fn deflate() {
+    use ct_codecs::{Base64, Decoder};
+
+    for data in [
+        &b""[..],
+        b"a",
+        b"abcabcabcabcabcabcabcabcabcabcabc",
+        &[0u8; 100_000],
+        &(0..70_000)
+            .map(|i| (i * 7 % 251) as u8)
+            .collect::<Vec<u8>>(),
+    ] {
+        let compressed = compress(data);
+        assert_eq!(decompress(&compressed, data.len()).unwrap(), data);
+        if !data.is_empty() {
+            assert!(decompress(&compressed, data.len() - 1).is_err());
+        }
+    }
+    assert!(compress(&[0u8; 100_000]).len() < 1000);
+
+    // Python: zlib.compressobj(9, zlib.DEFLATED, -15), which uses dynamic Huffman codes
+    let compressed = Base64::decode_to_vec(
+        "zY3HDsIwGINfpfrPHNij7L33pqy0CSXQRUKZ4t0JQnDlys32J9t34K4KMiCDagQ8wDXbIcIzgrB0ZvRIJIRNagnEbINwkBVwOWHCv/OFB3Rmu86X8A96iV+BaDsIY2rp4tPr8weCoXAkGpPjiWQqncnm8oViqVyp1uqNZqvd6fb6g+FoPJnOlPliuVojVcNko2/pbm+Ylu0cGD+6p/PlevvPKXg8AQ==",
+        None,
+    )
+    .unwrap();
+    let mut expected = br#"{"sub":"alice","scope":"read write admin","roles":["user","admin"],"groups":["users","admins","users","admins","users","admins"],"padding":""#.to_vec();
+    for _ in 0..3 {
+        expected.extend(48u8..123);
+    }
+    expected.extend_from_slice(b"\"}");
+    assert_eq!(decompress(&compressed, 1000).unwrap(), expected);
+    assert!(decompress(&compressed, 100).is_err());
+    assert!(decompress(&compressed[..compressed.len() - 1], 1000).is_err());
+}
@@ -0,0 +1,286 @@
+use coarsetime::Duration;
+use ct_codecs::{Base64UrlSafeNoPadding, Encoder};
+use serde::{Deserialize, Serialize};
+
+use crate::claims::*;
+use crate::common::*;
+use crate::error::*;
+use crate::jwk::{public_key_from_jwk, JWK};
+use crate::nonce::{NonceProvider, RandomNonceProvider};
+use crate::replay_guard::{SharedTokenReplayGuard, TokenReplayGuard};
+use crate::token::*;
+
+/// The type ("typ") of DPoP proofs
+pub const DPOP_PROOF_TYPE: &str = "dpop+jwt";
+
+/// The default maximum age of a DPoP proof
+pub const DEFAULT_DPOP_PROOF_MAX_AGE: Duration = Duration::from_secs(300);
+
+/// The claims of a DPoP proof (RFC 9449), in addition to `jti`, `iat` and
+/// the optional `nonce`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DPoPClaims {
+    /// The HTTP method of the request ("htm")
+    #[serde(rename = "htm")]
+    pub http_method: String,
+
+    /// The HTTP URI of the request, without query and fragment ("htu")
+    #[serde(rename = "htu")]
+    pub http_uri: String,
+
+    /// The hash of the access token sent along with the proof ("ath")
+    #[serde(rename = "ath", default, skip_serializing_if = "Option::is_none")]
+    pub access_token_hash: Option<String>,
+}
+
+/// The request a DPoP proof is created for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DPoPProofOptions {
+    /// The HTTP method of the request
+    pub http_method: String,
+
+    /// The HTTP URI of the request
+    pub http_uri: String,
+
+    /// The access token sent along with the proof, if any
+    pub access_token: Option<String>,
+
+    /// The nonce previously sent by the server, if any
+    pub nonce: Option<String>,
+}
+
+impl DPoPProofOptions {
+    /// Create a proof for a request
+    pub fn new(http_method: impl ToString, http_uri: impl ToString) -> Self {
+        DPoPProofOptions {
+            http_method: http_method.to_string(),
+            http_uri: http_uri.to_string(),
+            access_token: None,
+            nonce: None,
+        }
+    }
+
+    /// Bind the proof to the access token sent along with it
+    pub fn with_access_token(mut self, access_token: impl ToString) -> Self {
+        self.access_token = Some(access_token.to_string());
+        self
+    }
+
+    /// Include a nonce sent by the server
+    pub fn with_nonce(mut self, nonce: impl ToString) -> Self {
+        self.nonce = Some(nonce.to_string());
+        self
+    }
+}
+
+/// How to validate DPoP proofs on the server
+#[derive(Debug, Clone)]
+pub struct DPoPVerificationOptions {
+    /// The HTTP method of the request
+    pub http_method: String,
+
+    /// The HTTP URI of the request. The query and the fragment are ignored.
+    pub http_uri: String,
+
+    /// The access token sent along with the proof, that the proof must be
+    /// bound to
+    pub access_token: Option<String>,
+
+    /// Require the proof to include this nonce
+    pub nonce: Option<String>,
+
+    /// Reject proofs created more than `max_age` ago
+    pub max_age: Duration,
+
+    /// How much clock drift to tolerate
+    pub time_tolerance: Option<Duration>,
+
+    /// Source of the current time. The system clock is used if not set.
+    pub time_source: Option<SharedTimeSource>,
+
+    /// Reject proofs whose identifier (`jti` claim) has already been seen
+    pub replay_guard: Option<SharedTokenReplayGuard>,
+}
+
+impl DPoPVerificationOptions {
+    /// Validate proofs for a request
+    pub fn new(http_method: impl ToString, http_uri: impl ToString) -> Self {
+        DPoPVerificationOptions {
+            http_method: http_method.to_string(),
+            http_uri: http_uri.to_string(),
+            access_token: None,
+            nonce: None,
+            max_age: DEFAULT_DPOP_PROOF_MAX_AGE,
+            time_tolerance: VerificationOptions::default().time_tolerance,
+            time_source: None,
+            replay_guard: None,
+        }
+    }
+
+    /// Require the proof to be bound to the access token sent along with it
+    pub fn with_access_token(mut self, access_token: impl ToString) -> Self {
+        self.access_token = Some(access_token.to_string());
+        self
+    }
+
+    /// Require the proof to include the nonce sent by the server
+    pub fn with_nonce(mut self, nonce: impl ToString) -> Self {
+        self.nonce = Some(nonce.to_string());
+        self
+    }
+
+    /// Set the maximum age of proofs
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Check the proof creation date against the given time source instead
+    /// of the system clock
+    pub fn with_time_source(mut self, time_source: impl TimeSource + 'static) -> Self {
+        self.time_source = Some(SharedTimeSource::new(time_source));
+        self
+    }
+
+    /// Reject proofs that have already been seen by the replay guard
+    pub fn with_replay_guard(mut self, replay_guard: impl TokenReplayGuard + 'static) -> Self {
+        self.replay_guard = Some(SharedTokenReplayGuard::new(replay_guard));
+        self
+    }
+}
+
+/// A verified DPoP proof
+#[derive(Debug, Clone)]
+pub struct DPoPProof {
+    claims: JWTClaims<DPoPClaims>,
+    public_key: JWK,
+    key_thumbprint: String,
+}
+
+impl DPoPProof {
+    /// Create a DPoP proof for a request, embedding the public key of the
+    /// signer
+    ///
+    /// The signer gets the claims and the header options, and must return a
+    /// token signed with the key pair of `public_key`, for example using
+    /// `sign_with_header_options()`.
+    pub fn create(
+        public_key: &JWK,
+        options: &DPoPProofOptions,
+        signer: impl FnOnce(JWTClaims<DPoPClaims>, &HeaderOptions) -> Result<String, Error>,
+    ) -> Result<String, Error> {
+        let dpop_claims = DPoPClaims {
+            http_method: options.http_method.clone(),
+            http_uri: options.http_uri.clone(),
+            access_token_hash: match &options.access_token {
+                Some(access_token) => Some(access_token_hash(access_token)?),
+                None => None,
+            },
+        };
+        let mut claims = Claims::with_custom_claims(dpop_claims, Duration::default())
+            .with_jwt_id(RandomNonceProvider.generate());
+        claims.expires_at = None;
+        claims.invalid_before = None;
+        if let Some(nonce) = &options.nonce {
+            claims = claims.with_nonce(nonce);
+        }
+        let header_options = HeaderOptions::default()
+            .with_signature_type(DPOP_PROOF_TYPE)
+            .with_public_key(public_key)?;
+        signer(claims, &header_options)
+    }
+
+    /// Verify a DPoP proof, using the public key it embeds, and check that
+    /// it was created for the request
+    pub fn verify(proof: &str, options: &DPoPVerificationOptions) -> Result<Self, Error> {
+        let metadata = Token::decode_metadata(proof)?;
+        let public_key = JWK::from_json(metadata.public_key().ok_or(JWTError::InvalidDPoPProof)?)?;
+        ensure!(
+            !public_key.has_private_members(),
+            JWTError::InvalidDPoPProof
+        );
+        let key = public_key_from_jwk(metadata.algorithm(), &public_key)?;
+        let verification_options = VerificationOptions {
+            required_typ: Some(DPOP_PROOF_TYPE.to_string()),
+            require_issued_at: true,
+            require_jwt_id: true,
+            required_nonce: options.nonce.clone(),
+            max_validity: Some(options.max_age),
+            time_tolerance: options.time_tolerance,
+            time_source: options.time_source.clone(),
+            ..Default::default()
+        };
+        let claims = Token::verify_with_keys::<DPoPClaims>(
+            &[key.as_ref()],
+            proof,
+            Some(verification_options.clone()),
+        )?;
+        ensure!(
+            claims.custom.http_method == options.http_method
+                && without_query_and_fragment(&claims.custom.http_uri)
+                    == without_query_and_fragment(&options.http_uri),
+            JWTError::DPoPProofMismatch
+        );
+        if let Some(access_token) = &options.access_token {
+            ensure!(
+                claims.custom.access_token_hash.as_deref()
+                    == Some(access_token_hash(access_token)?.as_str()),
+                JWTError::DPoPProofMismatch
+            );
+        }
+        if let Some(replay_guard) = &options.replay_guard {
+            let jwt_id = claims
+                .jwt_id
+                .as_deref()
+                .ok_or(JWTError::RequiredJWTIdMissing)?;
+            let issued_at = claims.issued_at.ok_or(JWTError::RequiredIssuedAtMissing)?;
+            let retain_until =
+                issued_at + options.max_age + options.time_tolerance.unwrap_or_default();
+            replay_guard.check_and_store(jwt_id, retain_until, verification_options.now())?;
+        }
+        let key_thumbprint = public_key.thumbprint()?;
+        Ok(DPoPProof {
+            claims,
+            public_key,
+            key_thumbprint,
+        })
+    }
+
+    /// The claims of the proof
+    pub fn claims(&self) -> &JWTClaims<DPoPClaims> {
+        &self.claims
+    }
+
+    /// The public key the proof was signed with
+    pub fn public_key(&self) -> &JWK {
+        &self.public_key
+    }
+
+    /// The RFC 7638 thumbprint of the public key ("jkt")
+    pub fn key_thumbprint(&self) -> &str {
+        &self.key_thumbprint
+    }
+
+    /// Require the access token to be bound (`cnf` claim) to the key of the
+    /// proof, in addition to the given verification options
+    pub fn access_token_options(
+        &self,
+        options: Option<VerificationOptions>,
+    ) -> VerificationOptions {
+        VerificationOptions {
+            required_confirmation_key_thumbprint: Some(self.key_thumbprint.clone()),
+            ..options.unwrap_or_default()
+        }
+    }
+}
+
+/// The hash of an access token ("ath"), to bind a DPoP proof to it
+pub fn access_token_hash(access_token: &str) -> Result<String, Error> {
+    Ok(Base64UrlSafeNoPadding::encode_to_string(
+        hmac_sha256::Hash::hash(access_token.as_bytes()),
+    )?)
+}
+
+fn without_query_and_fragment(uri: &str) -> &str {
+    uri.split(['?', '#']).next().unwrap_or_default()
+}
@@ -0,0 +1,294 @@
+//! Decryption of password-protected private keys.
+//!
+//! Two formats are supported:
+//! - encrypted PKCS#8 (RFC 5958) documents, using PBES2 (RFC 8018) with
+//!   PBKDF2 and AES-CBC, as created by `openssl pkcs8 -topk8 -v2 aes256`
+//! - legacy OpenSSL PEM encryption (`Proc-Type: 4,ENCRYPTED`), with AES-CBC
+//!
+//! The decrypted key is returned as a regular PEM document, that can be
+//! passed to the `from_pem()` function of key pairs.
+
+use aes::{Aes128, Aes192, Aes256};
+use ct_codecs::{Base64, Decoder, Encoder, Hex};
+use md5::{Digest, Md5};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::algorithms::{pbkdf2_sha1, pbkdf2_sha256, pbkdf2_sha384, pbkdf2_sha512};
+use crate::encrypted_token::cbc_decrypt;
+use crate::error::*;
+
+/// Maximum number of PBKDF2 iterations accepted when decrypting a key.
+const MAX_PBKDF2_ITERATIONS: u32 = 10_000_000;
+
+pub(crate) const DER_INTEGER: u8 = 0x02;
+pub(crate) const DER_OCTET_STRING: u8 = 0x04;
+pub(crate) const DER_OID: u8 = 0x06;
+pub(crate) const DER_SEQUENCE: u8 = 0x30;
+
+const OID_PBES2: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x05, 0x0d];
+const OID_PBKDF2: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x05, 0x0c];
+const OID_HMAC_SHA1: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x02, 0x07];
+const OID_HMAC_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x02, 0x09];
+const OID_HMAC_SHA384: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x02, 0x0a];
+const OID_HMAC_SHA512: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x02, 0x0b];
+const OID_AES128_CBC: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x01, 0x02];
+const OID_AES192_CBC: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x01, 0x16];
+const OID_AES256_CBC: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x01, 0x2a];
+
+pub(crate) struct DerReader<'t> {
+    data: &'t [u8],
+}
+
+impl<'t> DerReader<'t> {
+    pub(crate) fn new(data: &'t [u8]) -> Self {
+        DerReader { data }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Return the tag of the next element, if any
+    pub(crate) fn peek_tag(&self) -> Option<u8> {
+        self.data.first().copied()
+    }
+
+    /// Read an element with the given tag, and return its contents
+    pub(crate) fn read(&mut self, tag: u8) -> Result<&'t [u8], Error> {
+        let (header_length, element) = self.read_element_with_header(tag)?;
+        Ok(&element[header_length..])
+    }
+
+    /// Read an element with the given tag, and return it including its
+    /// header
+    pub(crate) fn read_element(&mut self, tag: u8) -> Result<&'t [u8], Error> {
+        let (_, element) = self.read_element_with_header(tag)?;
+        Ok(element)
+    }
+
+    fn read_element_with_header(&mut self, tag: u8) -> Result<(usize, &'t [u8]), Error> {
+        ensure!(
+            self.data.len() >= 2 && self.data[0] == tag,
+            JWTError::InvalidKeyPair
+        );
+        let (length, header_length) = match self.data[1] {
+            length @ 0..=0x7f => (length as usize, 2),
+            length_length @ 0x81..=0x84 => {
+                let length_length = (length_length & 0x7f) as usize;
+                ensure!(
+                    self.data.len() >= 2 + length_length,
+                    JWTError::InvalidKeyPair
+                );
+                let length = self.data[2..2 + length_length]
+                    .iter()
+                    .fold(0usize, |acc, &x| (acc << 8) | x as usize);
+                // DER requires the shortest length encoding
+                ensure!(self.data[2] != 0 && length > 0x7f, JWTError::InvalidKeyPair);
+                (length, 2 + length_length)
+            }
+            _ => bail!(JWTError::InvalidKeyPair),
+        };
+        ensure!(
+            self.data.len() - header_length >= length,
+            JWTError::InvalidKeyPair
+        );
+        let (element, rest) = self.data.split_at(header_length + length);
+        self.data = rest;
+        Ok((header_length, element))
+    }
+
+    pub(crate) fn read_u32(&mut self) -> Result<u32, Error> {
+        let integer = self.read(DER_INTEGER)?;
+        ensure!(
+            !integer.is_empty() && integer[0] & 0x80 == 0,
+            JWTError::InvalidKeyPair
+        );
+        let integer = match integer {
+            [0, rest @ ..] => rest,
+            _ => integer,
+        };
+        ensure!(integer.len() <= 4, JWTError::InvalidKeyPair);
+        Ok(integer.iter().fold(0u32, |acc, &x| (acc << 8) | x as u32))
+    }
+}
+
+pub(crate) struct PemDocument {
+    pub(crate) label: String,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) der: Zeroizing<Vec<u8>>,
+}
+
+pub(crate) fn pem_decode(pem: &str) -> Result<PemDocument, Error> {
+    let mut lines = pem.lines().map(|line| line.trim());
+    let label = lines
+        .find(|line| !line.is_empty())
+        .and_then(|line| line.strip_prefix("-----BEGIN "))
+        .and_then(|line| line.strip_suffix("-----"))
+        .ok_or(JWTError::InvalidKeyPair)?
+        .to_string();
+    let end = format!("-----END {}-----", label);
+    let mut headers = vec![];
+    let mut body = Zeroizing::new(String::new());
+    let mut complete = false;
+    for line in lines {
+        if line == end {
+            complete = true;
+            break;
+        }
+        match line.split_once(':') {
+            Some((name, value)) if body.is_empty() => {
+                headers.push((name.trim().to_string(), value.trim().to_string()))
+            }
+            _ => body.push_str(line),
+        }
+    }
+    ensure!(complete, JWTError::InvalidKeyPair);
+    let der = Zeroizing::new(
+        Base64::decode_to_vec(body.as_str(), None).map_err(|_| JWTError::InvalidKeyPair)?,
+    );
+    Ok(PemDocument {
+        label,
+        headers,
+        der,
+    })
+}
+
+fn pem_encode(label: &str, der: &[u8]) -> Result<Zeroizing<String>, Error> {
+    let encoded = Zeroizing::new(Base64::encode_to_string(der)?);
+    let mut pem = Zeroizing::new(format!("-----BEGIN {}-----\n", label));
+    for line in encoded.as_bytes().chunks(64) {
+        pem.push_str(std::str::from_utf8(line)?);
+        pem.push('\n');
+    }
+    pem.push_str(&format!("-----END {}-----\n", label));
+    Ok(pem)
+}
+
+fn aes_cbc_decrypt(key: &[u8], iv: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+    ensure!(iv.len() == 16, JWTError::InvalidKeyPair);
+    let plaintext = match key.len() {
+        16 => cbc_decrypt::<Aes128>(key, iv, ciphertext),
+        24 => cbc_decrypt::<Aes192>(key, iv, ciphertext),
+        32 => cbc_decrypt::<Aes256>(key, iv, ciphertext),
+        _ => bail!(JWTError::UnsupportedKeyEncryption),
+    };
+    plaintext.map_err(|_| JWTError::InvalidKeyPair.into())
+}
+
+/// Decrypt an `EncryptedPrivateKeyInfo` structure
+fn decrypt_pkcs8(der: &[u8], password: &[u8]) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let mut encrypted_private_key_info =
+        DerReader::new(der).read(DER_SEQUENCE).map(DerReader::new)?;
+    let mut encryption_algorithm = encrypted_private_key_info
+        .read(DER_SEQUENCE)
+        .map(DerReader::new)?;
+    let encrypted_data = encrypted_private_key_info.read(DER_OCTET_STRING)?;
+    ensure!(
+        encryption_algorithm.read(DER_OID)? == OID_PBES2,
+        JWTError::UnsupportedKeyEncryption
+    );
+    let mut pbes2_params = encryption_algorithm
+        .read(DER_SEQUENCE)
+        .map(DerReader::new)?;
+
+    let mut key_derivation_function = pbes2_params.read(DER_SEQUENCE).map(DerReader::new)?;
+    ensure!(
+        key_derivation_function.read(DER_OID)? == OID_PBKDF2,
+        JWTError::UnsupportedKeyEncryption
+    );
+    let mut pbkdf2_params = key_derivation_function
+        .read(DER_SEQUENCE)
+        .map(DerReader::new)?;
+    let salt = pbkdf2_params.read(DER_OCTET_STRING)?;
+    let iterations = pbkdf2_params.read_u32()?;
+    ensure!(
+        (1..=MAX_PBKDF2_ITERATIONS).contains(&iterations),
+        JWTError::InvalidKeyPair
+    );
+    if pbkdf2_params.data.first() == Some(&DER_INTEGER) {
+        pbkdf2_params.read_u32()?;
+    }
+    let prf = if pbkdf2_params.is_empty() {
+        OID_HMAC_SHA1
+    } else {
+        pbkdf2_params
+            .read(DER_SEQUENCE)
+            .map(DerReader::new)?
+            .read(DER_OID)?
+    };
+
+    let mut encryption_scheme = pbes2_params.read(DER_SEQUENCE).map(DerReader::new)?;
+    let key_length = match encryption_scheme.read(DER_OID)? {
+        OID_AES128_CBC => 16,
+        OID_AES192_CBC => 24,
+        OID_AES256_CBC => 32,
+        _ => bail!(JWTError::UnsupportedKeyEncryption),
+    };
+    let iv = encryption_scheme.read(DER_OCTET_STRING)?;
+
+    let mut key = Zeroizing::new(vec![0u8; key_length]);
+    match prf {
+        OID_HMAC_SHA1 => pbkdf2_sha1(password, salt, iterations, &mut key),
+        OID_HMAC_SHA256 => pbkdf2_sha256(password, salt, iterations, &mut key),
+        OID_HMAC_SHA384 => pbkdf2_sha384(password, salt, iterations, &mut key),
+        OID_HMAC_SHA512 => pbkdf2_sha512(password, salt, iterations, &mut key),
+        _ => bail!(JWTError::UnsupportedKeyEncryption),
+    }
+    Ok(Zeroizing::new(aes_cbc_decrypt(&key, iv, encrypted_data)?))
+}
+
+/// Decrypt a legacy OpenSSL encrypted PEM body
+fn decrypt_legacy(
+    dek_info: &str,
+    der: &[u8],
+    password: &[u8],
+) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let (cipher, iv) = dek_info.split_once(',').ok_or(JWTError::InvalidKeyPair)?;
+    let key_length = match cipher.trim() {
+        "AES-128-CBC" => 16,
+        "AES-192-CBC" => 24,
+        "AES-256-CBC" => 32,
+        _ => bail!(JWTError::UnsupportedKeyEncryption),
+    };
+    let iv = Hex::decode_to_vec(iv.trim(), None).map_err(|_| JWTError::InvalidKeyPair)?;
+    ensure!(iv.len() == 16, JWTError::InvalidKeyPair);
+
+    // EVP_BytesToKey with MD5, a single iteration, and the first 8 bytes of
+    // the IV as a salt
+    let mut key = Zeroizing::new(Vec::with_capacity(key_length + 16));
+    let mut block: Vec<u8> = vec![];
+    while key.len() < key_length {
+        let mut input = Zeroizing::new(block.clone());
+        input.extend_from_slice(password);
+        input.extend_from_slice(&iv[..8]);
+        block.zeroize();
+        block = Md5::digest(&input).to_vec();
+        key.extend_from_slice(&block);
+    }
+    block.zeroize();
+    key.truncate(key_length);
+    Ok(Zeroizing::new(aes_cbc_decrypt(&key, &iv, der)?))
+}
+
+/// Decrypt a password-protected private key, and return it as an
+/// unencrypted PEM document
+pub(crate) fn decrypt_pem(pem: &str, password: &[u8]) -> Result<Zeroizing<String>, Error> {
+    let document = pem_decode(pem)?;
+    if document.label == "ENCRYPTED PRIVATE KEY" {
+        let der = decrypt_pkcs8(&document.der, password)?;
+        return pem_encode("PRIVATE KEY", &der);
+    }
+    let encrypted = document
+        .headers
+        .iter()
+        .any(|(name, value)| name == "Proc-Type" && value == "4,ENCRYPTED");
+    ensure!(encrypted, JWTError::InvalidKeyPair);
+    let dek_info = document
+        .headers
+        .iter()
+        .find(|(name, _)| name == "DEK-Info")
+        .map(|(_, value)| value)
+        .ok_or(JWTError::InvalidKeyPair)?;
+    let der = decrypt_legacy(dek_info, &document.der, password)?;
+    pem_encode(&document.label, &der)
+}
@@ -0,0 +1,943 @@
+use aes::cipher::{generic_array::GenericArray, BlockDecrypt, BlockEncrypt};
+use aes::{Aes128, Aes192, Aes256};
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use ct_codecs::{Base64UrlSafeNoPadding, Decoder, Encoder};
+use hmac_sha512::sha384 as hmac_sha384;
+use rand::RngCore;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::claims::*;
+use crate::common::*;
+use crate::deflate;
+use crate::error::*;
+use crate::jwe_header::*;
+use crate::replay_guard::check_replay;
+use crate::revocation::check_revocation;
+use crate::token::{max_header_length, KeyLike, Token};
+
+const GCM_IV_LENGTH: usize = 12;
+const GCM_TAG_LENGTH: usize = 16;
+const CBC_BLOCK_LENGTH: usize = 16;
+
+struct EncryptedContent {
+    iv: Vec<u8>,
+    ciphertext: Vec<u8>,
+    tag: Vec<u8>,
+}
+
+/// Content encryption algorithms ("enc")
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ContentEncryption {
+    /// AES-256-GCM
+    A256GCM,
+    /// AES-128-CBC with HMAC-SHA-256, truncated to 128 bits
+    A128CBCHS256,
+    /// AES-192-CBC with HMAC-SHA-384, truncated to 192 bits
+    A192CBCHS384,
+    /// AES-256-CBC with HMAC-SHA-512, truncated to 256 bits
+    A256CBCHS512,
+}
+
+fn cbc_encrypt<Cipher: BlockEncrypt + KeyInit>(
+    key: &[u8],
+    iv: &[u8],
+    plaintext: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let cipher = Cipher::new_from_slice(key).map_err(|_| JWTError::InvalidEncryptionKey)?;
+    let padding_length = CBC_BLOCK_LENGTH - plaintext.len() % CBC_BLOCK_LENGTH;
+    let mut ciphertext = plaintext.to_vec();
+    ciphertext.resize(plaintext.len() + padding_length, padding_length as u8);
+    let mut previous = GenericArray::clone_from_slice(iv);
+    for chunk in ciphertext.chunks_mut(CBC_BLOCK_LENGTH) {
+        let mut block = GenericArray::clone_from_slice(chunk);
+        block
+            .iter_mut()
+            .zip(previous.iter())
+            .for_each(|(x, y)| *x ^= y);
+        cipher.encrypt_block(&mut block);
+        chunk.copy_from_slice(&block);
+        previous = block;
+    }
+    Ok(ciphertext)
+}
+
+/// Decrypt `ciphertext` and remove the padding.
+/// This must only be called after the authentication tag has been verified.
+pub(crate) fn cbc_decrypt<Cipher: BlockDecrypt + KeyInit>(
+    key: &[u8],
+    iv: &[u8],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, Error> {
+    ensure!(
+        !ciphertext.is_empty() && ciphertext.len() % CBC_BLOCK_LENGTH == 0,
+        JWTError::InvalidAuthenticationTag
+    );
+    let cipher = Cipher::new_from_slice(key).map_err(|_| JWTError::InvalidEncryptionKey)?;
+    let mut plaintext = ciphertext.to_vec();
+    let mut previous = GenericArray::clone_from_slice(iv);
+    for chunk in plaintext.chunks_mut(CBC_BLOCK_LENGTH) {
+        let encrypted = GenericArray::clone_from_slice(chunk);
+        let mut block = encrypted.clone();
+        cipher.decrypt_block(&mut block);
+        block
+            .iter_mut()
+            .zip(previous.iter())
+            .for_each(|(x, y)| *x ^= y);
+        chunk.copy_from_slice(&block);
+        previous = encrypted;
+    }
+    let padding_length = plaintext[plaintext.len() - 1] as usize;
+    ensure!(
+        (1..=CBC_BLOCK_LENGTH).contains(&padding_length)
+            && plaintext[plaintext.len() - padding_length..]
+                .iter()
+                .all(|&x| x as usize == padding_length),
+        JWTError::InvalidAuthenticationTag
+    );
+    plaintext.truncate(plaintext.len() - padding_length);
+    Ok(plaintext)
+}
+
+impl ContentEncryption {
+    /// The JWE content encryption algorithm name ("enc")
+    pub fn jwe_enc_name(&self) -> &'static str {
+        match self {
+            ContentEncryption::A256GCM => "A256GCM",
+            ContentEncryption::A128CBCHS256 => "A128CBC-HS256",
+            ContentEncryption::A192CBCHS384 => "A192CBC-HS384",
+            ContentEncryption::A256CBCHS512 => "A256CBC-HS512",
+        }
+    }
+
+    /// Look up a content encryption algorithm by name
+    pub fn from_jwe_enc_name(jwe_enc_name: &str) -> Result<Self, Error> {
+        match jwe_enc_name {
+            "A256GCM" => Ok(ContentEncryption::A256GCM),
+            "A128CBC-HS256" => Ok(ContentEncryption::A128CBCHS256),
+            "A192CBC-HS384" => Ok(ContentEncryption::A192CBCHS384),
+            "A256CBC-HS512" => Ok(ContentEncryption::A256CBCHS512),
+            _ => bail!(JWTError::UnsupportedContentEncryption),
+        }
+    }
+
+    /// The length of the content encryption key, in bytes
+    pub fn key_length(&self) -> usize {
+        match self {
+            ContentEncryption::A256GCM => 32,
+            ContentEncryption::A128CBCHS256 => 32,
+            ContentEncryption::A192CBCHS384 => 48,
+            ContentEncryption::A256CBCHS512 => 64,
+        }
+    }
+
+    /// Compute the authentication tag of the CBC-HMAC algorithms
+    /// (RFC 7518, section 5.2.2.1)
+    fn cbc_hmac_tag(
+        &self,
+        mac_key: &[u8],
+        aad: &[u8],
+        iv: &[u8],
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let aad_bits = ((aad.len() as u64) * 8).to_be_bytes();
+        let tag = match self {
+            ContentEncryption::A256GCM => bail!(JWTError::UnsupportedContentEncryption),
+            ContentEncryption::A128CBCHS256 => {
+                let mut mac = hmac_sha256::HMAC::new(mac_key);
+                [aad, iv, ciphertext, &aad_bits]
+                    .iter()
+                    .for_each(|x| mac.update(x));
+                mac.finalize()[..16].to_vec()
+            }
+            ContentEncryption::A192CBCHS384 => {
+                let mut mac = hmac_sha384::HMAC::new(mac_key);
+                [aad, iv, ciphertext, &aad_bits]
+                    .iter()
+                    .for_each(|x| mac.update(x));
+                mac.finalize()[..24].to_vec()
+            }
+            ContentEncryption::A256CBCHS512 => {
+                let mut mac = hmac_sha512::HMAC::new(mac_key);
+                [aad, iv, ciphertext, &aad_bits]
+                    .iter()
+                    .for_each(|x| mac.update(x));
+                mac.finalize()[..32].to_vec()
+            }
+        };
+        Ok(tag)
+    }
+
+    fn cbc_encrypt(&self, enc_key: &[u8], iv: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+        match self {
+            ContentEncryption::A256GCM => bail!(JWTError::UnsupportedContentEncryption),
+            ContentEncryption::A128CBCHS256 => cbc_encrypt::<Aes128>(enc_key, iv, plaintext),
+            ContentEncryption::A192CBCHS384 => cbc_encrypt::<Aes192>(enc_key, iv, plaintext),
+            ContentEncryption::A256CBCHS512 => cbc_encrypt::<Aes256>(enc_key, iv, plaintext),
+        }
+    }
+
+    fn cbc_decrypt(&self, enc_key: &[u8], iv: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+        match self {
+            ContentEncryption::A256GCM => bail!(JWTError::UnsupportedContentEncryption),
+            ContentEncryption::A128CBCHS256 => cbc_decrypt::<Aes128>(enc_key, iv, ciphertext),
+            ContentEncryption::A192CBCHS384 => cbc_decrypt::<Aes192>(enc_key, iv, ciphertext),
+            ContentEncryption::A256CBCHS512 => cbc_decrypt::<Aes256>(enc_key, iv, ciphertext),
+        }
+    }
+
+    /// Encrypt `plaintext`, returning the IV, the ciphertext and the
+    /// authentication tag
+    fn encrypt(&self, cek: &[u8], aad: &[u8], plaintext: &[u8]) -> Result<EncryptedContent, Error> {
+        ensure!(
+            cek.len() == self.key_length(),
+            JWTError::InvalidEncryptionKey
+        );
+        match self {
+            ContentEncryption::A256GCM => {
+                let cipher =
+                    Aes256Gcm::new_from_slice(cek).map_err(|_| JWTError::InvalidEncryptionKey)?;
+                let mut iv = vec![0u8; GCM_IV_LENGTH];
+                rand::thread_rng().fill_bytes(&mut iv);
+                let mut ciphertext = cipher
+                    .encrypt(
+                        Nonce::from_slice(&iv),
+                        Payload {
+                            msg: plaintext,
+                            aad,
+                        },
+                    )
+                    .map_err(|_| JWTError::InternalError("Encryption failed".into()))?;
+                let tag = ciphertext.split_off(ciphertext.len() - GCM_TAG_LENGTH);
+                Ok(EncryptedContent {
+                    iv,
+                    ciphertext,
+                    tag,
+                })
+            }
+            ContentEncryption::A128CBCHS256
+            | ContentEncryption::A192CBCHS384
+            | ContentEncryption::A256CBCHS512 => {
+                let (mac_key, enc_key) = cek.split_at(cek.len() / 2);
+                let mut iv = vec![0u8; CBC_BLOCK_LENGTH];
+                rand::thread_rng().fill_bytes(&mut iv);
+                let ciphertext = self.cbc_encrypt(enc_key, &iv, plaintext)?;
+                let tag = self.cbc_hmac_tag(mac_key, aad, &iv, &ciphertext)?;
+                Ok(EncryptedContent {
+                    iv,
+                    ciphertext,
+                    tag,
+                })
+            }
+        }
+    }
+
+    /// Verify the authentication tag and decrypt `ciphertext`
+    fn decrypt(
+        &self,
+        cek: &[u8],
+        aad: &[u8],
+        iv: &[u8],
+        ciphertext: &[u8],
+        tag: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        ensure!(
+            cek.len() == self.key_length(),
+            JWTError::InvalidEncryptionKey
+        );
+        match self {
+            ContentEncryption::A256GCM => {
+                ensure!(
+                    iv.len() == GCM_IV_LENGTH && tag.len() == GCM_TAG_LENGTH,
+                    JWTError::InvalidAuthenticationTag
+                );
+                let cipher =
+                    Aes256Gcm::new_from_slice(cek).map_err(|_| JWTError::InvalidEncryptionKey)?;
+                let mut ciphertext_and_tag = ciphertext.to_vec();
+                ciphertext_and_tag.extend_from_slice(tag);
+                let plaintext = cipher
+                    .decrypt(
+                        Nonce::from_slice(iv),
+                        Payload {
+                            msg: &ciphertext_and_tag,
+                            aad,
+                        },
+                    )
+                    .map_err(|_| JWTError::InvalidAuthenticationTag)?;
+                Ok(plaintext)
+            }
+            ContentEncryption::A128CBCHS256
+            | ContentEncryption::A192CBCHS384
+            | ContentEncryption::A256CBCHS512 => {
+                ensure!(
+                    iv.len() == CBC_BLOCK_LENGTH,
+                    JWTError::InvalidAuthenticationTag
+                );
+                let (mac_key, enc_key) = cek.split_at(cek.len() / 2);
+                let expected_tag = self.cbc_hmac_tag(mac_key, aad, iv, ciphertext)?;
+                ensure!(
+                    timingsafe_eq(&expected_tag, tag),
+                    JWTError::InvalidAuthenticationTag
+                );
+                self.cbc_decrypt(enc_key, iv, ciphertext)
+            }
+        }
+    }
+}
+
+/// Utilities to get information about a JWE token
+pub struct EncryptedToken;
+
+/// JWE token information useful before decryption
+#[derive(Debug, Clone, Default)]
+pub struct EncryptedTokenMetadata {
+    pub(crate) jwe_header: JWEHeader,
+}
+
+impl EncryptedTokenMetadata {
+    /// The key management algorithm for this token ("alg")
+    /// This information should not be trusted: it is unprotected and can be
+    /// freely modified by a third party.
+    pub fn algorithm(&self) -> &str {
+        &self.jwe_header.algorithm
+    }
+
+    /// The content encryption algorithm for this token ("enc")
+    /// This information should not be trusted: it is unprotected and can be
+    /// freely modified by a third party.
+    pub fn content_encryption(&self) -> &str {
+        &self.jwe_header.content_encryption
+    }
+
+    /// The content type for this token ("cty")
+    pub fn content_type(&self) -> Option<&str> {
+        self.jwe_header.content_type.as_deref()
+    }
+
+    /// The key identifier for this token ("kid")
+    pub fn key_id(&self) -> Option<&str> {
+        self.jwe_header.key_id.as_deref()
+    }
+
+    /// The token type ("typ")
+    pub fn token_type(&self) -> Option<&str> {
+        self.jwe_header.token_type.as_deref()
+    }
+
+    /// The set of raw critical properties for this token ("crit")
+    pub fn critical(&self) -> Option<&[String]> {
+        self.jwe_header.critical.as_deref()
+    }
+}
+
+/// The decrypted payload of a JWE token, along with its content type
+#[doc(hidden)]
+#[derive(Debug, Clone)]
+pub struct DecryptedPayload {
+    pub(crate) payload: Vec<u8>,
+    pub(crate) content_type: Option<String>,
+}
+
+impl Drop for DecryptedPayload {
+    fn drop(&mut self) {
+        self.payload.zeroize();
+    }
+}
+
+/// Content type of a signed token nested in an encrypted token
+const NESTED_JWT_CONTENT_TYPE: &str = "JWT";
+
+fn is_nested_jwt(content_type: Option<&str>) -> bool {
+    content_type
+        .is_some_and(|content_type| content_type.eq_ignore_ascii_case(NESTED_JWT_CONTENT_TYPE))
+}
+
+/// Compression algorithm for the payload of encrypted tokens
+const DEFLATE_COMPRESSION: &str = "DEF";
+
+/// Additional features to enable during encryption
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EncryptionOptions {
+    /// Compress the payload using DEFLATE (`"zip":"DEF"`) prior to encryption
+    pub compress: bool,
+}
+
+/// A key that can encrypt JWE tokens, regardless of its type.
+///
+/// This is implemented by shared keys, passwords, and public keys of recipients.
+pub trait EncryptionKeyLike {
+    /// Encrypt an arbitrary payload, with an optional content type ("cty")
+    #[doc(hidden)]
+    fn encrypt_payload(
+        &self,
+        payload: &[u8],
+        content_type: Option<&str>,
+        options: &EncryptionOptions,
+    ) -> Result<String, Error>;
+
+    /// Encrypt a content encryption key, adding the key management
+    /// parameters of the recipient ("alg", "kid", etc.) to the header
+    #[doc(hidden)]
+    fn encrypt_key(&self, jwe_header: &mut JWEHeader, cek: &[u8]) -> Result<Vec<u8>, Error>;
+
+    /// Encrypt claims into a JWE token
+    fn encrypt<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+    ) -> Result<String, Error>
+    where
+        Self: Sized,
+    {
+        self.encrypt_with_options(claims, &EncryptionOptions::default())
+    }
+
+    /// Encrypt claims into a JWE token, with additional options
+    fn encrypt_with_options<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+        options: &EncryptionOptions,
+    ) -> Result<String, Error>
+    where
+        Self: Sized,
+    {
+        let claims_json = serde_json::to_vec(&claims)?;
+        self.encrypt_payload(&claims_json, None, options)
+    }
+
+    /// Sign claims using `sign_fn`, and encrypt the resulting token into a
+    /// nested JWT (`"cty":"JWT"`)
+    fn sign_and_encrypt<CustomClaims, SignFn>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+        sign_fn: SignFn,
+    ) -> Result<String, Error>
+    where
+        Self: Sized,
+        CustomClaims: Serialize + DeserializeOwned,
+        SignFn: FnOnce(JWTClaims<CustomClaims>) -> Result<String, Error>,
+    {
+        let signed_token = sign_fn(claims)?;
+        Token::decode_metadata(&signed_token)?;
+        self.encrypt_payload(
+            signed_token.as_bytes(),
+            Some(NESTED_JWT_CONTENT_TYPE),
+            &EncryptionOptions::default(),
+        )
+    }
+}
+
+/// A key that can decrypt JWE tokens, regardless of its type.
+///
+/// This is implemented by shared keys, passwords, and key pairs of recipients.
+pub trait DecryptionKeyLike {
+    /// Decrypt a token, returning its payload and content type ("cty")
+    #[doc(hidden)]
+    fn decrypt_payload(
+        &self,
+        token: &str,
+        options: &VerificationOptions,
+    ) -> Result<DecryptedPayload, Error>;
+
+    /// Decrypt a JWE token and validate its claims
+    fn decrypt_token<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        let options = options.unwrap_or_default();
+        let decrypted = self.decrypt_payload(token, &options)?;
+        ensure!(
+            !is_nested_jwt(decrypted.content_type.as_deref()),
+            JWTError::UnexpectedContentType
+        );
+        let claims: JWTClaims<CustomClaims> = decode_claims(&decrypted.payload, &options)?;
+        claims.validate(&options)?;
+        check_json_claims(&options, &decrypted.payload)?;
+        check_revocation(&options, &claims)?;
+        check_replay(&options, &claims)?;
+        Ok(claims)
+    }
+
+    /// Decrypt a nested JWT (`"cty":"JWT"`), verify the signed token it
+    /// contains using `verification_key`, and validate its claims
+    fn decrypt_and_verify<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        verification_key: &dyn KeyLike,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        let options = options.unwrap_or_default();
+        let decrypted = self.decrypt_payload(token, &options)?;
+        ensure!(
+            is_nested_jwt(decrypted.content_type.as_deref()),
+            JWTError::UnexpectedContentType
+        );
+        let signed_token =
+            std::str::from_utf8(&decrypted.payload).map_err(|_| JWTError::CompactEncodingError)?;
+        Token::verify_with_keys(&[verification_key], signed_token, Some(options))
+    }
+}
+
+/// Header parameters shared by all the recipients of a token, stored in the
+/// protected header when using the JSON serialization
+const SHARED_HEADER_PARAMETERS: [&str; 5] = ["enc", "zip", "cty", "typ", "crit"];
+
+/// Header parameters of a token, either shared by all recipients or specific
+/// to a recipient
+fn header_parameters(jwe_header: &JWEHeader, shared: bool) -> Result<Map<String, Value>, Error> {
+    let mut parameters = match serde_json::to_value(jwe_header)? {
+        Value::Object(parameters) => parameters,
+        _ => bail!(JWTError::InternalError("Invalid header".to_string())),
+    };
+    parameters.retain(|name, _| SHARED_HEADER_PARAMETERS.contains(&name.as_str()) == shared);
+    Ok(parameters)
+}
+
+/// Merge header parameters, which must not appear more than once
+fn merge_header_parameters(headers: &[Option<&Map<String, Value>>]) -> Result<JWEHeader, Error> {
+    let mut merged = Map::new();
+    for header in headers.iter().flatten() {
+        for (name, value) in header.iter() {
+            ensure!(
+                merged.insert(name.clone(), value.clone()).is_none(),
+                JWTError::DuplicateHeaderParameter
+            );
+        }
+    }
+    Ok(serde_json::from_value(Value::Object(merged))?)
+}
+
+/// The components of an encrypted token, for a single recipient
+struct EncryptedParts {
+    jwe_header: JWEHeader,
+    aad: String,
+    encrypted_key: Vec<u8>,
+    iv: Vec<u8>,
+    ciphertext: Vec<u8>,
+    tag: Vec<u8>,
+}
+
+impl EncryptedParts {
+    fn from_compact(token: &str, options: &VerificationOptions) -> Result<Self, Error> {
+        let mut parts = token.split('.');
+        let jwe_header_b64 = parts.next().ok_or(JWTError::CompactEncodingError)?;
+        ensure!(
+            jwe_header_b64.len() <= max_header_length(Some(options)),
+            JWTError::HeaderTooLarge
+        );
+        let encrypted_key_b64 = parts.next().ok_or(JWTError::CompactEncodingError)?;
+        let iv_b64 = parts.next().ok_or(JWTError::CompactEncodingError)?;
+        let ciphertext_b64 = parts.next().ok_or(JWTError::CompactEncodingError)?;
+        let tag_b64 = parts.next().ok_or(JWTError::CompactEncodingError)?;
+        ensure!(parts.next().is_none(), JWTError::CompactEncodingError);
+        let jwe_header: JWEHeader = serde_json::from_slice(
+            &Base64UrlSafeNoPadding::decode_to_vec(jwe_header_b64, None)?,
+        )?;
+        Ok(EncryptedParts {
+            jwe_header,
+            aad: jwe_header_b64.to_string(),
+            encrypted_key: Base64UrlSafeNoPadding::decode_to_vec(encrypted_key_b64, None)?,
+            iv: Base64UrlSafeNoPadding::decode_to_vec(iv_b64, None)?,
+            ciphertext: Base64UrlSafeNoPadding::decode_to_vec(ciphertext_b64, None)?,
+            tag: Base64UrlSafeNoPadding::decode_to_vec(tag_b64, None)?,
+        })
+    }
+
+    /// Parse a token using the JSON serialization, keeping the entry of the
+    /// recipient with the given algorithm and key identifier
+    fn from_json(
+        token: &str,
+        jwe_alg_name: &str,
+        key_id: Option<&str>,
+        options: &VerificationOptions,
+    ) -> Result<Self, Error> {
+        let jwe_json: JWEJson = serde_json::from_str(token)?;
+        let protected_b64 = jwe_json.protected.unwrap_or_default();
+        ensure!(
+            protected_b64.len() <= max_header_length(Some(options)),
+            JWTError::HeaderTooLarge
+        );
+        let protected: Option<Map<String, Value>> = match protected_b64.is_empty() {
+            true => None,
+            false => Some(serde_json::from_slice(
+                &Base64UrlSafeNoPadding::decode_to_vec(&protected_b64, None)?,
+            )?),
+        };
+        let mut candidates = vec![];
+        for recipient in jwe_json.recipients {
+            let jwe_header = merge_header_parameters(&[
+                protected.as_ref(),
+                jwe_json.unprotected.as_ref(),
+                recipient.header.as_ref(),
+            ])?;
+            if jwe_header.algorithm != jwe_alg_name {
+                continue;
+            }
+            match (key_id, jwe_header.key_id.as_deref()) {
+                (Some(key_id), Some(recipient_key_id)) if key_id == recipient_key_id => {
+                    candidates = vec![(jwe_header, recipient.encrypted_key)];
+                    break;
+                }
+                (Some(_), _) => continue,
+                (None, _) => candidates.push((jwe_header, recipient.encrypted_key)),
+            }
+        }
+        // Without a key identifier, the recipient entry must be unambiguous
+        let (jwe_header, encrypted_key_b64) = match candidates.len() {
+            0 if key_id.is_some() => bail!(JWTError::KeyIdentifierMismatch),
+            0 => bail!(JWTError::AlgorithmMismatch),
+            1 => candidates.pop().unwrap(),
+            _ => bail!(JWTError::MissingJWTKeyIdentifier),
+        };
+        let aad = match &jwe_json.aad {
+            Some(aad) => format!("{}.{}", protected_b64, aad),
+            None => protected_b64,
+        };
+        Ok(EncryptedParts {
+            jwe_header,
+            aad,
+            encrypted_key: match encrypted_key_b64 {
+                Some(encrypted_key_b64) => {
+                    Base64UrlSafeNoPadding::decode_to_vec(encrypted_key_b64, None)?
+                }
+                None => vec![],
+            },
+            iv: Base64UrlSafeNoPadding::decode_to_vec(&jwe_json.iv, None)?,
+            ciphertext: Base64UrlSafeNoPadding::decode_to_vec(&jwe_json.ciphertext, None)?,
+            tag: Base64UrlSafeNoPadding::decode_to_vec(&jwe_json.tag, None)?,
+        })
+    }
+}
+
+impl EncryptedToken {
+    /// Compress the payload if required by the options, updating the header
+    fn compress_payload(
+        jwe_header: &mut JWEHeader,
+        payload: &[u8],
+        options: &EncryptionOptions,
+    ) -> Option<Zeroizing<Vec<u8>>> {
+        if !options.compress {
+            return None;
+        }
+        jwe_header.compression = Some(DEFLATE_COMPRESSION.to_string());
+        Some(Zeroizing::new(deflate::compress(payload)))
+    }
+
+    /// Encrypt a payload into a token using the compact serialization.
+    ///
+    /// `key_management_fn` can update the header, and returns the content
+    /// encryption key along with its encrypted representation.
+    pub(crate) fn build_with_payload<KeyManagementFn>(
+        mut jwe_header: JWEHeader,
+        payload: &[u8],
+        options: &EncryptionOptions,
+        key_management_fn: KeyManagementFn,
+    ) -> Result<String, Error>
+    where
+        KeyManagementFn: FnOnce(&mut JWEHeader) -> Result<(Vec<u8>, Vec<u8>), Error>,
+    {
+        let content_encryption =
+            ContentEncryption::from_jwe_enc_name(&jwe_header.content_encryption)?;
+        let compressed_payload = Self::compress_payload(&mut jwe_header, payload, options);
+        let (mut cek, encrypted_key) = key_management_fn(&mut jwe_header)?;
+        let jwe_header_json = serde_json::to_string(&jwe_header)?;
+        let jwe_header_b64 = Base64UrlSafeNoPadding::encode_to_string(jwe_header_json)?;
+        let res = content_encryption.encrypt(
+            &cek,
+            jwe_header_b64.as_bytes(),
+            compressed_payload
+                .as_deref()
+                .map_or(payload, |x| x.as_slice()),
+        );
+        cek.zeroize();
+        let encrypted_content = res?;
+        Ok(format!(
+            "{}.{}.{}.{}.{}",
+            jwe_header_b64,
+            Base64UrlSafeNoPadding::encode_to_string(encrypted_key)?,
+            Base64UrlSafeNoPadding::encode_to_string(encrypted_content.iv)?,
+            Base64UrlSafeNoPadding::encode_to_string(encrypted_content.ciphertext)?,
+            Base64UrlSafeNoPadding::encode_to_string(encrypted_content.tag)?
+        ))
+    }
+
+    /// Encrypt a payload into a token using the compact serialization, with
+    /// a random content encryption key encrypted by `key`
+    pub(crate) fn build_with_key<Key: EncryptionKeyLike + ?Sized>(
+        key: &Key,
+        content_encryption: ContentEncryption,
+        payload: &[u8],
+        content_type: Option<&str>,
+        options: &EncryptionOptions,
+    ) -> Result<String, Error> {
+        let jwe_header = JWEHeader {
+            content_encryption: content_encryption.jwe_enc_name().to_string(),
+            content_type: content_type.map(|x| x.to_string()),
+            ..Default::default()
+        };
+        Self::build_with_payload(jwe_header, payload, options, |jwe_header| {
+            let mut cek = vec![0u8; content_encryption.key_length()];
+            rand::thread_rng().fill_bytes(&mut cek);
+            match key.encrypt_key(jwe_header, &cek) {
+                Ok(encrypted_key) => Ok((cek, encrypted_key)),
+                Err(err) => {
+                    cek.zeroize();
+                    Err(err)
+                }
+            }
+        })
+    }
+
+    /// Encrypt claims to multiple recipients, using the general JWE JSON
+    /// serialization.
+    ///
+    /// The content is encrypted once using `content_encryption`, and its key
+    /// is encrypted to every recipient. Keys that directly encrypt the
+    /// content (`dir` and `ECDH-ES`) cannot be used.
+    pub fn encrypt_to_recipients<CustomClaims: Serialize + DeserializeOwned>(
+        recipients: &[&dyn EncryptionKeyLike],
+        content_encryption: ContentEncryption,
+        claims: JWTClaims<CustomClaims>,
+        options: &EncryptionOptions,
+    ) -> Result<String, Error> {
+        ensure!(!recipients.is_empty(), JWTError::NoRecipients);
+        let claims_json = serde_json::to_vec(&claims)?;
+        let mut jwe_header = JWEHeader {
+            content_encryption: content_encryption.jwe_enc_name().to_string(),
+            ..Default::default()
+        };
+        let compressed_payload = Self::compress_payload(&mut jwe_header, &claims_json, options);
+        let mut cek = Zeroizing::new(vec![0u8; content_encryption.key_length()]);
+        rand::thread_rng().fill_bytes(&mut cek);
+        let mut jwe_recipients = vec![];
+        for recipient in recipients {
+            let mut recipient_header = jwe_header.clone();
+            let encrypted_key = recipient.encrypt_key(&mut recipient_header, &cek)?;
+            jwe_recipients.push(JWERecipient {
+                header: Some(header_parameters(&recipient_header, false)?),
+                encrypted_key: Some(Base64UrlSafeNoPadding::encode_to_string(encrypted_key)?),
+            });
+        }
+        let protected_json = serde_json::to_string(&header_parameters(&jwe_header, true)?)?;
+        let protected_b64 = Base64UrlSafeNoPadding::encode_to_string(protected_json)?;
+        let encrypted_content = content_encryption.encrypt(
+            &cek,
+            protected_b64.as_bytes(),
+            compressed_payload
+                .as_deref()
+                .map_or(&claims_json[..], |x| x.as_slice()),
+        )?;
+        let jwe_json = JWEJson {
+            protected: Some(protected_b64),
+            unprotected: None,
+            recipients: jwe_recipients,
+            aad: None,
+            iv: Base64UrlSafeNoPadding::encode_to_string(encrypted_content.iv)?,
+            ciphertext: Base64UrlSafeNoPadding::encode_to_string(encrypted_content.ciphertext)?,
+            tag: Base64UrlSafeNoPadding::encode_to_string(encrypted_content.tag)?,
+        };
+        Ok(serde_json::to_string(&jwe_json)?)
+    }
+
+    /// Decrypt a token, checking its header against the options.
+    ///
+    /// Tokens using the JSON serialization are also accepted, in which case
+    /// the entry for the recipient is chosen using the algorithm and the key
+    /// identifier of the key.
+    ///
+    /// `key_management_fn` receives the header and the encrypted key, and
+    /// returns the content encryption key.
+    pub(crate) fn decrypt_payload<KeyManagementFn>(
+        jwe_alg_name: &'static str,
+        key_id: Option<&str>,
+        token: &str,
+        options: &VerificationOptions,
+        key_management_fn: KeyManagementFn,
+    ) -> Result<DecryptedPayload, Error>
+    where
+        KeyManagementFn: FnOnce(&JWEHeader, &[u8]) -> Result<Vec<u8>, Error>,
+    {
+        if let Some(max_token_length) = options.max_token_length {
+            ensure!(token.len() <= max_token_length, JWTError::TokenTooLong);
+        }
+        let parts = if token.starts_with('{') {
+            EncryptedParts::from_json(token, jwe_alg_name, key_id, options)?
+        } else {
+            EncryptedParts::from_compact(token, options)?
+        };
+        let jwe_header = parts.jwe_header;
+        options.check_critical_header(
+            jwe_header.critical.as_deref(),
+            &jwe_header.parameters,
+            &[],
+        )?;
+        options.check_typ(jwe_header.token_type.as_deref())?;
+        if let Some(token_type) = &jwe_header.token_type {
+            let token_type_uc = token_type.to_uppercase();
+            ensure!(
+                token_type_uc == "JWT" || token_type_uc.ends_with("+JWT"),
+                JWTError::NotJWT
+            );
+        }
+        ensure!(
+            jwe_header.algorithm == jwe_alg_name,
+            JWTError::AlgorithmMismatch
+        );
+        if let Some(allowed_algorithms) = &options.allowed_algorithms {
+            ensure!(
+                allowed_algorithms.contains(jwe_alg_name),
+                JWTError::AlgorithmMismatch
+            );
+        }
+        if let Some(required_key_id) = &options.required_key_id {
+            if let Some(key_id) = &jwe_header.key_id {
+                ensure!(key_id == required_key_id, JWTError::KeyIdentifierMismatch);
+            } else {
+                bail!(JWTError::MissingJWTKeyIdentifier)
+            }
+        }
+        let content_encryption =
+            ContentEncryption::from_jwe_enc_name(&jwe_header.content_encryption)?;
+        if let Some(compression) = &jwe_header.compression {
+            ensure!(
+                compression == DEFLATE_COMPRESSION,
+                JWTError::UnsupportedCompression
+            );
+        }
+        let mut cek = key_management_fn(&jwe_header, &parts.encrypted_key)?;
+        let res = content_encryption.decrypt(
+            &cek,
+            parts.aad.as_bytes(),
+            &parts.iv,
+            &parts.ciphertext,
+            &parts.tag,
+        );
+        cek.zeroize();
+        let mut payload = res?;
+        if jwe_header.compression.is_some() {
+            let max_length = options.max_decompressed_length.unwrap_or(usize::MAX);
+            let res = deflate::decompress(&payload, max_length);
+            payload.zeroize();
+            payload = res?;
+        }
+        Ok(DecryptedPayload {
+            payload,
+            content_type: jwe_header.content_type,
+        })
+    }
+
+    /// Decode token information that can be useful prior to decryption
+    pub fn decode_metadata(token: &str) -> Result<EncryptedTokenMetadata, Error> {
+        let mut parts = token.split('.');
+        let jwe_header_b64 = parts.next().ok_or(JWTError::CompactEncodingError)?;
+        ensure!(
+            jwe_header_b64.len() <= max_header_length(None),
+            JWTError::HeaderTooLarge
+        );
+        ensure!(parts.count() == 4, JWTError::CompactEncodingError);
+        let jwe_header: JWEHeader = serde_json::from_slice(
+            &Base64UrlSafeNoPadding::decode_to_vec(jwe_header_b64, None)?,
+        )?;
+        Ok(EncryptedTokenMetadata { jwe_header })
+    }
+}
+
+#[test]
+fn direct_encryption() {
+    use crate::prelude::*;
+
+    let key = DirectKey::generate(ContentEncryption::A256GCM).with_key_id("key 1");
+    let claims = Claims::create(Duration::from_mins(10)).with_subject("alice");
+    let token = key.encrypt(claims).unwrap();
+    assert_eq!(token.split('.').count(), 5);
+    let metadata = EncryptedToken::decode_metadata(&token).unwrap();
+    assert_eq!(metadata.algorithm(), "dir");
+    assert_eq!(metadata.content_encryption(), "A256GCM");
+    assert_eq!(metadata.key_id(), Some("key 1"));
+    let claims = key.decrypt_token::<NoCustomClaims>(&token, None).unwrap();
+    assert_eq!(claims.subject.as_deref(), Some("alice"));
+
+    let other_key = DirectKey::generate(ContentEncryption::A256GCM);
+    assert!(other_key
+        .decrypt_token::<NoCustomClaims>(&token, None)
+        .is_err());
+
+    let mut parts: Vec<String> = token.split('.').map(|x| x.to_string()).collect();
+    let mut ciphertext = Base64UrlSafeNoPadding::decode_to_vec(&parts[3], None).unwrap();
+    ciphertext[0] ^= 1;
+    parts[3] = Base64UrlSafeNoPadding::encode_to_string(ciphertext).unwrap();
+    assert!(key
+        .decrypt_token::<NoCustomClaims>(&parts.join("."), None)
+        .is_err());
+
+    assert!(DirectKey::from_bytes(ContentEncryption::A256GCM, &[0u8; 16]).is_err());
+
+    let key =
+        DirectKey::from_bytes(ContentEncryption::A256GCM, &(0u8..32).collect::<Vec<u8>>()).unwrap();
+    let token = "eyJhbGciOiJkaXIiLCJlbmMiOiJBMjU2R0NNIiwidHlwIjoiSldUIn0..ZGVmZ2hpamtsbW5v.Mzm3Bw3LbK8JUm_Y6lVazXLuJG_zHNFIk-Cces-XkXCk2WziP2f5JsfRGVOUIa_tZg.lSqhrqb0z4uIffVdEw4aog";
+    let claims = key.decrypt_token::<NoCustomClaims>(token, None).unwrap();
+    assert_eq!(claims.subject.as_deref(), Some("alice"));
+}
+
+#[test]
+fn cbc_hmac_content_encryption() {
+    use crate::prelude::*;
+
+    for content_encryption in [
+        ContentEncryption::A128CBCHS256,
+        ContentEncryption::A192CBCHS384,
+        ContentEncryption::A256CBCHS512,
+    ] {
+        let key =
+            WrappingKey::generate(KeyWrapping::A256KW).with_content_encryption(content_encryption);
+        let claims = Claims::create(Duration::from_mins(10)).with_subject("alice");
+        let token = key.encrypt(claims).unwrap();
+        let metadata = EncryptedToken::decode_metadata(&token).unwrap();
+        assert_eq!(
+            metadata.content_encryption(),
+            content_encryption.jwe_enc_name()
+        );
+        let claims = key.decrypt_token::<NoCustomClaims>(&token, None).unwrap();
+        assert_eq!(claims.subject.as_deref(), Some("alice"));
+
+        let mut parts: Vec<String> = token.split('.').map(|x| x.to_string()).collect();
+        let mut tag = Base64UrlSafeNoPadding::decode_to_vec(&parts[4], None).unwrap();
+        tag[0] ^= 1;
+        parts[4] = Base64UrlSafeNoPadding::encode_to_string(tag).unwrap();
+        assert!(key
+            .decrypt_token::<NoCustomClaims>(&parts.join("."), None)
+            .is_err());
+    }
+
+    // RFC 7516, appendix A.3
+    let key = WrappingKey::from_bytes(
+        KeyWrapping::A128KW,
+        &Base64UrlSafeNoPadding::decode_to_vec("GawgguFyGrWKav7AX4VKUg", None).unwrap(),
+    )
+    .unwrap();
+    let token = "eyJhbGciOiJBMTI4S1ciLCJlbmMiOiJBMTI4Q0JDLUhTMjU2In0.6KB707dM9YTIgHtLvtgWQ8mKwboJW3of9locizkDTHzBC2IlrT1oOQ.AxY8DCtDaGlsbGljb3RoZQ.KDlTtXchhZTGufMYmOYGS4HffxPSUrfmqCHXaI9wOGY.U0m_YmjN04DJvceFICbCVQ";
+    let decrypted = key
+        .decrypt_payload(token, &VerificationOptions::default())
+        .unwrap();
+    assert_eq!(decrypted.payload, b"Live long and prosper.");
+
+    let key = DirectKey::from_bytes(
+        ContentEncryption::A192CBCHS384,
+        &(0u8..48).collect::<Vec<u8>>(),
+    )
+    .unwrap();
+    let token = "eyJhbGciOiJkaXIiLCJlbmMiOiJBMTkyQ0JDLUhTMzg0In0..3V_AVnBd2tGhBpiBT42Ifg.-AUSKn5lu9nbuLIs36kkGGfegXyBs3kdB_IbbGRdcO1U4p-E-Hy4rdeWpVh5kXgKZxEeML9EKZvSF8Mo2mYk2Q.YMroFKp2kITtHSKvPsAcTFQWF8sJe4Tj";
+    let claims = key.decrypt_token::<NoCustomClaims>(token, None).unwrap();
+    assert_eq!(claims.subject.as_deref(), Some("bob"));
+
+    let key = DirectKey::from_bytes(
+        ContentEncryption::A256CBCHS512,
+        &(0u8..64).collect::<Vec<u8>>(),
+    )
+    .unwrap();
+    let token = "eyJhbGciOiJkaXIiLCJlbmMiOiJBMjU2Q0JDLUhTNTEyIn0..lNobMgiorkrL8KxdXfhXhg.kCsJy2yYad172BEHUmSccR-qCyPXAJ0yGxa5jxrMUUqh6vqAMU4vi0QMXztXm8MFdMjDj5Jix2PIZAn2_2lVEg.f2qjNxsdvJEVOvAerf9VFYrhDLWHXX-l4mGXTWwrQVk";
+    let claims = key.decrypt_token::<NoCustomClaims>(token, None).unwrap();
+    assert_eq!(claims.subject.as_deref(), Some("bob"));
+}
@@ -1,4 +1,4 @@
-pub use anyhow::{anyhow, bail, ensure, Error};
+pub use anyhow::{bail, ensure, Error};
 
 #[derive(Debug, thiserror::Error)]
 pub enum JWTError {
@@ -6,8 +6,42 @@ pub enum JWTError {
     InternalError(String),
     #[error("JWT compact encoding error")]
     CompactEncodingError,
+    #[error("JWS JSON serialization error")]
+    JWSJSONEncodingError,
+    #[error("JWS signatures are for different payloads")]
+    JWSPayloadMismatch,
+    #[error("Not enough valid signatures")]
+    NotEnoughSignatures,
+    #[error("Invalid SD-JWT encoding")]
+    SDJWTEncodingError,
+    #[error("SD-JWT disclosure doesn't match the issuer-signed JWT")]
+    SDJWTDisclosureMismatch,
+    #[error("Missing SD-JWT key binding")]
+    SDJWTKeyBindingMissing,
+    #[error("SD-JWT key binding doesn't match the presentation")]
+    SDJWTKeyBindingMismatch,
+    #[error("Invalid DPoP proof")]
+    InvalidDPoPProof,
+    #[error("DPoP proof doesn't match the request")]
+    DPoPProofMismatch,
+    #[error("Invalid verifiable credential")]
+    InvalidVerifiableCredential,
+    #[error("Invalid OpenID provider metadata")]
+    InvalidProviderMetadata,
+    #[error("Unexpected payload encoding")]
+    PayloadEncodingMismatch,
+    #[error("Unsupported critical header parameter")]
+    UnsupportedCriticalHeader,
+    #[error("Reserved header parameter")]
+    ReservedHeaderParameter,
+    #[error("Invalid critical header parameters")]
+    InvalidCriticalHeader,
     #[error("CWT decoding error")]
     CWTDecodingError,
+    #[error("CWT encoding error")]
+    CWTEncodingError,
+    #[error("Default limits have already been installed")]
+    DefaultLimitsAlreadySet,
     #[error("JWT header too large")]
     HeaderTooLarge,
     #[error("JWT algorithm mismatch")]
@@ -26,6 +60,16 @@ pub enum JWTError {
     ClockDrift,
     #[error("Token is too old")]
     TokenIsTooOld,
+    #[error("Token lifetime is too long")]
+    TokenLifetimeTooLong,
+    #[error("Timestamp out of range")]
+    TimestampOutOfRange,
+    #[error("Invalid date-time")]
+    InvalidDateTime,
+    #[error("Token has already been used")]
+    TokenReplayed,
+    #[error("Token has been revoked")]
+    TokenRevoked,
     #[error("Token not valid yet")]
     TokenNotValidYet,
     #[error("Token has expired")]
@@ -44,14 +88,114 @@ pub enum JWTError {
     RequiredSubjectMissing,
     #[error("Required audience missing")]
     RequiredAudienceMissing,
+    #[error("Required expiration date missing")]
+    RequiredExpirationMissing,
+    #[error("Required creation date missing")]
+    RequiredIssuedAtMissing,
+    #[error("Required start date missing")]
+    RequiredNotBeforeMissing,
+    #[error("Required JWT identifier missing")]
+    RequiredJWTIdMissing,
     #[error("Required audience mismatch")]
     RequiredAudienceMismatch,
     #[error("Unsupported RSA modulus")]
     UnsupportedRSAModulus,
+    #[error("Unsupported RSA public exponent")]
+    UnsupportedRSAPublicExponent,
+    #[error("Key is too weak")]
+    WeakKey,
+    #[error("Unsupported JWE content encryption algorithm")]
+    UnsupportedContentEncryption,
+    #[error("Invalid encryption key")]
+    InvalidEncryptionKey,
+    #[error("Unexpected JWE content type")]
+    UnexpectedContentType,
+    #[error("JWE key management algorithm doesn't support multiple recipients")]
+    UnsupportedKeyManagement,
+    #[error("No JWE recipients")]
+    NoRecipients,
+    #[error("Duplicate JWE header parameter")]
+    DuplicateHeaderParameter,
+    #[error("Unsupported JWE compression algorithm")]
+    UnsupportedCompression,
+    #[error("Invalid compressed data")]
+    InvalidCompressedData,
+    #[error("Decompressed payload is too large")]
+    DecompressedPayloadTooLarge,
     #[error("Invalid public key")]
     InvalidPublicKey,
     #[error("Invalid key pair")]
     InvalidKeyPair,
+    #[error("Invalid JWK")]
+    InvalidJWK,
+    #[error("Unsupported private key encryption")]
+    UnsupportedKeyEncryption,
+    #[error("Invalid certificate")]
+    InvalidCertificate,
+    #[error("Certificate chain is not trusted")]
+    UntrustedCertificateChain,
+    #[error("Certificate has expired or is not valid yet")]
+    CertificateExpired,
+    #[error("Missing certificate chain")]
+    MissingCertificateChain,
+    #[error("Certificate thumbprint mismatch")]
+    CertificateThumbprintMismatch,
+    #[error("Missing certificate thumbprint")]
+    MissingCertificateThumbprint,
+    #[error("Key not found in the key ring")]
+    KeyNotInKeyRing,
+    #[error("Duplicate key identifier")]
+    DuplicateKeyIdentifier,
+    #[error("No current signing key")]
+    NoCurrentKey,
+    #[error("The current signing key cannot be removed")]
+    CurrentKeyInUse,
+    #[error("PKCS#11 module couldn't be loaded")]
+    PKCS11ModuleNotLoaded,
+    #[error("PKCS#11 key not found")]
+    PKCS11KeyNotFound,
+    #[error("PKCS#11 operation failed with error {0:#x}")]
+    PKCS11Error(u64),
+    #[error("Public key thumbprint mismatch")]
+    PublicKeyThumbprintMismatch,
+    #[error("Issuer not registered")]
+    UnknownIssuer,
+    #[error("Remote key set not retrieved yet")]
+    KeySetUnavailable,
+    #[error("Required claim missing: {0}")]
+    RequiredClaimMissing(String),
+    #[error("Inconsistent timestamps")]
+    InconsistentTimestamps,
+    #[error("Empty claim: {0}")]
+    EmptyClaim(String),
+    #[error("Required scope missing: {0}")]
+    RequiredScopeMissing(String),
+    #[error("Required confirmation missing")]
+    RequiredConfirmationMissing,
+    #[error("Confirmation mismatch")]
+    ConfirmationMismatch,
+    #[error("Actor chain too deep")]
+    ActorChainTooDeep,
+    #[error("Required authentication time missing")]
+    RequiredAuthTimeMissing,
+    #[error("Authentication too old")]
+    AuthenticationTooOld,
+    #[error("Required authentication context class missing")]
+    RequiredAcrMissing,
+    #[error("Required authentication context class mismatch")]
+    RequiredAcrMismatch,
+    #[error("Required authorized party missing")]
+    RequiredAuthorizedPartyMissing,
+    #[error("Required authorized party mismatch")]
+    RequiredAuthorizedPartyMismatch,
+    #[error("Required token hash missing")]
+    RequiredTokenHashMissing,
+    #[error("Token hash mismatch")]
+    TokenHashMismatch,
+    #[error("Unsupported algorithm for token hashes")]
+    UnsupportedTokenHashAlgorithm,
+    #[error("Key format not supported by this key type")]
+    UnsupportedKeyFormat,
     #[error("At most one audience can be represented as a string instead of a set")]
     TooManyAudiences,
     #[error("Too many issuers to be represented as a string")]
@@ -60,8 +204,14 @@ pub enum JWTError {
     InvalidCertThumprint,
     #[error("Not a JWT token")]
     NotJWT,
+    #[error("Claims too large")]
+    ClaimsTooLarge,
+    #[error("Token type mismatch")]
+    TokenTypeMismatch,
     #[error("Token is too long")]
     TokenTooLong,
+    #[error("Invalid password key derivation parameters")]
+    InvalidPasswordKDFParameters,
 }
 
 impl From<&str> for JWTError {
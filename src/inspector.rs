@@ -0,0 +1,176 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use coarsetime::UnixTimeStamp;
+use ct_codecs::{Base64UrlSafeNoPadding, Decoder};
+use serde::{Serialize, Serializer};
+use serde_json::{Map, Value};
+
+use crate::common::*;
+use crate::error::*;
+use crate::token::*;
+
+/// The value replacing redacted claims
+pub const REDACTED_CLAIM_VALUE: &str = "[REDACTED]";
+
+/// A human-readable description of a token, for debugging purposes
+///
+/// The token is decoded, but NOT VERIFIED. Nothing in this description can
+/// be trusted. The signature itself is never included, only its length.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TokenExplanation {
+    /// The decoded header
+    pub header: Map<String, Value>,
+
+    /// The decoded claims, with the values of redacted claims replaced
+    pub claims: Map<String, Value>,
+
+    /// The length of the signature or authentication tag, in bytes
+    pub signature_length: usize,
+
+    /// The validity period of the token, according to its time claims
+    pub validity: TokenValidity,
+}
+
+/// The validity period of a token, as claimed by the token itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TokenValidity {
+    /// The time the token was checked at
+    #[serde(serialize_with = "serialize_timestamp")]
+    pub checked_at: UnixTimeStamp,
+
+    /// The creation date of the token ("iat")
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        with = "crate::serde_additions::unix_timestamp"
+    )]
+    pub issued_at: Option<UnixTimeStamp>,
+
+    /// The start date of the token ("nbf")
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        with = "crate::serde_additions::unix_timestamp"
+    )]
+    pub invalid_before: Option<UnixTimeStamp>,
+
+    /// The expiration date of the token ("exp")
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        with = "crate::serde_additions::unix_timestamp"
+    )]
+    pub expires_at: Option<UnixTimeStamp>,
+
+    /// The status of the token at `checked_at`, without any time tolerance
+    pub status: TokenStatus,
+}
+
+/// The status of a token, according to its time claims
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenStatus {
+    /// The token is within its validity period
+    Valid,
+    /// The start date of the token is in the future
+    NotYetValid,
+    /// The token has expired
+    Expired,
+}
+
+impl TokenExplanation {
+    /// Replace the values of the given claims, that may contain personal or
+    /// sensitive information
+    pub fn with_redacted_claims(mut self, claims: &[impl ToString]) -> Self {
+        let claims: HashSet<String> = claims.iter().map(|claim| claim.to_string()).collect();
+        for (name, value) in self.claims.iter_mut() {
+            if claims.contains(name) {
+                *value = Value::from(REDACTED_CLAIM_VALUE);
+            }
+        }
+        self
+    }
+
+    /// The explanation, as a JSON object
+    pub fn to_json(&self) -> Result<Value, Error> {
+        Ok(serde_json::to_value(self)?)
+    }
+}
+
+impl fmt::Display for TokenExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let json = serde_json::to_string_pretty(self).map_err(|_| fmt::Error)?;
+        f.write_str(&json)
+    }
+}
+
+impl Token {
+    /// Describe a token WITHOUT VERIFYING IT, for debug endpoints and tools
+    ///
+    /// The validity period is checked against the system clock.
+    pub fn explain(token: &str) -> Result<TokenExplanation, Error> {
+        Self::explain_with_time_source(token, &SystemTimeSource)
+    }
+
+    /// Describe a token WITHOUT VERIFYING IT, checking its validity period
+    /// against the given time source
+    pub fn explain_with_time_source(
+        token: &str,
+        time_source: &dyn TimeSource,
+    ) -> Result<TokenExplanation, Error> {
+        ensure!(
+            token.len() <= DEFAULT_MAX_TOKEN_LENGTH,
+            JWTError::TokenTooLong
+        );
+        let mut parts = token.split('.');
+        let header_b64 = parts.next().ok_or(JWTError::CompactEncodingError)?;
+        ensure!(
+            header_b64.len() <= max_header_length(None),
+            JWTError::HeaderTooLarge
+        );
+        let claims_b64 = parts.next().ok_or(JWTError::CompactEncodingError)?;
+        let signature_b64 = parts.next().ok_or(JWTError::CompactEncodingError)?;
+        ensure!(parts.next().is_none(), JWTError::CompactEncodingError);
+        let header: Map<String, Value> =
+            serde_json::from_slice(&Base64UrlSafeNoPadding::decode_to_vec(header_b64, None)?)?;
+        let claims: Map<String, Value> =
+            serde_json::from_slice(&Base64UrlSafeNoPadding::decode_to_vec(claims_b64, None)?)?;
+        let signature_length = Base64UrlSafeNoPadding::decode_to_vec(signature_b64, None)?.len();
+
+        let time_claim = |name: &str| {
+            claims
+                .get(name)
+                .and_then(Value::as_u64)
+                .map(UnixTimeStamp::from_secs)
+        };
+        let checked_at = time_source.now();
+        let issued_at = time_claim("iat");
+        let invalid_before = time_claim("nbf");
+        let expires_at = time_claim("exp");
+        let status = if expires_at.is_some_and(|expires_at| checked_at >= expires_at) {
+            TokenStatus::Expired
+        } else if invalid_before.is_some_and(|invalid_before| checked_at < invalid_before) {
+            TokenStatus::NotYetValid
+        } else {
+            TokenStatus::Valid
+        };
+        let validity = TokenValidity {
+            checked_at,
+            issued_at,
+            invalid_before,
+            expires_at,
+            status,
+        };
+        Ok(TokenExplanation {
+            header,
+            claims,
+            signature_length,
+            validity,
+        })
+    }
+}
+
+fn serialize_timestamp<S: Serializer>(
+    time: &UnixTimeStamp,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(time.as_secs())
+}
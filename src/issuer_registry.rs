@@ -0,0 +1,107 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::claims::*;
+use crate::common::*;
+use crate::error::*;
+use crate::jwk::*;
+use crate::token::*;
+
+#[derive(Deserialize)]
+struct UnverifiedIssuer {
+    #[serde(rename = "iss", default)]
+    issuer: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct IssuerConfig {
+    key_set: JWKSet,
+    options: VerificationOptions,
+}
+
+/// Key sets and verification options for multiple token issuers
+///
+/// The issuer of a token is read from its claims before verification, in
+/// order to select the key set and the options to verify it with. The token
+/// is then fully verified, and its issuer must match the one it was verified
+/// for.
+#[derive(Debug, Clone, Default)]
+pub struct IssuerRegistry {
+    issuers: HashMap<String, IssuerConfig>,
+}
+
+impl IssuerRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register an issuer, along with its key set and the options to verify
+    /// its tokens with. A previous registration of the same issuer is
+    /// replaced.
+    pub fn add_issuer(
+        &mut self,
+        issuer: impl ToString,
+        key_set: JWKSet,
+        options: Option<VerificationOptions>,
+    ) {
+        let config = IssuerConfig {
+            key_set,
+            options: options.unwrap_or_default(),
+        };
+        self.issuers.insert(issuer.to_string(), config);
+    }
+
+    /// Remove an issuer
+    pub fn remove_issuer(&mut self, issuer: &str) -> Result<(), Error> {
+        ensure!(
+            self.issuers.remove(issuer).is_some(),
+            JWTError::UnknownIssuer
+        );
+        Ok(())
+    }
+
+    /// The registered issuers
+    pub fn issuers(&self) -> impl Iterator<Item = &str> {
+        self.issuers.keys().map(|issuer| issuer.as_str())
+    }
+
+    /// The key set of an issuer, if it is registered
+    pub fn key_set(&self, issuer: &str) -> Option<&JWKSet> {
+        self.issuers.get(issuer).map(|config| &config.key_set)
+    }
+
+    /// The verification options of an issuer, if it is registered
+    pub fn options(&self, issuer: &str) -> Option<&VerificationOptions> {
+        self.issuers.get(issuer).map(|config| &config.options)
+    }
+
+    /// Verify a token using the key set and the options of its issuer, and
+    /// validate its claims
+    ///
+    /// Tokens without an issuer, or from an issuer that is not registered,
+    /// are rejected.
+    pub fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        let unverified: UnverifiedIssuer = Token::decode_unverified_claims(token)?;
+        let issuer = unverified.issuer.ok_or(JWTError::RequiredIssuerMissing)?;
+        let config = self.issuers.get(&issuer).ok_or(JWTError::UnknownIssuer)?;
+        let mut allowed_issuers = HashSet::new();
+        allowed_issuers.insert(issuer);
+        let options = VerificationOptions {
+            allowed_issuers: Some(allowed_issuers),
+            ..config.options.clone()
+        };
+        config.key_set.verify_token(token, Some(options))
+    }
+
+    /// Verify a token like `verify_token()`, and return the whole payload as
+    /// a JSON value, without a compile-time schema
+    pub fn verify_token_untyped(&self, token: &str) -> Result<serde_json::Value, Error> {
+        let claims = self.verify_token::<NoCustomClaims>(token)?;
+        Ok(serde_json::to_value(claims)?)
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+#[doc(hidden)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JWEHeader {
+    #[serde(rename = "alg")]
+    pub(crate) algorithm: String,
+
+    #[serde(rename = "enc")]
+    pub(crate) content_encryption: String,
+
+    #[serde(rename = "cty", default, skip_serializing_if = "Option::is_none")]
+    pub(crate) content_type: Option<String>,
+
+    #[serde(rename = "zip", default, skip_serializing_if = "Option::is_none")]
+    pub(crate) compression: Option<String>,
+
+    #[serde(rename = "kid", default, skip_serializing_if = "Option::is_none")]
+    pub(crate) key_id: Option<String>,
+
+    #[serde(rename = "typ", default, skip_serializing_if = "Option::is_none")]
+    pub(crate) token_type: Option<String>,
+
+    #[serde(rename = "crit", default, skip_serializing_if = "Option::is_none")]
+    pub(crate) critical: Option<Vec<String>>,
+
+    #[serde(rename = "epk", default, skip_serializing_if = "Option::is_none")]
+    pub(crate) ephemeral_public_key: Option<EphemeralPublicKey>,
+
+    #[serde(rename = "apu", default, skip_serializing_if = "Option::is_none")]
+    pub(crate) agreement_party_u_info: Option<String>,
+
+    #[serde(rename = "apv", default, skip_serializing_if = "Option::is_none")]
+    pub(crate) agreement_party_v_info: Option<String>,
+
+    #[serde(rename = "iv", default, skip_serializing_if = "Option::is_none")]
+    pub(crate) key_wrapping_iv: Option<String>,
+
+    #[serde(rename = "tag", default, skip_serializing_if = "Option::is_none")]
+    pub(crate) key_wrapping_tag: Option<String>,
+
+    #[serde(rename = "p2s", default, skip_serializing_if = "Option::is_none")]
+    pub(crate) pbes2_salt_input: Option<String>,
+
+    #[serde(rename = "p2c", default, skip_serializing_if = "Option::is_none")]
+    pub(crate) pbes2_count: Option<u32>,
+
+    #[serde(flatten)]
+    pub(crate) parameters: BTreeMap<String, Value>,
+}
+
+/// Ephemeral public key of the sender, for key agreement algorithms
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct EphemeralPublicKey {
+    #[serde(rename = "kty")]
+    pub(crate) key_type: String,
+
+    #[serde(rename = "crv")]
+    pub(crate) curve: String,
+
+    pub(crate) x: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) y: Option<String>,
+}
+
+impl Default for JWEHeader {
+    fn default() -> Self {
+        JWEHeader {
+            algorithm: "Not set".to_string(),
+            content_encryption: "Not set".to_string(),
+            content_type: None,
+            compression: None,
+            key_id: None,
+            token_type: Some("JWT".to_string()),
+            critical: None,
+            ephemeral_public_key: None,
+            agreement_party_u_info: None,
+            agreement_party_v_info: None,
+            key_wrapping_iv: None,
+            key_wrapping_tag: None,
+            pbes2_salt_input: None,
+            pbes2_count: None,
+            parameters: BTreeMap::new(),
+        }
+    }
+}
+
+impl JWEHeader {
+    pub(crate) fn new(
+        algorithm: String,
+        content_encryption: String,
+        key_id: Option<String>,
+    ) -> Self {
+        JWEHeader {
+            algorithm,
+            content_encryption,
+            key_id,
+            ..Default::default()
+        }
+    }
+}
+
+/// A token using the general JWE JSON serialization
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct JWEJson {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) protected: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) unprotected: Option<Map<String, Value>>,
+
+    pub(crate) recipients: Vec<JWERecipient>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) aad: Option<String>,
+
+    pub(crate) iv: String,
+
+    pub(crate) ciphertext: String,
+
+    pub(crate) tag: String,
+}
+
+/// Per-recipient header and encrypted key of a token using the JSON serialization
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct JWERecipient {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) header: Option<Map<String, Value>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) encrypted_key: Option<String>,
+}
@@ -0,0 +1,465 @@
+use ct_codecs::{Base64UrlSafeNoPadding, Decoder, Encoder};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::claims::*;
+use crate::common::*;
+use crate::error::*;
+use crate::token::*;
+
+/// A JSON Web Key (RFC 7517), suitable for publishing in a key set
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JWK {
+    /// Key type ("kty")
+    #[serde(rename = "kty")]
+    pub key_type: String,
+
+    /// Curve name ("crv")
+    #[serde(rename = "crv", default, skip_serializing_if = "Option::is_none")]
+    pub curve: Option<String>,
+
+    /// Public key or x coordinate, base64url-encoded ("x")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x: Option<String>,
+
+    /// y coordinate, base64url-encoded ("y")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub y: Option<String>,
+
+    /// RSA modulus, base64url-encoded ("n")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n: Option<String>,
+
+    /// RSA public exponent, base64url-encoded ("e")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub e: Option<String>,
+
+    /// Private key or RSA private exponent, base64url-encoded ("d")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub d: Option<String>,
+
+    /// First RSA prime factor, base64url-encoded ("p")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub p: Option<String>,
+
+    /// Second RSA prime factor, base64url-encoded ("q")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub q: Option<String>,
+
+    /// First RSA CRT exponent, base64url-encoded ("dp")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dp: Option<String>,
+
+    /// Second RSA CRT exponent, base64url-encoded ("dq")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dq: Option<String>,
+
+    /// RSA CRT coefficient, base64url-encoded ("qi")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub qi: Option<String>,
+
+    /// Symmetric key, base64url-encoded ("k")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub k: Option<String>,
+
+    /// Public key of an algorithm key pair, base64url-encoded ("pub")
+    #[serde(rename = "pub", default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+
+    /// Private key of an algorithm key pair, base64url-encoded ("priv")
+    #[serde(rename = "priv", default, skip_serializing_if = "Option::is_none")]
+    pub private_key: Option<String>,
+
+    /// Key identifier ("kid")
+    #[serde(rename = "kid", default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+
+    /// Algorithm the key is intended to be used with ("alg")
+    #[serde(rename = "alg", default, skip_serializing_if = "Option::is_none")]
+    pub algorithm: Option<String>,
+
+    /// Intended use of the key: `"sig"` or `"enc"` ("use")
+    #[serde(rename = "use", default, skip_serializing_if = "Option::is_none")]
+    pub public_key_use: Option<String>,
+
+    /// Operations the key is intended to be used for ("key_ops")
+    #[serde(rename = "key_ops", default, skip_serializing_if = "Option::is_none")]
+    pub key_operations: Option<Vec<String>>,
+}
+
+fn encode_member(raw: &[u8]) -> Result<Option<String>, Error> {
+    Ok(Some(Base64UrlSafeNoPadding::encode_to_string(raw)?))
+}
+
+fn decode_member(member: &Option<String>) -> Result<Vec<u8>, Error> {
+    let member = member.as_ref().ok_or(JWTError::InvalidJWK)?;
+    Base64UrlSafeNoPadding::decode_to_vec(member, None).map_err(|_| JWTError::InvalidJWK.into())
+}
+
+impl JWK {
+    /// Parse a JWK from its JSON representation
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        serde_json::from_str(json).map_err(|_| JWTError::InvalidJWK.into())
+    }
+
+    /// Serialize the JWK as JSON
+    pub fn to_json(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Compute the RFC 7638 thumbprint of the key, using SHA-256
+    ///
+    /// Only the required members of the key are hashed, so that the thumbprint
+    /// doesn't depend on the key identifier or on other optional members.
+    pub fn thumbprint(&self) -> Result<String, Error> {
+        let thumbprint = hmac_sha256::Hash::hash(self.thumbprint_input()?.as_bytes());
+        Ok(Base64UrlSafeNoPadding::encode_to_string(thumbprint)?)
+    }
+
+    /// Compute the RFC 7638 thumbprint of the key, using SHA-1, for legacy
+    /// applications
+    pub fn thumbprint_sha1(&self) -> Result<String, Error> {
+        let thumbprint = hmac_sha1_compact::Hash::hash(self.thumbprint_input()?.as_bytes());
+        Ok(Base64UrlSafeNoPadding::encode_to_string(thumbprint)?)
+    }
+
+    fn thumbprint_input(&self) -> Result<String, Error> {
+        let key_type = Some(self.key_type.clone());
+        let members = match self.key_type.as_str() {
+            "EC" => vec![
+                ("crv", &self.curve),
+                ("kty", &key_type),
+                ("x", &self.x),
+                ("y", &self.y),
+            ],
+            "OKP" => vec![("crv", &self.curve), ("kty", &key_type), ("x", &self.x)],
+            "RSA" => vec![("e", &self.e), ("kty", &key_type), ("n", &self.n)],
+            "oct" => vec![("k", &self.k), ("kty", &key_type)],
+            "AKP" => vec![
+                ("alg", &self.algorithm),
+                ("kty", &key_type),
+                ("pub", &self.public_key),
+            ],
+            _ => bail!(JWTError::InvalidJWK),
+        };
+        let mut encoded_members = vec![];
+        for (name, value) in members {
+            let value = value.as_ref().ok_or(JWTError::InvalidJWK)?;
+            encoded_members.push(format!(
+                "{}:{}",
+                serde_json::to_string(name)?,
+                serde_json::to_string(value)?
+            ));
+        }
+        Ok(format!("{{{}}}", encoded_members.join(",")))
+    }
+
+    /// Create a JWK for a symmetric key
+    pub(crate) fn new_octet_sequence(k: &[u8]) -> Result<Self, Error> {
+        Ok(JWK {
+            key_type: "oct".to_string(),
+            k: encode_member(k)?,
+            ..Default::default()
+        })
+    }
+
+    /// Create a JWK for an elliptic curve public key, given its uncompressed
+    /// SEC1 encoding
+    pub(crate) fn new_elliptic_curve(curve: &str, point: &[u8]) -> Result<Self, Error> {
+        ensure!(
+            point.len() % 2 == 1 && point[0] == 0x04,
+            JWTError::InvalidPublicKey
+        );
+        let (x, y) = point[1..].split_at(point.len() / 2);
+        Ok(JWK {
+            key_type: "EC".to_string(),
+            curve: Some(curve.to_string()),
+            x: encode_member(x)?,
+            y: encode_member(y)?,
+            ..Default::default()
+        })
+    }
+
+    /// Create a JWK for an octet key pair public key (RFC 8037)
+    pub(crate) fn new_octet_key_pair(curve: &str, x: &[u8]) -> Result<Self, Error> {
+        Ok(JWK {
+            key_type: "OKP".to_string(),
+            curve: Some(curve.to_string()),
+            x: encode_member(x)?,
+            ..Default::default()
+        })
+    }
+
+    /// Create a JWK for an RSA public key
+    pub(crate) fn new_rsa(n: &[u8], e: &[u8]) -> Result<Self, Error> {
+        Ok(JWK {
+            key_type: "RSA".to_string(),
+            n: encode_member(n)?,
+            e: encode_member(e)?,
+            ..Default::default()
+        })
+    }
+
+    /// Create a JWK for an algorithm key pair public key, such as ML-DSA
+    #[cfg(feature = "pq")]
+    pub(crate) fn new_algorithm_key_pair(public_key: &[u8]) -> Result<Self, Error> {
+        Ok(JWK {
+            key_type: "AKP".to_string(),
+            public_key: encode_member(public_key)?,
+            ..Default::default()
+        })
+    }
+
+    /// Add the private key to a public key JWK
+    pub(crate) fn with_private_key(mut self, private_key: &[u8]) -> Result<Self, Error> {
+        if self.key_type == "AKP" {
+            self.private_key = encode_member(private_key)?;
+        } else {
+            self.d = encode_member(private_key)?;
+        }
+        Ok(self)
+    }
+
+    /// Add the private RSA parameters to a public key JWK
+    pub(crate) fn with_rsa_private_key(
+        mut self,
+        members: &[impl AsRef<[u8]>; 6],
+    ) -> Result<Self, Error> {
+        let [d, p, q, dp, dq, qi] = members;
+        self.d = encode_member(d.as_ref())?;
+        self.p = encode_member(p.as_ref())?;
+        self.q = encode_member(q.as_ref())?;
+        self.dp = encode_member(dp.as_ref())?;
+        self.dq = encode_member(dq.as_ref())?;
+        self.qi = encode_member(qi.as_ref())?;
+        Ok(self)
+    }
+
+    /// Set the key identifier, the algorithm and the intended use of the key
+    pub(crate) fn with_usage(
+        mut self,
+        key_id: &Option<String>,
+        algorithm: &str,
+        public_key_use: &str,
+    ) -> Self {
+        self.key_id = key_id.clone();
+        self.algorithm = Some(algorithm.to_string());
+        self.public_key_use = Some(public_key_use.to_string());
+        self
+    }
+
+    /// Check that the key can be used with `algorithm`, for `public_key_use`,
+    /// to perform at least one of `key_operations`
+    pub(crate) fn check_usage(
+        &self,
+        algorithm: &str,
+        public_key_use: &str,
+        key_operations: &[&str],
+    ) -> Result<(), Error> {
+        if let Some(jwk_algorithm) = &self.algorithm {
+            ensure!(jwk_algorithm == algorithm, JWTError::AlgorithmMismatch);
+        }
+        if let Some(jwk_public_key_use) = &self.public_key_use {
+            ensure!(jwk_public_key_use == public_key_use, JWTError::InvalidJWK);
+        }
+        if let Some(jwk_key_operations) = &self.key_operations {
+            ensure!(
+                jwk_key_operations
+                    .iter()
+                    .any(|x| key_operations.contains(&x.as_str())),
+                JWTError::InvalidJWK
+            );
+        }
+        Ok(())
+    }
+
+    fn check_key_type(&self, key_type: &str, curve: Option<&str>) -> Result<(), Error> {
+        ensure!(
+            self.key_type == key_type && self.curve.as_deref() == curve,
+            JWTError::InvalidJWK
+        );
+        Ok(())
+    }
+
+    /// The symmetric key of an "oct" JWK
+    pub(crate) fn octet_sequence(&self) -> Result<Zeroizing<Vec<u8>>, Error> {
+        self.check_key_type("oct", None)?;
+        Ok(Zeroizing::new(decode_member(&self.k)?))
+    }
+
+    /// The uncompressed SEC1 encoding of an "EC" JWK public key
+    pub(crate) fn elliptic_curve_point(
+        &self,
+        curve: &str,
+        coordinate_length: usize,
+    ) -> Result<Vec<u8>, Error> {
+        self.check_key_type("EC", Some(curve))?;
+        let x = decode_member(&self.x)?;
+        let y = decode_member(&self.y)?;
+        ensure!(
+            x.len() == coordinate_length && y.len() == coordinate_length,
+            JWTError::InvalidJWK
+        );
+        let mut point = vec![0x04];
+        point.extend_from_slice(&x);
+        point.extend_from_slice(&y);
+        Ok(point)
+    }
+
+    /// The public key of an "OKP" JWK
+    pub(crate) fn octet_key_pair_public_key(&self, curve: &str) -> Result<Vec<u8>, Error> {
+        self.check_key_type("OKP", Some(curve))?;
+        decode_member(&self.x)
+    }
+
+    /// The modulus and the public exponent of an "RSA" JWK
+    pub(crate) fn rsa_public_key(&self) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        self.check_key_type("RSA", None)?;
+        Ok((decode_member(&self.n)?, decode_member(&self.e)?))
+    }
+
+    /// The private exponent and the prime factors of an "RSA" JWK
+    pub(crate) fn rsa_private_key(&self) -> Result<[Zeroizing<Vec<u8>>; 3], Error> {
+        self.check_key_type("RSA", None)?;
+        Ok([
+            Zeroizing::new(decode_member(&self.d)?),
+            Zeroizing::new(decode_member(&self.p)?),
+            Zeroizing::new(decode_member(&self.q)?),
+        ])
+    }
+
+    /// The public key of an "AKP" JWK
+    #[cfg(feature = "pq")]
+    pub(crate) fn algorithm_key_pair_public_key(&self) -> Result<Vec<u8>, Error> {
+        self.check_key_type("AKP", None)?;
+        decode_member(&self.public_key)
+    }
+
+    /// The private key of an "EC", "OKP" or "AKP" JWK
+    pub(crate) fn private_key(&self) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let private_key = if self.key_type == "AKP" {
+            &self.private_key
+        } else {
+            &self.d
+        };
+        Ok(Zeroizing::new(decode_member(private_key)?))
+    }
+
+    /// Return `true` if the key includes private or symmetric key members
+    pub(crate) fn has_private_members(&self) -> bool {
+        [
+            &self.d,
+            &self.p,
+            &self.q,
+            &self.dp,
+            &self.dq,
+            &self.qi,
+            &self.k,
+            &self.private_key,
+        ]
+        .iter()
+        .any(|member| member.is_some())
+    }
+
+    /// Check that the public members of a key pair JWK, if present, match
+    /// the public key computed from the private key
+    pub(crate) fn check_public_key(&self, public_jwk: &JWK) -> Result<(), Error> {
+        self.check_key_type(&public_jwk.key_type, public_jwk.curve.as_deref())?;
+        for (member, expected) in [
+            (&self.x, &public_jwk.x),
+            (&self.y, &public_jwk.y),
+            (&self.public_key, &public_jwk.public_key),
+        ] {
+            if member.is_some() {
+                ensure!(
+                    decode_member(member)? == decode_member(expected)?,
+                    JWTError::InvalidKeyPair
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A JWK set (RFC 7517, section 5), such as the documents published by
+/// OpenID Connect providers
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JWKSet {
+    /// The keys of the set
+    pub keys: Vec<JWK>,
+}
+
+impl JWKSet {
+    /// Parse a JWK set from its JSON representation
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        serde_json::from_str(json).map_err(|_| JWTError::InvalidJWK.into())
+    }
+
+    /// Serialize the JWK set as JSON
+    pub fn to_json(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Return the first key with the given identifier
+    pub fn key_with_id(&self, key_id: &str) -> Option<&JWK> {
+        self.keys
+            .iter()
+            .find(|jwk| jwk.key_id.as_deref() == Some(key_id))
+    }
+
+    /// Return the keys that can be used with the given algorithm: keys made
+    /// for that algorithm, and keys that don't specify an algorithm
+    pub fn keys_for_algorithm<'t>(&'t self, algorithm: &'t str) -> impl Iterator<Item = &'t JWK> {
+        self.keys.iter().filter(move |jwk| match &jwk.algorithm {
+            Some(jwk_algorithm) => jwk_algorithm == algorithm,
+            None => true,
+        })
+    }
+
+    /// Verify a signed token using the key set, and validate its claims
+    ///
+    /// The key is selected according to the algorithm and the key identifier
+    /// of the token. Only public keys are used: shared keys, as well as keys
+    /// that cannot be imported for the algorithm of the token, are ignored.
+    pub fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        let metadata = Token::decode_metadata_with_max_header_length(
+            token,
+            max_header_length(options.as_ref()),
+        )?;
+        let algorithm = metadata.algorithm();
+        let keys: Vec<Box<dyn KeyLike>> = self
+            .keys_for_algorithm(algorithm)
+            .filter(|jwk| match (&jwk.key_id, metadata.key_id()) {
+                (Some(key_id), Some(token_key_id)) => key_id == token_key_id,
+                _ => true,
+            })
+            .filter_map(|jwk| public_key_from_jwk(algorithm, jwk).ok())
+            .collect();
+        let keys: Vec<&dyn KeyLike> = keys.iter().map(|key| key.as_ref()).collect();
+        Token::verify_with_keys(&keys, token, options)
+    }
+
+    /// Verify a token like `verify_token()`, and return the whole payload as
+    /// a JSON value, without a compile-time schema
+    pub fn verify_token_untyped(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<serde_json::Value, Error> {
+        let claims = self.verify_token::<NoCustomClaims>(token, options)?;
+        Ok(serde_json::to_value(claims)?)
+    }
+}
+
+pub(crate) fn public_key_from_jwk(algorithm: &str, jwk: &JWK) -> Result<Box<dyn KeyLike>, Error> {
+    crate::with_key_pair_algorithm!(
+        algorithm,
+        jwk.curve.as_deref(),
+        |KeyPair, PublicKey| Ok(Box::new(PublicKey::from_jwk(jwk)?) as Box<dyn KeyLike>),
+        bail!(JWTError::AlgorithmMismatch)
+    )
+}
@@ -0,0 +1,422 @@
+use std::collections::HashSet;
+
+use ct_codecs::{Base64UrlSafeNoPadding, Decoder};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::claims::*;
+use crate::common::*;
+use crate::error::*;
+use crate::token::*;
+
+/// A signature of a JWS in the JSON serialization
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JWSSignature {
+    /// The protected header, base64url-encoded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protected: Option<String>,
+
+    /// The unprotected header, that is not covered by the signature
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header: Option<serde_json::Map<String, serde_json::Value>>,
+
+    /// The signature, base64url-encoded
+    pub signature: String,
+}
+
+impl JWSSignature {
+    /// Split a token in the compact serialization into its payload and its
+    /// signature
+    pub(crate) fn from_compact(token: &str) -> Result<(&str, JWSSignature), Error> {
+        let mut parts = token.split('.');
+        let protected = parts.next().ok_or(JWTError::CompactEncodingError)?;
+        let payload = parts.next().ok_or(JWTError::CompactEncodingError)?;
+        let signature = parts.next().ok_or(JWTError::CompactEncodingError)?;
+        ensure!(parts.next().is_none(), JWTError::CompactEncodingError);
+        let signature = JWSSignature {
+            protected: Some(protected.to_string()),
+            header: None,
+            signature: signature.to_string(),
+        };
+        Ok((payload, signature))
+    }
+
+    /// The compact serialization of the signature over `payload`
+    ///
+    /// The algorithm must be in the protected header, and the protected and
+    /// unprotected headers must not share any parameters.
+    pub(crate) fn to_compact(&self, payload: &str) -> Result<String, Error> {
+        let protected = self
+            .protected
+            .as_deref()
+            .ok_or(JWTError::JWSJSONEncodingError)?;
+        if let Some(header) = &self.header {
+            let protected_header: serde_json::Map<String, serde_json::Value> =
+                serde_json::from_slice(&Base64UrlSafeNoPadding::decode_to_vec(protected, None)?)?;
+            ensure!(
+                header
+                    .keys()
+                    .all(|name| !protected_header.contains_key(name)),
+                JWTError::JWSJSONEncodingError
+            );
+        }
+        Ok(format!("{}.{}.{}", protected, payload, self.signature))
+    }
+
+    /// Verify the signature over `payload` using any of the given keys, and
+    /// validate the claims
+    ///
+    /// If the unprotected header includes a key identifier, only the keys
+    /// with that identifier are tried.
+    fn verify<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        payload: &str,
+        keys: &[&dyn KeyLike],
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        let token = self.to_compact(payload)?;
+        let keys: Vec<&dyn KeyLike> = keys
+            .iter()
+            .copied()
+            .filter(|key| self.may_be_signed_by(*key))
+            .collect();
+        Token::verify_with_keys(&keys, &token, options)
+    }
+
+    /// Return `false` if the unprotected header includes a key identifier
+    /// that doesn't match the identifier of the key
+    fn may_be_signed_by(&self, key: &dyn KeyLike) -> bool {
+        let unprotected_key_id = self
+            .header
+            .as_ref()
+            .and_then(|header| header.get("kid"))
+            .and_then(|key_id| key_id.as_str());
+        match (unprotected_key_id, key.key_identifier()) {
+            (Some(unprotected_key_id), Some(key_id)) => unprotected_key_id == key_id,
+            _ => true,
+        }
+    }
+}
+
+/// How many keys must have signed a document
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignaturePolicy {
+    /// At least one of the keys
+    AnyOf,
+    /// All the keys
+    AllOf,
+    /// At least this number of keys
+    Threshold(usize),
+}
+
+/// A JWS in the general JSON serialization (RFC 7515, section 7.2.1)
+///
+/// This represents a payload along with one or more signatures, possibly
+/// created with different keys and algorithms. Each signature is equivalent
+/// to a token in the compact serialization, so that documents can be built
+/// from tokens created with the usual `sign()` and `authenticate()` functions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GeneralJWS {
+    payload: String,
+    signatures: Vec<JWSSignature>,
+}
+
+impl GeneralJWS {
+    /// Combine tokens in the compact serialization, that must all have the
+    /// same payload
+    pub fn from_compact_tokens(tokens: &[impl AsRef<str>]) -> Result<Self, Error> {
+        let mut tokens = tokens.iter();
+        let first = tokens.next().ok_or(JWTError::JWSJSONEncodingError)?;
+        let (payload, signature) = JWSSignature::from_compact(first.as_ref())?;
+        let mut jws = GeneralJWS {
+            payload: payload.to_string(),
+            signatures: vec![signature],
+        };
+        for token in tokens {
+            jws.add_compact_token(token.as_ref())?;
+        }
+        Ok(jws)
+    }
+
+    /// Add the signature of a token in the compact serialization, that must
+    /// have the same payload
+    pub fn add_compact_token(&mut self, token: &str) -> Result<(), Error> {
+        let (payload, signature) = JWSSignature::from_compact(token)?;
+        ensure!(payload == self.payload, JWTError::JWSPayloadMismatch);
+        self.signatures.push(signature);
+        Ok(())
+    }
+
+    /// Parse a JWS in the general JSON serialization
+    pub fn parse(json: &str) -> Result<Self, Error> {
+        let jws: GeneralJWS =
+            serde_json::from_str(json).map_err(|_| JWTError::JWSJSONEncodingError)?;
+        ensure!(!jws.signatures.is_empty(), JWTError::JWSJSONEncodingError);
+        Ok(jws)
+    }
+
+    /// Serialize the JWS as JSON
+    pub fn to_json(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// The payload, base64url-encoded
+    pub fn payload(&self) -> &str {
+        &self.payload
+    }
+
+    /// The decoded payload, WITHOUT VERIFYING IT
+    pub fn payload_unverified(&self) -> Result<Vec<u8>, Error> {
+        Ok(Base64UrlSafeNoPadding::decode_to_vec(&self.payload, None)?)
+    }
+
+    /// The signatures
+    pub fn signatures(&self) -> &[JWSSignature] {
+        &self.signatures
+    }
+
+    /// Each signature as a token in the compact serialization
+    pub fn to_compact_tokens(&self) -> Result<Vec<String>, Error> {
+        self.signatures
+            .iter()
+            .map(|signature| signature.to_compact(&self.payload))
+            .collect()
+    }
+
+    /// Sign claims with multiple keys, and combine the signatures
+    ///
+    /// Each signer gets a copy of the claims, and must return a token in the
+    /// compact serialization, for example using `sign()` or
+    /// `authenticate()`.
+    pub fn sign_with<CustomClaims: Clone>(
+        claims: JWTClaims<CustomClaims>,
+        signers: &[&dyn Fn(JWTClaims<CustomClaims>) -> Result<String, Error>],
+    ) -> Result<Self, Error> {
+        let tokens = signers
+            .iter()
+            .map(|signer| signer(claims.clone()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::from_compact_tokens(&tokens)
+    }
+
+    /// Add a signature to the document, for example to co-sign a document
+    /// received from another party
+    ///
+    /// The signer gets the claims of the payload, WITHOUT VERIFYING THEM, and
+    /// must return a token in the compact serialization. This fails with
+    /// `JWTError::JWSPayloadMismatch` if the claims were not serialized
+    /// exactly as in the original payload, which can happen if the payload
+    /// was not created with the same custom claims type.
+    pub fn add_signature<CustomClaims: DeserializeOwned>(
+        &mut self,
+        signer: impl FnOnce(JWTClaims<CustomClaims>) -> Result<String, Error>,
+    ) -> Result<(), Error> {
+        let claims = serde_json::from_slice(&self.payload_unverified()?)?;
+        self.add_compact_token(&signer(claims)?)
+    }
+
+    /// Verify that the JWS was signed by enough of the given keys according
+    /// to the policy, and validate its claims
+    ///
+    /// Keys and signatures are matched one-to-one: a signature never counts
+    /// for more than one key, and keys with the same thumbprint are counted
+    /// once. Fails with `JWTError::NotEnoughSignatures` if the policy is not
+    /// satisfied.
+    pub fn verify_with_policy<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        keys: &[&dyn KeyLike],
+        policy: SignaturePolicy,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        let options = options.unwrap_or_default();
+        let tokens: Vec<(&JWSSignature, String)> = self
+            .signatures
+            .iter()
+            .filter_map(|signature| {
+                let token = signature.to_compact(&self.payload).ok()?;
+                Some((signature, token))
+            })
+            .collect();
+        let mut thumbprints = HashSet::new();
+        let distinct_keys: Vec<&dyn KeyLike> = keys
+            .iter()
+            .copied()
+            .filter(|key| match key.key_thumbprint() {
+                Some(thumbprint) => thumbprints.insert(thumbprint),
+                None => true,
+            })
+            .collect();
+        let verified_signatures: Vec<Vec<usize>> = distinct_keys
+            .iter()
+            .map(|key| {
+                tokens
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (signature, token))| {
+                        signature.may_be_signed_by(*key)
+                            && Token::verify_signature_with_key(*key, token, &options).is_ok()
+                    })
+                    .map(|(i, _)| i)
+                    .collect()
+            })
+            .collect();
+        let signers = count_distinct_signers(&verified_signatures, tokens.len());
+        let required_signers = match policy {
+            SignaturePolicy::AnyOf => 1,
+            SignaturePolicy::AllOf => distinct_keys.len(),
+            SignaturePolicy::Threshold(threshold) => threshold,
+        };
+        ensure!(
+            required_signers > 0 && signers >= required_signers,
+            JWTError::NotEnoughSignatures
+        );
+        Token::verify_claims(&self.payload_unverified()?, &options)
+    }
+
+    /// Verify the JWS using any of the given keys, and validate its claims
+    ///
+    /// Signatures are tried in order, and the claims are returned as soon as
+    /// one of them can be verified with one of the keys, as with
+    /// `Token::verify_with_keys()`.
+    pub fn verify_with_keys<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        keys: &[&dyn KeyLike],
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        let mut last_error = None;
+        for signature in &self.signatures {
+            match signature.verify(&self.payload, keys, options.clone()) {
+                Ok(claims) => return Ok(claims),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| JWTError::JWSJSONEncodingError.into()))
+    }
+}
+
+/// The maximum number of keys that can be matched with distinct signatures,
+/// given the indices of the signatures each key verifies
+fn count_distinct_signers(verified_signatures: &[Vec<usize>], signatures_count: usize) -> usize {
+    fn assign(
+        key: usize,
+        verified_signatures: &[Vec<usize>],
+        visited: &mut [bool],
+        signers: &mut [Option<usize>],
+    ) -> bool {
+        for &signature in &verified_signatures[key] {
+            if visited[signature] {
+                continue;
+            }
+            visited[signature] = true;
+            let reassigned = match signers[signature] {
+                None => true,
+                Some(other_key) => assign(other_key, verified_signatures, visited, signers),
+            };
+            if reassigned {
+                signers[signature] = Some(key);
+                return true;
+            }
+        }
+        false
+    }
+
+    let mut signers = vec![None; signatures_count];
+    (0..verified_signatures.len())
+        .filter(|&key| {
+            let mut visited = vec![false; signatures_count];
+            assign(key, verified_signatures, &mut visited, &mut signers)
+        })
+        .count()
+}
+
+/// A JWS in the flattened JSON serialization (RFC 7515, section 7.2.2)
+///
+/// This represents a payload with a single signature. Unlike the compact
+/// serialization, it can include an unprotected header, that is not covered
+/// by the signature.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FlattenedJWS {
+    payload: String,
+    #[serde(flatten)]
+    signature: JWSSignature,
+}
+
+impl FlattenedJWS {
+    /// Convert a token in the compact serialization
+    pub fn from_compact(token: &str) -> Result<Self, Error> {
+        let (payload, signature) = JWSSignature::from_compact(token)?;
+        Ok(FlattenedJWS {
+            payload: payload.to_string(),
+            signature,
+        })
+    }
+
+    /// Convert the JWS to the compact serialization
+    ///
+    /// The unprotected header cannot be represented in the compact
+    /// serialization, and is dropped.
+    pub fn to_compact(&self) -> Result<String, Error> {
+        self.signature.to_compact(&self.payload)
+    }
+
+    /// Add a parameter to the unprotected header
+    ///
+    /// Parameters of the protected header cannot be overridden.
+    pub fn with_unprotected_header_parameter(
+        mut self,
+        name: impl ToString,
+        value: impl Into<serde_json::Value>,
+    ) -> Result<Self, Error> {
+        self.signature
+            .header
+            .get_or_insert_with(serde_json::Map::new)
+            .insert(name.to_string(), value.into());
+        self.to_compact()?;
+        Ok(self)
+    }
+
+    /// Parse a JWS in the flattened JSON serialization
+    pub fn parse(json: &str) -> Result<Self, Error> {
+        serde_json::from_str(json).map_err(|_| JWTError::JWSJSONEncodingError.into())
+    }
+
+    /// Serialize the JWS as JSON
+    pub fn to_json(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// The payload, base64url-encoded
+    pub fn payload(&self) -> &str {
+        &self.payload
+    }
+
+    /// The decoded payload, WITHOUT VERIFYING IT
+    pub fn payload_unverified(&self) -> Result<Vec<u8>, Error> {
+        Ok(Base64UrlSafeNoPadding::decode_to_vec(&self.payload, None)?)
+    }
+
+    /// The signature, along with its headers
+    pub fn signature(&self) -> &JWSSignature {
+        &self.signature
+    }
+
+    /// Verify the JWS using any of the given keys, and validate its claims
+    ///
+    /// If the unprotected header includes a key identifier, only the keys
+    /// with that identifier are tried.
+    pub fn verify_with_keys<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        keys: &[&dyn KeyLike],
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        self.signature.verify(&self.payload, keys, options)
+    }
+}
+
+impl From<FlattenedJWS> for GeneralJWS {
+    fn from(jws: FlattenedJWS) -> Self {
+        GeneralJWS {
+            payload: jws.payload,
+            signatures: vec![jws.signature],
+        }
+    }
+}
@@ -1,6 +1,9 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
 use crate::common::*;
+use crate::error::*;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct JWTHeader {
@@ -19,13 +22,21 @@ pub(crate) struct JWTHeader {
     #[serde(rename = "crit", default, skip_serializing_if = "Option::is_none")]
     pub(crate) critical: Option<Vec<String>>,
 
+    #[serde(rename = "b64", default, skip_serializing_if = "Option::is_none")]
+    pub(crate) base64_payload: Option<bool>,
+
     #[serde(rename = "x5c", default, skip_serializing_if = "Option::is_none")]
     pub(crate) certificate_chain: Option<Vec<String>>,
 
     #[serde(rename = "jku", default, skip_serializing_if = "Option::is_none")]
     pub key_set_url: Option<String>,
 
-    #[serde(rename = "jwk", default, skip_serializing_if = "Option::is_none")]
+    #[serde(
+        rename = "jwk",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::serde_additions::json_object_string"
+    )]
     pub public_key: Option<String>,
 
     #[serde(rename = "x5u", default, skip_serializing_if = "Option::is_none")]
@@ -36,6 +47,9 @@ pub(crate) struct JWTHeader {
 
     #[serde(rename = "x5t#S256", default, skip_serializing_if = "Option::is_none")]
     pub certificate_sha256_thumbprint: Option<String>,
+
+    #[serde(flatten)]
+    pub(crate) parameters: BTreeMap<String, serde_json::Value>,
 }
 
 impl Default for JWTHeader {
@@ -52,6 +66,8 @@ impl Default for JWTHeader {
             certificate_sha256_thumbprint: None,
             signature_type: Some("JWT".to_string()),
             critical: None,
+            base64_payload: None,
+            parameters: BTreeMap::new(),
         }
     }
 }
@@ -79,6 +95,9 @@ impl JWTHeader {
         if self.certificate_url.is_none() {
             self.certificate_url = metadata.certificate_url.clone();
         }
+        if self.certificate_chain.is_none() {
+            self.certificate_chain = metadata.certificate_chain.clone();
+        }
         if self.certificate_sha1_thumbprint.is_none() {
             self.certificate_sha1_thumbprint = metadata.certificate_sha1_thumbprint.clone();
         }
@@ -87,4 +106,56 @@ impl JWTHeader {
         }
         self
     }
+
+    pub(crate) fn with_options(mut self, options: &HeaderOptions) -> Self {
+        if options.public_key.is_some() {
+            self.public_key = options.public_key.clone();
+        }
+        if options.content_type.is_some() {
+            self.content_type = options.content_type.clone();
+        }
+        if options.signature_type.is_some() {
+            self.signature_type = options.signature_type.clone();
+        }
+        if !options.critical.is_empty() {
+            self.critical = Some(options.critical.clone());
+        }
+        self.parameters.extend(
+            options
+                .parameters
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone())),
+        );
+        self
+    }
+
+    /// Check that all the critical header parameters are understood
+    pub(crate) fn check_critical(&self, options: &VerificationOptions) -> Result<(), Error> {
+        let builtin: &[&str] = if self.base64_payload.is_some() {
+            &["b64"]
+        } else {
+            &[]
+        };
+        options.check_critical_header(self.critical.as_deref(), &self.parameters, builtin)
+    }
+
+    /// Check that the payload is unencoded (RFC 7797) if, and only if, it is
+    /// expected to be
+    ///
+    /// Unencoded payloads must be marked as critical.
+    pub(crate) fn check_payload_encoding(&self, unencoded: bool) -> Result<(), Error> {
+        if !unencoded {
+            ensure!(
+                self.base64_payload != Some(false),
+                JWTError::PayloadEncodingMismatch
+            );
+            return Ok(());
+        }
+        let critical = self.critical.as_deref().unwrap_or_default();
+        ensure!(
+            self.base64_payload == Some(false) && critical.iter().any(|name| name == "b64"),
+            JWTError::PayloadEncodingMismatch
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,215 @@
+use crate::algorithms::*;
+use crate::error::*;
+use crate::jwk::*;
+
+/// An encoding keys can be imported from and exported to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyFormat {
+    /// Raw key, as returned by the `to_bytes()` function of the key
+    Raw,
+    /// Compressed SEC1 point, for elliptic curve public keys
+    CompressedPoint,
+    /// Uncompressed SEC1 point, for elliptic curve public keys
+    UncompressedPoint,
+    /// DER-encoded PKCS#8 private key or SubjectPublicKeyInfo public key
+    DER,
+    /// PEM-encoded PKCS#8 private key or SubjectPublicKeyInfo public key
+    PEM,
+    /// JSON-encoded JWK
+    JWK,
+}
+
+/// Import and export keys in any of the formats they support
+///
+/// Formats that are not supported by a key type return
+/// `JWTError::UnsupportedKeyFormat`.
+pub trait KeyConversion: Sized {
+    /// Import a key encoded in the given format
+    fn import(data: &[u8], format: KeyFormat) -> Result<Self, Error>;
+
+    /// Export the key in the given format
+    fn export(&self, format: KeyFormat) -> Result<Vec<u8>, Error>;
+
+    /// Convert a key from one format to another
+    fn convert(data: &[u8], from: KeyFormat, to: KeyFormat) -> Result<Vec<u8>, Error> {
+        Self::import(data, from)?.export(to)
+    }
+}
+
+trait Exported {
+    fn into_bytes(self) -> Result<Vec<u8>, Error>;
+}
+
+impl Exported for Vec<u8> {
+    fn into_bytes(self) -> Result<Vec<u8>, Error> {
+        Ok(self)
+    }
+}
+
+impl Exported for String {
+    fn into_bytes(self) -> Result<Vec<u8>, Error> {
+        Ok(self.into_bytes())
+    }
+}
+
+impl<T: Exported> Exported for Result<T, Error> {
+    fn into_bytes(self) -> Result<Vec<u8>, Error> {
+        self?.into_bytes()
+    }
+}
+
+macro_rules! import_as {
+    ($key:ty, $data:expr, Raw) => {
+        <$key>::from_bytes($data)
+    };
+    ($key:ty, $data:expr, CompressedPoint) => {{
+        ensure!(
+            matches!($data.first(), Some(0x02) | Some(0x03)),
+            JWTError::InvalidPublicKey
+        );
+        <$key>::from_bytes($data)
+    }};
+    ($key:ty, $data:expr, UncompressedPoint) => {{
+        ensure!($data.first() == Some(&0x04), JWTError::InvalidPublicKey);
+        <$key>::from_bytes($data)
+    }};
+    ($key:ty, $data:expr, DER) => {
+        <$key>::from_der($data)
+    };
+    ($key:ty, $data:expr, PEM) => {
+        <$key>::from_pem(std::str::from_utf8($data)?)
+    };
+    ($key:ty, $data:expr, JWK) => {
+        <$key>::from_jwk(&JWK::from_json(std::str::from_utf8($data)?)?)
+    };
+}
+
+macro_rules! export_as {
+    ($key:expr, Raw) => {
+        Exported::into_bytes($key.to_bytes())
+    };
+    ($key:expr, CompressedPoint) => {
+        Exported::into_bytes($key.to_bytes_compressed())
+    };
+    ($key:expr, UncompressedPoint) => {
+        Exported::into_bytes($key.to_bytes_uncompressed())
+    };
+    ($key:expr, DER) => {
+        Exported::into_bytes($key.to_der())
+    };
+    ($key:expr, PEM) => {
+        Exported::into_bytes($key.to_pem())
+    };
+    ($key:expr, JWK) => {
+        Exported::into_bytes($key.to_jwk()?.to_json())
+    };
+}
+
+macro_rules! key_conversion {
+    ($($key:ty: [$($format:ident),*]),* $(,)?) => {
+        $(
+            impl KeyConversion for $key {
+                #[allow(unreachable_patterns)]
+                fn import(data: &[u8], format: KeyFormat) -> Result<Self, Error> {
+                    match format {
+                        $(KeyFormat::$format => import_as!($key, data, $format),)*
+                        _ => bail!(JWTError::UnsupportedKeyFormat),
+                    }
+                }
+
+                #[allow(unreachable_patterns)]
+                fn export(&self, format: KeyFormat) -> Result<Vec<u8>, Error> {
+                    match format {
+                        $(KeyFormat::$format => export_as!(self, $format),)*
+                        _ => bail!(JWTError::UnsupportedKeyFormat),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! shared_key_conversion {
+    ($($key:ty),* $(,)?) => {
+        $(
+            impl KeyConversion for $key {
+                fn import(data: &[u8], format: KeyFormat) -> Result<Self, Error> {
+                    match format {
+                        KeyFormat::Raw => Ok(<$key>::from_bytes(data)),
+                        KeyFormat::JWK => import_as!($key, data, JWK),
+                        _ => bail!(JWTError::UnsupportedKeyFormat),
+                    }
+                }
+
+                fn export(&self, format: KeyFormat) -> Result<Vec<u8>, Error> {
+                    match format {
+                        KeyFormat::Raw => export_as!(self, Raw),
+                        KeyFormat::JWK => export_as!(self, JWK),
+                        _ => bail!(JWTError::UnsupportedKeyFormat),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+shared_key_conversion!(HS256Key, HS384Key, HS512Key);
+
+#[cfg(feature = "sha3")]
+shared_key_conversion!(HS256SHA3Key, HS384SHA3Key, HS512SHA3Key);
+
+#[cfg(feature = "blake2b")]
+shared_key_conversion!(Blake2bKey);
+
+key_conversion!(
+    ES256KeyPair: [Raw, DER, PEM, JWK],
+    ES256PublicKey: [Raw, CompressedPoint, UncompressedPoint, DER, PEM, JWK],
+    ES384KeyPair: [Raw, DER, PEM, JWK],
+    ES384PublicKey: [Raw, CompressedPoint, UncompressedPoint, DER, PEM, JWK],
+    ES512KeyPair: [Raw, DER, PEM, JWK],
+    ES512PublicKey: [Raw, CompressedPoint, UncompressedPoint, DER, PEM, JWK],
+    ES256kKeyPair: [Raw, DER, PEM, JWK],
+    ES256kPublicKey: [Raw, CompressedPoint, UncompressedPoint, DER, PEM, JWK],
+    BP256R1KeyPair: [Raw, DER, PEM, JWK],
+    BP256R1PublicKey: [Raw, CompressedPoint, UncompressedPoint, DER, PEM, JWK],
+    BP384R1KeyPair: [Raw, DER, PEM, JWK],
+    BP384R1PublicKey: [Raw, CompressedPoint, UncompressedPoint, DER, PEM, JWK],
+    BIP340KeyPair: [Raw, DER, PEM, JWK],
+    BIP340PublicKey: [Raw, DER, PEM, JWK],
+    Ed25519KeyPair: [Raw, DER, PEM, JWK],
+    Ed25519PublicKey: [Raw, DER, PEM, JWK],
+    Ed448KeyPair: [Raw, DER, PEM, JWK],
+    Ed448PublicKey: [Raw, DER, PEM, JWK],
+    RS256KeyPair: [DER, PEM, JWK],
+    RS256PublicKey: [DER, PEM, JWK],
+    RS384KeyPair: [DER, PEM, JWK],
+    RS384PublicKey: [DER, PEM, JWK],
+    RS512KeyPair: [DER, PEM, JWK],
+    RS512PublicKey: [DER, PEM, JWK],
+    PS256KeyPair: [DER, PEM, JWK],
+    PS256PublicKey: [DER, PEM, JWK],
+    PS384KeyPair: [DER, PEM, JWK],
+    PS384PublicKey: [DER, PEM, JWK],
+    PS512KeyPair: [DER, PEM, JWK],
+    PS512PublicKey: [DER, PEM, JWK],
+    RSAOAEPKeyPair: [DER, PEM, JWK],
+    RSAOAEPPublicKey: [DER, PEM, JWK],
+    RSAOAEP256KeyPair: [DER, PEM, JWK],
+    RSAOAEP256PublicKey: [DER, PEM, JWK],
+    ECDHP256KeyPair: [Raw, DER, PEM, JWK],
+    ECDHP256PublicKey: [Raw, CompressedPoint, UncompressedPoint, DER, PEM, JWK],
+    X25519KeyPair: [Raw, DER, PEM, JWK],
+    X25519PublicKey: [Raw, DER, PEM, JWK],
+    X448KeyPair: [Raw, DER, PEM, JWK],
+    X448PublicKey: [Raw, DER, PEM, JWK],
+);
+
+#[cfg(feature = "pq")]
+key_conversion!(
+    MLDSA44KeyPair: [Raw, DER, PEM, JWK],
+    MLDSA44PublicKey: [Raw, DER, PEM, JWK],
+    MLDSA65KeyPair: [Raw, DER, PEM, JWK],
+    MLDSA65PublicKey: [Raw, DER, PEM, JWK],
+    MLDSA65Ed25519KeyPair: [Raw, DER, PEM, JWK],
+    MLDSA65Ed25519PublicKey: [Raw, DER, PEM, JWK],
+);
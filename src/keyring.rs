@@ -0,0 +1,293 @@
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+use zeroize::Zeroize;
+
+use crate::algorithms::*;
+use crate::claims::*;
+use crate::common::*;
+use crate::error::*;
+use crate::jwk::*;
+use crate::token::*;
+
+type SignFn = Box<dyn Fn(JWTClaims<serde_json::Value>) -> Result<String, Error> + Send + Sync>;
+
+struct KeyRingEntry {
+    jwk: JWK,
+    signer: SignFn,
+    verifier: Box<dyn KeyLike + Send + Sync>,
+}
+
+impl Drop for KeyRingEntry {
+    fn drop(&mut self) {
+        let jwk = &mut self.jwk;
+        for member in [
+            &mut jwk.d,
+            &mut jwk.p,
+            &mut jwk.q,
+            &mut jwk.dp,
+            &mut jwk.dq,
+            &mut jwk.qi,
+            &mut jwk.k,
+            &mut jwk.private_key,
+        ] {
+            member.zeroize();
+        }
+    }
+}
+
+impl KeyRingEntry {
+    fn key_id(&self) -> &str {
+        self.jwk.key_id.as_deref().unwrap_or_default()
+    }
+
+    fn from_jwk(jwk: JWK) -> Result<Self, Error> {
+        ensure!(jwk.key_id.is_some(), JWTError::MissingJWTKeyIdentifier);
+        let algorithm = jwk.algorithm.as_deref().ok_or(JWTError::InvalidJWK)?;
+
+        let (signer, verifier): (SignFn, Box<dyn KeyLike + Send + Sync>) = crate::with_shared_key_algorithm!(
+            algorithm,
+            |Key| {
+                let key = Key::from_jwk(&jwk)?;
+                let verifier = key.clone();
+                (
+                    Box::new(move |claims| key.authenticate(claims)),
+                    Box::new(verifier),
+                )
+            },
+            crate::with_key_pair_algorithm!(
+                algorithm,
+                jwk.curve.as_deref(),
+                |KeyPair, PublicKey| {
+                    let key_pair = KeyPair::from_jwk(&jwk)?;
+                    let verifier = key_pair.public_key();
+                    (
+                        Box::new(move |claims| key_pair.sign(claims)),
+                        Box::new(verifier),
+                    )
+                },
+                bail!(JWTError::AlgorithmMismatch)
+            )
+        );
+        Ok(KeyRingEntry {
+            jwk,
+            signer,
+            verifier,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct KeyRingDocument {
+    #[serde(rename = "current", default, skip_serializing_if = "Option::is_none")]
+    current_key_id: Option<String>,
+    keys: Vec<JWK>,
+}
+
+/// A set of named signing keys, with a current key used to create new
+/// tokens, and previous keys that are still accepted for verification.
+///
+/// Keys are added as JWKs that include the private key, a key identifier and
+/// an algorithm, such as the ones returned by the `to_jwk()` function of key
+/// pairs and shared keys.
+///
+/// A key ring can be serialized, including all the private keys, in order to
+/// be stored and shared between services.
+#[derive(Default)]
+pub struct KeyRing {
+    keys: Vec<KeyRingEntry>,
+    current_key_id: Option<String>,
+}
+
+impl KeyRing {
+    /// Create an empty key ring
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a key, only used for verification until it becomes the current key
+    pub fn add_key(&mut self, jwk: JWK) -> Result<(), Error> {
+        let entry = KeyRingEntry::from_jwk(jwk)?;
+        ensure!(
+            self.entry(entry.key_id()).is_none(),
+            JWTError::DuplicateKeyIdentifier
+        );
+        self.keys.push(entry);
+        Ok(())
+    }
+
+    /// Add a key, and make it the current key. The previous current key is
+    /// retained for verification.
+    pub fn rotate(&mut self, jwk: JWK) -> Result<(), Error> {
+        let key_id = jwk.key_id.clone();
+        self.add_key(jwk)?;
+        self.current_key_id = key_id;
+        Ok(())
+    }
+
+    /// Use the key with the given identifier to create new tokens
+    pub fn set_current_key(&mut self, key_id: &str) -> Result<(), Error> {
+        ensure!(self.entry(key_id).is_some(), JWTError::KeyNotInKeyRing);
+        self.current_key_id = Some(key_id.to_string());
+        Ok(())
+    }
+
+    /// Remove a key that is not the current key
+    pub fn remove_key(&mut self, key_id: &str) -> Result<(), Error> {
+        ensure!(
+            self.current_key_id.as_deref() != Some(key_id),
+            JWTError::CurrentKeyInUse
+        );
+        let len = self.keys.len();
+        self.keys.retain(|entry| entry.key_id() != key_id);
+        ensure!(self.keys.len() < len, JWTError::KeyNotInKeyRing);
+        Ok(())
+    }
+
+    /// The identifier of the current key, if any
+    pub fn current_key_id(&self) -> Option<&str> {
+        self.current_key_id.as_deref()
+    }
+
+    /// The identifiers of all the keys, in the order they were added
+    pub fn key_ids(&self) -> impl Iterator<Item = &str> {
+        self.keys.iter().map(|entry| entry.key_id())
+    }
+
+    /// The number of keys
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Return `true` if the key ring doesn't contain any keys
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    fn entry(&self, key_id: &str) -> Option<&KeyRingEntry> {
+        self.keys.iter().find(|entry| entry.key_id() == key_id)
+    }
+
+    /// Sign or authenticate a set of claims using the current key
+    pub fn sign<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+    ) -> Result<String, Error> {
+        let current_key_id = self
+            .current_key_id
+            .as_deref()
+            .ok_or(JWTError::NoCurrentKey)?;
+        let entry = self
+            .entry(current_key_id)
+            .ok_or(JWTError::KeyNotInKeyRing)?;
+        let claims = claims.map_custom_claims(serde_json::to_value)?;
+        (entry.signer)(claims)
+    }
+
+    /// Verify a token using any of the keys, and validate its claims
+    ///
+    /// The key is selected according to the algorithm and the key identifier
+    /// of the token.
+    pub fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        let keys: Vec<&dyn KeyLike> = self
+            .keys
+            .iter()
+            .map(|entry| entry.verifier.as_ref() as &dyn KeyLike)
+            .collect();
+        Token::verify_with_keys(&keys, token, options)
+    }
+
+    /// Verify a token using any of the keys like `verify_token()`, but report
+    /// every failed claims check instead of stopping at the first one
+    pub fn verify_token_full_report<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<VerificationReport<CustomClaims>, Error> {
+        let keys: Vec<&dyn KeyLike> = self
+            .keys
+            .iter()
+            .map(|entry| entry.verifier.as_ref() as &dyn KeyLike)
+            .collect();
+        Token::verify_with_keys_full_report(&keys, token, options)
+    }
+
+    /// Verify a token like `verify_token()`, and return the whole payload as
+    /// a JSON value, without a compile-time schema
+    pub fn verify_token_untyped(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<serde_json::Value, Error> {
+        let claims = self.verify_token::<NoCustomClaims>(token, options)?;
+        Ok(serde_json::to_value(claims)?)
+    }
+
+    /// The public keys, as a key set that can be published
+    ///
+    /// Shared keys are not included.
+    pub fn public_key_set(&self) -> JWKSet {
+        let keys = self
+            .keys
+            .iter()
+            .filter(|entry| entry.jwk.key_type != "oct")
+            .map(|entry| JWK {
+                d: None,
+                p: None,
+                q: None,
+                dp: None,
+                dq: None,
+                qi: None,
+                private_key: None,
+                key_operations: None,
+                ..entry.jwk.clone()
+            })
+            .collect();
+        JWKSet { keys }
+    }
+
+    /// Parse a key ring from its JSON representation
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let document: KeyRingDocument =
+            serde_json::from_str(json).map_err(|_| JWTError::InvalidJWK)?;
+        Self::from_document(document)
+    }
+
+    /// Serialize the key ring as JSON, including the private keys
+    pub fn to_json(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string(&self.to_document())?)
+    }
+
+    fn from_document(document: KeyRingDocument) -> Result<Self, Error> {
+        let mut key_ring = KeyRing::new();
+        for jwk in document.keys {
+            key_ring.add_key(jwk)?;
+        }
+        if let Some(current_key_id) = &document.current_key_id {
+            key_ring.set_current_key(current_key_id)?;
+        }
+        Ok(key_ring)
+    }
+
+    fn to_document(&self) -> KeyRingDocument {
+        KeyRingDocument {
+            current_key_id: self.current_key_id.clone(),
+            keys: self.keys.iter().map(|entry| entry.jwk.clone()).collect(),
+        }
+    }
+}
+
+impl Serialize for KeyRing {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_document().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyRing {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let document = KeyRingDocument::deserialize(deserializer)?;
+        KeyRing::from_document(document).map_err(serde::de::Error::custom)
+    }
+}
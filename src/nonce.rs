@@ -0,0 +1,67 @@
+use coarsetime::Clock;
+use ct_codecs::{Base64UrlSafeNoPadding, Encoder, Hex};
+use rand::RngCore;
+
+/// A generator of unique values, for nonces and JWT identifiers (`jti` claim)
+pub trait NonceProvider: Send + Sync {
+    /// Generate a new value
+    fn generate(&self) -> String;
+}
+
+/// 24 random bytes, base64url-encoded
+///
+/// This is the default for nonces.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RandomNonceProvider;
+
+impl NonceProvider for RandomNonceProvider {
+    fn generate(&self) -> String {
+        let mut raw_nonce = [0u8; 24];
+        rand::thread_rng().fill_bytes(&mut raw_nonce);
+        Base64UrlSafeNoPadding::encode_to_string(raw_nonce).unwrap()
+    }
+}
+
+/// Random UUIDs (version 4)
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UUIDv4NonceProvider;
+
+impl NonceProvider for UUIDv4NonceProvider {
+    fn generate(&self) -> String {
+        let mut uuid = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut uuid);
+        format_uuid(uuid, 4)
+    }
+}
+
+/// Time-ordered UUIDs (version 7)
+///
+/// The first 48 bits are the current time in milliseconds, so that values
+/// sort by creation time, with a millisecond resolution. The remaining bits
+/// are random.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UUIDv7NonceProvider;
+
+impl NonceProvider for UUIDv7NonceProvider {
+    fn generate(&self) -> String {
+        let mut uuid = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut uuid[6..]);
+        let now_ms = Clock::now_since_epoch().as_millis();
+        uuid[..6].copy_from_slice(&now_ms.to_be_bytes()[2..]);
+        format_uuid(uuid, 7)
+    }
+}
+
+fn format_uuid(mut uuid: [u8; 16], version: u8) -> String {
+    uuid[6] = (uuid[6] & 0x0f) | (version << 4);
+    uuid[8] = (uuid[8] & 0x3f) | 0x80;
+    let hex = Hex::encode_to_string(uuid).unwrap();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
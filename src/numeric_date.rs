@@ -0,0 +1,199 @@
+use std::convert::TryFrom;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use coarsetime::{Duration, UnixTimeStamp};
+
+use crate::error::*;
+
+/// The latest timestamp that can be represented, in seconds since the Unix
+/// epoch
+const MAX_TIMESTAMP_SECS: u64 = u32::MAX as u64;
+
+/// Conversions between `UnixTimeStamp`, used for the time claims of a token,
+/// and `std::time::SystemTime`, as well as `chrono::DateTime<Utc>` and
+/// `time::OffsetDateTime` with the `chrono` and `time` features
+///
+/// Sub-second precision is kept by the conversions, but timestamps are
+/// serialized in tokens as whole seconds, rounded down. Durations can be
+/// converted from and to `std::time::Duration` with `From`/`Into`.
+pub trait NumericDate: Sized {
+    /// Convert a system time, that must not be earlier than the Unix epoch
+    fn from_system_time(time: SystemTime) -> Result<Self, Error>;
+
+    /// Convert to a system time
+    fn to_system_time(&self) -> SystemTime;
+
+    /// Convert a `chrono` date-time, that must not be earlier than the Unix
+    /// epoch
+    #[cfg(feature = "chrono")]
+    fn from_chrono(date_time: chrono::DateTime<chrono::Utc>) -> Result<Self, Error> {
+        let secs =
+            u64::try_from(date_time.timestamp()).map_err(|_| JWTError::TimestampOutOfRange)?;
+        let since_epoch = std::time::Duration::new(secs, date_time.timestamp_subsec_nanos());
+        Self::from_system_time(UNIX_EPOCH + since_epoch)
+    }
+
+    /// Convert to a `chrono` date-time
+    #[cfg(feature = "chrono")]
+    fn to_chrono(&self) -> Result<chrono::DateTime<chrono::Utc>, Error> {
+        let since_epoch = self
+            .to_system_time()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| JWTError::TimestampOutOfRange)?;
+        let secs =
+            i64::try_from(since_epoch.as_secs()).map_err(|_| JWTError::TimestampOutOfRange)?;
+        let date_time = chrono::DateTime::from_timestamp(secs, since_epoch.subsec_nanos())
+            .ok_or(JWTError::TimestampOutOfRange)?;
+        Ok(date_time)
+    }
+
+    /// Convert a `time` date-time, that must not be earlier than the Unix
+    /// epoch
+    #[cfg(feature = "time")]
+    fn from_offset_date_time(date_time: time::OffsetDateTime) -> Result<Self, Error> {
+        let nanos = u128::try_from(date_time.unix_timestamp_nanos())
+            .map_err(|_| JWTError::TimestampOutOfRange)?;
+        let since_epoch = std::time::Duration::new(
+            (nanos / 1_000_000_000) as u64,
+            (nanos % 1_000_000_000) as u32,
+        );
+        Self::from_system_time(UNIX_EPOCH + since_epoch)
+    }
+
+    /// Convert to a `time` date-time, in UTC
+    #[cfg(feature = "time")]
+    fn to_offset_date_time(&self) -> Result<time::OffsetDateTime, Error> {
+        let since_epoch = self
+            .to_system_time()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| JWTError::TimestampOutOfRange)?;
+        let nanos =
+            i128::try_from(since_epoch.as_nanos()).map_err(|_| JWTError::TimestampOutOfRange)?;
+        let date_time = time::OffsetDateTime::from_unix_timestamp_nanos(nanos)
+            .map_err(|_| JWTError::TimestampOutOfRange)?;
+        Ok(date_time)
+    }
+}
+
+impl NumericDate for UnixTimeStamp {
+    fn from_system_time(time: SystemTime) -> Result<Self, Error> {
+        let since_epoch = time
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| JWTError::TimestampOutOfRange)?;
+        ensure!(
+            since_epoch.as_secs() <= MAX_TIMESTAMP_SECS,
+            JWTError::TimestampOutOfRange
+        );
+        Ok(Duration::from(since_epoch))
+    }
+
+    fn to_system_time(&self) -> SystemTime {
+        UNIX_EPOCH + std::time::Duration::from(*self)
+    }
+}
+
+/// Format a timestamp as an RFC 3339 date-time in UTC, with a precision of
+/// one second (`1970-01-01T00:00:00Z`)
+pub(crate) fn to_rfc3339(timestamp: UnixTimeStamp) -> String {
+    let secs = timestamp.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let secs_of_day = secs % 86400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
+
+/// Parse an RFC 3339 date-time, ignoring fractions of seconds
+pub(crate) fn from_rfc3339(date_time: &str) -> Result<UnixTimeStamp, Error> {
+    let bytes = date_time.as_bytes();
+    ensure!(bytes.len() >= 20, JWTError::InvalidDateTime);
+    let number = |range: std::ops::Range<usize>| -> Result<i64, Error> {
+        let digits = &bytes[range];
+        ensure!(
+            digits.iter().all(u8::is_ascii_digit),
+            JWTError::InvalidDateTime
+        );
+        Ok(digits
+            .iter()
+            .fold(0, |n, digit| n * 10 + (digit - b'0') as i64))
+    };
+    ensure!(
+        bytes[4] == b'-'
+            && bytes[7] == b'-'
+            && bytes[10].eq_ignore_ascii_case(&b'T')
+            && bytes[13] == b':'
+            && bytes[16] == b':',
+        JWTError::InvalidDateTime
+    );
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    ensure!(
+        (1..=12).contains(&month)
+            && (1..=31).contains(&day)
+            && hour < 24
+            && minute < 60
+            && second <= 60,
+        JWTError::InvalidDateTime
+    );
+    let mut offset_start = 19;
+    if bytes[offset_start] == b'.' {
+        offset_start += 1;
+        while offset_start < bytes.len() && bytes[offset_start].is_ascii_digit() {
+            offset_start += 1;
+        }
+    }
+    let offset = match &bytes[offset_start..] {
+        [b'Z' | b'z'] => 0,
+        [sign @ (b'+' | b'-'), _, _, b':', _, _] => {
+            let offset = number(offset_start + 1..offset_start + 3)? * 3600
+                + number(offset_start + 4..offset_start + 6)? * 60;
+            if *sign == b'+' {
+                offset
+            } else {
+                -offset
+            }
+        }
+        _ => bail!(JWTError::InvalidDateTime),
+    };
+    let secs =
+        days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second - offset;
+    let secs = u64::try_from(secs).map_err(|_| JWTError::TimestampOutOfRange)?;
+    ensure!(secs <= MAX_TIMESTAMP_SECS, JWTError::TimestampOutOfRange);
+    Ok(UnixTimeStamp::from_secs(secs))
+}
+
+// Conversions between days since the Unix epoch and dates of the proleptic
+// Gregorian calendar, from http://howardhinnant.github.io/date_algorithms.html
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
@@ -0,0 +1,358 @@
+use std::collections::HashSet;
+
+use coarsetime::{Duration, UnixTimeStamp};
+use ct_codecs::{Base64UrlSafeNoPadding, Decoder, Encoder};
+use hmac_sha512::sha384 as hmac_sha384;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::claims::*;
+use crate::common::*;
+use crate::error::*;
+use crate::serde_additions;
+use crate::token::*;
+
+/// Claims of OpenID Connect ID tokens, in addition to the standard JWT claims
+///
+/// The nonce is a standard claim (`JWTClaims::nonce`). Application-defined
+/// claims can still be added, as `OIDCClaims<CustomClaims>`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OIDCClaims<CustomClaims = NoCustomClaims> {
+    /// Time when the end-user authentication occurred
+    #[serde(
+        rename = "auth_time",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "self::serde_additions::unix_timestamp"
+    )]
+    pub auth_time: Option<UnixTimeStamp>,
+
+    /// Authentication context class reference
+    #[serde(rename = "acr", default, skip_serializing_if = "Option::is_none")]
+    pub authentication_context_class: Option<String>,
+
+    /// Authentication methods references
+    #[serde(rename = "amr", default, skip_serializing_if = "Option::is_none")]
+    pub authentication_methods: Option<Vec<String>>,
+
+    /// Authorized party - The client the token was issued to
+    #[serde(rename = "azp", default, skip_serializing_if = "Option::is_none")]
+    pub authorized_party: Option<String>,
+
+    /// Access token hash, binding the ID token to an access token
+    #[serde(rename = "at_hash", default, skip_serializing_if = "Option::is_none")]
+    pub access_token_hash: Option<String>,
+
+    /// Code hash, binding the ID token to an authorization code
+    #[serde(rename = "c_hash", default, skip_serializing_if = "Option::is_none")]
+    pub code_hash: Option<String>,
+
+    /// Custom (application-defined) claims
+    #[serde(flatten)]
+    pub custom: CustomClaims,
+}
+
+impl OIDCClaims<NoCustomClaims> {
+    /// Create an empty set of OpenID Connect claims
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<CustomClaims> OIDCClaims<CustomClaims> {
+    /// Create OpenID Connect claims, along with application-defined claims
+    pub fn with_custom_claims(custom: CustomClaims) -> Self {
+        OIDCClaims {
+            auth_time: None,
+            authentication_context_class: None,
+            authentication_methods: None,
+            authorized_party: None,
+            access_token_hash: None,
+            code_hash: None,
+            custom,
+        }
+    }
+
+    /// Set the time when the end-user authentication occurred
+    pub fn with_auth_time(mut self, auth_time: UnixTimeStamp) -> Self {
+        self.auth_time = Some(auth_time);
+        self
+    }
+
+    /// Set the authentication context class reference
+    pub fn with_acr(mut self, acr: impl ToString) -> Self {
+        self.authentication_context_class = Some(acr.to_string());
+        self
+    }
+
+    /// Set the authentication methods references
+    pub fn with_amr(mut self, amr: impl IntoIterator<Item = impl ToString>) -> Self {
+        self.authentication_methods = Some(amr.into_iter().map(|x| x.to_string()).collect());
+        self
+    }
+
+    /// Set the authorized party
+    pub fn with_authorized_party(mut self, authorized_party: impl ToString) -> Self {
+        self.authorized_party = Some(authorized_party.to_string());
+        self
+    }
+
+    /// Bind the ID token to an access token, by setting the `at_hash` claim
+    /// for the algorithm the ID token will be signed with
+    pub fn with_access_token(mut self, access_token: &str, algorithm: &str) -> Result<Self, Error> {
+        self.access_token_hash = Some(token_hash(access_token, algorithm)?);
+        Ok(self)
+    }
+
+    /// Bind the ID token to an authorization code, by setting the `c_hash`
+    /// claim for the algorithm the ID token will be signed with
+    pub fn with_code(mut self, code: &str, algorithm: &str) -> Result<Self, Error> {
+        self.code_hash = Some(token_hash(code, algorithm)?);
+        Ok(self)
+    }
+
+    /// Check that the `at_hash` claim matches an access token, given the
+    /// algorithm the ID token was signed with
+    pub fn verify_access_token(&self, access_token: &str, algorithm: &str) -> Result<(), Error> {
+        let access_token_hash = self
+            .access_token_hash
+            .as_deref()
+            .ok_or(JWTError::RequiredTokenHashMissing)?;
+        verify_token_hash(access_token, algorithm, access_token_hash)
+    }
+
+    /// Check that the `c_hash` claim matches an authorization code, given the
+    /// algorithm the ID token was signed with
+    pub fn verify_code(&self, code: &str, algorithm: &str) -> Result<(), Error> {
+        let code_hash = self
+            .code_hash
+            .as_deref()
+            .ok_or(JWTError::RequiredTokenHashMissing)?;
+        verify_token_hash(code, algorithm, code_hash)
+    }
+}
+
+/// Compute the `at_hash` or `c_hash` value of an access token or an
+/// authorization code, for an ID token signed with `algorithm`
+///
+/// This is the left half of the hash of the value, using the hash function
+/// of the algorithm, base64url-encoded.
+pub fn token_hash(value: &str, algorithm: &str) -> Result<String, Error> {
+    let hash = match algorithm {
+        "HS256" | "RS256" | "PS256" | "ES256" | "ES256K" | "BP256R1" | "BIP340" => {
+            hmac_sha256::Hash::hash(value.as_bytes()).to_vec()
+        }
+        "HS384" | "RS384" | "PS384" | "ES384" | "BP384R1" => {
+            hmac_sha384::Hash::hash(value.as_bytes()).to_vec()
+        }
+        "HS512" | "RS512" | "PS512" | "ES512" | "EdDSA" => {
+            hmac_sha512::Hash::hash(value.as_bytes()).to_vec()
+        }
+        _ => bail!(JWTError::UnsupportedTokenHashAlgorithm),
+    };
+    Ok(Base64UrlSafeNoPadding::encode_to_string(
+        &hash[..hash.len() / 2],
+    )?)
+}
+
+/// Check that an `at_hash` or `c_hash` value matches an access token or an
+/// authorization code, for an ID token signed with `algorithm`
+pub fn verify_token_hash(value: &str, algorithm: &str, expected_hash: &str) -> Result<(), Error> {
+    let hash = Base64UrlSafeNoPadding::decode_to_vec(token_hash(value, algorithm)?, None)?;
+    let expected_hash = Base64UrlSafeNoPadding::decode_to_vec(expected_hash, None)
+        .map_err(|_| JWTError::TokenHashMismatch)?;
+    ensure!(hash == expected_hash, JWTError::TokenHashMismatch);
+    Ok(())
+}
+
+/// The algorithm ID tokens are expected to be signed with if none was
+/// registered by the client, according to OpenID Connect Core
+pub const DEFAULT_ID_TOKEN_ALGORITHM: &str = "RS256";
+
+/// Verification of ID tokens, as required by OpenID Connect Core, for a
+/// given issuer and client
+///
+/// The issuer must match exactly, the client must be one of the audiences
+/// and, if present, the authorized party (`azp` claim), and the expiration
+/// and creation dates are required. The token must be signed with one of the
+/// allowed algorithms, `RS256` by default.
+#[derive(Debug, Clone)]
+pub struct IdTokenVerifier {
+    issuer: String,
+    client_id: String,
+    nonce: Option<String>,
+    allowed_algorithms: HashSet<String>,
+    max_auth_age: Option<Duration>,
+    allowed_acr_values: Option<HashSet<String>>,
+    access_token: Option<String>,
+    options: VerificationOptions,
+}
+
+impl IdTokenVerifier {
+    /// Verify ID tokens issued by `issuer` to the client `client_id`
+    pub fn new(issuer: impl ToString, client_id: impl ToString) -> Self {
+        IdTokenVerifier {
+            issuer: issuer.to_string(),
+            client_id: client_id.to_string(),
+            nonce: None,
+            allowed_algorithms: HashSet::from([DEFAULT_ID_TOKEN_ALGORITHM.to_string()]),
+            max_auth_age: None,
+            allowed_acr_values: None,
+            access_token: None,
+            options: VerificationOptions::default(),
+        }
+    }
+
+    /// Require the nonce sent in the authentication request
+    pub fn with_nonce(mut self, nonce: impl ToString) -> Self {
+        self.nonce = Some(nonce.to_string());
+        self
+    }
+
+    /// Set the algorithms ID tokens can be signed with, such as the one
+    /// registered by the client (`id_token_signed_response_alg`)
+    pub fn with_allowed_algorithms(mut self, algorithms: &[impl ToString]) -> Self {
+        self.allowed_algorithms = algorithms.iter().map(|x| x.to_string()).collect();
+        self
+    }
+
+    /// Reject tokens whose end-user authentication occurred more than
+    /// `max_auth_age` ago, for authentication requests with a `max_age`
+    /// parameter
+    pub fn with_max_auth_age(mut self, max_auth_age: Duration) -> Self {
+        self.max_auth_age = Some(max_auth_age);
+        self
+    }
+
+    /// Require the authentication context class reference to be one of the
+    /// given values
+    pub fn with_allowed_acr_values(mut self, acr_values: &[impl ToString]) -> Self {
+        self.allowed_acr_values = Some(acr_values.iter().map(|x| x.to_string()).collect());
+        self
+    }
+
+    /// Require the token to be bound to the access token returned along with
+    /// it (`at_hash` claim)
+    pub fn with_access_token(mut self, access_token: impl ToString) -> Self {
+        self.access_token = Some(access_token.to_string());
+        self
+    }
+
+    /// Start from the given verification options, for example to set a time
+    /// tolerance or a time source, instead of the default ones
+    ///
+    /// The options enforced by the verifier take precedence.
+    pub fn with_verification_options(mut self, options: VerificationOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// The verification options enforcing the checks of the verifier, except
+    /// for the access token hash
+    ///
+    /// This can be used to verify ID tokens with a key set, followed by
+    /// `check_access_token_hash()`.
+    pub fn verification_options(&self) -> VerificationOptions {
+        VerificationOptions {
+            require_expiration: true,
+            require_issued_at: true,
+            require_issuer: true,
+            require_audience: true,
+            required_nonce: self.nonce.clone(),
+            allowed_issuers: Some(HashSet::from([self.issuer.clone()])),
+            issuer_normalization: None,
+            allowed_audiences: Some(HashSet::from([self.client_id.clone()])),
+            allowed_audience_patterns: None,
+            allowed_algorithms: Some(self.allowed_algorithms.clone()),
+            max_auth_age: self.max_auth_age,
+            allowed_acr_values: self.allowed_acr_values.clone(),
+            required_authorized_party: Some(self.client_id.clone()),
+            ..self.options.clone()
+        }
+    }
+
+    /// Verify an ID token using any of the given keys, and validate its
+    /// claims
+    pub fn verify<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        keys: &[&dyn KeyLike],
+        id_token: &str,
+    ) -> Result<JWTClaims<OIDCClaims<CustomClaims>>, Error> {
+        let claims = Token::verify_with_keys::<OIDCClaims<CustomClaims>>(
+            keys,
+            id_token,
+            Some(self.verification_options()),
+        )?;
+        self.check_access_token_hash(id_token, &claims)?;
+        Ok(claims)
+    }
+
+    /// Check that a verified ID token is bound to the access token, if one
+    /// was set
+    pub fn check_access_token_hash<CustomClaims>(
+        &self,
+        id_token: &str,
+        claims: &JWTClaims<OIDCClaims<CustomClaims>>,
+    ) -> Result<(), Error> {
+        if let Some(access_token) = &self.access_token {
+            let metadata = Token::decode_metadata_with_options(id_token, &self.options)?;
+            claims
+                .custom
+                .verify_access_token(access_token, metadata.algorithm())?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn has_oidc_checks(options: &VerificationOptions) -> bool {
+    options.max_auth_age.is_some()
+        || options.allowed_acr_values.is_some()
+        || options.required_authorized_party.is_some()
+}
+
+pub(crate) fn check_oidc_claims<CustomClaims>(
+    options: &VerificationOptions,
+    claims: &OIDCClaims<CustomClaims>,
+    audiences: Option<&Audiences>,
+) -> Result<(), Error> {
+    if let Some(max_auth_age) = options
+        .max_auth_age
+        .filter(|_| !options.time_checks_disabled)
+    {
+        let auth_time = claims.auth_time.ok_or(JWTError::RequiredAuthTimeMissing)?;
+        let now = options.now();
+        let time_tolerance = options.time_tolerance.unwrap_or_default();
+        ensure!(
+            now <= auth_time || now - auth_time <= max_auth_age + time_tolerance,
+            JWTError::AuthenticationTooOld
+        );
+    }
+    if let Some(allowed_acr_values) = &options.allowed_acr_values {
+        let acr = claims
+            .authentication_context_class
+            .as_ref()
+            .ok_or(JWTError::RequiredAcrMissing)?;
+        ensure!(
+            allowed_acr_values.contains(acr),
+            JWTError::RequiredAcrMismatch
+        );
+    }
+    if let Some(required_authorized_party) = &options.required_authorized_party {
+        match &claims.authorized_party {
+            Some(authorized_party) => ensure!(
+                authorized_party == required_authorized_party,
+                JWTError::RequiredAuthorizedPartyMismatch
+            ),
+            None => {
+                let multiple_audiences = match audiences {
+                    Some(Audiences::AsSet(audiences)) => audiences.len() > 1,
+                    _ => false,
+                };
+                ensure!(
+                    !multiple_audiences,
+                    JWTError::RequiredAuthorizedPartyMissing
+                );
+            }
+        }
+    }
+    Ok(())
+}
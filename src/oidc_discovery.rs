@@ -0,0 +1,171 @@
+use std::collections::HashSet;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::claims::*;
+use crate::common::*;
+use crate::error::*;
+use crate::oidc::*;
+use crate::remote_jwks::*;
+
+/// The path of the OpenID Connect discovery document, relative to the issuer
+pub const OPENID_CONFIGURATION_PATH: &str = "/.well-known/openid-configuration";
+
+/// The metadata of an OpenID provider, as published in its discovery
+/// document
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProviderMetadata {
+    /// The issuer identifier, that must match the `iss` claim of its tokens
+    pub issuer: String,
+
+    /// The URL of the key set of the provider
+    pub jwks_uri: String,
+
+    /// The algorithms ID tokens can be signed with
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub id_token_signing_alg_values_supported: Vec<String>,
+
+    /// The URL of the authorization endpoint
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authorization_endpoint: Option<String>,
+
+    /// The URL of the token endpoint
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_endpoint: Option<String>,
+
+    /// The URL of the userinfo endpoint
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub userinfo_endpoint: Option<String>,
+
+    /// Other metadata
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ProviderMetadata {
+    /// Parse a discovery document
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// The URL of the discovery document of `issuer`
+    pub fn discovery_url(issuer: &str) -> String {
+        format!(
+            "{}{}",
+            issuer.trim_end_matches('/'),
+            OPENID_CONFIGURATION_PATH
+        )
+    }
+
+    /// The algorithms ID tokens can be signed with, excluding `none`, or
+    /// `RS256` if the provider doesn't list any
+    pub fn id_token_algorithms(&self) -> HashSet<String> {
+        let algorithms: HashSet<String> = self
+            .id_token_signing_alg_values_supported
+            .iter()
+            .filter(|alg| *alg != "none")
+            .cloned()
+            .collect();
+        if algorithms.is_empty() {
+            return HashSet::from([DEFAULT_ID_TOKEN_ALGORITHM.to_string()]);
+        }
+        algorithms
+    }
+}
+
+/// An OpenID provider, configured from its discovery document, along with its
+/// remote key set
+pub struct OIDCProvider<Fetcher: JWKSFetcher> {
+    metadata: ProviderMetadata,
+    key_set: RemoteJWKSet<Fetcher>,
+}
+
+impl<Fetcher: JWKSFetcher> OIDCProvider<Fetcher> {
+    /// Retrieve the discovery document of `issuer` using `fetcher`, and set up
+    /// its remote key set
+    ///
+    /// The issuer of the document must match `issuer` exactly, as required by
+    /// OpenID Connect Discovery.
+    pub async fn discover(issuer: &str, fetcher: Fetcher) -> Result<Self, Error> {
+        let response = fetcher
+            .fetch(&ProviderMetadata::discovery_url(issuer))
+            .await?;
+        let metadata = ProviderMetadata::from_json(&response.body)?;
+        Self::from_metadata(issuer, metadata, fetcher)
+    }
+
+    /// Set up a provider from a discovery document that has already been
+    /// retrieved
+    pub fn from_metadata(
+        issuer: &str,
+        metadata: ProviderMetadata,
+        fetcher: Fetcher,
+    ) -> Result<Self, Error> {
+        ensure!(
+            metadata.issuer == issuer && !metadata.jwks_uri.is_empty(),
+            JWTError::InvalidProviderMetadata
+        );
+        let key_set = RemoteJWKSet::new(&metadata.jwks_uri, fetcher);
+        Ok(OIDCProvider { metadata, key_set })
+    }
+
+    /// Replace the remote key set, for example to change its caching options
+    pub fn with_key_set(mut self, key_set: RemoteJWKSet<Fetcher>) -> Self {
+        self.key_set = key_set;
+        self
+    }
+
+    /// The metadata of the provider
+    pub fn metadata(&self) -> &ProviderMetadata {
+        &self.metadata
+    }
+
+    /// The remote key set of the provider
+    pub fn key_set(&self) -> &RemoteJWKSet<Fetcher> {
+        &self.key_set
+    }
+
+    /// An ID token verifier for the client `client_id`, accepting the
+    /// algorithms supported by the provider
+    pub fn id_token_verifier(&self, client_id: impl ToString) -> IdTokenVerifier {
+        let algorithms: Vec<String> = self.metadata.id_token_algorithms().into_iter().collect();
+        IdTokenVerifier::new(&self.metadata.issuer, client_id).with_allowed_algorithms(&algorithms)
+    }
+
+    /// Verify an ID token with the key set of the provider
+    pub async fn verify_id_token<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        verifier: &IdTokenVerifier,
+        id_token: &str,
+    ) -> Result<JWTClaims<OIDCClaims<CustomClaims>>, Error> {
+        let claims = self
+            .key_set
+            .verify_token::<OIDCClaims<CustomClaims>>(
+                id_token,
+                Some(verifier.verification_options()),
+            )
+            .await?;
+        verifier.check_access_token_hash(id_token, &claims)?;
+        Ok(claims)
+    }
+
+    /// Verify a token issued by the provider with its key set, and validate
+    /// its claims
+    ///
+    /// Unless set in `options`, the issuer must be the provider, and the
+    /// algorithm one of the algorithms it supports for ID tokens.
+    pub async fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        let mut options = options.unwrap_or_default();
+        options
+            .allowed_issuers
+            .get_or_insert_with(|| HashSet::from([self.metadata.issuer.clone()]));
+        options
+            .allowed_algorithms
+            .get_or_insert_with(|| self.metadata.id_token_algorithms());
+        self.key_set.verify_token(token, Some(options)).await
+    }
+}
@@ -0,0 +1,258 @@
+//! Import of keys in the formats used by OpenSSH.
+//!
+//! Private keys are read from `openssh-key-v1` documents
+//! (`BEGIN OPENSSH PRIVATE KEY`), as created by `ssh-keygen`. Keys protected
+//! with a passphrase are decrypted using bcrypt_pbkdf and AES-CTR or AES-CBC.
+//!
+//! Public keys are read from the single-line format of `.pub` and
+//! `authorized_keys` files.
+
+use aes::cipher::{
+    block_padding::NoPadding, consts::U16, BlockCipher, BlockDecrypt, BlockDecryptMut,
+    BlockEncrypt, BlockSizeUser, KeyInit, KeyIvInit, StreamCipher,
+};
+use aes::{Aes128, Aes192, Aes256};
+use ct_codecs::{Base64, Decoder};
+use zeroize::Zeroizing;
+
+use crate::encrypted_pem::pem_decode;
+use crate::error::*;
+
+/// Maximum number of bcrypt_pbkdf rounds accepted when decrypting a key.
+const MAX_BCRYPT_PBKDF_ROUNDS: u32 = 10_000;
+
+const OPENSSH_KEY_MAGIC: &[u8] = b"openssh-key-v1\0";
+const SSH_ED25519: &str = "ssh-ed25519";
+const SSH_RSA: &str = "ssh-rsa";
+
+/// A private key decoded from an OpenSSH document
+pub(crate) enum OpenSSHPrivateKey {
+    /// The 32-byte seed, followed by the 32-byte public key
+    Ed25519(Zeroizing<Vec<u8>>),
+
+    Rsa {
+        n: Vec<u8>,
+        e: Vec<u8>,
+        d: Zeroizing<Vec<u8>>,
+        p: Zeroizing<Vec<u8>>,
+        q: Zeroizing<Vec<u8>>,
+    },
+}
+
+/// A public key decoded from an OpenSSH public key line
+pub(crate) enum OpenSSHPublicKey {
+    Ed25519(Vec<u8>),
+    Rsa { n: Vec<u8>, e: Vec<u8> },
+}
+
+/// Reader for the wire encoding of RFC 4251
+struct SSHReader<'t> {
+    data: &'t [u8],
+}
+
+impl<'t> SSHReader<'t> {
+    fn new(data: &'t [u8]) -> Self {
+        SSHReader { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn read_bytes(&mut self, length: usize) -> Result<&'t [u8], Error> {
+        ensure!(self.data.len() >= length, JWTError::InvalidKeyPair);
+        let (bytes, rest) = self.data.split_at(length);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, Error> {
+        let bytes = self.read_bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn read_string(&mut self) -> Result<&'t [u8], Error> {
+        let length = self.read_u32()? as usize;
+        self.read_bytes(length)
+    }
+
+    /// Read a non-negative multiple precision integer, without leading zeros
+    fn read_mpint(&mut self) -> Result<&'t [u8], Error> {
+        let mpint = self.read_string()?;
+        ensure!(
+            mpint.first().is_none_or(|&x| x & 0x80 == 0),
+            JWTError::InvalidKeyPair
+        );
+        let leading_zeros = mpint.iter().take_while(|&&x| x == 0).count();
+        Ok(&mpint[leading_zeros..])
+    }
+}
+
+/// Parse the public key blob shared by both formats
+fn parse_public_key(blob: &[u8]) -> Result<OpenSSHPublicKey, Error> {
+    let mut reader = SSHReader::new(blob);
+    let key_type = reader.read_string()?;
+    let public_key = if key_type == SSH_ED25519.as_bytes() {
+        OpenSSHPublicKey::Ed25519(reader.read_string()?.to_vec())
+    } else if key_type == SSH_RSA.as_bytes() {
+        let e = reader.read_mpint()?.to_vec();
+        let n = reader.read_mpint()?.to_vec();
+        OpenSSHPublicKey::Rsa { n, e }
+    } else {
+        bail!(JWTError::InvalidKeyPair);
+    };
+    ensure!(reader.is_empty(), JWTError::InvalidPublicKey);
+    Ok(public_key)
+}
+
+/// Decode a public key line, such as the content of an `id_ed25519.pub`
+/// file, or an entry of an `authorized_keys` file
+pub(crate) fn decode_public_key(line: &str) -> Result<OpenSSHPublicKey, Error> {
+    let line = line
+        .lines()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .ok_or(JWTError::InvalidPublicKey)?;
+
+    // `authorized_keys` entries may start with options, and the key type
+    // is followed by the base64-encoded key and an optional comment
+    let mut fields = line.split_whitespace();
+    fields
+        .find(|&field| field == SSH_ED25519 || field == SSH_RSA)
+        .ok_or(JWTError::InvalidPublicKey)?;
+    let encoded = fields.next().ok_or(JWTError::InvalidPublicKey)?;
+    let blob = Base64::decode_to_vec(encoded, None).map_err(|_| JWTError::InvalidPublicKey)?;
+    parse_public_key(&blob).map_err(|_| JWTError::InvalidPublicKey.into())
+}
+
+/// Decode an `openssh-key-v1` private key, decrypting it with `passphrase`
+/// if it is encrypted
+pub(crate) fn decode_private_key(
+    pem: &str,
+    passphrase: Option<&[u8]>,
+) -> Result<OpenSSHPrivateKey, Error> {
+    let document = pem_decode(pem)?;
+    ensure!(
+        document.label == "OPENSSH PRIVATE KEY",
+        JWTError::InvalidKeyPair
+    );
+    let data = document
+        .der
+        .strip_prefix(OPENSSH_KEY_MAGIC)
+        .ok_or(JWTError::InvalidKeyPair)?;
+    let mut reader = SSHReader::new(data);
+    let cipher_name = reader.read_string()?;
+    let kdf_name = reader.read_string()?;
+    let kdf_options = reader.read_string()?;
+    ensure!(reader.read_u32()? == 1, JWTError::InvalidKeyPair);
+    let public_key = parse_public_key(reader.read_string()?)?;
+    let encrypted = reader.read_string()?;
+
+    let private_section = match (cipher_name, kdf_name) {
+        (b"none", b"none") => Zeroizing::new(encrypted.to_vec()),
+        (_, b"bcrypt") => {
+            let passphrase = passphrase.ok_or(JWTError::InvalidKeyPair)?;
+            let mut kdf_options = SSHReader::new(kdf_options);
+            let salt = kdf_options.read_string()?;
+            let rounds = kdf_options.read_u32()?;
+            ensure!(
+                (1..=MAX_BCRYPT_PBKDF_ROUNDS).contains(&rounds),
+                JWTError::UnsupportedKeyEncryption
+            );
+            decrypt(cipher_name, passphrase, salt, rounds, encrypted)?
+        }
+        _ => bail!(JWTError::UnsupportedKeyEncryption),
+    };
+
+    let mut reader = SSHReader::new(&private_section);
+    // Both check values only match if the passphrase was correct
+    ensure!(
+        reader.read_u32()? == reader.read_u32()?,
+        JWTError::InvalidKeyPair
+    );
+    let key_type = reader.read_string()?;
+    let private_key = match public_key {
+        OpenSSHPublicKey::Ed25519(pk) if key_type == SSH_ED25519.as_bytes() => {
+            ensure!(reader.read_string()? == pk, JWTError::InvalidKeyPair);
+            let secret_key = reader.read_string()?;
+            ensure!(
+                secret_key.len() == 64 && secret_key[32..] == pk[..],
+                JWTError::InvalidKeyPair
+            );
+            OpenSSHPrivateKey::Ed25519(Zeroizing::new(secret_key.to_vec()))
+        }
+        OpenSSHPublicKey::Rsa { n, e } if key_type == SSH_RSA.as_bytes() => {
+            ensure!(reader.read_mpint()? == n, JWTError::InvalidKeyPair);
+            ensure!(reader.read_mpint()? == e, JWTError::InvalidKeyPair);
+            let d = Zeroizing::new(reader.read_mpint()?.to_vec());
+            let _iqmp = reader.read_mpint()?;
+            let p = Zeroizing::new(reader.read_mpint()?.to_vec());
+            let q = Zeroizing::new(reader.read_mpint()?.to_vec());
+            OpenSSHPrivateKey::Rsa { n, e, d, p, q }
+        }
+        _ => bail!(JWTError::InvalidKeyPair),
+    };
+    let _comment = reader.read_string()?;
+    ensure!(
+        reader
+            .data
+            .iter()
+            .enumerate()
+            .all(|(i, &x)| x as usize == i + 1),
+        JWTError::InvalidKeyPair
+    );
+    Ok(private_key)
+}
+
+/// Decrypt the private section of a key
+fn decrypt(
+    cipher_name: &[u8],
+    passphrase: &[u8],
+    salt: &[u8],
+    rounds: u32,
+    ciphertext: &[u8],
+) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let key_length = match cipher_name {
+        b"aes128-ctr" | b"aes128-cbc" => 16,
+        b"aes192-ctr" | b"aes192-cbc" => 24,
+        b"aes256-ctr" | b"aes256-cbc" => 32,
+        _ => bail!(JWTError::UnsupportedKeyEncryption),
+    };
+    ensure!(ciphertext.len() % 16 == 0, JWTError::InvalidKeyPair);
+    let mut key_iv = Zeroizing::new(vec![0u8; key_length + 16]);
+    bcrypt_pbkdf::bcrypt_pbkdf(passphrase, salt, rounds, &mut key_iv)
+        .map_err(|_| JWTError::InvalidKeyPair)?;
+    let (key, iv) = key_iv.split_at(key_length);
+    let mut plaintext = Zeroizing::new(ciphertext.to_vec());
+    match (key_length, cipher_name.ends_with(b"-ctr")) {
+        (16, true) => aes_ctr::<Aes128>(key, iv, &mut plaintext),
+        (24, true) => aes_ctr::<Aes192>(key, iv, &mut plaintext),
+        (32, true) => aes_ctr::<Aes256>(key, iv, &mut plaintext),
+        (16, false) => aes_cbc_decrypt::<Aes128>(key, iv, &mut plaintext)?,
+        (24, false) => aes_cbc_decrypt::<Aes192>(key, iv, &mut plaintext)?,
+        _ => aes_cbc_decrypt::<Aes256>(key, iv, &mut plaintext)?,
+    }
+    Ok(plaintext)
+}
+
+/// AES-CTR, with the whole IV used as a big-endian counter
+fn aes_ctr<Cipher: BlockCipher + BlockEncrypt + KeyInit + BlockSizeUser<BlockSize = U16>>(
+    key: &[u8],
+    iv: &[u8],
+    data: &mut [u8],
+) {
+    let mut cipher = ctr::Ctr128BE::<Cipher>::new(key.into(), iv.into());
+    cipher.apply_keystream(data);
+}
+
+/// AES-CBC, without padding
+fn aes_cbc_decrypt<Cipher: BlockCipher + BlockDecrypt + KeyInit>(
+    key: &[u8],
+    iv: &[u8],
+    data: &mut [u8],
+) -> Result<(), Error> {
+    cbc::Decryptor::<Cipher>::new(key.into(), iv.into())
+        .decrypt_padded_mut::<NoPadding>(data)
+        .map_err(|_| JWTError::InvalidKeyPair)?;
+    Ok(())
+}
@@ -0,0 +1,608 @@
+//! Token signing with keys stored in a PKCS#11 module (HSM, smartcard...).
+//!
+//! The module is loaded at runtime, and the private key never leaves the
+//! device.
+
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+use std::os::raw::{c_char, c_uchar, c_ulong, c_void};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError, Weak};
+
+use serde::{de::DeserializeOwned, Serialize};
+use zeroize::Zeroizing;
+
+use crate::claims::*;
+use crate::error::*;
+use crate::jwt_header::*;
+use crate::token::*;
+
+type CkRv = c_ulong;
+type CkUlong = c_ulong;
+type CkSessionHandle = c_ulong;
+type CkObjectHandle = c_ulong;
+type CkSlotId = c_ulong;
+
+const CKR_OK: CkRv = 0x000;
+const CKR_USER_ALREADY_LOGGED_IN: CkRv = 0x100;
+const CKR_CRYPTOKI_ALREADY_INITIALIZED: CkRv = 0x191;
+
+const CKF_OS_LOCKING_OK: CkUlong = 0x2;
+const CKF_SERIAL_SESSION: CkUlong = 0x4;
+const CKU_USER: CkUlong = 1;
+
+const CKA_CLASS: CkUlong = 0x000;
+const CKA_LABEL: CkUlong = 0x003;
+const CKA_ID: CkUlong = 0x102;
+const CKO_PRIVATE_KEY: CkUlong = 3;
+
+const CKM_SHA256_RSA_PKCS: CkUlong = 0x040;
+const CKM_SHA384_RSA_PKCS: CkUlong = 0x041;
+const CKM_SHA512_RSA_PKCS: CkUlong = 0x042;
+const CKM_SHA256_RSA_PKCS_PSS: CkUlong = 0x043;
+const CKM_SHA384_RSA_PKCS_PSS: CkUlong = 0x044;
+const CKM_SHA512_RSA_PKCS_PSS: CkUlong = 0x045;
+const CKM_SHA256: CkUlong = 0x250;
+const CKM_SHA384: CkUlong = 0x260;
+const CKM_SHA512: CkUlong = 0x270;
+const CKM_ECDSA: CkUlong = 0x1041;
+const CKM_EDDSA: CkUlong = 0x1057;
+const CKG_MGF1_SHA256: CkUlong = 0x2;
+const CKG_MGF1_SHA384: CkUlong = 0x3;
+const CKG_MGF1_SHA512: CkUlong = 0x4;
+
+#[repr(C)]
+struct CkVersion {
+    major: c_uchar,
+    minor: c_uchar,
+}
+
+#[repr(C)]
+struct CkCInitializeArgs {
+    create_mutex: *const c_void,
+    destroy_mutex: *const c_void,
+    lock_mutex: *const c_void,
+    unlock_mutex: *const c_void,
+    flags: CkUlong,
+    reserved: *const c_void,
+}
+
+#[repr(C)]
+struct CkAttribute {
+    attribute_type: CkUlong,
+    value: *const c_void,
+    value_len: CkUlong,
+}
+
+#[repr(C)]
+struct CkMechanism {
+    mechanism: CkUlong,
+    parameter: *const c_void,
+    parameter_len: CkUlong,
+}
+
+#[repr(C)]
+struct CkRsaPkcsPssParams {
+    hash_alg: CkUlong,
+    mgf: CkUlong,
+    salt_len: CkUlong,
+}
+
+type Unused = Option<unsafe extern "C" fn()>;
+
+// Layout of CK_FUNCTION_LIST, up to C_Sign
+#[repr(C)]
+struct CkFunctionList {
+    version: CkVersion,
+    c_initialize: Option<unsafe extern "C" fn(*const CkCInitializeArgs) -> CkRv>,
+    c_finalize: Option<unsafe extern "C" fn(*const c_void) -> CkRv>,
+    c_get_info: Unused,
+    c_get_function_list: Unused,
+    c_get_slot_list: Option<unsafe extern "C" fn(c_uchar, *mut CkSlotId, *mut CkUlong) -> CkRv>,
+    c_get_slot_info: Unused,
+    c_get_token_info: Unused,
+    c_get_mechanism_list: Unused,
+    c_get_mechanism_info: Unused,
+    c_init_token: Unused,
+    c_init_pin: Unused,
+    c_set_pin: Unused,
+    c_open_session: Option<
+        unsafe extern "C" fn(
+            CkSlotId,
+            CkUlong,
+            *const c_void,
+            *const c_void,
+            *mut CkSessionHandle,
+        ) -> CkRv,
+    >,
+    c_close_session: Option<unsafe extern "C" fn(CkSessionHandle) -> CkRv>,
+    c_close_all_sessions: Unused,
+    c_get_session_info: Unused,
+    c_get_operation_state: Unused,
+    c_set_operation_state: Unused,
+    c_login:
+        Option<unsafe extern "C" fn(CkSessionHandle, CkUlong, *const c_uchar, CkUlong) -> CkRv>,
+    c_logout: Unused,
+    c_create_object: Unused,
+    c_copy_object: Unused,
+    c_destroy_object: Unused,
+    c_get_object_size: Unused,
+    c_get_attribute_value: Unused,
+    c_set_attribute_value: Unused,
+    c_find_objects_init:
+        Option<unsafe extern "C" fn(CkSessionHandle, *const CkAttribute, CkUlong) -> CkRv>,
+    c_find_objects: Option<
+        unsafe extern "C" fn(CkSessionHandle, *mut CkObjectHandle, CkUlong, *mut CkUlong) -> CkRv,
+    >,
+    c_find_objects_final: Option<unsafe extern "C" fn(CkSessionHandle) -> CkRv>,
+    c_encrypt_init: Unused,
+    c_encrypt: Unused,
+    c_encrypt_update: Unused,
+    c_encrypt_final: Unused,
+    c_decrypt_init: Unused,
+    c_decrypt: Unused,
+    c_decrypt_update: Unused,
+    c_decrypt_final: Unused,
+    c_digest_init: Unused,
+    c_digest: Unused,
+    c_digest_update: Unused,
+    c_digest_key: Unused,
+    c_digest_final: Unused,
+    c_sign_init:
+        Option<unsafe extern "C" fn(CkSessionHandle, *const CkMechanism, CkObjectHandle) -> CkRv>,
+    c_sign: Option<
+        unsafe extern "C" fn(
+            CkSessionHandle,
+            *const c_uchar,
+            CkUlong,
+            *mut c_uchar,
+            *mut CkUlong,
+        ) -> CkRv,
+    >,
+}
+
+/// Signature algorithms supported by `PKCS11Signer`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PKCS11Algorithm {
+    /// RSASSA-PKCS1-v1_5 with SHA-256 (`CKM_SHA256_RSA_PKCS`)
+    RS256,
+    /// RSASSA-PKCS1-v1_5 with SHA-384 (`CKM_SHA384_RSA_PKCS`)
+    RS384,
+    /// RSASSA-PKCS1-v1_5 with SHA-512 (`CKM_SHA512_RSA_PKCS`)
+    RS512,
+    /// RSASSA-PSS with SHA-256 (`CKM_SHA256_RSA_PKCS_PSS`)
+    PS256,
+    /// RSASSA-PSS with SHA-384 (`CKM_SHA384_RSA_PKCS_PSS`)
+    PS384,
+    /// RSASSA-PSS with SHA-512 (`CKM_SHA512_RSA_PKCS_PSS`)
+    PS512,
+    /// ECDSA over P-256 with SHA-256 (`CKM_ECDSA`)
+    ES256,
+    /// ECDSA over P-384 with SHA-384 (`CKM_ECDSA`)
+    ES384,
+    /// Ed25519 (`CKM_EDDSA`)
+    EdDSA,
+}
+
+impl PKCS11Algorithm {
+    /// The JWT algorithm name ("alg")
+    pub fn jwt_alg_name(&self) -> &'static str {
+        match self {
+            PKCS11Algorithm::RS256 => "RS256",
+            PKCS11Algorithm::RS384 => "RS384",
+            PKCS11Algorithm::RS512 => "RS512",
+            PKCS11Algorithm::PS256 => "PS256",
+            PKCS11Algorithm::PS384 => "PS384",
+            PKCS11Algorithm::PS512 => "PS512",
+            PKCS11Algorithm::ES256 => "ES256",
+            PKCS11Algorithm::ES384 => "ES384",
+            PKCS11Algorithm::EdDSA => "EdDSA",
+        }
+    }
+
+    fn pss_params(&self) -> Option<CkRsaPkcsPssParams> {
+        let (hash_alg, mgf, salt_len) = match self {
+            PKCS11Algorithm::PS256 => (CKM_SHA256, CKG_MGF1_SHA256, 32),
+            PKCS11Algorithm::PS384 => (CKM_SHA384, CKG_MGF1_SHA384, 48),
+            PKCS11Algorithm::PS512 => (CKM_SHA512, CKG_MGF1_SHA512, 64),
+            _ => return None,
+        };
+        Some(CkRsaPkcsPssParams {
+            hash_alg,
+            mgf,
+            salt_len,
+        })
+    }
+
+    fn mechanism_type(&self) -> CkUlong {
+        match self {
+            PKCS11Algorithm::RS256 => CKM_SHA256_RSA_PKCS,
+            PKCS11Algorithm::RS384 => CKM_SHA384_RSA_PKCS,
+            PKCS11Algorithm::RS512 => CKM_SHA512_RSA_PKCS,
+            PKCS11Algorithm::PS256 => CKM_SHA256_RSA_PKCS_PSS,
+            PKCS11Algorithm::PS384 => CKM_SHA384_RSA_PKCS_PSS,
+            PKCS11Algorithm::PS512 => CKM_SHA512_RSA_PKCS_PSS,
+            PKCS11Algorithm::ES256 | PKCS11Algorithm::ES384 => CKM_ECDSA,
+            PKCS11Algorithm::EdDSA => CKM_EDDSA,
+        }
+    }
+
+    // CKM_ECDSA signs a digest, and already returns r || s
+    fn input(&self, message: &[u8]) -> Vec<u8> {
+        match self {
+            PKCS11Algorithm::ES256 => hmac_sha256::Hash::hash(message).to_vec(),
+            PKCS11Algorithm::ES384 => hmac_sha512::sha384::Hash::hash(message).to_vec(),
+            _ => message.to_vec(),
+        }
+    }
+}
+
+/// Location of a signing key in a PKCS#11 module
+#[derive(Clone, Default)]
+pub struct PKCS11Config {
+    /// Slot containing the token. If `None`, the first slot with a token
+    /// present is used.
+    pub slot_id: Option<u64>,
+
+    /// User PIN, if the token requires a login
+    pub pin: Option<Zeroizing<String>>,
+
+    /// Label of the private key (`CKA_LABEL`)
+    pub key_label: Option<String>,
+
+    /// Identifier of the private key (`CKA_ID`)
+    pub key_object_id: Option<Vec<u8>>,
+}
+
+struct PKCS11Module {
+    library: *mut c_void,
+    functions: *const CkFunctionList,
+    finalize: bool,
+}
+
+// PKCS#11 modules are initialized with CKF_OS_LOCKING_OK, and the session is
+// only used while holding a lock.
+unsafe impl Send for PKCS11Module {}
+unsafe impl Sync for PKCS11Module {}
+
+// CK_ULONG is 32 bits on some platforms
+#[allow(clippy::unnecessary_cast)]
+fn pkcs11_error(rv: CkRv) -> JWTError {
+    JWTError::PKCS11Error(rv as u64)
+}
+
+fn check(rv: CkRv) -> Result<(), Error> {
+    ensure!(rv == CKR_OK, pkcs11_error(rv));
+    Ok(())
+}
+
+impl PKCS11Module {
+    fn load(module_path: &Path) -> Result<Self, Error> {
+        let path = module_path
+            .to_str()
+            .ok_or(JWTError::PKCS11ModuleNotLoaded)?;
+        let path = CString::new(path).map_err(|_| JWTError::PKCS11ModuleNotLoaded)?;
+        let library = unsafe { libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+        ensure!(!library.is_null(), JWTError::PKCS11ModuleNotLoaded);
+        let mut module = PKCS11Module {
+            library,
+            functions: ptr::null(),
+            finalize: false,
+        };
+        let symbol =
+            unsafe { libc::dlsym(library, b"C_GetFunctionList\0".as_ptr() as *const c_char) };
+        ensure!(!symbol.is_null(), JWTError::PKCS11ModuleNotLoaded);
+        let get_function_list: unsafe extern "C" fn(*mut *const CkFunctionList) -> CkRv =
+            unsafe { std::mem::transmute(symbol) };
+        check(unsafe { get_function_list(&mut module.functions) })?;
+        ensure!(!module.functions.is_null(), JWTError::PKCS11ModuleNotLoaded);
+
+        let args = CkCInitializeArgs {
+            create_mutex: ptr::null(),
+            destroy_mutex: ptr::null(),
+            lock_mutex: ptr::null(),
+            unlock_mutex: ptr::null(),
+            flags: CKF_OS_LOCKING_OK,
+            reserved: ptr::null(),
+        };
+        let c_initialize = module
+            .functions()
+            .c_initialize
+            .ok_or(JWTError::PKCS11ModuleNotLoaded)?;
+        match unsafe { c_initialize(&args) } {
+            CKR_OK => module.finalize = true,
+            CKR_CRYPTOKI_ALREADY_INITIALIZED => {}
+            rv => bail!(pkcs11_error(rv)),
+        }
+        Ok(module)
+    }
+
+    fn functions(&self) -> &CkFunctionList {
+        unsafe { &*self.functions }
+    }
+}
+
+/// The modules currently in use, so that signers using the same module share
+/// it, and the module is only finalized once the last of them is dropped
+static MODULES: OnceLock<Mutex<HashMap<PathBuf, Weak<PKCS11Module>>>> = OnceLock::new();
+
+fn loaded_modules() -> MutexGuard<'static, HashMap<PathBuf, Weak<PKCS11Module>>> {
+    MODULES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// A module shared by all the signers using it
+struct SharedPKCS11Module {
+    path: PathBuf,
+    module: ManuallyDrop<Arc<PKCS11Module>>,
+}
+
+impl SharedPKCS11Module {
+    /// Return the module at `module_path`, loading it if it is not in use yet
+    fn open(module_path: &Path) -> Result<Self, Error> {
+        let mut modules = loaded_modules();
+        let module = match modules.get(module_path).and_then(Weak::upgrade) {
+            Some(module) => module,
+            None => {
+                let module = Arc::new(PKCS11Module::load(module_path)?);
+                modules.insert(module_path.to_path_buf(), Arc::downgrade(&module));
+                module
+            }
+        };
+        Ok(SharedPKCS11Module {
+            path: module_path.to_path_buf(),
+            module: ManuallyDrop::new(module),
+        })
+    }
+}
+
+impl Deref for SharedPKCS11Module {
+    type Target = PKCS11Module;
+
+    fn deref(&self) -> &PKCS11Module {
+        &self.module
+    }
+}
+
+impl Drop for SharedPKCS11Module {
+    fn drop(&mut self) {
+        // The last user finalizes the module while holding the lock, so that
+        // it cannot be loaded again in the meantime
+        let mut modules = loaded_modules();
+        let module = unsafe { ManuallyDrop::take(&mut self.module) };
+        if Arc::strong_count(&module) == 1 {
+            modules.remove(&self.path);
+        }
+        drop(module);
+    }
+}
+
+impl Drop for PKCS11Module {
+    fn drop(&mut self) {
+        if self.finalize {
+            if let Some(c_finalize) = self.functions().c_finalize {
+                unsafe { c_finalize(ptr::null()) };
+            }
+        }
+        unsafe { libc::dlclose(self.library) };
+    }
+}
+
+/// A signer for keys stored in a PKCS#11 module.
+///
+/// Tokens can be created with `sign()`, or with `Token::build_async()`.
+pub struct PKCS11Signer {
+    algorithm: PKCS11Algorithm,
+    key_id: Option<String>,
+    session: Mutex<CkSessionHandle>,
+    key: CkObjectHandle,
+    module: SharedPKCS11Module,
+}
+
+impl PKCS11Signer {
+    /// Load the PKCS#11 module at `module_path`, and open a session to use
+    /// the private key described by `config` with `algorithm`.
+    ///
+    /// Signers opened with the same module path share the loaded module. If
+    /// more than one key matches, the first one is used.
+    pub fn open(
+        module_path: impl AsRef<Path>,
+        config: &PKCS11Config,
+        algorithm: PKCS11Algorithm,
+    ) -> Result<Self, Error> {
+        let module = SharedPKCS11Module::open(module_path.as_ref())?;
+        let functions = module.functions();
+        let missing = || JWTError::PKCS11ModuleNotLoaded;
+
+        let slot_id = match config.slot_id {
+            Some(slot_id) => slot_id as CkSlotId,
+            None => {
+                let c_get_slot_list = functions.c_get_slot_list.ok_or_else(missing)?;
+                let mut count: CkUlong = 0;
+                check(unsafe { c_get_slot_list(1, ptr::null_mut(), &mut count) })?;
+                let mut slots = vec![0; count as usize];
+                check(unsafe { c_get_slot_list(1, slots.as_mut_ptr(), &mut count) })?;
+                slots.truncate(count as usize);
+                *slots.first().ok_or(JWTError::PKCS11KeyNotFound)?
+            }
+        };
+
+        let c_open_session = functions.c_open_session.ok_or_else(missing)?;
+        let mut session: CkSessionHandle = 0;
+        check(unsafe {
+            c_open_session(
+                slot_id,
+                CKF_SERIAL_SESSION,
+                ptr::null(),
+                ptr::null(),
+                &mut session,
+            )
+        })?;
+        let mut signer = PKCS11Signer {
+            algorithm,
+            key_id: None,
+            session: Mutex::new(session),
+            key: 0,
+            module,
+        };
+        signer.key = signer.login_and_find_key(session, config)?;
+        Ok(signer)
+    }
+
+    fn login_and_find_key(
+        &self,
+        session: CkSessionHandle,
+        config: &PKCS11Config,
+    ) -> Result<CkObjectHandle, Error> {
+        let functions = self.module.functions();
+        let missing = || JWTError::PKCS11ModuleNotLoaded;
+
+        if let Some(pin) = &config.pin {
+            let c_login = functions.c_login.ok_or_else(missing)?;
+            match unsafe { c_login(session, CKU_USER, pin.as_ptr(), pin.len() as CkUlong) } {
+                CKR_OK | CKR_USER_ALREADY_LOGGED_IN => {}
+                rv => bail!(pkcs11_error(rv)),
+            }
+        }
+
+        let class = CKO_PRIVATE_KEY;
+        let mut template = vec![CkAttribute {
+            attribute_type: CKA_CLASS,
+            value: &class as *const CkUlong as *const c_void,
+            value_len: std::mem::size_of::<CkUlong>() as CkUlong,
+        }];
+        if let Some(key_label) = &config.key_label {
+            template.push(CkAttribute {
+                attribute_type: CKA_LABEL,
+                value: key_label.as_ptr() as *const c_void,
+                value_len: key_label.len() as CkUlong,
+            });
+        }
+        if let Some(key_object_id) = &config.key_object_id {
+            template.push(CkAttribute {
+                attribute_type: CKA_ID,
+                value: key_object_id.as_ptr() as *const c_void,
+                value_len: key_object_id.len() as CkUlong,
+            });
+        }
+        let c_find_objects_init = functions.c_find_objects_init.ok_or_else(missing)?;
+        let c_find_objects = functions.c_find_objects.ok_or_else(missing)?;
+        let c_find_objects_final = functions.c_find_objects_final.ok_or_else(missing)?;
+        check(unsafe {
+            c_find_objects_init(session, template.as_ptr(), template.len() as CkUlong)
+        })?;
+        let mut key: CkObjectHandle = 0;
+        let mut count: CkUlong = 0;
+        let rv = unsafe { c_find_objects(session, &mut key, 1, &mut count) };
+        unsafe { c_find_objects_final(session) };
+        check(rv)?;
+        ensure!(count == 1, JWTError::PKCS11KeyNotFound);
+        Ok(key)
+    }
+
+    /// Set the key identifier to include in new tokens ("kid")
+    pub fn with_key_id(mut self, key_id: &str) -> Self {
+        self.key_id = Some(key_id.to_string());
+        self
+    }
+
+    /// The signature algorithm
+    pub fn algorithm(&self) -> PKCS11Algorithm {
+        self.algorithm
+    }
+
+    /// Compute the signature of `message` using the private key
+    pub fn sign_message(&self, message: &[u8]) -> Result<Vec<u8>, Error> {
+        let functions = self.module.functions();
+        let missing = || JWTError::PKCS11ModuleNotLoaded;
+        let c_sign_init = functions.c_sign_init.ok_or_else(missing)?;
+        let c_sign = functions.c_sign.ok_or_else(missing)?;
+
+        let pss_params = self.algorithm.pss_params();
+        let mechanism = match &pss_params {
+            Some(pss_params) => CkMechanism {
+                mechanism: self.algorithm.mechanism_type(),
+                parameter: pss_params as *const CkRsaPkcsPssParams as *const c_void,
+                parameter_len: std::mem::size_of::<CkRsaPkcsPssParams>() as CkUlong,
+            },
+            None => CkMechanism {
+                mechanism: self.algorithm.mechanism_type(),
+                parameter: ptr::null(),
+                parameter_len: 0,
+            },
+        };
+        let input = self.algorithm.input(message);
+
+        let session = self
+            .session
+            .lock()
+            .map_err(|_| JWTError::InternalError("PKCS#11 session lock poisoned".to_string()))?;
+        check(unsafe { c_sign_init(*session, &mechanism, self.key) })?;
+        let mut signature_len: CkUlong = 0;
+        check(unsafe {
+            c_sign(
+                *session,
+                input.as_ptr(),
+                input.len() as CkUlong,
+                ptr::null_mut(),
+                &mut signature_len,
+            )
+        })?;
+        let mut signature = vec![0u8; signature_len as usize];
+        check(unsafe {
+            c_sign(
+                *session,
+                input.as_ptr(),
+                input.len() as CkUlong,
+                signature.as_mut_ptr(),
+                &mut signature_len,
+            )
+        })?;
+        signature.truncate(signature_len as usize);
+        Ok(signature)
+    }
+
+    /// Sign a set of claims
+    pub fn sign<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<CustomClaims>,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(
+            self.algorithm.jwt_alg_name().to_string(),
+            self.key_id.clone(),
+        );
+        Token::build(&jwt_header, claims, |authenticated| {
+            self.sign_message(authenticated)
+        })
+    }
+}
+
+impl Drop for PKCS11Signer {
+    fn drop(&mut self) {
+        if let (Some(c_close_session), Ok(session)) = (
+            self.module.functions().c_close_session,
+            self.session.get_mut(),
+        ) {
+            unsafe { c_close_session(*session) };
+        }
+    }
+}
+
+impl AsyncTokenSigner for PKCS11Signer {
+    fn jwt_alg_name(&self) -> &str {
+        self.algorithm.jwt_alg_name()
+    }
+
+    fn key_id(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    async fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Error> {
+        self.sign_message(message)
+    }
+}
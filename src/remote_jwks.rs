@@ -0,0 +1,381 @@
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use coarsetime::{Duration, UnixTimeStamp};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::claims::*;
+use crate::common::*;
+use crate::error::*;
+use crate::jwk::*;
+use crate::token::*;
+
+/// Default lifetime of a key set whose response doesn't include a `max-age`
+pub const DEFAULT_JWKS_MAX_AGE_SECS: u64 = 3600;
+
+/// Default minimum delay between two key set requests
+pub const DEFAULT_JWKS_MIN_REFRESH_INTERVAL_SECS: u64 = 30;
+
+/// Default delay after expiration during which a key set can still be used
+/// if it cannot be retrieved again
+pub const DEFAULT_JWKS_MAX_STALENESS_SECS: u64 = 86400;
+
+/// Maximum lifetime of a key set, whatever the `max-age` of its response is
+pub const MAX_JWKS_MAX_AGE_SECS: u64 = 86400;
+
+/// A key set document, as returned by a `JWKSFetcher`
+#[derive(Debug, Clone, Default)]
+pub struct JWKSResponse {
+    /// The response body
+    pub body: String,
+
+    /// The value of the `Cache-Control` header, if any
+    pub cache_control: Option<String>,
+}
+
+/// A client able to retrieve a key set document.
+///
+/// This crate doesn't depend on any HTTP client or async runtime: applications
+/// implement this trait using the ones they already use.
+pub trait JWKSFetcher {
+    /// Retrieve the document at `url`
+    fn fetch(&self, url: &str) -> impl Future<Output = Result<JWKSResponse, Error>> + Send;
+}
+
+/// Keys added to or removed from a remote key set after it has been
+/// retrieved
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JWKSetChanges {
+    /// Keys that were not in the previous version of the key set
+    pub added: Vec<JWK>,
+
+    /// Keys that are not in the key set any more
+    pub removed: Vec<JWK>,
+}
+
+impl JWKSetChanges {
+    /// Return `true` if the key set didn't change
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+type RotationCallback = Box<dyn Fn(&JWKSetChanges) + Send + Sync>;
+
+#[derive(Default)]
+struct CachedJWKSet {
+    key_set: Option<Arc<JWKSet>>,
+    retired_keys: Vec<(JWK, UnixTimeStamp)>,
+    requested_at: Option<UnixTimeStamp>,
+    expires_at: Option<UnixTimeStamp>,
+}
+
+impl CachedJWKSet {
+    /// Record a new request at `now`, unless the previous one was sent less
+    /// than `min_refresh_interval` ago
+    fn start_refresh(&mut self, now: UnixTimeStamp, min_refresh_interval: Duration) -> bool {
+        if let Some(requested_at) = self.requested_at {
+            match requested_at.checked_add(min_refresh_interval) {
+                Some(refreshable_at) if now >= refreshable_at => {}
+                _ => return false,
+            }
+        }
+        self.requested_at = Some(now);
+        true
+    }
+
+    fn update(
+        &mut self,
+        key_set: JWKSet,
+        now: UnixTimeStamp,
+        grace_period: Duration,
+    ) -> JWKSetChanges {
+        let previous_keys = match self.key_set.take() {
+            Some(previous_key_set) => previous_key_set.keys.clone(),
+            None => vec![],
+        };
+        let changes = JWKSetChanges {
+            added: key_set
+                .keys
+                .iter()
+                .filter(|jwk| !previous_keys.contains(jwk))
+                .cloned()
+                .collect(),
+            removed: previous_keys
+                .into_iter()
+                .filter(|jwk| !key_set.keys.contains(jwk))
+                .collect(),
+        };
+        self.retired_keys
+            .retain(|(jwk, _)| !key_set.keys.contains(jwk));
+        self.retired_keys.extend(
+            changes
+                .removed
+                .iter()
+                .map(|jwk| (jwk.clone(), now + grace_period)),
+        );
+        self.key_set = Some(Arc::new(key_set));
+        changes
+    }
+
+    /// The current keys, followed by the removed keys that are still within
+    /// their grace period
+    fn effective_key_set(&mut self, now: UnixTimeStamp) -> Option<Arc<JWKSet>> {
+        self.retired_keys
+            .retain(|(_, retired_until)| now < *retired_until);
+        let key_set = self.key_set.as_ref()?;
+        if self.retired_keys.is_empty() {
+            return Some(key_set.clone());
+        }
+        let mut keys = key_set.keys.clone();
+        keys.extend(self.retired_keys.iter().map(|(jwk, _)| jwk.clone()));
+        Some(Arc::new(JWKSet { keys }))
+    }
+}
+
+/// A key set retrieved from a remote URL, such as the `jwks_uri` of an
+/// OpenID Connect provider.
+///
+/// The key set is cached according to the `max-age` directive of the
+/// response, and is refreshed when it expires, or when a token refers to an
+/// unknown key identifier. Requests are never sent more often than the
+/// minimum refresh interval, whether they succeed or not.
+///
+/// If the key set cannot be retrieved again, the expired key set keeps being
+/// used for up to the maximum staleness.
+///
+/// Keys removed from the remote key set can be retained for a grace period,
+/// so that tokens signed just before a key rotation can still be verified.
+pub struct RemoteJWKSet<Fetcher: JWKSFetcher> {
+    url: String,
+    fetcher: Fetcher,
+    default_max_age: Duration,
+    min_refresh_interval: Duration,
+    refresh_interval: Option<Duration>,
+    max_staleness: Duration,
+    grace_period: Duration,
+    rotation_callback: Option<RotationCallback>,
+    time_source: SharedTimeSource,
+    cache: Mutex<CachedJWKSet>,
+}
+
+impl<Fetcher: JWKSFetcher> RemoteJWKSet<Fetcher> {
+    /// Create a remote key set, retrieved from `url` using `fetcher`
+    pub fn new(url: impl ToString, fetcher: Fetcher) -> Self {
+        RemoteJWKSet {
+            url: url.to_string(),
+            fetcher,
+            default_max_age: Duration::from_secs(DEFAULT_JWKS_MAX_AGE_SECS),
+            min_refresh_interval: Duration::from_secs(DEFAULT_JWKS_MIN_REFRESH_INTERVAL_SECS),
+            refresh_interval: None,
+            max_staleness: Duration::from_secs(DEFAULT_JWKS_MAX_STALENESS_SECS),
+            grace_period: Duration::from_secs(0),
+            rotation_callback: None,
+            time_source: SharedTimeSource::new(SystemTimeSource),
+            cache: Mutex::new(CachedJWKSet::default()),
+        }
+    }
+
+    /// Set the lifetime of key sets whose response doesn't include a
+    /// `max-age` directive
+    pub fn with_default_max_age(mut self, default_max_age: Duration) -> Self {
+        self.default_max_age = default_max_age;
+        self
+    }
+
+    /// Set the minimum delay between two requests
+    pub fn with_min_refresh_interval(mut self, min_refresh_interval: Duration) -> Self {
+        self.min_refresh_interval = min_refresh_interval;
+        self
+    }
+
+    /// Set the maximum lifetime of the cached key set, even if the response
+    /// allows it to be cached for longer
+    pub fn with_refresh_interval(mut self, refresh_interval: Duration) -> Self {
+        self.refresh_interval = Some(refresh_interval);
+        self
+    }
+
+    /// Set how long an expired key set can still be used when it cannot be
+    /// retrieved again
+    pub fn with_max_staleness(mut self, max_staleness: Duration) -> Self {
+        self.max_staleness = max_staleness;
+        self
+    }
+
+    /// Keep using keys removed from the key set for `grace_period`
+    pub fn with_grace_period(mut self, grace_period: Duration) -> Self {
+        self.grace_period = grace_period;
+        self
+    }
+
+    /// Set a function to call every time keys are added to or removed from
+    /// the key set. Keys of the first retrieved key set are reported as
+    /// added.
+    pub fn with_rotation_callback(
+        mut self,
+        rotation_callback: impl Fn(&JWKSetChanges) + Send + Sync + 'static,
+    ) -> Self {
+        self.rotation_callback = Some(Box::new(rotation_callback));
+        self
+    }
+
+    /// Set the time source used to expire the cached key set, instead of the
+    /// system clock
+    pub fn with_time_source(mut self, time_source: impl TimeSource + 'static) -> Self {
+        self.time_source = SharedTimeSource::new(time_source);
+        self
+    }
+
+    /// The URL of the key set
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Return the cached key set, retrieving it if it is missing or expired
+    ///
+    /// Removed keys that are still within their grace period are included.
+    ///
+    /// If the key set was requested too recently to be retrieved again, or
+    /// if it cannot be retrieved, the expired key set is returned, until it
+    /// is older than the maximum staleness.
+    pub async fn key_set(&self) -> Result<Arc<JWKSet>, Error> {
+        let stale_key_set = {
+            let now = self.time_source.now();
+            let mut cache = self.cache.lock().unwrap();
+            let stale_key_set = match (cache.expires_at, cache.effective_key_set(now)) {
+                (Some(expires_at), Some(key_set)) => {
+                    if now < expires_at {
+                        return Ok(key_set);
+                    }
+                    expires_at
+                        .checked_add(self.max_staleness)
+                        .filter(|stale_until| now < *stale_until)
+                        .map(|_| key_set)
+                }
+                _ => None,
+            };
+            if !cache.start_refresh(now, self.min_refresh_interval) {
+                return stale_key_set.ok_or_else(|| JWTError::KeySetUnavailable.into());
+            }
+            stale_key_set
+        };
+        match self.fetch_key_set().await {
+            Ok(key_set) => Ok(key_set),
+            Err(e) => stale_key_set.ok_or(e),
+        }
+    }
+
+    /// Retrieve the key set, ignoring the cache
+    pub async fn refresh(&self) -> Result<Arc<JWKSet>, Error> {
+        self.cache.lock().unwrap().requested_at = Some(self.time_source.now());
+        self.fetch_key_set().await
+    }
+
+    async fn fetch_key_set(&self) -> Result<Arc<JWKSet>, Error> {
+        let fetched_at = self.time_source.now();
+        let response = self.fetcher.fetch(&self.url).await?;
+        let key_set = JWKSet::from_json(&response.body)?;
+        let mut max_age = response
+            .cache_control
+            .as_deref()
+            .and_then(parse_max_age)
+            .unwrap_or(self.default_max_age);
+        if let Some(refresh_interval) = self.refresh_interval {
+            max_age = max_age.min(refresh_interval);
+        }
+        let (key_set, changes) = {
+            let mut cache = self.cache.lock().unwrap();
+            let changes = cache.update(key_set, fetched_at, self.grace_period);
+            let expires_at = fetched_at.checked_add(max_age.max(self.min_refresh_interval));
+            cache.expires_at = Some(expires_at.unwrap_or(fetched_at));
+            (cache.effective_key_set(fetched_at), changes)
+        };
+        if let Some(rotation_callback) = &self.rotation_callback {
+            if !changes.is_empty() {
+                rotation_callback(&changes);
+            }
+        }
+        Ok(key_set.unwrap_or_default())
+    }
+
+    /// Return the time left before the cached key set expires, or before it
+    /// can be requested again if it has already expired
+    ///
+    /// Applications that want to refresh the key set in the background, and
+    /// not while verifying a token, can call `key_set()` in a loop, waiting
+    /// for that duration between iterations.
+    pub fn time_until_refresh(&self) -> Duration {
+        let cache = self.cache.lock().unwrap();
+        let refreshable_at = cache
+            .requested_at
+            .and_then(|requested_at| requested_at.checked_add(self.min_refresh_interval));
+        let refresh_at = match (cache.expires_at, refreshable_at) {
+            (Some(expires_at), Some(refreshable_at)) => Some(expires_at.max(refreshable_at)),
+            (expires_at, refreshable_at) => expires_at.or(refreshable_at),
+        };
+        refresh_at
+            .and_then(|refresh_at| refresh_at.checked_sub(self.time_source.now()))
+            .unwrap_or_default()
+    }
+
+    fn start_refresh(&self) -> bool {
+        let mut cache = self.cache.lock().unwrap();
+        cache.start_refresh(self.time_source.now(), self.min_refresh_interval)
+    }
+
+    /// Verify a signed token using the remote key set, and validate its
+    /// claims
+    ///
+    /// If the token refers to a key identifier that is not in the cached key
+    /// set, the key set is retrieved again before verification, unless it
+    /// has been retrieved too recently.
+    pub async fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        let metadata = Token::decode_metadata_with_max_header_length(
+            token,
+            max_header_length(options.as_ref()),
+        )?;
+        let mut key_set = self.key_set().await?;
+        if let Some(key_id) = metadata.key_id() {
+            if key_set.key_with_id(key_id).is_none() && self.start_refresh() {
+                key_set = self.fetch_key_set().await?;
+            }
+        }
+        key_set.verify_token(token, options)
+    }
+
+    /// Verify a token like `verify_token()`, and return the whole payload as
+    /// a JSON value, without a compile-time schema
+    pub async fn verify_token_untyped(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<serde_json::Value, Error> {
+        let claims = self.verify_token::<NoCustomClaims>(token, options).await?;
+        Ok(serde_json::to_value(claims)?)
+    }
+}
+
+/// Return the lifetime set by a `Cache-Control` header value, if any, up to
+/// `MAX_JWKS_MAX_AGE_SECS`
+fn parse_max_age(cache_control: &str) -> Option<Duration> {
+    let mut max_age = None;
+    for directive in cache_control.split(',') {
+        let directive = directive.trim().to_ascii_lowercase();
+        if directive == "no-store" || directive == "no-cache" {
+            return Some(Duration::from_secs(0));
+        }
+        if let Some(value) = directive.strip_prefix("max-age=") {
+            max_age = value
+                .trim_matches('"')
+                .parse::<u64>()
+                .ok()
+                .map(|max_age| Duration::from_secs(max_age.min(MAX_JWKS_MAX_AGE_SECS)));
+        }
+    }
+    max_age
+}
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use coarsetime::UnixTimeStamp;
+
+use crate::claims::*;
+use crate::common::*;
+use crate::error::*;
+
+/// Detection of reused tokens, based on their identifier (`jti` claim)
+///
+/// When a guard is attached to `VerificationOptions`, it is called after a
+/// token has been fully verified. Tokens without an identifier or without an
+/// expiration date are rejected.
+pub trait TokenReplayGuard: Send + Sync {
+    /// Return `JWTError::TokenReplayed` if the identifier has already been
+    /// seen, or store it until `retain_until` otherwise
+    ///
+    /// This must be atomic, so that concurrent verifications of the same token
+    /// cannot both succeed. `now` can be used to evict expired identifiers.
+    fn check_and_store(
+        &self,
+        jwt_id: &str,
+        retain_until: UnixTimeStamp,
+        now: UnixTimeStamp,
+    ) -> Result<(), Error>;
+}
+
+impl<T: TokenReplayGuard + ?Sized> TokenReplayGuard for Arc<T> {
+    fn check_and_store(
+        &self,
+        jwt_id: &str,
+        retain_until: UnixTimeStamp,
+        now: UnixTimeStamp,
+    ) -> Result<(), Error> {
+        (**self).check_and_store(jwt_id, retain_until, now)
+    }
+}
+
+/// An in-memory replay guard, for a single process
+///
+/// Identifiers are evicted once the tokens they belong to have expired.
+#[derive(Debug, Default)]
+pub struct InMemoryReplayGuard {
+    seen: Mutex<HashMap<String, UnixTimeStamp>>,
+}
+
+impl InMemoryReplayGuard {
+    /// Create an empty replay guard
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of identifiers currently stored
+    pub fn len(&self) -> usize {
+        self.seen.lock().unwrap().len()
+    }
+
+    /// Return `true` if no identifiers are currently stored
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl TokenReplayGuard for InMemoryReplayGuard {
+    fn check_and_store(
+        &self,
+        jwt_id: &str,
+        retain_until: UnixTimeStamp,
+        now: UnixTimeStamp,
+    ) -> Result<(), Error> {
+        let mut seen = self.seen.lock().unwrap();
+        seen.retain(|_, retain_until| *retain_until >= now);
+        ensure!(!seen.contains_key(jwt_id), JWTError::TokenReplayed);
+        seen.insert(jwt_id.to_string(), retain_until);
+        Ok(())
+    }
+}
+
+/// A replay guard that can be attached to `VerificationOptions`
+///
+/// Replay guards are compared by identity.
+#[derive(Clone)]
+pub struct SharedTokenReplayGuard(Arc<dyn TokenReplayGuard>);
+
+impl SharedTokenReplayGuard {
+    /// Wrap a replay guard
+    pub fn new(replay_guard: impl TokenReplayGuard + 'static) -> Self {
+        SharedTokenReplayGuard(Arc::new(replay_guard))
+    }
+
+    pub(crate) fn check_and_store(
+        &self,
+        jwt_id: &str,
+        retain_until: UnixTimeStamp,
+        now: UnixTimeStamp,
+    ) -> Result<(), Error> {
+        self.0.check_and_store(jwt_id, retain_until, now)
+    }
+}
+
+impl fmt::Debug for SharedTokenReplayGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SharedTokenReplayGuard")
+    }
+}
+
+impl PartialEq for SharedTokenReplayGuard {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for SharedTokenReplayGuard {}
+
+pub(crate) fn check_replay<CustomClaims>(
+    options: &VerificationOptions,
+    claims: &JWTClaims<CustomClaims>,
+) -> Result<(), Error> {
+    let replay_guard = match &options.replay_guard {
+        Some(replay_guard) => replay_guard,
+        None => return Ok(()),
+    };
+    let jwt_id = claims
+        .jwt_id
+        .as_deref()
+        .ok_or(JWTError::RequiredJWTIdMissing)?;
+    let expires_at = claims
+        .expires_at
+        .ok_or(JWTError::RequiredExpirationMissing)?;
+    let expiration_tolerance = options
+        .expiration_tolerance
+        .or(options.time_tolerance)
+        .unwrap_or_default()
+        + options.expiration_grace_period.unwrap_or_default();
+    replay_guard
+        .0
+        .check_and_store(jwt_id, expires_at + expiration_tolerance, options.now())
+}
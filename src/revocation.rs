@@ -0,0 +1,151 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+use coarsetime::UnixTimeStamp;
+
+use crate::claims::*;
+use crate::common::*;
+use crate::error::*;
+
+/// A denylist of revoked tokens
+///
+/// When a revocation check is attached to `VerificationOptions`, it is called
+/// after a token has been fully verified, and the token is rejected with
+/// `JWTError::TokenRevoked` if it has been revoked.
+pub trait RevocationCheck: Send + Sync {
+    /// Return `true` if a token with the given identifier (`jti` claim),
+    /// subject (`sub` claim) and creation date (`iat` claim) has been revoked
+    fn is_revoked(
+        &self,
+        jwt_id: Option<&str>,
+        subject: Option<&str>,
+        issued_at: Option<UnixTimeStamp>,
+    ) -> Result<bool, Error>;
+}
+
+impl<T: RevocationCheck + ?Sized> RevocationCheck for Arc<T> {
+    fn is_revoked(
+        &self,
+        jwt_id: Option<&str>,
+        subject: Option<&str>,
+        issued_at: Option<UnixTimeStamp>,
+    ) -> Result<bool, Error> {
+        (**self).is_revoked(jwt_id, subject, issued_at)
+    }
+}
+
+#[derive(Debug, Default)]
+struct RevocationState {
+    jwt_ids: HashSet<String>,
+    subjects: HashMap<String, UnixTimeStamp>,
+    issued_before: Option<UnixTimeStamp>,
+}
+
+/// An in-memory revocation list, for a single process
+///
+/// Tokens can be revoked individually by identifier, for a subject if they
+/// were created before a date (for example to log a user out everywhere), or
+/// all at once if they were created before a date. Tokens without a creation
+/// date are considered revoked if their subject or all tokens have been
+/// revoked.
+#[derive(Debug, Default)]
+pub struct InMemoryRevocationList {
+    state: RwLock<RevocationState>,
+}
+
+impl InMemoryRevocationList {
+    /// Create an empty revocation list
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Revoke the token with the given identifier
+    pub fn revoke_jwt_id(&self, jwt_id: impl ToString) {
+        let mut state = self.state.write().unwrap();
+        state.jwt_ids.insert(jwt_id.to_string());
+    }
+
+    /// Revoke the tokens of a subject created before `issued_before`
+    pub fn revoke_subject(&self, subject: impl ToString, issued_before: UnixTimeStamp) {
+        let mut state = self.state.write().unwrap();
+        let revoked_before = state
+            .subjects
+            .entry(subject.to_string())
+            .or_insert(issued_before);
+        *revoked_before = (*revoked_before).max(issued_before);
+    }
+
+    /// Revoke all the tokens created before `issued_before`
+    pub fn revoke_issued_before(&self, issued_before: UnixTimeStamp) {
+        let mut state = self.state.write().unwrap();
+        state.issued_before = state.issued_before.max(Some(issued_before));
+    }
+}
+
+impl RevocationCheck for InMemoryRevocationList {
+    fn is_revoked(
+        &self,
+        jwt_id: Option<&str>,
+        subject: Option<&str>,
+        issued_at: Option<UnixTimeStamp>,
+    ) -> Result<bool, Error> {
+        let state = self.state.read().unwrap();
+        let issued_before = |revoked_before: UnixTimeStamp| {
+            issued_at.is_none_or(|issued_at| issued_at < revoked_before)
+        };
+        if jwt_id.is_some_and(|jwt_id| state.jwt_ids.contains(jwt_id)) {
+            return Ok(true);
+        }
+        if let Some(revoked_before) = subject.and_then(|subject| state.subjects.get(subject)) {
+            if issued_before(*revoked_before) {
+                return Ok(true);
+            }
+        }
+        Ok(state.issued_before.is_some_and(issued_before))
+    }
+}
+
+/// A revocation check that can be attached to `VerificationOptions`
+///
+/// Revocation checks are compared by identity.
+#[derive(Clone)]
+pub struct SharedRevocationCheck(Arc<dyn RevocationCheck>);
+
+impl SharedRevocationCheck {
+    /// Wrap a revocation check
+    pub fn new(revocation_check: impl RevocationCheck + 'static) -> Self {
+        SharedRevocationCheck(Arc::new(revocation_check))
+    }
+}
+
+impl fmt::Debug for SharedRevocationCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SharedRevocationCheck")
+    }
+}
+
+impl PartialEq for SharedRevocationCheck {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for SharedRevocationCheck {}
+
+pub(crate) fn check_revocation<CustomClaims>(
+    options: &VerificationOptions,
+    claims: &JWTClaims<CustomClaims>,
+) -> Result<(), Error> {
+    let revocation_check = match &options.revocation_check {
+        Some(revocation_check) => revocation_check,
+        None => return Ok(()),
+    };
+    let revoked = revocation_check.0.is_revoked(
+        claims.jwt_id.as_deref(),
+        claims.subject.as_deref(),
+        claims.issued_at,
+    )?;
+    ensure!(!revoked, JWTError::TokenRevoked);
+    Ok(())
+}
@@ -0,0 +1,390 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use coarsetime::Duration;
+use ct_codecs::{Base64UrlSafeNoPadding, Decoder, Encoder};
+use rand::RngCore;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::claims::*;
+use crate::common::*;
+use crate::error::*;
+use crate::jwk::public_key_from_jwk;
+use crate::token::*;
+
+const SD_DIGESTS: &str = "_sd";
+const SD_ALGORITHM: &str = "_sd_alg";
+const SD_ALGORITHM_SHA256: &str = "sha-256";
+const SD_HASH: &str = "sd_hash";
+
+/// The type ("typ") of key-binding JWTs
+pub const KEY_BINDING_JWT_TYPE: &str = "kb+jwt";
+
+/// The default maximum age of a key-binding JWT
+pub const DEFAULT_KEY_BINDING_MAX_AGE: Duration = Duration::from_secs(300);
+
+/// A claim that can be selectively disclosed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disclosure {
+    encoded: String,
+    claim_name: String,
+    claim_value: serde_json::Value,
+}
+
+impl Disclosure {
+    fn new(claim_name: String, claim_value: serde_json::Value) -> Result<Self, Error> {
+        let mut salt = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut salt);
+        let salt = Base64UrlSafeNoPadding::encode_to_string(salt)?;
+        let json = serde_json::to_string(&(salt, &claim_name, &claim_value))?;
+        Ok(Disclosure {
+            encoded: Base64UrlSafeNoPadding::encode_to_string(json)?,
+            claim_name,
+            claim_value,
+        })
+    }
+
+    fn parse(encoded: &str) -> Result<Self, Error> {
+        let json = Base64UrlSafeNoPadding::decode_to_vec(encoded, None)?;
+        let (_salt, claim_name, claim_value): (String, String, serde_json::Value) =
+            serde_json::from_slice(&json).map_err(|_| JWTError::SDJWTEncodingError)?;
+        Ok(Disclosure {
+            encoded: encoded.to_string(),
+            claim_name,
+            claim_value,
+        })
+    }
+
+    /// The name of the disclosed claim
+    pub fn claim_name(&self) -> &str {
+        &self.claim_name
+    }
+
+    /// The value of the disclosed claim
+    pub fn claim_value(&self) -> &serde_json::Value {
+        &self.claim_value
+    }
+
+    /// The base64url-encoded disclosure
+    pub fn encoded(&self) -> &str {
+        &self.encoded
+    }
+
+    /// The digest of the disclosure, as included in the issuer-signed JWT
+    fn digest(&self) -> Result<String, Error> {
+        Ok(Base64UrlSafeNoPadding::encode_to_string(
+            hmac_sha256::Hash::hash(self.encoded.as_bytes()),
+        )?)
+    }
+}
+
+/// A JWT with selectively disclosable claims (SD-JWT)
+///
+/// The issuer-signed JWT only includes digests of these claims. The claims
+/// themselves are in separate disclosures, and holders can choose which ones
+/// to reveal to a verifier.
+///
+/// Only top-level claims can be selectively disclosable.
+///
+/// Presentations can be bound to a key of the holder with a key-binding JWT,
+/// so that they cannot be replayed by another party.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SDJWT {
+    issuer_jwt: String,
+    disclosures: Vec<Disclosure>,
+    key_binding_jwt: Option<String>,
+}
+
+/// What the key-binding JWT of a presentation must be bound to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyBindingOptions {
+    /// The audience of the presentation, identifying the verifier ("aud")
+    pub audience: String,
+
+    /// The nonce sent by the verifier to the holder ("nonce")
+    pub nonce: String,
+
+    /// Reject key-binding JWTs created more than `max_age` ago
+    pub max_age: Duration,
+}
+
+impl KeyBindingOptions {
+    /// Require presentations to be bound to the given audience and nonce
+    pub fn new(audience: impl ToString, nonce: impl ToString) -> Self {
+        KeyBindingOptions {
+            audience: audience.to_string(),
+            nonce: nonce.to_string(),
+            max_age: DEFAULT_KEY_BINDING_MAX_AGE,
+        }
+    }
+
+    /// Set the maximum age of key-binding JWTs
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+}
+
+impl SDJWT {
+    /// Issue an SD-JWT, where the claims named in `disclosable_claims` can be
+    /// selectively disclosed
+    ///
+    /// The signer gets the claims with these claims replaced by their
+    /// digests, and must return a token in the compact serialization, for
+    /// example using `sign()`. Names of claims that are not set are ignored.
+    pub fn issue<CustomClaims: Serialize>(
+        claims: JWTClaims<CustomClaims>,
+        disclosable_claims: &[&str],
+        signer: impl FnOnce(JWTClaims<NoCustomClaims>) -> Result<String, Error>,
+    ) -> Result<Self, Error> {
+        let mut claims = match serde_json::to_value(&claims)? {
+            serde_json::Value::Object(claims) => claims,
+            _ => bail!(JWTError::SDJWTEncodingError),
+        };
+        ensure!(
+            !claims.contains_key(SD_DIGESTS) && !claims.contains_key(SD_ALGORITHM),
+            JWTError::SDJWTEncodingError
+        );
+        let mut disclosures = vec![];
+        for claim_name in disclosable_claims {
+            if let Some(claim_value) = claims.remove(*claim_name) {
+                disclosures.push(Disclosure::new(claim_name.to_string(), claim_value)?);
+            }
+        }
+        let mut digests = disclosures
+            .iter()
+            .map(Disclosure::digest)
+            .collect::<Result<Vec<_>, _>>()?;
+        // Don't reveal the order of the claims
+        digests.sort();
+        claims.insert(SD_DIGESTS.to_string(), digests.into());
+        claims.insert(SD_ALGORITHM.to_string(), SD_ALGORITHM_SHA256.into());
+        let claims = serde_json::from_value(serde_json::Value::Object(claims))?;
+        Ok(SDJWT {
+            issuer_jwt: signer(claims)?,
+            disclosures,
+            key_binding_jwt: None,
+        })
+    }
+
+    /// Parse an SD-JWT (`<issuer-signed JWT>~<disclosure>~...~`), optionally
+    /// followed by a key-binding JWT
+    pub fn parse(sd_jwt: &str) -> Result<Self, Error> {
+        let mut parts = sd_jwt.split('~');
+        let issuer_jwt = parts.next().ok_or(JWTError::SDJWTEncodingError)?;
+        let key_binding_jwt = match parts.next_back() {
+            Some("") => None,
+            Some(key_binding_jwt) => Some(key_binding_jwt.to_string()),
+            None => bail!(JWTError::SDJWTEncodingError),
+        };
+        let disclosures = parts.map(Disclosure::parse).collect::<Result<_, _>>()?;
+        Ok(SDJWT {
+            issuer_jwt: issuer_jwt.to_string(),
+            disclosures,
+            key_binding_jwt,
+        })
+    }
+
+    /// The issuer-signed JWT
+    pub fn issuer_jwt(&self) -> &str {
+        &self.issuer_jwt
+    }
+
+    /// The disclosures, WITHOUT VERIFYING THEM
+    pub fn disclosures(&self) -> &[Disclosure] {
+        &self.disclosures
+    }
+
+    /// The key-binding JWT, if the presentation has one
+    pub fn key_binding_jwt(&self) -> Option<&str> {
+        self.key_binding_jwt.as_deref()
+    }
+
+    /// Create a presentation that only reveals the given claims
+    ///
+    /// This is what holders send to verifiers. Claims that were not
+    /// selectively disclosable are always revealed.
+    pub fn present(&self, claim_names: &[&str]) -> Self {
+        let disclosures = self
+            .disclosures
+            .iter()
+            .filter(|disclosure| claim_names.contains(&disclosure.claim_name()))
+            .cloned()
+            .collect();
+        SDJWT {
+            issuer_jwt: self.issuer_jwt.clone(),
+            disclosures,
+            key_binding_jwt: None,
+        }
+    }
+
+    /// Bind the presentation to the key of the holder, with a key-binding
+    /// JWT (KB-JWT) for the given verifier and nonce
+    ///
+    /// The signer gets the claims and the header options, must use the key
+    /// from the confirmation claim (`cnf`) of the issuer-signed JWT, and
+    /// return a token in the compact serialization, for example using
+    /// `sign_with_header_options()`.
+    pub fn with_key_binding(
+        mut self,
+        audience: impl ToString,
+        nonce: impl ToString,
+        signer: impl FnOnce(JWTClaims<NoCustomClaims>, &HeaderOptions) -> Result<String, Error>,
+    ) -> Result<Self, Error> {
+        self.key_binding_jwt = None;
+        let mut claims = Claims::create(Duration::default())
+            .with_audience(audience)
+            .with_nonce(nonce);
+        claims.expires_at = None;
+        claims
+            .extra_claims
+            .insert(SD_HASH.to_string(), self.sd_hash()?.into());
+        let header_options = HeaderOptions::default().with_signature_type(KEY_BINDING_JWT_TYPE);
+        self.key_binding_jwt = Some(signer(claims, &header_options)?);
+        Ok(self)
+    }
+
+    /// Verify the issuer-signed JWT using any of the given keys, check the
+    /// disclosures, and validate the claims
+    ///
+    /// The returned claims include the disclosed claims, but not the other
+    /// selectively disclosable claims. Every disclosure must match a digest
+    /// of the issuer-signed JWT.
+    pub fn verify_with_keys<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        keys: &[&dyn KeyLike],
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        let options = options.unwrap_or_default();
+        let mut last_error = None;
+        let mut payload = None;
+        for key in keys {
+            match Token::verify_signature_with_key(*key, &self.issuer_jwt, &options) {
+                Ok(verified_payload) => {
+                    payload = Some(verified_payload);
+                    break;
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        let payload = match payload {
+            Some(payload) => payload,
+            None => return Err(last_error.unwrap_or_else(|| JWTError::AlgorithmMismatch.into())),
+        };
+        let claims_json = self.disclosed_claims(&payload)?;
+        Token::verify_claims(&claims_json, &options)
+    }
+
+    /// Verify the presentation like `verify_with_keys()`, and also require a
+    /// key-binding JWT, created for this presentation with the key from the
+    /// confirmation claim (`cnf`)
+    ///
+    /// The key-binding JWT must have the `kb+jwt` type. Its audience, nonce
+    /// and age are checked according to `key_binding_options`.
+    pub fn verify_with_key_binding<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        keys: &[&dyn KeyLike],
+        options: Option<VerificationOptions>,
+        key_binding_options: &KeyBindingOptions,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        let options = options.unwrap_or_default();
+        let claims: JWTClaims<CustomClaims> = self.verify_with_keys(keys, Some(options.clone()))?;
+        let key_binding_jwt = self
+            .key_binding_jwt
+            .as_deref()
+            .ok_or(JWTError::SDJWTKeyBindingMissing)?;
+        let holder_jwk = claims
+            .confirmation
+            .as_ref()
+            .and_then(|confirmation| confirmation.public_key.as_ref())
+            .ok_or(JWTError::RequiredConfirmationMissing)?;
+        let metadata = Token::decode_metadata(key_binding_jwt)?;
+        let holder_key = public_key_from_jwk(metadata.algorithm(), holder_jwk)?;
+        let key_binding_verification_options = VerificationOptions {
+            require_issued_at: true,
+            required_typ: Some(KEY_BINDING_JWT_TYPE.to_string()),
+            allowed_audiences: Some(HashSet::from([key_binding_options.audience.clone()])),
+            required_nonce: Some(key_binding_options.nonce.clone()),
+            max_validity: Some(key_binding_options.max_age),
+            time_tolerance: options.time_tolerance,
+            time_source: options.time_source.clone(),
+            ..Default::default()
+        };
+        let key_binding_claims = Token::verify_with_keys::<NoCustomClaims>(
+            &[holder_key.as_ref()],
+            key_binding_jwt,
+            Some(key_binding_verification_options),
+        )?;
+        let sd_hash = key_binding_claims
+            .extra_claims
+            .get(SD_HASH)
+            .and_then(|sd_hash| sd_hash.as_str());
+        ensure!(
+            sd_hash == Some(self.sd_hash()?.as_str()),
+            JWTError::SDJWTKeyBindingMismatch
+        );
+        Ok(claims)
+    }
+
+    /// The hash of the presentation, without the key-binding JWT
+    fn sd_hash(&self) -> Result<String, Error> {
+        Ok(Base64UrlSafeNoPadding::encode_to_string(
+            hmac_sha256::Hash::hash(self.without_key_binding().as_bytes()),
+        )?)
+    }
+
+    /// The presentation, without the key-binding JWT
+    fn without_key_binding(&self) -> String {
+        let mut sd_jwt = format!("{}~", self.issuer_jwt);
+        for disclosure in &self.disclosures {
+            sd_jwt.push_str(disclosure.encoded());
+            sd_jwt.push('~');
+        }
+        sd_jwt
+    }
+
+    /// Replace the digests of the issuer-signed payload with the disclosed
+    /// claims
+    fn disclosed_claims(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut claims: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_slice(payload)?;
+        match claims.remove(SD_ALGORITHM) {
+            None => {}
+            Some(serde_json::Value::String(algorithm)) if algorithm == SD_ALGORITHM_SHA256 => {}
+            Some(_) => bail!(JWTError::UnsupportedTokenHashAlgorithm),
+        }
+        let digests: HashSet<String> = match claims.remove(SD_DIGESTS) {
+            None => HashSet::new(),
+            Some(digests) => {
+                serde_json::from_value(digests).map_err(|_| JWTError::SDJWTEncodingError)?
+            }
+        };
+        let mut disclosed_digests = HashSet::new();
+        for disclosure in &self.disclosures {
+            let digest = disclosure.digest()?;
+            ensure!(
+                digests.contains(&digest) && disclosed_digests.insert(digest),
+                JWTError::SDJWTDisclosureMismatch
+            );
+            let claim_name = disclosure.claim_name();
+            ensure!(
+                claim_name != SD_DIGESTS
+                    && claim_name != SD_ALGORITHM
+                    && !claims.contains_key(claim_name),
+                JWTError::SDJWTDisclosureMismatch
+            );
+            claims.insert(claim_name.to_string(), disclosure.claim_value().clone());
+        }
+        Ok(serde_json::to_vec(&claims)?)
+    }
+}
+
+impl fmt::Display for SDJWT {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.without_key_binding())?;
+        if let Some(key_binding_jwt) = &self.key_binding_jwt {
+            write!(f, "{}", key_binding_jwt)?;
+        }
+        Ok(())
+    }
+}
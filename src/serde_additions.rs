@@ -67,7 +67,7 @@ pub mod audiences {
     struct AudiencesVisitor;
 
     impl<'de> Visitor<'de> for AudiencesVisitor {
-        type Value = Audiences;
+        type Value = Option<Audiences>;
 
         fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
             formatter.write_str("Audiences")
@@ -77,14 +77,14 @@ pub mod audiences {
         where
             E: DeError,
         {
-            Ok(Audiences::AsString(value))
+            Ok(Some(Audiences::AsString(value)))
         }
 
         fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
         where
             E: DeError,
         {
-            Ok(Audiences::AsString(value.to_string()))
+            Ok(Some(Audiences::AsString(value.to_string())))
         }
 
         fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
@@ -96,7 +96,21 @@ pub mod audiences {
             while let Some(audience) = seq.next_element()? {
                 audiences_set.insert(audience);
             }
-            Ok(Audiences::AsSet(audiences_set))
+            Ok(Some(Audiences::AsSet(audiences_set)))
+        }
+
+        fn visit_unit<E>(self) -> Result<Self::Value, E>
+        where
+            E: DeError,
+        {
+            Ok(None)
+        }
+
+        fn visit_none<E>(self) -> Result<Self::Value, E>
+        where
+            E: DeError,
+        {
+            Ok(None)
         }
     }
 
@@ -114,7 +128,33 @@ pub mod audiences {
     pub fn deserialize<'de, D: Deserializer<'de>>(
         deserializer: D,
     ) -> Result<Option<Audiences>, D::Error> {
-        let audiences = deserializer.deserialize_any(AudiencesVisitor)?;
-        Ok(Some(audiences))
+        deserializer.deserialize_any(AudiencesVisitor)
+    }
+}
+
+/// A JSON object stored as a string, such as a JWK. Strings are also
+/// accepted, and kept as-is.
+pub mod json_object_string {
+    use serde::{de::Error as DeError, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        json: &Option<String>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let json = json.as_deref().unwrap_or_default();
+        match serde_json::from_str::<serde_json::Value>(json) {
+            Ok(object @ serde_json::Value::Object(_)) => object.serialize(serializer),
+            _ => serializer.serialize_str(json),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<String>, D::Error> {
+        match serde_json::Value::deserialize(deserializer)? {
+            serde_json::Value::String(json) => Ok(Some(json)),
+            object @ serde_json::Value::Object(_) => Ok(Some(object.to_string())),
+            _ => Err(D::Error::custom("expected a JSON object")),
+        }
     }
 }
@@ -1,13 +1,29 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+
+use coarsetime::UnixTimeStamp;
 use ct_codecs::{Base64UrlSafeNoPadding, Decoder, Encoder};
-use serde::{de::DeserializeOwned, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use crate::claims::*;
 use crate::common::*;
 use crate::error::*;
 use crate::jwt_header::*;
+use crate::replay_guard::check_replay;
+use crate::revocation::check_revocation;
 
 pub const MAX_HEADER_LENGTH: usize = 8192;
 
+/// The maximum header length to accept, according to the verification options
+/// or the process-wide limits
+pub(crate) fn max_header_length(options: Option<&VerificationOptions>) -> usize {
+    match options {
+        Some(options) => options.max_header_length,
+        None => DefaultLimits::current().max_header_length,
+    }
+    .unwrap_or(MAX_HEADER_LENGTH)
+}
+
 /// Utilities to get information about a JWT token
 pub struct Token;
 
@@ -15,6 +31,30 @@ pub struct Token;
 #[derive(Debug, Clone, Default)]
 pub struct TokenMetadata {
     pub(crate) jwt_header: JWTHeader,
+    pub(crate) times: Option<UnverifiedTimes>,
+}
+
+/// Time claims of a token, decoded without verification
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub(crate) struct UnverifiedTimes {
+    #[serde(
+        rename = "iat",
+        default,
+        with = "crate::serde_additions::unix_timestamp"
+    )]
+    issued_at: Option<UnixTimeStamp>,
+    #[serde(
+        rename = "exp",
+        default,
+        with = "crate::serde_additions::unix_timestamp"
+    )]
+    expires_at: Option<UnixTimeStamp>,
+    #[serde(
+        rename = "nbf",
+        default,
+        with = "crate::serde_additions::unix_timestamp"
+    )]
+    invalid_before: Option<UnixTimeStamp>,
 }
 
 impl TokenMetadata {
@@ -92,25 +132,643 @@ impl TokenMetadata {
     pub fn certificate_sha256_thumbprint(&self) -> Option<&str> {
         self.jwt_header.certificate_sha256_thumbprint.as_deref()
     }
+
+    /// A custom header parameter for this token, that is not a registered
+    /// parameter with its own getter
+    /// This information should not be trusted: the token hasn't been verified.
+    pub fn header_parameter(&self, name: &str) -> Option<&serde_json::Value> {
+        self.jwt_header.parameters.get(name)
+    }
+
+    /// A custom header parameter for this token, deserialized as `T`
+    /// This information should not be trusted: the token hasn't been verified.
+    pub fn header_parameter_as<T: DeserializeOwned>(&self, name: &str) -> Result<Option<T>, Error> {
+        match self.jwt_header.parameters.get(name) {
+            None => Ok(None),
+            Some(value) => Ok(Some(serde_json::from_value(value.clone())?)),
+        }
+    }
+
+    /// All the custom header parameters for this token, as a JSON map
+    /// This information should not be trusted: the token hasn't been verified.
+    pub fn header_parameters(&self) -> &BTreeMap<String, serde_json::Value> {
+        &self.jwt_header.parameters
+    }
+
+    /// The expiration date of this token ("exp"), if the metadata was
+    /// decoded with `Token::decode_metadata_with_times()`
+    /// This information should not be trusted: the token hasn't been verified.
+    pub fn expires_at(&self) -> Option<UnixTimeStamp> {
+        self.times.and_then(|times| times.expires_at)
+    }
+
+    /// The creation date of this token ("iat"), if the metadata was decoded
+    /// with `Token::decode_metadata_with_times()`
+    /// This information should not be trusted: the token hasn't been verified.
+    pub fn issued_at(&self) -> Option<UnixTimeStamp> {
+        self.times.and_then(|times| times.issued_at)
+    }
+
+    /// The start date of this token ("nbf"), if the metadata was decoded with
+    /// `Token::decode_metadata_with_times()`
+    /// This information should not be trusted: the token hasn't been verified.
+    pub fn invalid_before(&self) -> Option<UnixTimeStamp> {
+        self.times.and_then(|times| times.invalid_before)
+    }
+
+    /// Return `true` if the token has an expiration date, and has expired
+    /// according to the time source and the tolerances of `options`
+    ///
+    /// Since the token hasn't been verified, this can only be used to drop
+    /// expired tokens early. A token that hasn't expired must still be fully
+    /// verified.
+    pub fn has_expired(&self, options: &VerificationOptions) -> bool {
+        if options.time_checks_disabled {
+            return false;
+        }
+        let expiration_tolerance = options
+            .expiration_tolerance
+            .or(options.time_tolerance)
+            .unwrap_or_default();
+        let expiration_grace_period = options.expiration_grace_period.unwrap_or_default();
+        self.expires_at().is_some_and(|expires_at| {
+            expiration_tolerance
+                .checked_add(expiration_grace_period)
+                .and_then(|tolerance| expires_at.checked_add(tolerance))
+                .is_some_and(|limit| options.now() > limit)
+        })
+    }
+}
+
+/// A signature or authentication algorithm implemented outside of this crate.
+///
+/// Implementations can be used with `Token::build_with_algorithm()` and
+/// `Token::verify_with_algorithm()` in order to create and verify tokens,
+/// with the same claims validation as the built-in algorithms.
+pub trait JWTAlgorithm {
+    /// The JWT algorithm name ("alg")
+    fn jwt_alg_name() -> &'static str;
+
+    /// The key identifier to include in new tokens ("kid")
+    fn key_id(&self) -> Option<&str> {
+        None
+    }
+
+    /// Compute the signature or authentication tag of `authenticated`
+    fn sign(&self, authenticated: &[u8]) -> Result<Vec<u8>, Error>;
+
+    /// Verify the signature or authentication tag of `authenticated`
+    fn verify(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error>;
+}
+
+/// A signer that produces signatures asynchronously, without the private key
+/// being available to the application.
+///
+/// This can be implemented to sign tokens using a remote key management
+/// service or an HSM, such as AWS KMS, GCP KMS, Azure Key Vault or Vault
+/// Transit, with `Token::build_async()`.
+///
+/// Signatures must use the JWS encoding for the algorithm. In particular,
+/// ECDSA signatures must be the fixed-size concatenation of `r` and `s`, not
+/// the DER encoding that most key management services return.
+pub trait AsyncTokenSigner {
+    /// The JWT algorithm name ("alg")
+    fn jwt_alg_name(&self) -> &str;
+
+    /// The key identifier to include in new tokens ("kid")
+    fn key_id(&self) -> Option<&str> {
+        None
+    }
+
+    /// Compute the signature of `message`
+    fn sign(&self, message: &[u8]) -> impl Future<Output = Result<Vec<u8>, Error>> + Send;
+}
+
+/// A key that can verify tokens, regardless of its type.
+///
+/// This is implemented by all shared keys and public keys, as well as custom
+/// algorithms, so that keys for different algorithms can be used together
+/// with `Token::verify_with_keys()`.
+pub trait KeyLike {
+    /// The JWT algorithm name ("alg") this key can verify
+    fn key_algorithm(&self) -> &'static str;
+
+    /// The key identifier ("kid"), if any
+    fn key_identifier(&self) -> Option<&str>;
+
+    /// Verify the signature or authentication tag of `authenticated`
+    fn verify_authenticated(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error>;
+
+    /// Properties of the key, checked against the key policy
+    fn key_parameters(&self) -> KeyParameters {
+        KeyParameters::Other
+    }
+
+    /// The RFC 7638 thumbprint of the public key, if the key has one
+    fn key_thumbprint(&self) -> Option<String> {
+        None
+    }
+
+    /// The SHA-256 thumbprint of the certificate the key was imported from,
+    /// if any
+    fn key_certificate_thumbprint(&self) -> Option<&[u8; 32]> {
+        None
+    }
+
+    /// Verify a token, and report whether it is stale
+    ///
+    /// See `Token::verify_with_keys_with_status()`.
+    fn verify_token_with_status<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<VerifiedToken<CustomClaims>, Error>
+    where
+        Self: Sized,
+    {
+        Token::verify_with_keys_with_status(&[self], token, options)
+    }
+
+    /// Verify a token, and report every failed claims check instead of
+    /// stopping at the first one
+    ///
+    /// See `Token::verify_with_keys_full_report()`.
+    fn verify_token_full_report<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<VerificationReport<CustomClaims>, Error>
+    where
+        Self: Sized,
+    {
+        Token::verify_with_keys_full_report(&[self], token, options)
+    }
+
+    /// Verify a token with a detached payload (RFC 7797)
+    ///
+    /// See `Token::verify_detached_with_keys()`.
+    fn verify_detached(
+        &self,
+        token: &str,
+        payload: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<(), Error>
+    where
+        Self: Sized,
+    {
+        Token::verify_detached_with_keys(&[self], token, payload, options)
+    }
+
+    /// Verify a token with a detached binary payload
+    ///
+    /// See `Token::verify_bytes_detached_with_keys()`.
+    fn verify_bytes_detached(
+        &self,
+        token: &str,
+        payload: &[u8],
+        options: Option<VerificationOptions>,
+    ) -> Result<(), Error>
+    where
+        Self: Sized,
+    {
+        Token::verify_bytes_detached_with_keys(&[self], token, payload, options)
+    }
+}
+
+/// A payload that is signed as-is, with `"b64": false` (RFC 7797), but not
+/// included in a token
+#[derive(Clone, Copy)]
+enum DetachedPayload<'a> {
+    /// A text payload
+    Unencoded(&'a str),
+    /// An arbitrary sequence of bytes
+    Bytes(&'a [u8]),
+}
+
+impl DetachedPayload<'_> {
+    fn as_bytes(&self) -> &[u8] {
+        match self {
+            DetachedPayload::Unencoded(payload) => payload.as_bytes(),
+            DetachedPayload::Bytes(payload) => payload,
+        }
+    }
+
+    /// The signing input, given the encoded header
+    fn authenticated(&self, jwt_header_b64: &str) -> Vec<u8> {
+        let payload = self.as_bytes();
+        let mut authenticated = Vec::with_capacity(jwt_header_b64.len() + 1 + payload.len());
+        authenticated.extend_from_slice(jwt_header_b64.as_bytes());
+        authenticated.push(b'.');
+        authenticated.extend_from_slice(payload);
+        authenticated
+    }
+}
+
+/// The result of verifying a token with `verify_token_full_report()`
+///
+/// The signature of the token has been verified, but its claims are only
+/// valid if no checks failed.
+#[derive(Debug)]
+pub struct VerificationReport<CustomClaims> {
+    claims: JWTClaims<CustomClaims>,
+    failures: Vec<Error>,
+}
+
+impl<CustomClaims> VerificationReport<CustomClaims> {
+    /// Return `true` if all the checks passed
+    pub fn is_valid(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// The failed checks, in the order they were run
+    pub fn failures(&self) -> &[Error] {
+        &self.failures
+    }
+
+    /// The claims of the token, that may have failed some checks
+    pub fn claims(&self) -> &JWTClaims<CustomClaims> {
+        &self.claims
+    }
+
+    /// The claims of the token if all the checks passed, or the first
+    /// failure otherwise
+    pub fn into_claims(self) -> Result<JWTClaims<CustomClaims>, Error> {
+        match self.failures.into_iter().next() {
+            Some(failure) => Err(failure),
+            None => Ok(self.claims),
+        }
+    }
+}
+
+/// The result of verifying a token with `verify_token_with_status()`
+///
+/// The signature and the claims of the token have been verified.
+#[derive(Debug)]
+pub struct VerifiedToken<CustomClaims> {
+    claims: JWTClaims<CustomClaims>,
+    stale: bool,
+}
+
+impl<CustomClaims> VerifiedToken<CustomClaims> {
+    /// Return `true` if the token had expired when it was verified, but was
+    /// still within the grace period set with `expiration_grace_period`
+    pub fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    /// The verified claims
+    pub fn claims(&self) -> &JWTClaims<CustomClaims> {
+        &self.claims
+    }
+
+    /// Return the verified claims
+    pub fn into_claims(self) -> JWTClaims<CustomClaims> {
+        self.claims
+    }
+}
+
+impl<Algorithm: JWTAlgorithm> KeyLike for Algorithm {
+    fn key_algorithm(&self) -> &'static str {
+        Algorithm::jwt_alg_name()
+    }
+
+    fn key_identifier(&self) -> Option<&str> {
+        self.key_id()
+    }
+
+    fn verify_authenticated(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+        self.verify(authenticated, signature)
+    }
 }
 
 impl Token {
+    /// Verify a token using any of the given keys, and validate its claims
+    ///
+    /// Only keys for the algorithm of the token are tried. If both the token
+    /// and a key have a key identifier, they must match. The token algorithm
+    /// must also be in `allowed_algorithms`, if that option is set.
+    pub fn verify_with_keys<CustomClaims: Serialize + DeserializeOwned>(
+        keys: &[&dyn KeyLike],
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        Token::verify_with_keys_with_status(keys, token, options).map(VerifiedToken::into_claims)
+    }
+
+    /// Verify a token using any of the given keys like `verify_with_keys()`,
+    /// and also report whether it is stale
+    ///
+    /// A token is stale if it was only accepted thanks to the
+    /// `expiration_grace_period` option.
+    pub fn verify_with_keys_with_status<CustomClaims: Serialize + DeserializeOwned>(
+        keys: &[&dyn KeyLike],
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<VerifiedToken<CustomClaims>, Error> {
+        let options = options.unwrap_or_default();
+        Token::verify_with_candidate_keys(keys, token, &options, |key, verify_signature| {
+            Token::verify_with_status(
+                key.key_algorithm(),
+                token,
+                Some(options.clone()),
+                verify_signature,
+            )
+        })
+    }
+
+    /// Verify a token using any of the given keys like `verify_with_keys()`,
+    /// but run all the claims checks and report every failure, instead of
+    /// stopping at the first one
+    ///
+    /// This fails only if the token cannot be decoded, or if its signature
+    /// cannot be verified, since its claims cannot be trusted in that case.
+    pub fn verify_with_keys_full_report<CustomClaims: Serialize + DeserializeOwned>(
+        keys: &[&dyn KeyLike],
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<VerificationReport<CustomClaims>, Error> {
+        let options = options.unwrap_or_default();
+        Token::verify_with_candidate_keys(keys, token, &options, |key, verify_signature| {
+            Token::verify_full_report(
+                key.key_algorithm(),
+                token,
+                Some(options.clone()),
+                verify_signature,
+            )
+        })
+    }
+
+    /// Verify a token with a detached payload (RFC 7797), using any of the
+    /// given keys
+    ///
+    /// The token must have been created with `sign_detached()` or
+    /// `authenticate_detached()`, and the payload is the one that was signed.
+    /// Only the header and the signature are checked: the payload is not
+    /// interpreted as a set of claims.
+    pub fn verify_detached_with_keys(
+        keys: &[&dyn KeyLike],
+        token: &str,
+        payload: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<(), Error> {
+        Token::verify_detached_payload_with_keys(
+            keys,
+            token,
+            DetachedPayload::Unencoded(payload),
+            options,
+        )
+    }
+
+    /// Verify a token with a detached binary payload, using any of the given
+    /// keys
+    ///
+    /// The token must have been created with `sign_bytes_detached()` or
+    /// `authenticate_bytes_detached()`, and the payload is the one that was
+    /// signed.
+    pub fn verify_bytes_detached_with_keys(
+        keys: &[&dyn KeyLike],
+        token: &str,
+        payload: &[u8],
+        options: Option<VerificationOptions>,
+    ) -> Result<(), Error> {
+        Token::verify_detached_payload_with_keys(
+            keys,
+            token,
+            DetachedPayload::Bytes(payload),
+            options,
+        )
+    }
+
+    fn verify_detached_payload_with_keys(
+        keys: &[&dyn KeyLike],
+        token: &str,
+        payload: DetachedPayload<'_>,
+        options: Option<VerificationOptions>,
+    ) -> Result<(), Error> {
+        let options = options.unwrap_or_default();
+        Token::verify_with_candidate_keys(keys, token, &options, |key, verify_signature| {
+            Token::verified_payload(
+                key.key_algorithm(),
+                token,
+                Some(payload),
+                &options,
+                verify_signature,
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Verify a token with each candidate key in turn, until one of them
+    /// verifies its signature
+    ///
+    /// Once the signature has been verified, the outcome of the remaining
+    /// checks is returned as-is, and the other keys are not tried.
+    fn verify_with_candidate_keys<T>(
+        keys: &[&dyn KeyLike],
+        token: &str,
+        options: &VerificationOptions,
+        verify: impl Fn(
+            &dyn KeyLike,
+            &mut dyn FnMut(&[u8], &[u8]) -> Result<(), Error>,
+        ) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let candidates = Token::candidate_keys(keys, token, options)?;
+        let mut last_error = None;
+        for key in candidates {
+            let mut signature_verified = false;
+            let result = check_key_policy(Some(options), key.key_parameters())
+                .and_then(|_| check_key_thumbprint(Some(options), || key.key_thumbprint()))
+                .and_then(|_| {
+                    check_certificate_thumbprint(Some(options), key.key_certificate_thumbprint())
+                })
+                .and_then(|_| {
+                    verify(key, &mut |authenticated, signature| {
+                        key.verify_authenticated(authenticated, signature)?;
+                        signature_verified = true;
+                        Ok(())
+                    })
+                });
+            match result {
+                Ok(verified) => return Ok(verified),
+                Err(e) if signature_verified => return Err(e),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap())
+    }
+
+    /// The keys that may have signed a token, according to its algorithm and
+    /// key identifier
+    fn candidate_keys<'k>(
+        keys: &[&'k dyn KeyLike],
+        token: &str,
+        options: &VerificationOptions,
+    ) -> Result<Vec<&'k dyn KeyLike>, Error> {
+        let metadata = Token::decode_metadata_with_options(token, options)?;
+        let jwt_alg_name = metadata.algorithm();
+        if let Some(allowed_algorithms) = &options.allowed_algorithms {
+            ensure!(
+                allowed_algorithms.contains(jwt_alg_name),
+                JWTError::AlgorithmMismatch
+            );
+        }
+        let candidates: Vec<&dyn KeyLike> = keys
+            .iter()
+            .copied()
+            .filter(|key| key.key_algorithm() == jwt_alg_name)
+            .filter(|key| match (key.key_identifier(), metadata.key_id()) {
+                (Some(key_id), Some(token_key_id)) => key_id == token_key_id,
+                _ => true,
+            })
+            .collect();
+        ensure!(!candidates.is_empty(), JWTError::AlgorithmMismatch);
+        Ok(candidates)
+    }
+
+    /// Create a token using a custom algorithm
+    pub fn build_with_algorithm<
+        Algorithm: JWTAlgorithm,
+        CustomClaims: Serialize + DeserializeOwned,
+    >(
+        algorithm: &Algorithm,
+        claims: JWTClaims<CustomClaims>,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(
+            Algorithm::jwt_alg_name().to_string(),
+            algorithm.key_id().map(|key_id| key_id.to_string()),
+        );
+        Token::build(&jwt_header, claims, |authenticated| {
+            algorithm.sign(authenticated)
+        })
+    }
+
+    /// Create a token whose signature is computed by an asynchronous signer
+    pub async fn build_async<
+        Signer: AsyncTokenSigner,
+        CustomClaims: Serialize + DeserializeOwned,
+    >(
+        signer: &Signer,
+        claims: JWTClaims<CustomClaims>,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(
+            signer.jwt_alg_name().to_string(),
+            signer.key_id().map(|key_id| key_id.to_string()),
+        );
+        let authenticated = Token::encode_authenticated(&jwt_header, claims)?;
+        let signature = signer.sign(authenticated.as_bytes()).await?;
+        Token::append_signature(authenticated, signature)
+    }
+
+    /// Verify a token using a custom algorithm, and validate its claims
+    pub fn verify_with_algorithm<
+        Algorithm: JWTAlgorithm,
+        CustomClaims: Serialize + DeserializeOwned,
+    >(
+        algorithm: &Algorithm,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        check_key_policy(options.as_ref(), algorithm.key_parameters())?;
+        check_certificate_thumbprint(options.as_ref(), algorithm.key_certificate_thumbprint())?;
+        Token::verify(
+            Algorithm::jwt_alg_name(),
+            token,
+            options,
+            |authenticated, signature| algorithm.verify(authenticated, signature),
+        )
+    }
+
     pub(crate) fn build<AuthenticationOrSignatureFn, CustomClaims: Serialize + DeserializeOwned>(
         jwt_header: &JWTHeader,
         claims: JWTClaims<CustomClaims>,
         authentication_or_signature_fn: AuthenticationOrSignatureFn,
     ) -> Result<String, Error>
     where
-        AuthenticationOrSignatureFn: FnOnce(&str) -> Result<Vec<u8>, Error>,
+        AuthenticationOrSignatureFn: FnOnce(&[u8]) -> Result<Vec<u8>, Error>,
+    {
+        let authenticated = Token::encode_authenticated(jwt_header, claims)?;
+        let authentication_tag_or_signature =
+            authentication_or_signature_fn(authenticated.as_bytes())?;
+        Token::append_signature(authenticated, authentication_tag_or_signature)
+    }
+
+    /// Create a token with a detached payload (RFC 7797)
+    ///
+    /// The payload is signed without being encoded, and is not included in
+    /// the token.
+    pub(crate) fn build_detached<AuthenticationOrSignatureFn>(
+        jwt_header: JWTHeader,
+        payload: &str,
+        authentication_or_signature_fn: AuthenticationOrSignatureFn,
+    ) -> Result<String, Error>
+    where
+        AuthenticationOrSignatureFn: FnOnce(&[u8]) -> Result<Vec<u8>, Error>,
+    {
+        Token::build_detached_payload(
+            jwt_header,
+            DetachedPayload::Unencoded(payload),
+            authentication_or_signature_fn,
+        )
+    }
+
+    /// Create a token with a detached binary payload (RFC 7797)
+    ///
+    /// The raw bytes of the payload are signed without being encoded, and
+    /// are not included in the token.
+    pub(crate) fn build_bytes_detached<AuthenticationOrSignatureFn>(
+        jwt_header: JWTHeader,
+        payload: &[u8],
+        authentication_or_signature_fn: AuthenticationOrSignatureFn,
+    ) -> Result<String, Error>
+    where
+        AuthenticationOrSignatureFn: FnOnce(&[u8]) -> Result<Vec<u8>, Error>,
     {
+        Token::build_detached_payload(
+            jwt_header,
+            DetachedPayload::Bytes(payload),
+            authentication_or_signature_fn,
+        )
+    }
+
+    fn build_detached_payload<AuthenticationOrSignatureFn>(
+        mut jwt_header: JWTHeader,
+        payload: DetachedPayload<'_>,
+        authentication_or_signature_fn: AuthenticationOrSignatureFn,
+    ) -> Result<String, Error>
+    where
+        AuthenticationOrSignatureFn: FnOnce(&[u8]) -> Result<Vec<u8>, Error>,
+    {
+        jwt_header.signature_type = None;
+        jwt_header.base64_payload = Some(false);
+        jwt_header.critical = Some(vec!["b64".to_string()]);
+        let jwt_header_b64 =
+            Base64UrlSafeNoPadding::encode_to_string(serde_json::to_string(&jwt_header)?)?;
+        let authentication_tag_or_signature =
+            authentication_or_signature_fn(&payload.authenticated(&jwt_header_b64))?;
+        Ok(format!(
+            "{}..{}",
+            jwt_header_b64,
+            Base64UrlSafeNoPadding::encode_to_string(authentication_tag_or_signature)?
+        ))
+    }
+
+    fn encode_authenticated<CustomClaims: Serialize + DeserializeOwned>(
+        jwt_header: &JWTHeader,
+        claims: JWTClaims<CustomClaims>,
+    ) -> Result<String, Error> {
         let jwt_header_json = serde_json::to_string(&jwt_header)?;
         let claims_json = serde_json::to_string(&claims)?;
-        let authenticated = format!(
+        Ok(format!(
             "{}.{}",
             Base64UrlSafeNoPadding::encode_to_string(jwt_header_json)?,
             Base64UrlSafeNoPadding::encode_to_string(claims_json)?
-        );
-        let authentication_tag_or_signature = authentication_or_signature_fn(&authenticated)?;
+        ))
+    }
+
+    fn append_signature(
+        authenticated: String,
+        authentication_tag_or_signature: Vec<u8>,
+    ) -> Result<String, Error> {
         let mut token = authenticated;
         token.push('.');
         token.push_str(&Base64UrlSafeNoPadding::encode_to_string(
@@ -126,10 +784,129 @@ impl Token {
         authentication_or_signature_fn: AuthenticationOrSignatureFn,
     ) -> Result<JWTClaims<CustomClaims>, Error>
     where
-        AuthenticationOrSignatureFn: FnOnce(&str, &[u8]) -> Result<(), Error>,
+        AuthenticationOrSignatureFn: FnOnce(&[u8], &[u8]) -> Result<(), Error>,
+    {
+        Token::verify_with_status(jwt_alg_name, token, options, authentication_or_signature_fn)
+            .map(VerifiedToken::into_claims)
+    }
+
+    pub(crate) fn verify_with_status<
+        AuthenticationOrSignatureFn,
+        CustomClaims: Serialize + DeserializeOwned,
+    >(
+        jwt_alg_name: &'static str,
+        token: &str,
+        options: Option<VerificationOptions>,
+        authentication_or_signature_fn: AuthenticationOrSignatureFn,
+    ) -> Result<VerifiedToken<CustomClaims>, Error>
+    where
+        AuthenticationOrSignatureFn: FnOnce(&[u8], &[u8]) -> Result<(), Error>,
+    {
+        let options = options.unwrap_or_default();
+        let claims_json = Token::verified_payload(
+            jwt_alg_name,
+            token,
+            None,
+            &options,
+            authentication_or_signature_fn,
+        )?;
+        Token::verify_claims_with_status(&claims_json, &options)
+    }
+
+    /// Validate the claims of a token whose signature has been verified
+    pub(crate) fn verify_claims<CustomClaims: Serialize + DeserializeOwned>(
+        claims_json: &[u8],
+        options: &VerificationOptions,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        Token::verify_claims_with_status(claims_json, options).map(VerifiedToken::into_claims)
+    }
+
+    fn verify_claims_with_status<CustomClaims: Serialize + DeserializeOwned>(
+        claims_json: &[u8],
+        options: &VerificationOptions,
+    ) -> Result<VerifiedToken<CustomClaims>, Error> {
+        let now = options.now();
+        let claims: JWTClaims<CustomClaims> = decode_claims(claims_json, options)?;
+        claims.validate_at(options, now)?;
+        check_json_claims(options, claims_json)?;
+        check_revocation(options, &claims)?;
+        check_replay(options, &claims)?;
+        let stale = claims.is_stale_at(options, now);
+        Ok(VerifiedToken { claims, stale })
+    }
+
+    /// Verify the signature of a token with a single key, WITHOUT VALIDATING
+    /// ITS CLAIMS, and return its payload
+    pub(crate) fn verify_signature_with_key(
+        key: &dyn KeyLike,
+        token: &str,
+        options: &VerificationOptions,
+    ) -> Result<Vec<u8>, Error> {
+        let metadata = Token::decode_metadata_with_options(token, options)?;
+        ensure!(
+            key.key_algorithm() == metadata.algorithm(),
+            JWTError::AlgorithmMismatch
+        );
+        if let (Some(key_id), Some(token_key_id)) = (key.key_identifier(), metadata.key_id()) {
+            ensure!(key_id == token_key_id, JWTError::KeyIdentifierMismatch);
+        }
+        check_key_policy(Some(options), key.key_parameters())?;
+        check_key_thumbprint(Some(options), || key.key_thumbprint())?;
+        check_certificate_thumbprint(Some(options), key.key_certificate_thumbprint())?;
+        Token::verified_payload(
+            key.key_algorithm(),
+            token,
+            None,
+            options,
+            |authenticated, signature| key.verify_authenticated(authenticated, signature),
+        )
+    }
+
+    pub(crate) fn verify_full_report<
+        AuthenticationOrSignatureFn,
+        CustomClaims: Serialize + DeserializeOwned,
+    >(
+        jwt_alg_name: &'static str,
+        token: &str,
+        options: Option<VerificationOptions>,
+        authentication_or_signature_fn: AuthenticationOrSignatureFn,
+    ) -> Result<VerificationReport<CustomClaims>, Error>
+    where
+        AuthenticationOrSignatureFn: FnOnce(&[u8], &[u8]) -> Result<(), Error>,
     {
         let options = options.unwrap_or_default();
+        let claims_json = Token::verified_payload(
+            jwt_alg_name,
+            token,
+            None,
+            &options,
+            authentication_or_signature_fn,
+        )?;
+        let claims: JWTClaims<CustomClaims> = decode_claims(&claims_json, &options)?;
+        let mut failures = claims.validation_failures(&options);
+        failures.extend(check_json_claims(&options, &claims_json).err());
+        failures.extend(check_revocation(&options, &claims).err());
+        if failures.is_empty() {
+            failures.extend(check_replay(&options, &claims).err());
+        }
+        Ok(VerificationReport { claims, failures })
+    }
 
+    /// Check the header and the signature or authentication tag of a token,
+    /// and return its payload
+    ///
+    /// If `detached_payload` is set, the token must have an empty payload,
+    /// and the detached payload is verified instead.
+    fn verified_payload<AuthenticationOrSignatureFn>(
+        jwt_alg_name: &'static str,
+        token: &str,
+        detached_payload: Option<DetachedPayload<'_>>,
+        options: &VerificationOptions,
+        authentication_or_signature_fn: AuthenticationOrSignatureFn,
+    ) -> Result<Vec<u8>, Error>
+    where
+        AuthenticationOrSignatureFn: FnOnce(&[u8], &[u8]) -> Result<(), Error>,
+    {
         if let Some(max_token_length) = options.max_token_length {
             ensure!(token.len() <= max_token_length, JWTError::TokenTooLong);
         }
@@ -137,7 +914,7 @@ impl Token {
         let mut parts = token.split('.');
         let jwt_header_b64 = parts.next().ok_or(JWTError::CompactEncodingError)?;
         ensure!(
-            jwt_header_b64.len() <= options.max_header_length.unwrap_or(MAX_HEADER_LENGTH),
+            jwt_header_b64.len() <= max_header_length(Some(options)),
             JWTError::HeaderTooLarge
         );
         let claims_b64 = parts.next().ok_or(JWTError::CompactEncodingError)?;
@@ -146,17 +923,31 @@ impl Token {
         let jwt_header: JWTHeader = serde_json::from_slice(
             &Base64UrlSafeNoPadding::decode_to_vec(jwt_header_b64, None)?,
         )?;
-        if let Some(signature_type) = &jwt_header.signature_type {
-            let signature_type_uc = signature_type.to_uppercase();
-            ensure!(
-                signature_type_uc == "JWT" || signature_type_uc.ends_with("+JWT"),
-                JWTError::NotJWT
-            );
+        if detached_payload.is_some() {
+            ensure!(claims_b64.is_empty(), JWTError::CompactEncodingError);
+            jwt_header.check_payload_encoding(true)?;
+        } else {
+            if let Some(signature_type) = &jwt_header.signature_type {
+                let signature_type_uc = signature_type.to_uppercase();
+                ensure!(
+                    signature_type_uc == "JWT" || signature_type_uc.ends_with("+JWT"),
+                    JWTError::NotJWT
+                );
+            }
+            jwt_header.check_payload_encoding(false)?;
         }
+        jwt_header.check_critical(options)?;
+        options.check_typ(jwt_header.signature_type.as_deref())?;
         ensure!(
             jwt_header.algorithm == jwt_alg_name,
             JWTError::AlgorithmMismatch
         );
+        if let Some(allowed_algorithms) = &options.allowed_algorithms {
+            ensure!(
+                allowed_algorithms.contains(jwt_alg_name),
+                JWTError::AlgorithmMismatch
+            );
+        }
         if let Some(required_key_id) = &options.required_key_id {
             if let Some(key_id) = &jwt_header.key_id {
                 ensure!(key_id == required_key_id, JWTError::KeyIdentifierMismatch);
@@ -166,27 +957,124 @@ impl Token {
         }
         let authentication_tag =
             Base64UrlSafeNoPadding::decode_to_vec(authentication_tag_b64, None)?;
+        if let Some(max_claims_length) = options.max_claims_length {
+            let claims_length = match &detached_payload {
+                Some(detached_payload) => detached_payload.as_bytes().len(),
+                None => claims_b64.len() / 4 * 3 + (claims_b64.len() % 4).saturating_sub(1),
+            };
+            ensure!(claims_length <= max_claims_length, JWTError::ClaimsTooLarge);
+        }
+        if let Some(detached_payload) = detached_payload {
+            let authenticated = detached_payload.authenticated(jwt_header_b64);
+            authentication_or_signature_fn(&authenticated, &authentication_tag)?;
+            return Ok(detached_payload.as_bytes().to_vec());
+        }
         let authenticated = &token[..jwt_header_b64.len() + 1 + claims_b64.len()];
-        authentication_or_signature_fn(authenticated, &authentication_tag)?;
-        let claims: JWTClaims<CustomClaims> =
-            serde_json::from_slice(&Base64UrlSafeNoPadding::decode_to_vec(claims_b64, None)?)?;
-        claims.validate(&options)?;
-        Ok(claims)
+        authentication_or_signature_fn(authenticated.as_bytes(), &authentication_tag)?;
+        Ok(Base64UrlSafeNoPadding::decode_to_vec(claims_b64, None)?)
+    }
+
+    /// Normalize a token copied from a log, a command line or an
+    /// `Authorization` header, so that it can be verified
+    ///
+    /// A leading `Bearer ` prefix is removed, along with any whitespace, and
+    /// the base64 padding of each part. Parsing and verification are always
+    /// strict otherwise: this function must be called explicitly.
+    pub fn normalize_lenient(token: &str) -> Result<String, Error> {
+        let token = token.trim_start();
+        let token = match token.get(..7) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("bearer ") => &token[7..],
+            _ => token,
+        };
+        let token: String = token.chars().filter(|c| !c.is_whitespace()).collect();
+        let normalized = token
+            .split('.')
+            .map(|part| part.trim_end_matches('='))
+            .collect::<Vec<_>>()
+            .join(".");
+        ensure!(
+            !normalized.is_empty()
+                && normalized
+                    .bytes()
+                    .all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_' || c == b'.'),
+            JWTError::CompactEncodingError
+        );
+        Ok(normalized)
     }
 
     /// Decode token information that can be usedful prior to signature/tag
     /// verification
     pub fn decode_metadata(token: &str) -> Result<TokenMetadata, Error> {
+        Self::decode_metadata_with_max_header_length(token, max_header_length(None))
+    }
+
+    /// Decode token information that can be useful prior to signature/tag
+    /// verification, with the header length limit of the verification
+    /// options
+    pub fn decode_metadata_with_options(
+        token: &str,
+        options: &VerificationOptions,
+    ) -> Result<TokenMetadata, Error> {
+        Self::decode_metadata_with_max_header_length(token, max_header_length(Some(options)))
+    }
+
+    pub(crate) fn decode_metadata_with_max_header_length(
+        token: &str,
+        max_header_length: usize,
+    ) -> Result<TokenMetadata, Error> {
         let mut parts = token.split('.');
         let jwt_header_b64 = parts.next().ok_or(JWTError::CompactEncodingError)?;
         ensure!(
-            jwt_header_b64.len() <= MAX_HEADER_LENGTH,
+            jwt_header_b64.len() <= max_header_length,
             JWTError::HeaderTooLarge
         );
         let jwt_header: JWTHeader = serde_json::from_slice(
             &Base64UrlSafeNoPadding::decode_to_vec(jwt_header_b64, None)?,
         )?;
-        Ok(TokenMetadata { jwt_header })
+        Ok(TokenMetadata {
+            jwt_header,
+            times: None,
+        })
+    }
+
+    /// Decode token information, along with its time claims ("iat", "exp",
+    /// "nbf"), prior to signature/tag verification
+    ///
+    /// This is cheaper than verifying the token, so that obviously expired
+    /// tokens can be rejected early.
+    pub fn decode_metadata_with_times(token: &str) -> Result<TokenMetadata, Error> {
+        let mut metadata = Self::decode_metadata(token)?;
+        metadata.times = Some(Self::decode_unverified_claims(token)?);
+        Ok(metadata)
+    }
+
+    /// Decode the claims of a token WITHOUT VERIFYING IT
+    ///
+    /// Nothing in the result can be trusted: the token may have been forged
+    /// or tampered with, and it may have expired. The claims must only be used
+    /// to select the keys and options to verify the token with, for example
+    /// from its issuer.
+    pub fn decode_claims_unverified<CustomClaims: DeserializeOwned>(
+        token: &str,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        Self::decode_unverified_claims(token)
+    }
+
+    /// Decode the claims of a token without verifying it. The result must
+    /// only be used to select the keys and options to verify the token with.
+    pub(crate) fn decode_unverified_claims<T: DeserializeOwned>(token: &str) -> Result<T, Error> {
+        ensure!(
+            token.len() <= DEFAULT_MAX_TOKEN_LENGTH,
+            JWTError::TokenTooLong
+        );
+        let mut parts = token.split('.');
+        let _jwt_header_b64 = parts.next().ok_or(JWTError::CompactEncodingError)?;
+        let claims_b64 = parts.next().ok_or(JWTError::CompactEncodingError)?;
+        let _authentication_tag_b64 = parts.next().ok_or(JWTError::CompactEncodingError)?;
+        ensure!(parts.next().is_none(), JWTError::CompactEncodingError);
+        Ok(serde_json::from_slice(
+            &Base64UrlSafeNoPadding::decode_to_vec(claims_b64, None)?,
+        )?)
     }
 }
 
@@ -261,3 +1149,134 @@ fn explicitly_empty_audiences() {
     let decoded = key.verify_token::<NoCustomClaims>(&token, None).unwrap();
     assert!(decoded.audiences.is_none());
 }
+
+#[test]
+fn custom_algorithm() {
+    use crate::prelude::*;
+
+    struct CustomHS256 {
+        key: Vec<u8>,
+    }
+
+    impl JWTAlgorithm for CustomHS256 {
+        fn jwt_alg_name() -> &'static str {
+            "X-CUSTOM-HS256"
+        }
+
+        fn key_id(&self) -> Option<&str> {
+            Some("custom key")
+        }
+
+        fn sign(&self, authenticated: &[u8]) -> Result<Vec<u8>, Error> {
+            Ok(hmac_sha256::HMAC::mac(authenticated, &self.key).to_vec())
+        }
+
+        fn verify(&self, authenticated: &[u8], signature: &[u8]) -> Result<(), Error> {
+            let expected = hmac_sha256::HMAC::mac(authenticated, &self.key);
+            ensure!(
+                timingsafe_eq(&expected, signature),
+                JWTError::InvalidAuthenticationTag
+            );
+            Ok(())
+        }
+    }
+
+    let algorithm = CustomHS256 {
+        key: b"custom key".to_vec(),
+    };
+    let claims = Claims::create(Duration::from_mins(10)).with_issuer("issuer");
+    let token = Token::build_with_algorithm(&algorithm, claims).unwrap();
+    let metadata = Token::decode_metadata(&token).unwrap();
+    assert_eq!(metadata.algorithm(), "X-CUSTOM-HS256");
+    assert_eq!(metadata.key_id(), Some("custom key"));
+    let claims =
+        Token::verify_with_algorithm::<_, NoCustomClaims>(&algorithm, &token, None).unwrap();
+    assert_eq!(claims.issuer.as_deref(), Some("issuer"));
+
+    let other_algorithm = CustomHS256 {
+        key: b"other key".to_vec(),
+    };
+    assert!(
+        Token::verify_with_algorithm::<_, NoCustomClaims>(&other_algorithm, &token, None).is_err()
+    );
+
+    let hs256_key = HS256Key::from_bytes(b"custom key");
+    assert!(hs256_key
+        .verify_token::<NoCustomClaims>(&token, None)
+        .is_err());
+
+    // Keys not covered by a restrictive policy are rejected
+    let options = VerificationOptions {
+        key_policy: Some(KeyPolicy {
+            allowed_curves: Some(HashSet::from_strings(&["P-256"])),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    assert!(Token::verify_with_algorithm::<_, NoCustomClaims>(
+        &algorithm,
+        &token,
+        Some(options.clone())
+    )
+    .is_err());
+    assert!(
+        Token::verify_with_keys::<NoCustomClaims>(&[&algorithm], &token, Some(options)).is_err()
+    );
+    let options = VerificationOptions {
+        key_policy: Some(KeyPolicy::default()),
+        ..Default::default()
+    };
+    Token::verify_with_algorithm::<_, NoCustomClaims>(&algorithm, &token, Some(options)).unwrap();
+}
+
+#[test]
+fn allowed_algorithms() {
+    use crate::prelude::*;
+
+    let hs256_key = HS256Key::generate().with_key_id("hs256");
+    let es256_key_pair = ES256KeyPair::generate().with_key_id("es256");
+    let es256_public_key = es256_key_pair.public_key();
+    let ed25519_key_pair = Ed25519KeyPair::generate();
+    let keys: [&dyn KeyLike; 2] = [&hs256_key, &es256_public_key];
+
+    let hs256_token = hs256_key
+        .authenticate(Claims::create(Duration::from_mins(10)))
+        .unwrap();
+    let es256_token = es256_key_pair
+        .sign(Claims::create(Duration::from_mins(10)))
+        .unwrap();
+    let ed25519_token = ed25519_key_pair
+        .sign(Claims::create(Duration::from_mins(10)))
+        .unwrap();
+
+    Token::verify_with_keys::<NoCustomClaims>(&keys, &hs256_token, None).unwrap();
+    Token::verify_with_keys::<NoCustomClaims>(&keys, &es256_token, None).unwrap();
+    assert!(matches!(
+        Token::verify_with_keys::<NoCustomClaims>(&keys, &ed25519_token, None)
+            .unwrap_err()
+            .downcast_ref::<JWTError>(),
+        Some(JWTError::AlgorithmMismatch)
+    ));
+
+    let options = VerificationOptions {
+        allowed_algorithms: Some(HashSet::from_strings(&["ES256", "EdDSA"])),
+        ..Default::default()
+    };
+    Token::verify_with_keys::<NoCustomClaims>(&keys, &es256_token, Some(options.clone())).unwrap();
+    assert!(matches!(
+        Token::verify_with_keys::<NoCustomClaims>(&keys, &hs256_token, Some(options.clone()))
+            .unwrap_err()
+            .downcast_ref::<JWTError>(),
+        Some(JWTError::AlgorithmMismatch)
+    ));
+    assert!(hs256_key
+        .verify_token::<NoCustomClaims>(&hs256_token, Some(options))
+        .is_err());
+
+    let other_es256_public_key = ES256KeyPair::generate().public_key();
+    let keys: [&dyn KeyLike; 2] = [&other_es256_public_key, &es256_public_key];
+    Token::verify_with_keys::<NoCustomClaims>(&keys, &es256_token, None).unwrap();
+    let wrong_key_id = es256_public_key.clone().with_key_id("other");
+    let keys: [&dyn KeyLike; 1] = [&wrong_key_id];
+    assert!(Token::verify_with_keys::<NoCustomClaims>(&keys, &es256_token, None).is_err());
+}
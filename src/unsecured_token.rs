@@ -0,0 +1,75 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::claims::*;
+use crate::common::*;
+use crate::error::*;
+use crate::jwt_header::*;
+use crate::token::*;
+
+/// The JWT algorithm name of unsecured tokens
+pub const UNSECURED_JWT_ALG_NAME: &str = "none";
+
+/// Utilities to create and decode unsecured tokens (`"alg":"none"`).
+///
+/// These tokens have no signature and no authentication tag: anyone can
+/// create or modify them. They are only meant for test fixtures and
+/// debugging, and should never be accepted as a proof of anything.
+///
+/// Regular keys never accept unsecured tokens. The only way to decode one is
+/// to explicitly call `UnsecuredToken::decode_unsecured()`.
+pub struct UnsecuredToken;
+
+impl UnsecuredToken {
+    /// Create an unsecured token, with an empty signature
+    pub fn build_unsecured<CustomClaims: Serialize + DeserializeOwned>(
+        claims: JWTClaims<CustomClaims>,
+    ) -> Result<String, Error> {
+        let jwt_header = JWTHeader::new(UNSECURED_JWT_ALG_NAME.to_string(), None);
+        Token::build(&jwt_header, claims, |_authenticated| Ok(vec![]))
+    }
+
+    /// Decode an unsecured token and validate its claims.
+    ///
+    /// This doesn't verify anything about the origin of the token. The token
+    /// is rejected if it is not explicitly marked as unsecured, or if it
+    /// includes a signature.
+    pub fn decode_unsecured<CustomClaims: Serialize + DeserializeOwned>(
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        check_certificate_thumbprint(options.as_ref(), None)?;
+        Token::verify(
+            UNSECURED_JWT_ALG_NAME,
+            token,
+            options,
+            |_authenticated, signature| {
+                ensure!(signature.is_empty(), JWTError::InvalidSignature);
+                Ok(())
+            },
+        )
+    }
+}
+
+#[test]
+fn unsecured_token() {
+    use crate::prelude::*;
+
+    let claims = Claims::create(Duration::from_mins(10)).with_subject("test");
+    let token = UnsecuredToken::build_unsecured(claims).unwrap();
+    assert!(token.ends_with('.'));
+    assert_eq!(Token::decode_metadata(&token).unwrap().algorithm(), "none");
+    let claims = UnsecuredToken::decode_unsecured::<NoCustomClaims>(&token, None).unwrap();
+    assert_eq!(claims.subject.as_deref(), Some("test"));
+
+    let key = HS256Key::generate();
+    assert!(key.verify_token::<NoCustomClaims>(&token, None).is_err());
+
+    let signed_token = key
+        .authenticate(Claims::create(Duration::from_mins(10)))
+        .unwrap();
+    assert!(UnsecuredToken::decode_unsecured::<NoCustomClaims>(&signed_token, None).is_err());
+
+    let parts: Vec<&str> = signed_token.split('.').collect();
+    let forged = format!("{}.{}", token.trim_end_matches('.'), parts[2]);
+    assert!(UnsecuredToken::decode_unsecured::<NoCustomClaims>(&forged, None).is_err());
+}
@@ -0,0 +1,414 @@
+use coarsetime::{Duration, UnixTimeStamp};
+use serde::{Deserialize, Serialize};
+
+use crate::claims::*;
+use crate::common::*;
+use crate::error::*;
+use crate::numeric_date::{from_rfc3339, to_rfc3339};
+use crate::token::*;
+
+/// The base context of the W3C Verifiable Credentials Data Model v1.1
+pub const VC_CONTEXT_V1: &str = "https://www.w3.org/2018/credentials/v1";
+
+/// The base context of the W3C Verifiable Credentials Data Model v2.0
+pub const VC_CONTEXT_V2: &str = "https://www.w3.org/ns/credentials/v2";
+
+type JSONObject = serde_json::Map<String, serde_json::Value>;
+
+/// The claims of a JWT-encoded verifiable credential
+///
+/// The `vc` claim contains the credential, without the properties that are
+/// represented by standard JWT claims.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VCClaims {
+    /// The credential
+    pub vc: JSONObject,
+}
+
+/// The claims of a JWT-encoded verifiable presentation
+///
+/// The `vp` claim contains the presentation, without the properties that are
+/// represented by standard JWT claims.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VPClaims {
+    /// The presentation
+    pub vp: JSONObject,
+}
+
+/// A W3C verifiable credential
+///
+/// When encoded as a JWT, the issuer is represented by the `iss` claim, the
+/// identifier by `jti`, the identifier of the subject by `sub`, and the
+/// validity period by `nbf` and `exp`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiableCredential {
+    /// The JSON-LD contexts ("@context"), starting with the base context
+    pub context: Vec<serde_json::Value>,
+
+    /// The types of the credential ("type"), including "VerifiableCredential"
+    pub types: Vec<String>,
+
+    /// The identifier of the credential ("id")
+    pub id: Option<String>,
+
+    /// The identifier of the issuer ("issuer")
+    pub issuer: Option<String>,
+
+    /// The start of the validity period ("validFrom")
+    pub valid_from: Option<UnixTimeStamp>,
+
+    /// The end of the validity period ("validUntil")
+    pub valid_until: Option<UnixTimeStamp>,
+
+    /// The claims about the subject ("credentialSubject"), including its
+    /// identifier ("id"), if any
+    pub credential_subject: JSONObject,
+
+    /// Other properties, such as "credentialStatus" or "evidence"
+    pub properties: JSONObject,
+}
+
+impl VerifiableCredential {
+    /// Create a credential with the given claims about its subject, using
+    /// the v2.0 base context
+    pub fn new(credential_subject: JSONObject) -> Self {
+        VerifiableCredential {
+            context: vec![VC_CONTEXT_V2.into()],
+            types: vec!["VerifiableCredential".to_string()],
+            id: None,
+            issuer: None,
+            valid_from: None,
+            valid_until: None,
+            credential_subject,
+            properties: JSONObject::new(),
+        }
+    }
+
+    /// Add a context
+    pub fn with_context(mut self, context: impl Into<serde_json::Value>) -> Self {
+        self.context.push(context.into());
+        self
+    }
+
+    /// Add a type
+    pub fn with_type(mut self, credential_type: impl ToString) -> Self {
+        self.types.push(credential_type.to_string());
+        self
+    }
+
+    /// Set the identifier of the credential
+    pub fn with_id(mut self, id: impl ToString) -> Self {
+        self.id = Some(id.to_string());
+        self
+    }
+
+    /// Set the identifier of the issuer
+    pub fn with_issuer(mut self, issuer: impl ToString) -> Self {
+        self.issuer = Some(issuer.to_string());
+        self
+    }
+
+    /// Set the start of the validity period
+    pub fn with_valid_from(mut self, valid_from: UnixTimeStamp) -> Self {
+        self.valid_from = Some(valid_from);
+        self
+    }
+
+    /// Set the end of the validity period
+    pub fn with_valid_until(mut self, valid_until: UnixTimeStamp) -> Self {
+        self.valid_until = Some(valid_until);
+        self
+    }
+
+    /// Add another property
+    pub fn with_property(
+        mut self,
+        name: impl ToString,
+        value: impl Into<serde_json::Value>,
+    ) -> Self {
+        self.properties.insert(name.to_string(), value.into());
+        self
+    }
+
+    /// The identifier of the subject, if any
+    pub fn subject_id(&self) -> Option<&str> {
+        self.credential_subject.get("id").and_then(|id| id.as_str())
+    }
+
+    /// Convert the credential to JWT claims, that can be signed to issue it
+    pub fn to_claims(&self) -> Result<JWTClaims<VCClaims>, Error> {
+        let mut credential_subject = self.credential_subject.clone();
+        let subject = match credential_subject.remove("id") {
+            None => None,
+            Some(serde_json::Value::String(subject)) => Some(subject),
+            Some(_) => bail!(JWTError::InvalidVerifiableCredential),
+        };
+        let mut vc = self.properties.clone();
+        vc.insert("@context".to_string(), self.context.clone().into());
+        vc.insert("type".to_string(), self.types.clone().into());
+        vc.insert("credentialSubject".to_string(), credential_subject.into());
+        let mut claims = Claims::with_custom_claims(VCClaims { vc }, Duration::default());
+        claims.issuer = self.issuer.clone();
+        claims.jwt_id = self.id.clone();
+        claims.subject = subject;
+        claims.invalid_before = self.valid_from;
+        claims.expires_at = self.valid_until;
+        Ok(claims)
+    }
+
+    /// Restore a credential from the claims of a verified JWT
+    ///
+    /// The base context and the "VerifiableCredential" type are required.
+    /// Properties that are represented by JWT claims can also be present in
+    /// the credential, but must have the same values.
+    pub fn from_claims(claims: &JWTClaims<VCClaims>) -> Result<Self, Error> {
+        let mut vc = claims.custom.vc.clone();
+        let context = take_context(&mut vc)?;
+        let types = take_types(&mut vc, "VerifiableCredential")?;
+        let mut credential_subject = match vc.remove("credentialSubject") {
+            Some(serde_json::Value::Object(credential_subject)) => credential_subject,
+            _ => bail!(JWTError::InvalidVerifiableCredential),
+        };
+        let subject = take_string(&mut credential_subject, "id")?;
+        if let Some(subject) = merge(claims.subject.clone(), subject)? {
+            credential_subject.insert("id".to_string(), subject.into());
+        }
+        let issuer = match vc.remove("issuer") {
+            Some(serde_json::Value::Object(mut issuer)) => take_string(&mut issuer, "id")?,
+            Some(serde_json::Value::String(issuer)) => Some(issuer),
+            Some(_) => bail!(JWTError::InvalidVerifiableCredential),
+            None => None,
+        };
+        let valid_from = take_date_time(&mut vc, &["validFrom", "issuanceDate"])?;
+        let valid_until = take_date_time(&mut vc, &["validUntil", "expirationDate"])?;
+        Ok(VerifiableCredential {
+            context,
+            types,
+            id: merge(claims.jwt_id.clone(), take_string(&mut vc, "id")?)?,
+            issuer: merge(claims.issuer.clone(), issuer)?,
+            valid_from: merge_date_time(claims.invalid_before, valid_from)?,
+            valid_until: merge_date_time(claims.expires_at, valid_until)?,
+            credential_subject,
+            properties: vc,
+        })
+    }
+
+    /// Verify a JWT-encoded credential using any of the given keys, validate
+    /// its claims, and return the credential
+    pub fn verify_with_keys(
+        keys: &[&dyn KeyLike],
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<Self, Error> {
+        let claims = Token::verify_with_keys::<VCClaims>(keys, token, options)?;
+        Self::from_claims(&claims)
+    }
+
+    /// The credential in the JSON representation of the data model, with
+    /// dates as RFC 3339 date-times
+    pub fn to_json(&self) -> Result<String, Error> {
+        let mut json = self.properties.clone();
+        json.insert("@context".to_string(), self.context.clone().into());
+        json.insert("type".to_string(), self.types.clone().into());
+        if let Some(id) = &self.id {
+            json.insert("id".to_string(), id.clone().into());
+        }
+        if let Some(issuer) = &self.issuer {
+            json.insert("issuer".to_string(), issuer.clone().into());
+        }
+        if let Some(valid_from) = self.valid_from {
+            json.insert("validFrom".to_string(), to_rfc3339(valid_from).into());
+        }
+        if let Some(valid_until) = self.valid_until {
+            json.insert("validUntil".to_string(), to_rfc3339(valid_until).into());
+        }
+        json.insert(
+            "credentialSubject".to_string(),
+            self.credential_subject.clone().into(),
+        );
+        Ok(serde_json::to_string(&json)?)
+    }
+}
+
+/// A W3C verifiable presentation of JWT-encoded credentials
+///
+/// When encoded as a JWT, the holder is represented by the `iss` claim and
+/// the identifier by `jti`. The audience and the nonce, that bind the
+/// presentation to a verifier, are the standard `aud` and `nonce` claims.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiablePresentation {
+    /// The JSON-LD contexts ("@context"), starting with the base context
+    pub context: Vec<serde_json::Value>,
+
+    /// The types of the presentation ("type"), including
+    /// "VerifiablePresentation"
+    pub types: Vec<String>,
+
+    /// The identifier of the presentation ("id")
+    pub id: Option<String>,
+
+    /// The identifier of the holder ("holder")
+    pub holder: Option<String>,
+
+    /// The JWT-encoded credentials ("verifiableCredential")
+    pub verifiable_credentials: Vec<String>,
+
+    /// Other properties
+    pub properties: JSONObject,
+}
+
+impl VerifiablePresentation {
+    /// Create a presentation of JWT-encoded credentials, using the v2.0 base
+    /// context
+    pub fn new(verifiable_credentials: Vec<String>) -> Self {
+        VerifiablePresentation {
+            context: vec![VC_CONTEXT_V2.into()],
+            types: vec!["VerifiablePresentation".to_string()],
+            id: None,
+            holder: None,
+            verifiable_credentials,
+            properties: JSONObject::new(),
+        }
+    }
+
+    /// Set the identifier of the presentation
+    pub fn with_id(mut self, id: impl ToString) -> Self {
+        self.id = Some(id.to_string());
+        self
+    }
+
+    /// Set the identifier of the holder
+    pub fn with_holder(mut self, holder: impl ToString) -> Self {
+        self.holder = Some(holder.to_string());
+        self
+    }
+
+    /// Convert the presentation to JWT claims, valid for `valid_for`, that
+    /// can be signed by the holder
+    ///
+    /// The audience and the nonce of the verifier can then be set with
+    /// `with_audience()` and `with_nonce()`.
+    pub fn to_claims(&self, valid_for: Duration) -> Result<JWTClaims<VPClaims>, Error> {
+        let mut vp = self.properties.clone();
+        vp.insert("@context".to_string(), self.context.clone().into());
+        vp.insert("type".to_string(), self.types.clone().into());
+        vp.insert(
+            "verifiableCredential".to_string(),
+            self.verifiable_credentials.clone().into(),
+        );
+        let mut claims = Claims::with_custom_claims(VPClaims { vp }, valid_for);
+        claims.issuer = self.holder.clone();
+        claims.jwt_id = self.id.clone();
+        Ok(claims)
+    }
+
+    /// Restore a presentation from the claims of a verified JWT
+    ///
+    /// The credentials are not verified.
+    pub fn from_claims(claims: &JWTClaims<VPClaims>) -> Result<Self, Error> {
+        let mut vp = claims.custom.vp.clone();
+        let context = take_context(&mut vp)?;
+        let types = take_types(&mut vp, "VerifiablePresentation")?;
+        let verifiable_credentials = match vp.remove("verifiableCredential") {
+            None => vec![],
+            Some(serde_json::Value::String(credential)) => vec![credential],
+            Some(credentials) => serde_json::from_value(credentials)
+                .map_err(|_| JWTError::InvalidVerifiableCredential)?,
+        };
+        Ok(VerifiablePresentation {
+            context,
+            types,
+            id: merge(claims.jwt_id.clone(), take_string(&mut vp, "id")?)?,
+            holder: merge(claims.issuer.clone(), take_string(&mut vp, "holder")?)?,
+            verifiable_credentials,
+            properties: vp,
+        })
+    }
+
+    /// Verify a JWT-encoded presentation using any of the given keys of the
+    /// holder, validate its claims, and return the presentation
+    ///
+    /// The credentials still have to be verified, with the keys of their
+    /// issuers.
+    pub fn verify_with_keys(
+        keys: &[&dyn KeyLike],
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<Self, Error> {
+        let claims = Token::verify_with_keys::<VPClaims>(keys, token, options)?;
+        Self::from_claims(&claims)
+    }
+}
+
+fn take_context(object: &mut JSONObject) -> Result<Vec<serde_json::Value>, Error> {
+    let context = match object.remove("@context") {
+        Some(serde_json::Value::Array(context)) => context,
+        Some(context @ serde_json::Value::String(_)) => vec![context],
+        _ => bail!(JWTError::InvalidVerifiableCredential),
+    };
+    ensure!(
+        matches!(
+            context.first().and_then(|base| base.as_str()),
+            Some(VC_CONTEXT_V1 | VC_CONTEXT_V2)
+        ),
+        JWTError::InvalidVerifiableCredential
+    );
+    Ok(context)
+}
+
+fn take_types(object: &mut JSONObject, required_type: &str) -> Result<Vec<String>, Error> {
+    let types: Vec<String> = match object.remove("type") {
+        Some(serde_json::Value::String(object_type)) => vec![object_type],
+        Some(types) => {
+            serde_json::from_value(types).map_err(|_| JWTError::InvalidVerifiableCredential)?
+        }
+        None => bail!(JWTError::InvalidVerifiableCredential),
+    };
+    ensure!(
+        types.iter().any(|object_type| object_type == required_type),
+        JWTError::InvalidVerifiableCredential
+    );
+    Ok(types)
+}
+
+fn take_string(object: &mut JSONObject, name: &str) -> Result<Option<String>, Error> {
+    match object.remove(name) {
+        None => Ok(None),
+        Some(serde_json::Value::String(value)) => Ok(Some(value)),
+        Some(_) => bail!(JWTError::InvalidVerifiableCredential),
+    }
+}
+
+fn take_date_time(object: &mut JSONObject, names: &[&str]) -> Result<Option<UnixTimeStamp>, Error> {
+    let mut date_time = None;
+    for name in names {
+        if let Some(value) = take_string(object, name)? {
+            date_time = merge_date_time(date_time, Some(from_rfc3339(&value)?))?;
+        }
+    }
+    Ok(date_time)
+}
+
+/// Combine a JWT claim with the credential property it represents
+fn merge<T: PartialEq>(claim: Option<T>, property: Option<T>) -> Result<Option<T>, Error> {
+    match (claim, property) {
+        (Some(claim), Some(property)) => {
+            ensure!(claim == property, JWTError::InvalidVerifiableCredential);
+            Ok(Some(claim))
+        }
+        (claim, property) => Ok(claim.or(property)),
+    }
+}
+
+/// Combine a JWT time claim with the credential date it represents, with a
+/// precision of one second
+fn merge_date_time(
+    claim: Option<UnixTimeStamp>,
+    property: Option<UnixTimeStamp>,
+) -> Result<Option<UnixTimeStamp>, Error> {
+    merge(
+        claim.map(|claim| claim.as_secs()),
+        property.map(|property| property.as_secs()),
+    )
+    .map(|date_time| date_time.map(UnixTimeStamp::from_secs))
+}
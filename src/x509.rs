@@ -0,0 +1,563 @@
+//! X.509 certificates (RFC 5280).
+//!
+//! Public keys can be extracted from certificates, and certificate chains
+//! included in token headers (`x5c`) can be validated against a set of
+//! trusted root certificates.
+//!
+//! Chain validation checks signatures, validity periods, basic constraints
+//! and key usages. Name constraints, policies and revocation are not
+//! supported.
+
+use coarsetime::{Duration, UnixTimeStamp};
+use ct_codecs::{Base64, Decoder};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::algorithms::*;
+use crate::claims::*;
+use crate::common::*;
+use crate::encrypted_pem::{
+    pem_decode, DerReader, DER_INTEGER, DER_OCTET_STRING, DER_OID, DER_SEQUENCE,
+};
+use crate::error::*;
+use crate::token::*;
+
+/// Default maximum number of certificates in a chain
+pub const DEFAULT_MAX_CERTIFICATE_CHAIN_LENGTH: usize = 10;
+
+const DER_BOOLEAN: u8 = 0x01;
+const DER_BIT_STRING: u8 = 0x03;
+const DER_UTC_TIME: u8 = 0x17;
+const DER_GENERALIZED_TIME: u8 = 0x18;
+const DER_CONTEXT_0: u8 = 0xa0;
+const DER_CONTEXT_1: u8 = 0x81;
+const DER_CONTEXT_2: u8 = 0x82;
+const DER_CONTEXT_3: u8 = 0xa3;
+
+const OID_SHA256_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b];
+const OID_SHA384_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0c];
+const OID_SHA512_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0d];
+const OID_ECDSA_WITH_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+const OID_ECDSA_WITH_SHA384: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x03];
+const OID_ECDSA_WITH_SHA512: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x04];
+const OID_ED25519: &[u8] = &[0x2b, 0x65, 0x70];
+
+const OID_BASIC_CONSTRAINTS: &[u8] = &[0x55, 0x1d, 0x13];
+const OID_KEY_USAGE: &[u8] = &[0x55, 0x1d, 0x0f];
+const OID_SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+
+const KEY_USAGE_DIGITAL_SIGNATURE: u16 = 0x8000;
+const KEY_USAGE_KEY_CERT_SIGN: u16 = 0x0400;
+
+/// A parsed certificate. The fields are parts of the DER encoding, either
+/// borrowed or copied.
+#[derive(Debug, Clone)]
+pub(crate) struct Certificate<B> {
+    der: B,
+    tbs_certificate: B,
+    signature_algorithm: B,
+    signature: B,
+    issuer: B,
+    subject: B,
+    not_before: u64,
+    not_after: u64,
+    public_key: B,
+    is_ca: bool,
+    max_path_length: Option<usize>,
+    key_usage: Option<u16>,
+}
+
+impl<'t> Certificate<&'t [u8]> {
+    pub(crate) fn from_der(der: &'t [u8]) -> Result<Self, Error> {
+        Self::parse(der).map_err(|_| JWTError::InvalidCertificate.into())
+    }
+
+    fn parse(der: &'t [u8]) -> Result<Self, Error> {
+        let mut reader = DerReader::new(der);
+        let mut certificate = DerReader::new(reader.read(DER_SEQUENCE)?);
+        ensure!(reader.is_empty(), JWTError::InvalidCertificate);
+        let tbs_certificate = certificate.read_element(DER_SEQUENCE)?;
+        let signature_algorithm = certificate.read(DER_SEQUENCE)?;
+        let signature = match certificate.read(DER_BIT_STRING)? {
+            [0, signature @ ..] => signature,
+            _ => bail!(JWTError::InvalidCertificate),
+        };
+        ensure!(certificate.is_empty(), JWTError::InvalidCertificate);
+
+        let mut tbs = DerReader::new(DerReader::new(tbs_certificate).read(DER_SEQUENCE)?);
+        if tbs.peek_tag() == Some(DER_CONTEXT_0) {
+            let _version = tbs.read(DER_CONTEXT_0)?;
+        }
+        let _serial_number = tbs.read(DER_INTEGER)?;
+        ensure!(
+            tbs.read(DER_SEQUENCE)? == signature_algorithm,
+            JWTError::InvalidCertificate
+        );
+        let issuer = tbs.read_element(DER_SEQUENCE)?;
+        let mut validity = DerReader::new(tbs.read(DER_SEQUENCE)?);
+        let not_before = read_time(&mut validity)?;
+        let not_after = read_time(&mut validity)?;
+        let subject = tbs.read_element(DER_SEQUENCE)?;
+        let public_key = tbs.read_element(DER_SEQUENCE)?;
+        for tag in [DER_CONTEXT_1, DER_CONTEXT_2] {
+            if tbs.peek_tag() == Some(tag) {
+                let _unique_id = tbs.read(tag)?;
+            }
+        }
+        let mut certificate = Certificate {
+            der,
+            tbs_certificate,
+            signature_algorithm,
+            signature,
+            issuer,
+            subject,
+            not_before,
+            not_after,
+            public_key,
+            is_ca: false,
+            max_path_length: None,
+            key_usage: None,
+        };
+        if tbs.peek_tag() == Some(DER_CONTEXT_3) {
+            let mut extensions =
+                DerReader::new(DerReader::new(tbs.read(DER_CONTEXT_3)?).read(DER_SEQUENCE)?);
+            while !extensions.is_empty() {
+                certificate.parse_extension(extensions.read(DER_SEQUENCE)?)?;
+            }
+        }
+        ensure!(tbs.is_empty(), JWTError::InvalidCertificate);
+        Ok(certificate)
+    }
+
+    fn parse_extension(&mut self, extension: &[u8]) -> Result<(), Error> {
+        let mut extension = DerReader::new(extension);
+        let oid = extension.read(DER_OID)?;
+        let critical = match extension.peek_tag() {
+            Some(DER_BOOLEAN) => extension.read(DER_BOOLEAN)? != [0],
+            _ => false,
+        };
+        let value = extension.read(DER_OCTET_STRING)?;
+        match oid {
+            OID_BASIC_CONSTRAINTS => {
+                let mut basic_constraints =
+                    DerReader::new(DerReader::new(value).read(DER_SEQUENCE)?);
+                if basic_constraints.peek_tag() == Some(DER_BOOLEAN) {
+                    self.is_ca = basic_constraints.read(DER_BOOLEAN)? != [0];
+                }
+                if basic_constraints.peek_tag() == Some(DER_INTEGER) {
+                    self.max_path_length = Some(basic_constraints.read_u32()? as usize);
+                }
+            }
+            OID_KEY_USAGE => {
+                let bits = match DerReader::new(value).read(DER_BIT_STRING)? {
+                    [_unused_bits, first] => (*first as u16) << 8,
+                    [_unused_bits, first, second, ..] => (*first as u16) << 8 | *second as u16,
+                    _ => 0,
+                };
+                self.key_usage = Some(bits);
+            }
+            OID_SUBJECT_ALT_NAME => {}
+            // Critical extensions that are not understood must be rejected.
+            // This includes the extended key usage, that is not enforced.
+            _ => ensure!(!critical, JWTError::InvalidCertificate),
+        }
+        Ok(())
+    }
+
+    /// The DER-encoded `SubjectPublicKeyInfo` of the certificate
+    pub(crate) fn public_key(&self) -> &'t [u8] {
+        self.public_key
+    }
+
+    /// Copy the certificate, so that it can be kept after the DER encoding
+    fn to_owned(&self) -> Certificate<Vec<u8>> {
+        Certificate {
+            der: self.der.to_vec(),
+            tbs_certificate: self.tbs_certificate.to_vec(),
+            signature_algorithm: self.signature_algorithm.to_vec(),
+            signature: self.signature.to_vec(),
+            issuer: self.issuer.to_vec(),
+            subject: self.subject.to_vec(),
+            not_before: self.not_before,
+            not_after: self.not_after,
+            public_key: self.public_key.to_vec(),
+            is_ca: self.is_ca,
+            max_path_length: self.max_path_length,
+            key_usage: self.key_usage,
+        }
+    }
+}
+
+impl<B: AsRef<[u8]>> Certificate<B> {
+    fn allows(&self, key_usage: u16) -> bool {
+        self.key_usage.is_none_or(|bits| bits & key_usage != 0)
+    }
+
+    fn is_valid_at(&self, now: u64, time_tolerance: u64) -> bool {
+        now.saturating_add(time_tolerance) >= self.not_before
+            && now.saturating_sub(time_tolerance) <= self.not_after
+    }
+
+    /// Check that `issuer` can issue certificates, and that it signed this
+    /// certificate
+    fn verify_issued_by(
+        &self,
+        issuer: &Certificate<impl AsRef<[u8]>>,
+        depth: usize,
+    ) -> Result<(), Error> {
+        ensure!(
+            self.issuer.as_ref() == issuer.subject.as_ref()
+                && issuer.is_ca
+                && issuer.allows(KEY_USAGE_KEY_CERT_SIGN)
+                && issuer.max_path_length.is_none_or(|max| depth <= max),
+            JWTError::UntrustedCertificateChain
+        );
+        verify_signature(
+            issuer.public_key.as_ref(),
+            self.signature_algorithm.as_ref(),
+            self.tbs_certificate.as_ref(),
+            self.signature.as_ref(),
+        )
+        .map_err(|_| JWTError::UntrustedCertificateChain.into())
+    }
+}
+
+/// Read a `Time` value, and return it as a UNIX timestamp
+fn read_time(reader: &mut DerReader) -> Result<u64, Error> {
+    let (digits, year) = match reader.peek_tag() {
+        Some(DER_UTC_TIME) => {
+            let time = reader.read(DER_UTC_TIME)?;
+            ensure!(time.len() == 13, JWTError::InvalidCertificate);
+            let year = parse_digits(&time[0..2])?;
+            (
+                &time[2..],
+                if year < 50 { 2000 + year } else { 1900 + year },
+            )
+        }
+        Some(DER_GENERALIZED_TIME) => {
+            let time = reader.read(DER_GENERALIZED_TIME)?;
+            ensure!(time.len() == 15, JWTError::InvalidCertificate);
+            (&time[4..], parse_digits(&time[0..4])?)
+        }
+        _ => bail!(JWTError::InvalidCertificate),
+    };
+    ensure!(digits[10] == b'Z', JWTError::InvalidCertificate);
+    let month = parse_digits(&digits[0..2])?;
+    let day = parse_digits(&digits[2..4])?;
+    let hours = parse_digits(&digits[4..6])?;
+    let minutes = parse_digits(&digits[6..8])?;
+    let seconds = parse_digits(&digits[8..10])?;
+    ensure!(
+        (1..=12).contains(&month)
+            && (1..=31).contains(&day)
+            && hours < 24
+            && minutes < 60
+            && seconds < 60,
+        JWTError::InvalidCertificate
+    );
+
+    // Days since the epoch, from the proleptic Gregorian calendar
+    let (year, month) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = (era * 146097 + day_of_era)
+        .checked_sub(719468)
+        .ok_or(JWTError::InvalidCertificate)?;
+    Ok(days * 86400 + hours * 3600 + minutes * 60 + seconds)
+}
+
+fn parse_digits(digits: &[u8]) -> Result<u64, Error> {
+    digits.iter().try_fold(0u64, |acc, &x| {
+        ensure!(x.is_ascii_digit(), JWTError::InvalidCertificate);
+        Ok(acc * 10 + (x - b'0') as u64)
+    })
+}
+
+/// Verify the signature of a certificate, using the `SubjectPublicKeyInfo`
+/// of its issuer
+fn verify_signature(
+    public_key: &[u8],
+    algorithm: &[u8],
+    message: &[u8],
+    signature: &[u8],
+) -> Result<(), Error> {
+    use p256::pkcs8::DecodePublicKey as _;
+    use p521::ecdsa::signature::hazmat::PrehashVerifier as _;
+
+    let oid = DerReader::new(algorithm).read(DER_OID)?;
+    let digest = match oid {
+        OID_SHA256_WITH_RSA | OID_ECDSA_WITH_SHA256 => hmac_sha256::Hash::hash(message).to_vec(),
+        OID_SHA384_WITH_RSA | OID_ECDSA_WITH_SHA384 => {
+            hmac_sha512::sha384::Hash::hash(message).to_vec()
+        }
+        OID_SHA512_WITH_RSA | OID_ECDSA_WITH_SHA512 => hmac_sha512::Hash::hash(message).to_vec(),
+        OID_ED25519 => {
+            let public_key = ed25519_compact::PublicKey::from_der(public_key)?;
+            let signature = ed25519_compact::Signature::from_slice(signature)?;
+            return Ok(public_key.verify(message, &signature)?);
+        }
+        _ => bail!(JWTError::UntrustedCertificateChain),
+    };
+    match oid {
+        OID_SHA256_WITH_RSA | OID_SHA384_WITH_RSA | OID_SHA512_WITH_RSA => {
+            let padding_scheme = match oid {
+                OID_SHA256_WITH_RSA => rsa::PaddingScheme::new_pkcs1v15_sign::<hmac_sha256::Hash>(),
+                OID_SHA384_WITH_RSA => {
+                    rsa::PaddingScheme::new_pkcs1v15_sign::<hmac_sha512::sha384::Hash>()
+                }
+                _ => rsa::PaddingScheme::new_pkcs1v15_sign::<hmac_sha512::Hash>(),
+            };
+            let public_key = RSAPublicKey::from_der(public_key)?;
+            rsa::PublicKey::verify(public_key.as_ref(), padding_scheme, &digest, signature)?;
+        }
+        _ => {
+            if let Ok(public_key) = p256::ecdsa::VerifyingKey::from_public_key_der(public_key) {
+                let signature = p256::ecdsa::Signature::from_der(signature)?;
+                public_key.verify_prehash(&digest, &signature)?;
+            } else if let Ok(public_key) =
+                p384::ecdsa::VerifyingKey::from_public_key_der(public_key)
+            {
+                let signature = p384::ecdsa::Signature::from_der(signature)?;
+                public_key.verify_prehash(&digest, &signature)?;
+            } else {
+                let public_key = p521::PublicKey::from_public_key_der(public_key)?;
+                let public_key = p521::ecdsa::VerifyingKey::from_affine(*public_key.as_affine())?;
+                let signature = p521::ecdsa::Signature::from_der(signature)?;
+                public_key.verify_prehash(&digest, &signature)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Return the DER-encoded `SubjectPublicKeyInfo` of a DER-encoded
+/// certificate
+pub(crate) fn certificate_public_key(der: &[u8]) -> Result<&[u8], Error> {
+    Ok(Certificate::from_der(der)?.public_key())
+}
+
+/// Return all the DER-encoded certificates of a PEM document
+pub(crate) fn certificates_from_pem(pem: &str) -> Result<Vec<Vec<u8>>, Error> {
+    const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+    const END: &str = "-----END CERTIFICATE-----";
+    let mut certificates = vec![];
+    let mut pem = pem;
+    while let Some(start) = pem.find(BEGIN) {
+        let end = pem[start..]
+            .find(END)
+            .map(|end| start + end + END.len())
+            .ok_or(JWTError::InvalidCertificate)?;
+        let document = pem_decode(&pem[start..end]).map_err(|_| JWTError::InvalidCertificate)?;
+        certificates.push(document.der.to_vec());
+        pem = &pem[end..];
+    }
+    ensure!(!certificates.is_empty(), JWTError::InvalidCertificate);
+    Ok(certificates)
+}
+
+/// Return the first DER-encoded certificate of a PEM document
+pub(crate) fn certificate_from_pem(pem: &str) -> Result<Vec<u8>, Error> {
+    let document = pem_decode(pem).map_err(|_| JWTError::InvalidCertificate)?;
+    ensure!(
+        document.label == "CERTIFICATE",
+        JWTError::InvalidCertificate
+    );
+    Ok(document.der.to_vec())
+}
+
+/// Return a verification key for `algorithm` from a `SubjectPublicKeyInfo`
+fn public_key_from_spki(algorithm: &str, spki: &[u8]) -> Result<Box<dyn KeyLike>, Error> {
+    let key: Box<dyn KeyLike> = match algorithm {
+        "ES256" => Box::new(ES256PublicKey::from_der(spki)?),
+        "ES384" => Box::new(ES384PublicKey::from_der(spki)?),
+        "ES512" => Box::new(ES512PublicKey::from_der(spki)?),
+        "ES256K" => Box::new(ES256kPublicKey::from_der(spki)?),
+        "BP256R1" => Box::new(BP256R1PublicKey::from_der(spki)?),
+        "BP384R1" => Box::new(BP384R1PublicKey::from_der(spki)?),
+        "EdDSA" => match Ed25519PublicKey::from_der(spki) {
+            Ok(public_key) => Box::new(public_key),
+            Err(_) => Box::new(Ed448PublicKey::from_der(spki)?),
+        },
+        "RS256" => Box::new(RS256PublicKey::from_der(spki)?),
+        "RS384" => Box::new(RS384PublicKey::from_der(spki)?),
+        "RS512" => Box::new(RS512PublicKey::from_der(spki)?),
+        "PS256" => Box::new(PS256PublicKey::from_der(spki)?),
+        "PS384" => Box::new(PS384PublicKey::from_der(spki)?),
+        "PS512" => Box::new(PS512PublicKey::from_der(spki)?),
+        #[cfg(feature = "pq")]
+        "ML-DSA-44" => Box::new(MLDSA44PublicKey::from_der(spki)?),
+        #[cfg(feature = "pq")]
+        "ML-DSA-65" => Box::new(MLDSA65PublicKey::from_der(spki)?),
+        _ => bail!(JWTError::AlgorithmMismatch),
+    };
+    Ok(key)
+}
+
+/// A set of trusted root certificates.
+///
+/// Tokens whose header includes a certificate chain (`x5c`) can be verified
+/// using the key of the first certificate of the chain, once the chain has
+/// been validated against the trusted roots.
+#[derive(Debug, Clone)]
+pub struct TrustedCertificates {
+    roots: Vec<Certificate<Vec<u8>>>,
+    max_chain_length: usize,
+}
+
+impl Default for TrustedCertificates {
+    fn default() -> Self {
+        TrustedCertificates {
+            roots: vec![],
+            max_chain_length: DEFAULT_MAX_CERTIFICATE_CHAIN_LENGTH,
+        }
+    }
+}
+
+impl TrustedCertificates {
+    /// Create an empty set of trusted certificates
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a set of trusted certificates from a PEM document containing
+    /// one or more certificates
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        let mut trusted_certificates = Self::new();
+        trusted_certificates.add_pem(pem)?;
+        Ok(trusted_certificates)
+    }
+
+    /// Trust a DER-encoded root certificate
+    pub fn add_der(&mut self, der: &[u8]) -> Result<(), Error> {
+        self.roots.push(Certificate::from_der(der)?.to_owned());
+        Ok(())
+    }
+
+    /// Trust all the certificates of a PEM document
+    pub fn add_pem(&mut self, pem: &str) -> Result<(), Error> {
+        for der in certificates_from_pem(pem)? {
+            self.add_der(&der)?;
+        }
+        Ok(())
+    }
+
+    /// Set the maximum number of certificates in a chain
+    pub fn with_max_chain_length(mut self, max_chain_length: usize) -> Self {
+        self.max_chain_length = max_chain_length;
+        self
+    }
+
+    /// The number of trusted root certificates
+    pub fn len(&self) -> usize {
+        self.roots.len()
+    }
+
+    /// Return `true` if no root certificates are trusted
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+
+    /// Validate a chain of DER-encoded certificates, starting with the leaf
+    /// certificate, and return the DER-encoded `SubjectPublicKeyInfo` of the
+    /// leaf certificate.
+    ///
+    /// Each certificate must be issued by the next one, and the last one
+    /// must either be a trusted root or be issued by a trusted root.
+    pub fn verify_certificate_chain(
+        &self,
+        chain: &[impl AsRef<[u8]>],
+        now: UnixTimeStamp,
+        time_tolerance: Duration,
+    ) -> Result<Vec<u8>, Error> {
+        ensure!(
+            !chain.is_empty() && chain.len() <= self.max_chain_length,
+            JWTError::UntrustedCertificateChain
+        );
+        let (now, time_tolerance) = (now.as_secs(), time_tolerance.as_secs());
+        let chain = chain
+            .iter()
+            .map(|der| Certificate::from_der(der.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let leaf = &chain[0];
+        ensure!(
+            leaf.allows(KEY_USAGE_DIGITAL_SIGNATURE),
+            JWTError::UntrustedCertificateChain
+        );
+        for (depth, certificate) in chain.iter().enumerate() {
+            ensure!(
+                certificate.is_valid_at(now, time_tolerance),
+                JWTError::CertificateExpired
+            );
+            if depth > 0 {
+                chain[depth - 1].verify_issued_by(certificate, depth - 1)?;
+            }
+            if self.roots.iter().any(|root| root.der == certificate.der) {
+                return Ok(leaf.public_key().to_vec());
+            }
+        }
+        let last = &chain[chain.len() - 1];
+        for root in &self.roots {
+            if root.is_valid_at(now, time_tolerance)
+                && last.verify_issued_by(root, chain.len() - 1).is_ok()
+            {
+                return Ok(leaf.public_key().to_vec());
+            }
+        }
+        bail!(JWTError::UntrustedCertificateChain)
+    }
+
+    /// Verify a token signed by the key of the first certificate of its
+    /// certificate chain (`x5c`), after having validated the chain
+    pub fn verify_token<CustomClaims: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<JWTClaims<CustomClaims>, Error> {
+        // Certificates and claims are checked with the same time and tolerance
+        let options = options.unwrap_or_default();
+        let metadata = Token::decode_metadata_with_max_header_length(
+            token,
+            max_header_length(Some(&options)),
+        )?;
+        let chain = metadata
+            .certificate_chain()
+            .ok_or(JWTError::MissingCertificateChain)?;
+        ensure!(
+            chain.len() <= self.max_chain_length,
+            JWTError::UntrustedCertificateChain
+        );
+        let chain = chain
+            .iter()
+            .map(|certificate| Base64::decode_to_vec(certificate, None))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| JWTError::InvalidCertificate)?;
+        let time_tolerance = options.time_tolerance.unwrap_or_default();
+        let public_key = self.verify_certificate_chain(&chain, options.now(), time_tolerance)?;
+        let key = public_key_from_spki(metadata.algorithm(), &public_key)?;
+
+        // The pinned certificate is the validated leaf certificate, not the
+        // one the key was imported from
+        check_certificate_thumbprint(Some(&options), Some(&hmac_sha256::Hash::hash(&chain[0])))?;
+        let options = VerificationOptions {
+            required_certificate_sha256_thumbprint: None,
+            ..options
+        };
+        Token::verify_with_keys(&[key.as_ref()], token, Some(options))
+    }
+
+    /// Verify a token like `verify_token()`, and return the whole payload as
+    /// a JSON value, without a compile-time schema
+    pub fn verify_token_untyped(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> Result<serde_json::Value, Error> {
+        let claims = self.verify_token::<NoCustomClaims>(token, options)?;
+        Ok(serde_json::to_value(claims)?)
+    }
+}